@@ -11,4 +11,12 @@ pub trait DebugInterface {
     fn get_gpr(&self, index: u32) -> u32;
 
     fn get_csr(&self, index: u32) -> u32;
+
+    /// Writes CSR as a debugger, so that triggers owned by debug mode can be set.
+    fn set_csr(&mut self, index: u32, value: u32);
+
+    fn is_debug_mode(&self) -> bool;
+
+    /// Leaves debug mode and resumes execution from `dpc`.
+    fn resume(&mut self);
 }
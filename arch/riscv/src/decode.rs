@@ -1,11 +1,17 @@
 //! Decode stage.
 mod operand_fetch;
+mod vector;
 
 use self::operand_fetch::OperandFetch;
+pub use self::vector::{
+    Avl, VectorArithInstr, VectorInstr, VectorMemInstr, VectorOperand, VsetInstr,
+};
 use crate::gpr::Gpr;
+use crate::isa::extension::{Extension, IsaConfig};
 use crate::isa::instr_format::*;
 use crate::isa::opcode::{AluOp, BranchType, CsrOp, LoadStoreType, Opcode, PrivOp};
 use bit_field::BitField;
+use bitfield::BitRange;
 use num::FromPrimitive;
 
 use std::result;
@@ -19,6 +25,9 @@ pub enum DecodeError {
 
     #[fail(display = "undefined funct3: 0b{:03b}", funct3)]
     UndefinedFunct3 { funct3: u32 },
+
+    #[fail(display = "illegal instruction: {:08x}", instr)]
+    IllegalInstr { instr: u32 },
 }
 
 #[derive(Debug, PartialEq)]
@@ -28,6 +37,7 @@ pub enum DecodedInstr {
     Alu(AluInstr),
     Br(BrInstr),
     Lsu(LsuInstr),
+    Vector { instr: VectorInstr, npc: u32 },
 }
 
 /// Decoded format for instructions executed in ALU.
@@ -162,7 +172,18 @@ impl LsuInstr {
 /// There are two sub-stage in the decode.
 ///   - Decode an instruction according to opcode.
 ///   - Prepare operand either reading GPR or zero/sign extending the immediate.
-pub fn decode(instr: u32, gpr: &Gpr, pc: u32, npc: u32) -> Result<DecodedInstr> {
+///
+/// Instructions of extensions which are not enabled in `isa` are illegal.
+pub fn decode(instr: u32, gpr: &Gpr, pc: u32, npc: u32, isa: IsaConfig) -> Result<DecodedInstr> {
+    let decoded = decode_instr(instr, gpr, pc, npc)?;
+    match required_extension(instr, &decoded) {
+        Some(ext) if !isa.has(ext) => Err(DecodeError::IllegalInstr { instr }),
+        _ => Ok(decoded),
+    }
+}
+
+// decode an instruction regardless of enabled extensions
+fn decode_instr(instr: u32, gpr: &Gpr, pc: u32, npc: u32) -> Result<DecodedInstr> {
     let opcode = get_opcode(instr)?;
     use self::DecodedInstr::*;
     use self::Opcode::*;
@@ -178,6 +199,33 @@ pub fn decode(instr: u32, gpr: &Gpr, pc: u32, npc: u32) -> Result<DecodedInstr>
         Jal => Ok(Br(decode_jal(JTypeInstr(instr), &gpr, pc, npc)?)),
         Branch => Ok(Br(decode_branch(BTypeInstr(instr), &gpr, pc, npc)?)),
         OpSystem => decode_system(ITypeInstr(instr), &gpr, npc),
+        LoadFp => Ok(Vector {
+            instr: vector::decode_vector_load_store(VMemInstr(instr), false, &gpr)?,
+            npc,
+        }),
+        StoreFp => Ok(Vector {
+            instr: vector::decode_vector_load_store(VMemInstr(instr), true, &gpr)?,
+            npc,
+        }),
+        OpV => Ok(Vector {
+            instr: vector::decode_op_v(OpVInstr(instr), &gpr)?,
+            npc,
+        }),
+    }
+}
+
+// Returns the extension which the decoded instruction belongs to.
+fn required_extension(instr: u32, decoded: &DecodedInstr) -> Option<Extension> {
+    use self::DecodedInstr::*;
+    match decoded {
+        // fence.i is decoded as NOP.
+        Alu(_) if get_opcode(instr) == Ok(Opcode::MiscMem) && instr.get_bits(12..15) == 1 => {
+            Some(Extension::Zifencei)
+        }
+        Alu(alu) => alu.alu_opcode.extension(),
+        Csr(_) => Some(Extension::Zicsr),
+        Vector { .. } => Some(Extension::Zve32x),
+        _ => None,
     }
 }
 
@@ -189,26 +237,49 @@ fn get_opcode(instr: u32) -> Result<Opcode> {
 
 // decode OP-IMM
 fn decode_op_imm(instr: ITypeInstr, gpr: &Gpr, npc: u32) -> Result<AluInstr> {
+    use crate::isa::funct::OpFunct7::{self, *};
     use crate::isa::funct::Rv32iOpImmFunct3::{self, *};
     let funct3 =
         Rv32iOpImmFunct3::from_u32(instr.funct3()).ok_or(DecodeError::UndefinedFunct3 {
             funct3: instr.funct3(),
         })?;
     let builder = AluInstrBuilder::new(true, &instr, &gpr, npc);
+    let illegal = DecodeError::IllegalInstr {
+        instr: instr.bit_range(31, 0),
+    };
     let decoded = match funct3 {
         ADDI => builder.build_instr(AluOp::ADD),
-        SLLI => builder.build_instr(AluOp::SLL),
         ORI => builder.build_instr(AluOp::OR),
         SLTI => builder.build_instr(AluOp::SLT),
         SLTIU => builder.build_instr(AluOp::SLTU),
         ANDI => builder.build_instr(AluOp::AND),
         XORI => builder.build_instr(AluOp::XOR),
-        SRxI => {
-            if instr.funct7() == 0b010_0000 {
-                builder.build_instr(AluOp::SRA)
-            } else {
-                builder.build_instr(AluOp::SRL)
-            }
+        SLLI | SRxI => {
+            // funct7 and shamt field encode the operation of shift-immediate instructions.
+            // Unknown funct7 is reserved, and raises illegal instruction.
+            let funct7 = OpFunct7::from_u32(instr.funct7());
+            let op = match (funct7, funct3) {
+                (Some(BASE), SLLI) => AluOp::SLL,
+                (Some(BSET), SLLI) => AluOp::BSET,
+                (Some(BCLR_BEXT), SLLI) => AluOp::BCLR,
+                (Some(BINV), SLLI) => AluOp::BINV,
+                (Some(ROTATE), SLLI) => match instr.shamt() {
+                    0b0_0000 => AluOp::CLZ,
+                    0b0_0001 => AluOp::CTZ,
+                    0b0_0010 => AluOp::CPOP,
+                    0b0_0100 => AluOp::SEXTB,
+                    0b0_0101 => AluOp::SEXTH,
+                    _ => return Err(illegal),
+                },
+                (Some(BASE), SRxI) => AluOp::SRL,
+                (Some(ALT), SRxI) => AluOp::SRA,
+                (Some(ROTATE), SRxI) => AluOp::ROR,
+                (Some(BCLR_BEXT), SRxI) => AluOp::BEXT,
+                (Some(BSET), SRxI) if instr.shamt() == 0b0_0111 => AluOp::ORCB,
+                (Some(BINV), SRxI) if instr.shamt() == 0b1_1000 => AluOp::REV8,
+                _ => return Err(illegal),
+            };
+            builder.build_instr(op)
         }
     };
     Ok(decoded)
@@ -216,34 +287,60 @@ fn decode_op_imm(instr: ITypeInstr, gpr: &Gpr, npc: u32) -> Result<AluInstr> {
 
 // decode OP
 fn decode_op(instr: RTypeInstr, gpr: &Gpr, npc: u32) -> Result<AluInstr> {
+    use crate::isa::funct::OpFunct7::{self, *};
     use crate::isa::funct::Rv32iOpFunct3::{self, *};
     let funct3 = Rv32iOpFunct3::from_u32(instr.funct3()).ok_or(DecodeError::UndefinedFunct3 {
         funct3: instr.funct3(),
     })?;
+    // Unknown funct7 is reserved, and raises illegal instruction.
+    let funct7 = OpFunct7::from_u32(instr.funct7());
     let builder = AluInstrBuilder::new(false, &instr, &gpr, npc);
-    let decoded = match funct3 {
-        ADD => {
-            if instr.funct7() == 0b010_0000 {
-                builder.build_instr(AluOp::SUB)
-            } else {
-                builder.build_instr(AluOp::ADD)
-            }
-        }
-        SLT => builder.build_instr(AluOp::SLT),
-        SLTU => builder.build_instr(AluOp::SLTU),
-        AND => builder.build_instr(AluOp::AND),
-        OR => builder.build_instr(AluOp::OR),
-        XOR => builder.build_instr(AluOp::XOR),
-        SLL => builder.build_instr(AluOp::SLL),
-        SRx => {
-            if instr.funct7() == 0b010_0000 {
-                builder.build_instr(AluOp::SRA)
-            } else {
-                builder.build_instr(AluOp::SRL)
-            }
+    let op = match (funct7, funct3) {
+        (Some(BASE), ADD) => AluOp::ADD,
+        (Some(BASE), SLT) => AluOp::SLT,
+        (Some(BASE), SLTU) => AluOp::SLTU,
+        (Some(BASE), AND) => AluOp::AND,
+        (Some(BASE), OR) => AluOp::OR,
+        (Some(BASE), XOR) => AluOp::XOR,
+        (Some(BASE), SLL) => AluOp::SLL,
+        (Some(BASE), SRx) => AluOp::SRL,
+        (Some(ALT), ADD) => AluOp::SUB,
+        (Some(ALT), SRx) => AluOp::SRA,
+        (Some(ALT), AND) => AluOp::ANDN,
+        (Some(ALT), OR) => AluOp::ORN,
+        (Some(ALT), XOR) => AluOp::XNOR,
+        (Some(MULDIV), ADD) => AluOp::MUL,
+        (Some(MULDIV), SLL) => AluOp::MULH,
+        (Some(MULDIV), SLT) => AluOp::MULHSU,
+        (Some(MULDIV), SLTU) => AluOp::MULHU,
+        (Some(MULDIV), XOR) => AluOp::DIV,
+        (Some(MULDIV), SRx) => AluOp::DIVU,
+        (Some(MULDIV), OR) => AluOp::REM,
+        (Some(MULDIV), AND) => AluOp::REMU,
+        (Some(SHADD), SLT) => AluOp::SH1ADD,
+        (Some(SHADD), XOR) => AluOp::SH2ADD,
+        (Some(SHADD), OR) => AluOp::SH3ADD,
+        (Some(MINMAX_CLMUL), XOR) => AluOp::MIN,
+        (Some(MINMAX_CLMUL), SRx) => AluOp::MINU,
+        (Some(MINMAX_CLMUL), OR) => AluOp::MAX,
+        (Some(MINMAX_CLMUL), AND) => AluOp::MAXU,
+        (Some(MINMAX_CLMUL), SLL) => AluOp::CLMUL,
+        (Some(MINMAX_CLMUL), SLT) => AluOp::CLMULR,
+        (Some(MINMAX_CLMUL), SLTU) => AluOp::CLMULH,
+        (Some(ZEXT), XOR) if instr.rs2() == 0 => AluOp::ZEXTH,
+        (Some(ROTATE), SLL) => AluOp::ROL,
+        (Some(ROTATE), SRx) => AluOp::ROR,
+        (Some(BSET), SLL) => AluOp::BSET,
+        (Some(BCLR_BEXT), SLL) => AluOp::BCLR,
+        (Some(BCLR_BEXT), SRx) => AluOp::BEXT,
+        (Some(BINV), SLL) => AluOp::BINV,
+        _ => {
+            return Err(DecodeError::IllegalInstr {
+                instr: instr.bit_range(31, 0),
+            })
         }
     };
-    Ok(decoded)
+    Ok(builder.build_instr(op))
 }
 
 // decode LUI
@@ -331,6 +428,19 @@ fn decode_system(instr: ITypeInstr, gpr: &Gpr, npc: u32) -> Result<DecodedInstr>
             funct3: instr.funct3(),
         })?;
 
+    // Writing a read-only CSR, e.g., `vl` and `vtype`, is illegal.
+    // CSRRS and CSRRC with rs1 = x0 do not write the CSR.
+    let write_csr = match funct3 {
+        PRIV => false,
+        CSRRW | CSRRWI => true,
+        CSRRS | CSRRC => instr.rs1() != 0,
+    };
+    if write_csr && instr.imm12().get_bits(10..12) == 0b11 {
+        return Err(DecodeError::IllegalInstr {
+            instr: instr.bit_range(31, 0),
+        });
+    }
+
     let decoded = match funct3 {
         PRIV => match instr.imm12() {
             0b0000_0000_0000 => DecodedInstr::System {
@@ -373,8 +483,8 @@ mod test {
     #[test]
     fn decode_undefined_opcode() {
         let gpr = Gpr::new();
-        let instr = 0x0000_0007u32; // FLW won't implement for the present.
-        let result = decode(instr, &gpr, 0, 4);
+        let instr = 0x0000_2007u32; // FLW won't implement for the present.
+        let result = decode(instr, &gpr, 0, 4, IsaConfig::default());
 
         assert_eq!(
             Err(DecodeError::UndefinedInstr { opcode: 0b000_0111 }),
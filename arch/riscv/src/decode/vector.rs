@@ -0,0 +1,278 @@
+//! Decode vector instructions of V extension (Zve32x subset).
+
+use super::{DecodeError, Result};
+use crate::gpr::Gpr;
+use crate::isa::funct::{OpIFunct6, OpMFunct6, OpVFunct3, VectorWidth};
+use crate::isa::instr_format::{OpVInstr, VMemInstr};
+use crate::isa::opcode::VectorOp;
+use bit_field::BitField;
+use bitfield::BitRange;
+use num::FromPrimitive;
+
+/// Decoded format for instructions executed in VPU.
+#[derive(Debug, PartialEq)]
+pub enum VectorInstr {
+    Config(VsetInstr),
+    Mem(VectorMemInstr),
+    Arith(VectorArithInstr),
+}
+
+/// Application vector length requested by vset{i}vl{i}.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Avl {
+    /// rs1 or uimm.
+    Value(u32),
+    /// rs1 is x0 and rd is not x0. Set vl to VLMAX.
+    Max,
+    /// Both rs1 and rd are x0. Keep current vl.
+    Keep,
+}
+
+/// Decoded format for vset{i}vl{i}.
+#[derive(Debug, PartialEq)]
+pub struct VsetInstr {
+    pub dest: u32,
+    pub avl: Avl,
+    pub vtype: u32,
+}
+
+/// Decoded format for vector unit-stride/strided load and store.
+/// `stride` is `None` for unit-stride.
+#[derive(Debug, PartialEq)]
+pub struct VectorMemInstr {
+    pub store: bool,
+    pub eew: usize,
+    pub vd: u32,
+    pub base: u32,
+    pub stride: Option<u32>,
+    pub masked: bool,
+}
+
+/// The first source operand is either a vector register or a scalar value.
+/// Immediates are extended to scalar in decode stage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VectorOperand {
+    Vector(u32),
+    Scalar(u32),
+}
+
+/// Decoded format for vector arithmetic.
+/// `dest` is a scalar register for instructions writing GPR, e.g., vmv.x.s.
+#[derive(Debug, PartialEq)]
+pub struct VectorArithInstr {
+    pub op: VectorOp,
+    pub dest: u32,
+    pub vs2: u32,
+    pub src1: VectorOperand,
+    pub masked: bool,
+}
+
+// decode OP-V
+pub fn decode_op_v(instr: OpVInstr, gpr: &Gpr) -> Result<VectorInstr> {
+    use crate::isa::funct::OpVFunct3::*;
+    let funct3 = OpVFunct3::from_u32(instr.funct3()).ok_or(DecodeError::UndefinedFunct3 {
+        funct3: instr.funct3(),
+    })?;
+
+    match funct3 {
+        OPIVV | OPIVX | OPIVI => decode_op_i(instr, funct3, gpr),
+        OPMVV | OPMVX => decode_op_m(instr, funct3, gpr),
+        OPCFG => decode_vset(instr, gpr),
+        OPFVV | OPFVF => Err(illegal(instr)),
+    }
+}
+
+// decode LOAD-FP/STORE-FP as vector load/store
+pub fn decode_vector_load_store(instr: VMemInstr, store: bool, gpr: &Gpr) -> Result<VectorInstr> {
+    use crate::isa::funct::VectorWidth::*;
+    // Other widths are scalar floating-point load/store which are not supported.
+    let width = VectorWidth::from_u32(instr.width()).ok_or(DecodeError::UndefinedInstr {
+        opcode: instr.opcode(),
+    })?;
+    let illegal = DecodeError::IllegalInstr {
+        instr: instr.bit_range(31, 0),
+    };
+    let eew = match width {
+        E8 => 1,
+        E16 => 2,
+        E32 => 4,
+        E64 => return Err(illegal), // ELEN is 32.
+    };
+    // Segment, indexed and fault-only-first accesses are not supported yet.
+    if instr.nf() != 0 || instr.mew() {
+        return Err(illegal);
+    }
+    let stride = match instr.mop() {
+        0b00 if instr.rs2() == 0 => None,
+        0b10 => Some(gpr.read_u32(instr.rs2())),
+        _ => return Err(illegal),
+    };
+
+    Ok(VectorInstr::Mem(VectorMemInstr {
+        store,
+        eew,
+        vd: instr.vd(),
+        base: gpr.read_u32(instr.rs1()),
+        stride,
+        masked: !instr.vm(),
+    }))
+}
+
+// decode vsetvli/vsetivli/vsetvl
+fn decode_vset(instr: OpVInstr, gpr: &Gpr) -> Result<VectorInstr> {
+    let rd = instr.vd();
+    let rs1 = instr.vs1();
+    let avl = match (rs1, rd) {
+        (0, 0) => Avl::Keep,
+        (0, _) => Avl::Max,
+        _ => Avl::Value(gpr.read_u32(rs1)),
+    };
+
+    let raw: u32 = instr.bit_range(31, 0);
+    let (avl, vtype) = if !raw.get_bit(31) {
+        // vsetvli
+        (avl, raw.get_bits(20..31))
+    } else if raw.get_bit(30) {
+        // vsetivli
+        (Avl::Value(rs1), raw.get_bits(20..30))
+    } else if raw.get_bits(25..31) == 0 {
+        // vsetvl
+        (avl, gpr.read_u32(instr.vs2()))
+    } else {
+        return Err(illegal(instr));
+    };
+
+    Ok(VectorInstr::Config(VsetInstr {
+        dest: rd,
+        avl,
+        vtype,
+    }))
+}
+
+// decode OPIVV/OPIVX/OPIVI
+fn decode_op_i(instr: OpVInstr, funct3: OpVFunct3, gpr: &Gpr) -> Result<VectorInstr> {
+    use crate::isa::funct::OpIFunct6::*;
+    use crate::isa::funct::OpVFunct3::*;
+    let funct6 = OpIFunct6::from_u32(instr.funct6()).ok_or_else(|| illegal(instr))?;
+
+    let is_shift = funct6 == VSLL || funct6 == VSRL || funct6 == VSRA;
+    let src1 = match funct3 {
+        OPIVV => VectorOperand::Vector(instr.vs1()),
+        OPIVX => VectorOperand::Scalar(gpr.read_u32(instr.vs1())),
+        // Shift amount is unsigned.
+        _ if is_shift => VectorOperand::Scalar(instr.vs1()),
+        _ => VectorOperand::Scalar(instr.simm5() as u32),
+    };
+
+    let op = match (funct6, funct3) {
+        (VADD, _) => VectorOp::VADD,
+        (VSUB, OPIVV) | (VSUB, OPIVX) => VectorOp::VSUB,
+        (VRSUB, OPIVX) | (VRSUB, OPIVI) => VectorOp::VRSUB,
+        (VMINU, OPIVV) | (VMINU, OPIVX) => VectorOp::VMINU,
+        (VMIN, OPIVV) | (VMIN, OPIVX) => VectorOp::VMIN,
+        (VMAXU, OPIVV) | (VMAXU, OPIVX) => VectorOp::VMAXU,
+        (VMAX, OPIVV) | (VMAX, OPIVX) => VectorOp::VMAX,
+        (VAND, _) => VectorOp::VAND,
+        (VOR, _) => VectorOp::VOR,
+        (VXOR, _) => VectorOp::VXOR,
+        (VMERGE, _) if !instr.vm() => VectorOp::VMERGE,
+        (VMERGE, _) if instr.vs2() == 0 => VectorOp::VMV,
+        (VMSEQ, _) => VectorOp::VMSEQ,
+        (VMSNE, _) => VectorOp::VMSNE,
+        (VMSLTU, OPIVV) | (VMSLTU, OPIVX) => VectorOp::VMSLTU,
+        (VMSLT, OPIVV) | (VMSLT, OPIVX) => VectorOp::VMSLT,
+        (VMSLEU, _) => VectorOp::VMSLEU,
+        (VMSLE, _) => VectorOp::VMSLE,
+        (VMSGTU, OPIVX) | (VMSGTU, OPIVI) => VectorOp::VMSGTU,
+        (VMSGT, OPIVX) | (VMSGT, OPIVI) => VectorOp::VMSGT,
+        (VSLL, _) => VectorOp::VSLL,
+        (VSRL, _) => VectorOp::VSRL,
+        (VSRA, _) => VectorOp::VSRA,
+        _ => return Err(illegal(instr)),
+    };
+
+    Ok(VectorInstr::Arith(VectorArithInstr {
+        op,
+        dest: instr.vd(),
+        vs2: instr.vs2(),
+        src1,
+        // vmerge uses v0 as a selector rather than a mask.
+        masked: !instr.vm() && op != VectorOp::VMERGE,
+    }))
+}
+
+// decode OPMVV/OPMVX
+fn decode_op_m(instr: OpVInstr, funct3: OpVFunct3, gpr: &Gpr) -> Result<VectorInstr> {
+    use crate::isa::funct::OpMFunct6::*;
+    use crate::isa::funct::OpVFunct3::*;
+    let funct6 = OpMFunct6::from_u32(instr.funct6()).ok_or_else(|| illegal(instr))?;
+
+    let src1 = match funct3 {
+        OPMVV => VectorOperand::Vector(instr.vs1()),
+        _ => VectorOperand::Scalar(gpr.read_u32(instr.vs1())),
+    };
+    let vv = funct3 == OPMVV;
+
+    let op = match funct6 {
+        VREDSUM if vv => VectorOp::VREDSUM,
+        VREDAND if vv => VectorOp::VREDAND,
+        VREDOR if vv => VectorOp::VREDOR,
+        VREDXOR if vv => VectorOp::VREDXOR,
+        VREDMINU if vv => VectorOp::VREDMINU,
+        VREDMIN if vv => VectorOp::VREDMIN,
+        VREDMAXU if vv => VectorOp::VREDMAXU,
+        VREDMAX if vv => VectorOp::VREDMAX,
+        VWXUNARY0 if vv => match instr.vs1() {
+            0b0_0000 if instr.vm() => VectorOp::VMV_X_S,
+            0b1_0000 => VectorOp::VCPOP,
+            0b1_0001 => VectorOp::VFIRST,
+            _ => return Err(illegal(instr)),
+        },
+        VWXUNARY0 if instr.vs2() == 0 && instr.vm() => VectorOp::VMV_S_X,
+        VMUNARY0 if vv && instr.vs1() == 0b1_0001 && instr.vs2() == 0 => VectorOp::VID,
+        VMANDN if vv && instr.vm() => VectorOp::VMANDN,
+        VMAND if vv && instr.vm() => VectorOp::VMAND,
+        VMOR if vv && instr.vm() => VectorOp::VMOR,
+        VMXOR if vv && instr.vm() => VectorOp::VMXOR,
+        VMORN if vv && instr.vm() => VectorOp::VMORN,
+        VMNAND if vv && instr.vm() => VectorOp::VMNAND,
+        VMNOR if vv && instr.vm() => VectorOp::VMNOR,
+        VMXNOR if vv && instr.vm() => VectorOp::VMXNOR,
+        VDIVU => VectorOp::VDIVU,
+        VDIV => VectorOp::VDIV,
+        VREMU => VectorOp::VREMU,
+        VREM => VectorOp::VREM,
+        VMULHU => VectorOp::VMULHU,
+        VMUL => VectorOp::VMUL,
+        VMULHSU => VectorOp::VMULHSU,
+        VMULH => VectorOp::VMULH,
+        VMADD => VectorOp::VMADD,
+        VNMSUB => VectorOp::VNMSUB,
+        VMACC => VectorOp::VMACC,
+        VNMSAC => VectorOp::VNMSAC,
+        _ => return Err(illegal(instr)),
+    };
+
+    // vs1 field of unary operations is a part of opcode.
+    let src1 = match op {
+        VectorOp::VID | VectorOp::VCPOP | VectorOp::VFIRST | VectorOp::VMV_X_S => {
+            VectorOperand::Scalar(0)
+        }
+        _ => src1,
+    };
+
+    Ok(VectorInstr::Arith(VectorArithInstr {
+        op,
+        dest: instr.vd(),
+        vs2: instr.vs2(),
+        src1,
+        masked: !instr.vm(),
+    }))
+}
+
+// helper to create illegal instruction error
+fn illegal(instr: OpVInstr) -> DecodeError {
+    DecodeError::IllegalInstr {
+        instr: instr.bit_range(31, 0),
+    }
+}
@@ -1,7 +1,7 @@
 //! Execute stage.
 //! Returns write back data.
 
-use crate::decode::{AluInstr, BrInstr, CsrInstr, DecodedInstr, LsuInstr};
+use crate::decode::{AluInstr, BrInstr, CsrInstr, DecodedInstr, LsuInstr, VectorInstr};
 use crate::isa::opcode::{AluOp, BranchType, LoadStoreType, PrivOp};
 use bit_field::BitField;
 
@@ -11,6 +11,7 @@ pub enum WriteBackData {
     Csr(CsrInstr),
     Lsu(LsuOp),
     Priv(PrivOp),
+    Vector(VectorInstr),
 }
 
 impl Default for WriteBackData {
@@ -46,6 +47,8 @@ pub fn execute(instr: DecodedInstr) -> Result<(WriteBackData, u32), ExecuteError
         DecodedInstr::Alu(decoded) => execute_alu(decoded),
         DecodedInstr::Br(decoded) => execute_branch(decoded),
         DecodedInstr::Lsu(decoded) => execute_lsu(decoded),
+        // Vector instructions are executed in VPU which has the vector register file.
+        DecodedInstr::Vector { instr, npc } => Ok((WriteBackData::Vector(instr), npc)),
     }
 }
 
@@ -98,7 +101,73 @@ fn alu_op(op: AluOp, src1: u32, src2: u32) -> u32 {
         SRA => ((src1 as i32) >> src2.get_bits(0..5)) as u32,
         LUI => src2 << 12,
         AUIPC => src1.wrapping_add(src2 << 12),
+        MUL => src1.wrapping_mul(src2),
+        MULH => ((i64::from(src1 as i32) * i64::from(src2 as i32)) >> 32) as u32,
+        MULHSU => ((i64::from(src1 as i32) * i64::from(src2)) >> 32) as u32,
+        MULHU => ((u64::from(src1) * u64::from(src2)) >> 32) as u32,
+        // Division by zero and overflow do not raise exceptions in RISC-V.
+        DIV => match src2 {
+            0 => 0xffff_ffff,
+            _ => (src1 as i32).wrapping_div(src2 as i32) as u32,
+        },
+        DIVU => match src2 {
+            0 => 0xffff_ffff,
+            _ => src1 / src2,
+        },
+        REM => match src2 {
+            0 => src1,
+            _ => (src1 as i32).wrapping_rem(src2 as i32) as u32,
+        },
+        REMU => match src2 {
+            0 => src1,
+            _ => src1 % src2,
+        },
+        SH1ADD => src2.wrapping_add(src1 << 1),
+        SH2ADD => src2.wrapping_add(src1 << 2),
+        SH3ADD => src2.wrapping_add(src1 << 3),
+        ANDN => src1 & !src2,
+        ORN => src1 | !src2,
+        XNOR => !(src1 ^ src2),
+        CLZ => src1.leading_zeros(),
+        CTZ => src1.trailing_zeros(),
+        CPOP => src1.count_ones(),
+        MAX => (src1 as i32).max(src2 as i32) as u32,
+        MAXU => src1.max(src2),
+        MIN => (src1 as i32).min(src2 as i32) as u32,
+        MINU => src1.min(src2),
+        SEXTB => i32::from(src1 as i8) as u32,
+        SEXTH => i32::from(src1 as i16) as u32,
+        ZEXTH => src1 & 0xffff,
+        ROL => src1.rotate_left(src2.get_bits(0..5)),
+        ROR => src1.rotate_right(src2.get_bits(0..5)),
+        ORCB => or_combine_bytes(src1),
+        REV8 => src1.swap_bytes(),
+        CLMUL => carry_less_mul(src1, src2) as u32,
+        CLMULH => (carry_less_mul(src1, src2) >> 32) as u32,
+        CLMULR => (carry_less_mul(src1, src2) >> 31) as u32,
+        BCLR => src1 & !(1 << src2.get_bits(0..5)),
+        BEXT => (src1 >> src2.get_bits(0..5)) & 1,
+        BINV => src1 ^ (1 << src2.get_bits(0..5)),
+        BSET => src1 | (1 << src2.get_bits(0..5)),
+    }
+}
+
+// helper for orc.b. Each byte becomes 0xff if any bit in the byte is set.
+fn or_combine_bytes(src: u32) -> u32 {
+    let mut bytes = src.to_le_bytes();
+    for byte in bytes.iter_mut() {
+        if *byte != 0 {
+            *byte = 0xff;
+        }
     }
+    u32::from_le_bytes(bytes)
+}
+
+// helper for clmul family. Returns the full 64-bit carry-less product.
+fn carry_less_mul(src1: u32, src2: u32) -> u64 {
+    (0..32)
+        .filter(|i| src2.get_bit(*i))
+        .fold(0u64, |acc, i| acc ^ (u64::from(src1) << i))
 }
 
 // Execute branch operation.
@@ -6,6 +6,7 @@
 //! According to RISC-V mailing list, Instruction fetch misaligned exceptions are not
 //! possible on machines the support compressed instruction set extension.
 
+use crate::trigger::{AccessType, TriggerHit, TriggerModule};
 use peripherals::error::MemoryAccessError;
 use peripherals::memory_access::MemoryAccess;
 
@@ -17,6 +18,9 @@ pub enum FetchError {
 
     #[fail(display = "instruction fetch misaligned at {}", pc)]
     MisalingedFetch { pc: u32 },
+
+    #[fail(display = "debug trigger fired at {:08x}", pc)]
+    Trigger { pc: u32, hit: TriggerHit },
 }
 
 impl From<MemoryAccessError> for FetchError {
@@ -27,11 +31,19 @@ impl From<MemoryAccessError> for FetchError {
 
 /// Fetches an instruction from the `instr_mem` of the `pc`.
 /// Result contains (a 32-bit instruction, next pc).
+/// Execute triggers are checked against both the `pc` and the fetched instruction.
 /// TODO: Improve the type information of Ok().
-pub fn fetch(instr_mem: &dyn MemoryAccess, pc: u32) -> Result<(u32, u32), FetchError> {
+pub fn fetch(
+    instr_mem: &dyn MemoryAccess,
+    pc: u32,
+    triggers: &mut TriggerModule,
+) -> Result<(u32, u32), FetchError> {
     alignment_check(pc)?;
 
     let instr = instr_mem.read_u32(pc as usize)?;
+    if let Some(hit) = triggers.check(AccessType::Execute, pc, Some(instr)) {
+        return Err(FetchError::Trigger { pc, hit });
+    }
     let next_pc = pc + 4;
     Ok((instr, next_pc))
 }
@@ -55,7 +67,8 @@ mod test {
         let program = vec![0x73, 0x00, 0x50, 0x10];
         let dram = Memory::new_with_filled_ram(&program, program.len());
 
-        let (instr, npc) = fetch(&dram, 0).expect("fail to fetch instruction from DRAM");
+        let (instr, npc) = fetch(&dram, 0, &mut TriggerModule::new())
+            .expect("fail to fetch instruction from DRAM");
         assert_eq!(0x1050_0073, instr, "endianess is not converted!");
         assert_eq!(4, npc, "invalid next pc");
     }
@@ -66,7 +79,7 @@ mod test {
         let dram = Memory::new_with_filled_ram(&program, program.len());
 
         // Well alignmented but out of range.
-        let instr = fetch(&dram, 8);
+        let instr = fetch(&dram, 8, &mut TriggerModule::new());
         match instr {
             Err(FetchError::InvalidMemoryAccess { .. }) => (),
             _ => panic!(),
@@ -78,7 +91,7 @@ mod test {
         let program = vec![0x00, 0x00, 0x00, 0x00]; // Don't care
         let dram = Memory::new_with_filled_ram(&program, program.len());
 
-        let instr = fetch(&dram, 1);
+        let instr = fetch(&dram, 1, &mut TriggerModule::new());
         assert_eq!(Err(FetchError::MisalingedFetch { pc: 1 }), instr);
     }
 }
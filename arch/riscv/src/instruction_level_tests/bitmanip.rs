@@ -0,0 +1,213 @@
+//! Instruction level tests for bit-manipulation extensions, i.e., Zba, Zbb, Zbc and Zbs.
+
+use super::rv32i::{create_riscv_cpu, execute_program};
+use crate::debug::DebugInterface;
+use crate::isa::abi_name::*;
+use crate::isa::csr_map::{mcause, mtval};
+use cpu::model::CpuModel;
+
+// Zba
+
+#[test]
+fn sh_add() {
+    let program = vec![
+        0x13, 0x05, 0x30, 0x00, // addi a0, zero, 3
+        0x93, 0x05, 0x00, 0x10, // addi a1, zero, 256
+        0x33, 0x26, 0xb5, 0x20, // sh1add a2, a0, a1
+        0xb3, 0x46, 0xb5, 0x20, // sh2add a3, a0, a1
+        0x33, 0x67, 0xb5, 0x20, // sh3add a4, a0, a1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0x106);
+    assert_eq!(riscv.get_gpr(a3), 0x10c);
+    assert_eq!(riscv.get_gpr(a4), 0x118);
+}
+
+// Zbb
+
+#[test]
+fn count_bits() {
+    let program = vec![
+        0x37, 0x05, 0xf0, 0x00, // lui a0, 0xf00
+        0x93, 0x15, 0x05, 0x60, // clz a1, a0
+        0x13, 0x16, 0x15, 0x60, // ctz a2, a0
+        0x93, 0x16, 0x25, 0x60, // cpop a3, a0
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a1), 8);
+    assert_eq!(riscv.get_gpr(a2), 20);
+    assert_eq!(riscv.get_gpr(a3), 4);
+}
+
+#[test]
+fn logical_with_negate() {
+    let program = vec![
+        0x37, 0x15, 0x00, 0x00, // lui a0, 0x1
+        0x13, 0x05, 0x05, 0xff, // addi a0, a0, -16
+        0x93, 0x05, 0xf0, 0x0f, // addi a1, zero, 255
+        0x33, 0x76, 0xb5, 0x40, // andn a2, a0, a1
+        0xb3, 0x66, 0xb5, 0x40, // orn a3, a0, a1
+        0x33, 0x47, 0xb5, 0x40, // xnor a4, a0, a1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0x0000_0f00);
+    assert_eq!(riscv.get_gpr(a3), 0xffff_fff0);
+    assert_eq!(riscv.get_gpr(a4), 0xffff_f0f0);
+}
+
+#[test]
+fn min_max() {
+    let program = vec![
+        0x13, 0x05, 0xf0, 0xff, // addi a0, zero, -1
+        0x93, 0x05, 0x10, 0x00, // addi a1, zero, 1
+        0x33, 0x46, 0xb5, 0x0a, // min a2, a0, a1
+        0xb3, 0x56, 0xb5, 0x0a, // minu a3, a0, a1
+        0x33, 0x67, 0xb5, 0x0a, // max a4, a0, a1
+        0xb3, 0x77, 0xb5, 0x0a, // maxu a5, a0, a1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0xffff_ffff);
+    assert_eq!(riscv.get_gpr(a3), 1);
+    assert_eq!(riscv.get_gpr(a4), 1);
+    assert_eq!(riscv.get_gpr(a5), 0xffff_ffff);
+}
+
+#[test]
+fn sign_zero_extend() {
+    let program = vec![
+        0x37, 0x85, 0x34, 0x12, // lui a0, 0x12348
+        0x13, 0x05, 0x15, 0x08, // addi a0, a0, 129
+        0x93, 0x15, 0x45, 0x60, // sext.b a1, a0
+        0x13, 0x16, 0x55, 0x60, // sext.h a2, a0
+        0xb3, 0x46, 0x05, 0x08, // zext.h a3, a0
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a1), 0xffff_ff81);
+    assert_eq!(riscv.get_gpr(a2), 0xffff_8081);
+    assert_eq!(riscv.get_gpr(a3), 0x0000_8081);
+}
+
+#[test]
+fn rotate() {
+    let program = vec![
+        0x37, 0x05, 0x00, 0x80, // lui a0, 0x80000
+        0x13, 0x05, 0x15, 0x00, // addi a0, a0, 1
+        0x93, 0x05, 0x40, 0x00, // addi a1, zero, 4
+        0x33, 0x16, 0xb5, 0x60, // rol a2, a0, a1
+        0xb3, 0x56, 0xb5, 0x60, // ror a3, a0, a1
+        0x13, 0x57, 0x15, 0x60, // rori a4, a0, 1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0x0000_0018);
+    assert_eq!(riscv.get_gpr(a3), 0x1800_0000);
+    assert_eq!(riscv.get_gpr(a4), 0xc000_0000);
+}
+
+#[test]
+fn byte_operations() {
+    let program = vec![
+        0x37, 0x85, 0x00, 0x01, // lui a0, 0x1008
+        0x93, 0x55, 0x75, 0x28, // orc.b a1, a0
+        0x13, 0x56, 0x85, 0x69, // rev8 a2, a0
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a1), 0xff00_ff00);
+    assert_eq!(riscv.get_gpr(a2), 0x0080_0001);
+}
+
+// Zbc
+
+#[test]
+fn carry_less_multiply() {
+    let program = vec![
+        0x37, 0x05, 0x00, 0x80, // lui a0, 0x80000
+        0x13, 0x05, 0x35, 0x00, // addi a0, a0, 3
+        0x93, 0x05, 0x30, 0x00, // addi a1, zero, 3
+        0x33, 0x16, 0xb5, 0x0a, // clmul a2, a0, a1
+        0xb3, 0x36, 0xb5, 0x0a, // clmulh a3, a0, a1
+        0x33, 0x27, 0xb5, 0x0a, // clmulr a4, a0, a1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0x8000_0005);
+    assert_eq!(riscv.get_gpr(a3), 0x0000_0001);
+    assert_eq!(riscv.get_gpr(a4), 0x0000_0003);
+}
+
+// Zbs
+
+#[test]
+fn single_bit() {
+    let program = vec![
+        0x93, 0x05, 0x50, 0x00, // addi a1, zero, 5
+        0x33, 0x16, 0xb0, 0x28, // bset a2, zero, a1
+        0xb3, 0x16, 0xb6, 0x48, // bclr a3, a2, a1
+        0x33, 0x17, 0xb6, 0x68, // binv a4, a2, a1
+        0xb3, 0x57, 0xb6, 0x48, // bext a5, a2, a1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0x20);
+    assert_eq!(riscv.get_gpr(a3), 0);
+    assert_eq!(riscv.get_gpr(a4), 0);
+    assert_eq!(riscv.get_gpr(a5), 1);
+}
+
+#[test]
+fn single_bit_imm() {
+    let program = vec![
+        0x13, 0x15, 0xf0, 0x29, // bseti a0, zero, 31
+        0x93, 0x15, 0xf5, 0x69, // binvi a1, a0, 31
+        0x13, 0x56, 0xf5, 0x49, // bexti a2, a0, 31
+        0x93, 0x16, 0xf5, 0x49, // bclri a3, a0, 31
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a0), 0x8000_0000);
+    assert_eq!(riscv.get_gpr(a1), 0);
+    assert_eq!(riscv.get_gpr(a2), 1);
+    assert_eq!(riscv.get_gpr(a3), 0);
+}
+
+#[test]
+fn reserved_funct7() {
+    // funct7 of 0b1111111 is not defined for OP nor OP-IMM.
+    for instr in [0xfe00_0533u32, 0xfe00_1513].iter() {
+        let mut program = instr.to_le_bytes().to_vec();
+        program.extend(&[0x73, 0x00, 0x50, 0x10]); // wfi
+        let mut riscv = create_riscv_cpu(program);
+        riscv.set_trap_vector(4);
+        let result = riscv.run();
+        assert!(result.is_ok(), "{}", result.unwrap_err());
+
+        assert_eq!(riscv.get_csr(mcause), 2);
+        assert_eq!(riscv.get_csr(mtval), *instr);
+    }
+}
@@ -1 +1,5 @@
+mod bitmanip;
 mod rv32i;
+mod rv32m;
+mod rvv;
+mod sdtrig;
@@ -1,5 +1,7 @@
 use crate::debug::DebugInterface;
 use crate::isa::abi_name::*;
+use crate::isa::extension::IsaConfig;
+use crate::lsu::MisalignedAccessPolicy;
 use crate::riscv::Riscv;
 use cpu::model::CpuModel;
 use debug::DebugMode;
@@ -653,9 +655,72 @@ fn ecall() {
     assert_eq!(riscv.get_csr(mcause), 11);
 }
 
+#[test]
+fn misaligned_load_trap() {
+    let program = vec![
+        0x83, 0x20, 0x21, 0x00, // lw ra, 2(sp)
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program_init_by(program, |riscv| riscv.set_trap_vector(4));
+
+    assert_eq!(riscv.get_pc(), 8);
+    assert_eq!(riscv.get_csr(mepc), 0);
+    assert_eq!(riscv.get_csr(mcause), 4);
+    assert_eq!(riscv.get_csr(mtval), 2);
+}
+
+#[test]
+fn misaligned_load_emulate() {
+    let program = vec![
+        0x83, 0x20, 0x21, 0x00, // lw ra, 2(sp)
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program_init_by(program, |riscv| {
+        riscv.set_misaligned_access_policy(MisalignedAccessPolicy::Emulate)
+    });
+
+    assert_eq!(riscv.get_gpr(ra), 0x0073_0021);
+}
+
+#[test]
+fn misa() {
+    let program = vec![
+        0x73, 0x25, 0x10, 0x30, // csrr a0, misa
+        0x73, 0x10, 0x10, 0x30, // csrw misa, zero
+        0xf3, 0x25, 0x10, 0x30, // csrr a1, misa
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let isa = "rv32imac_zicsr_zifencei".parse().unwrap();
+    let mut riscv = create_riscv_cpu_with_isa(program, isa);
+    let result = riscv.run();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    assert_eq!(riscv.get_gpr(a0), 0x4000_1105);
+    assert_eq!(riscv.get_gpr(a1), 0x4000_1105, "misa must be read-only");
+}
+
+#[test]
+fn fence_i_without_zifencei() {
+    let program = vec![
+        0x0f, 0x10, 0x00, 0x00, // fence.i
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let mut riscv = create_riscv_cpu_with_isa(program, "rv32i_zicsr".parse().unwrap());
+    riscv.set_trap_vector(4);
+    let result = riscv.run();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    assert_eq!(riscv.get_csr(mcause), 2);
+    assert_eq!(riscv.get_csr(mtval), 0x0000_100f);
+}
+
 // Helper for test.
 // Simply execute the program with memory.
-fn execute_program(program: Vec<u8>) -> Riscv<Mmio> {
+pub(super) fn execute_program(program: Vec<u8>) -> Riscv<Mmio> {
     let mut riscv = create_riscv_cpu(program);
     let result = riscv.run();
 
@@ -667,7 +732,10 @@ fn execute_program(program: Vec<u8>) -> Riscv<Mmio> {
 }
 
 // Helper for test.
-fn execute_program_init_by(program: Vec<u8>, initializer: fn(&mut Riscv<Mmio>)) -> Riscv<Mmio> {
+pub(super) fn execute_program_init_by(
+    program: Vec<u8>,
+    initializer: fn(&mut Riscv<Mmio>),
+) -> Riscv<Mmio> {
     let mut riscv = create_riscv_cpu(program);
     initializer(&mut riscv);
     let result = riscv.run();
@@ -680,7 +748,7 @@ fn execute_program_init_by(program: Vec<u8>, initializer: fn(&mut Riscv<Mmio>))
 }
 
 // helper for test.
-fn create_riscv_cpu(program: Vec<u8>) -> Riscv<Mmio> {
+pub(super) fn create_riscv_cpu(program: Vec<u8>) -> Riscv<Mmio> {
     // prepare minimum peripherals.
     let dram = Memory::new_with_filled_ram(&program, program.len());
     let mut mmio = Mmio::empty();
@@ -693,6 +761,20 @@ fn create_riscv_cpu(program: Vec<u8>) -> Riscv<Mmio> {
     riscv
 }
 
+// helper for test. Only extensions in `isa` are enabled.
+pub(super) fn create_riscv_cpu_with_isa(program: Vec<u8>, isa: IsaConfig) -> Riscv<Mmio> {
+    // prepare minimum peripherals.
+    let dram = Memory::new_with_filled_ram(&program, program.len());
+    let mut mmio = Mmio::empty();
+    mmio.add((0, program.len()), Box::new(dram)).unwrap();
+
+    // create object and run.
+    let mut riscv = Riscv::fabricate_with_isa(mmio, DebugMode::Disabled, isa);
+    riscv.init();
+
+    riscv
+}
+
 // Workaround. Make all tests start at 0x8000_0000.
 fn create_riscv_cpu_at_dram_address(program: Vec<u8>) -> Riscv<Mmio> {
     // prepare minimum peripherals.
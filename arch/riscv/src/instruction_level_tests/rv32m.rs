@@ -0,0 +1,99 @@
+//! Instruction level tests for standard extension for integer multiplication and division.
+
+use super::rv32i::{create_riscv_cpu_with_isa, execute_program};
+use crate::debug::DebugInterface;
+use crate::isa::abi_name::*;
+use crate::isa::csr_map::{mcause, mepc, mtval};
+use cpu::model::CpuModel;
+
+#[test]
+fn mul() {
+    let program = vec![
+        0x13, 0x05, 0xe0, 0xff, // addi a0, zero, -2
+        0x93, 0x05, 0x30, 0x00, // addi a1, zero, 3
+        0x33, 0x06, 0xb5, 0x02, // mul a2, a0, a1
+        0xb3, 0x16, 0xb5, 0x02, // mulh a3, a0, a1
+        0x33, 0x27, 0xb5, 0x02, // mulhsu a4, a0, a1
+        0xb3, 0x37, 0xb5, 0x02, // mulhu a5, a0, a1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0xffff_fffa);
+    assert_eq!(riscv.get_gpr(a3), 0xffff_ffff);
+    assert_eq!(riscv.get_gpr(a4), 0xffff_ffff);
+    assert_eq!(riscv.get_gpr(a5), 0x0000_0002);
+}
+
+#[test]
+fn div_rem() {
+    let program = vec![
+        0x13, 0x05, 0x90, 0xff, // addi a0, zero, -7
+        0x93, 0x05, 0x20, 0x00, // addi a1, zero, 2
+        0x33, 0x46, 0xb5, 0x02, // div a2, a0, a1
+        0xb3, 0x56, 0xb5, 0x02, // divu a3, a0, a1
+        0x33, 0x67, 0xb5, 0x02, // rem a4, a0, a1
+        0xb3, 0x77, 0xb5, 0x02, // remu a5, a0, a1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0xffff_fffd);
+    assert_eq!(riscv.get_gpr(a3), 0x7fff_fffc);
+    assert_eq!(riscv.get_gpr(a4), 0xffff_ffff);
+    assert_eq!(riscv.get_gpr(a5), 1);
+}
+
+// Division by zero never traps. Quotient is all ones and remainder is the dividend.
+#[test]
+fn divide_by_zero() {
+    let program = vec![
+        0x13, 0x05, 0x70, 0x00, // addi a0, zero, 7
+        0x33, 0x46, 0x05, 0x02, // div a2, a0, zero
+        0xb3, 0x76, 0x05, 0x02, // remu a3, a0, zero
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0xffff_ffff);
+    assert_eq!(riscv.get_gpr(a3), 7);
+}
+
+#[test]
+fn div_overflow() {
+    let program = vec![
+        0x37, 0x05, 0x00, 0x80, // lui a0, 0x80000
+        0x93, 0x05, 0xf0, 0xff, // addi a1, zero, -1
+        0x33, 0x46, 0xb5, 0x02, // div a2, a0, a1
+        0xb3, 0x66, 0xb5, 0x02, // rem a3, a0, a1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0x8000_0000);
+    assert_eq!(riscv.get_gpr(a3), 0);
+}
+
+#[test]
+fn mul_without_m_extension() {
+    let program = vec![
+        0x33, 0x06, 0xb5, 0x02, // mul a2, a0, a1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let mut riscv = create_riscv_cpu_with_isa(program, "rv32i_zicsr".parse().unwrap());
+    riscv.set_trap_vector(4);
+    riscv.set_gpr(a0, 2);
+    riscv.set_gpr(a1, 3);
+    let result = riscv.run();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    assert_eq!(riscv.get_gpr(a2), 0);
+    assert_eq!(riscv.get_csr(mepc), 0);
+    assert_eq!(riscv.get_csr(mcause), 2);
+    assert_eq!(riscv.get_csr(mtval), 0x02b5_0633);
+}
@@ -0,0 +1,268 @@
+//! Instruction level tests for vector extension (Zve32x).
+
+use super::rv32i::{create_riscv_cpu_with_isa, execute_program, execute_program_init_by};
+use crate::debug::DebugInterface;
+use crate::isa::abi_name::*;
+use crate::isa::csr_map::{mcause, mepc, mtval, vl, vlenb, vstart, vtype};
+use cpu::model::CpuModel;
+
+// unit-stride load/store, arithmetic and reduction
+#[test]
+fn unit_stride() {
+    let program = vec![
+        0x13, 0x05, 0x00, 0x04, // addi a0, zero, 64
+        0x93, 0x05, 0x00, 0x05, // addi a1, zero, 80
+        0xd7, 0x72, 0x02, 0xcd, // vsetivli t0, 4, e32, m1, ta, ma
+        0x87, 0x60, 0x05, 0x02, // vle32.v v1, (a0)
+        0x57, 0xb1, 0x10, 0x02, // vadd.vi v2, v1, 1
+        0x27, 0xe1, 0x05, 0x02, // vse32.v v2, (a1)
+        0xd7, 0x31, 0x00, 0x5e, // vmv.v.i v3, 0
+        0x57, 0xa2, 0x21, 0x02, // vredsum.vs v4, v2, v3
+        0x57, 0x26, 0x40, 0x42, // vmv.x.s a2, v4
+        0x83, 0x26, 0xc0, 0x05, // lw a3, 92(zero)
+        0x73, 0x00, 0x50, 0x10, // wfi
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x01, 0x00, 0x00, 0x00, // data@64
+        0x02, 0x00, 0x00, 0x00, // data@68
+        0x03, 0x00, 0x00, 0x00, // data@72
+        0x04, 0x00, 0x00, 0x00, // data@76
+        0x00, 0x00, 0x00, 0x00, // store@80
+        0x00, 0x00, 0x00, 0x00, // store@84
+        0x00, 0x00, 0x00, 0x00, // store@88
+        0x00, 0x00, 0x00, 0x00, // store@92
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(t0), 4);
+    assert_eq!(riscv.get_gpr(a2), 2 + 3 + 4 + 5);
+    assert_eq!(riscv.get_gpr(a3), 5);
+}
+
+// strided load, compare and masked operation
+#[test]
+fn strided_and_mask() {
+    let program = vec![
+        0x13, 0x05, 0x00, 0x04, // addi a0, zero, 64
+        0x93, 0x05, 0x20, 0x00, // addi a1, zero, 2
+        0xd7, 0x72, 0x04, 0xc4, // vsetivli t0, 8, e8, m1, ta, mu
+        0x87, 0x00, 0xb5, 0x0a, // vlse8.v v1, (a0), a1
+        0x57, 0xb0, 0x11, 0x7a, // vmsgtu.vi v0, v1, 3
+        0x57, 0x26, 0x08, 0x42, // vcpop.m a2, v0
+        0xd7, 0xa6, 0x08, 0x42, // vfirst.m a3, v0
+        0x57, 0x31, 0x00, 0x5e, // vmv.v.i v2, 0
+        0x57, 0x31, 0x15, 0x00, // vadd.vi v2, v1, 10, v0.t
+        0xd7, 0x21, 0x21, 0x1a, // vredmaxu.vs v3, v2, v2
+        0x57, 0x27, 0x30, 0x42, // vmv.x.s a4, v3
+        0x73, 0x00, 0x50, 0x10, // wfi
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x00, 0x00, 0x00, 0x00, // padding
+        0x01, 0xff, 0x02, 0xff, // data@64
+        0x03, 0xff, 0x04, 0xff, // data@68
+        0x05, 0xff, 0x06, 0xff, // data@72
+        0x07, 0xff, 0x08, 0xff, // data@76
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(t0), 8);
+    assert_eq!(riscv.get_gpr(a2), 5);
+    assert_eq!(riscv.get_gpr(a3), 3);
+    assert_eq!(riscv.get_gpr(a4), 8 + 10);
+}
+
+// vmerge selects vs1 where the mask is set and vs2 elsewhere.
+#[test]
+fn merge() {
+    let program = vec![
+        0xd7, 0x72, 0x02, 0xcd, // vsetivli t0, 4, e32, m1, ta, ma
+        0x13, 0x05, 0x50, 0x00, // addi a0, zero, 5
+        0x57, 0x60, 0x05, 0x42, // vmv.s.x v0, a0
+        0xd7, 0xa0, 0x08, 0x52, // vid.v v1
+        0x57, 0xb1, 0x03, 0x5e, // vmv.v.i v2, 7
+        0xd7, 0x81, 0x20, 0x5c, // vmerge.vvm v3, v2, v1, v0
+        0x57, 0xb2, 0x1f, 0x5c, // vmerge.vim v4, v1, -1, v0
+        0xd7, 0x32, 0x00, 0x5e, // vmv.v.i v5, 0
+        0x57, 0xa3, 0x32, 0x02, // vredsum.vs v6, v3, v5
+        0xd7, 0x25, 0x60, 0x42, // vmv.x.s a1, v6
+        0x57, 0xa3, 0x42, 0x02, // vredsum.vs v6, v4, v5
+        0x57, 0x26, 0x60, 0x42, // vmv.x.s a2, v6
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a1), 7 + 2 + 7);
+    assert_eq!(riscv.get_gpr(a2), (-1i32 + 1 - 1 + 3) as u32);
+}
+
+// vmerge (the masked form of vmv) to v0 is reserved.
+#[test]
+fn merge_to_v0() {
+    let program = vec![
+        0xd7, 0x72, 0x02, 0xcd, // vsetivli t0, 4, e32, m1, ta, ma
+        0x57, 0x80, 0x20, 0x5c, // vmerge.vvm v0, v2, v1, v0
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program_init_by(program, |riscv| riscv.set_trap_vector(8));
+
+    assert_eq!(riscv.get_pc(), 12);
+    assert_eq!(riscv.get_csr(mepc), 4);
+    assert_eq!(riscv.get_csr(mcause), 2);
+    assert_eq!(riscv.get_csr(mtval), 0x5c20_8057);
+}
+
+// Reductions can not be resumed from non-zero vstart.
+#[test]
+fn reduction_with_vstart() {
+    let program = vec![
+        0xd7, 0x72, 0x02, 0xcd, // vsetivli t0, 4, e32, m1, ta, ma
+        0x13, 0x05, 0x10, 0x00, // addi a0, zero, 1
+        0x73, 0x10, 0x85, 0x00, // csrw vstart, a0
+        0xd7, 0xa0, 0x21, 0x02, // vredsum.vs v1, v2, v3
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program_init_by(program, |riscv| riscv.set_trap_vector(16));
+
+    assert_eq!(riscv.get_pc(), 20);
+    assert_eq!(riscv.get_csr(mepc), 12);
+    assert_eq!(riscv.get_csr(mcause), 2);
+    assert_eq!(riscv.get_csr(mtval), 0x0221_a0d7);
+    assert_eq!(riscv.get_csr(vstart), 1);
+}
+
+// LMUL=2 groups two registers.
+#[test]
+fn register_group() {
+    let program = vec![
+        0xd7, 0x72, 0x90, 0x0c, // vsetvli t0, zero, e16, m2, ta, ma
+        0x73, 0x23, 0x00, 0xc2, // csrr t1, vl
+        0xf3, 0x23, 0x20, 0xc2, // csrr t2, vlenb
+        0x57, 0xa1, 0x08, 0x52, // vid.v v2
+        0x57, 0x22, 0x21, 0x96, // vmul.vv v4, v2, v2
+        0x57, 0x25, 0x40, 0x42, // vmv.x.s a0, v4
+        0x57, 0x23, 0x43, 0x02, // vredsum.vs v6, v4, v6
+        0xd7, 0x25, 0x60, 0x42, // vmv.x.s a1, v6
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(t0), 16);
+    assert_eq!(riscv.get_gpr(t1), 16);
+    assert_eq!(riscv.get_gpr(t2), 16);
+    assert_eq!(riscv.get_gpr(a0), 0);
+    assert_eq!(riscv.get_gpr(a1), (0..16).map(|x| x * x).sum());
+}
+
+#[test]
+fn misaligned_register_group() {
+    let program = vec![
+        0xd7, 0x72, 0x10, 0x0d, // vsetvli t0, zero, e32, m2, ta, ma
+        0xd7, 0x00, 0x22, 0x02, // vadd.vv v1, v2, v4
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program_init_by(program, |riscv| riscv.set_trap_vector(8));
+
+    assert_eq!(riscv.get_pc(), 12);
+    assert_eq!(riscv.get_csr(mepc), 4);
+    assert_eq!(riscv.get_csr(mcause), 2);
+    assert_eq!(riscv.get_csr(mtval), 0x0222_00d7);
+}
+
+// vtype is illegal until vsetvl is executed.
+#[test]
+fn illegal_vtype_at_reset() {
+    let program = vec![
+        0xd7, 0x80, 0x21, 0x02, // vadd.vv v1, v2, v3
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program_init_by(program, |riscv| riscv.set_trap_vector(4));
+
+    assert_eq!(riscv.get_pc(), 8);
+    assert_eq!(riscv.get_csr(mepc), 0);
+    assert_eq!(riscv.get_csr(mcause), 2);
+    assert_eq!(riscv.get_csr(mtval), 0x0221_80d7);
+}
+
+#[test]
+fn vector_disabled() {
+    let program = vec![
+        0xd7, 0x72, 0x00, 0x0c, // vsetvli t0, zero, e8, m1, ta, ma
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let isa = "rv32imac_zicsr_zifencei".parse().unwrap();
+    let mut riscv = create_riscv_cpu_with_isa(program, isa);
+    riscv.set_trap_vector(4);
+    let result = riscv.run();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    assert_eq!(riscv.get_gpr(t0), 0);
+    assert_eq!(riscv.get_csr(mcause), 2);
+    assert_eq!(riscv.get_csr(mtval), 0x0c00_72d7);
+    assert_eq!(riscv.get_csr(vlenb), 0, "vector CSRs must not exist");
+}
+
+// Vector CSRs other than vstart are read-only.
+#[test]
+fn write_read_only_vector_csr() {
+    let program = vec![
+        0x73, 0x10, 0x05, 0xc2, // csrw vl, a0
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program_init_by(program, |riscv| {
+        riscv.set_trap_vector(4);
+        riscv.set_gpr(a0, 0x1000);
+    });
+
+    assert_eq!(riscv.get_csr(mepc), 0);
+    assert_eq!(riscv.get_csr(mcause), 2);
+    assert_eq!(riscv.get_csr(mtval), 0xc205_1073);
+    assert_eq!(riscv.get_csr(vl), 0);
+}
+
+#[test]
+fn vstart_legal_bits() {
+    let program = vec![
+        0x13, 0x05, 0xf0, 0xff, // addi a0, zero, -1
+        0x73, 0x10, 0x85, 0x00, // csrw vstart, a0
+        0x73, 0x26, 0x80, 0x00, // csrr a2, vstart
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program(program);
+
+    assert_eq!(riscv.get_gpr(a2), 0x7f);
+}
+
+// Only VLMAX elements are processed even if a debugger writes a larger vl.
+#[test]
+fn oversized_vl() {
+    let program = vec![
+        0xd7, 0xaf, 0x08, 0x52, // vid.v v31
+        0xd7, 0x30, 0x00, 0x5e, // vmv.v.i v1, 0
+        0xd7, 0xa0, 0xf0, 0x03, // vredsum.vs v1, v31, v1
+        0x57, 0x25, 0x10, 0x42, // vmv.x.s a0, v1
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program_init_by(program, |riscv| {
+        // e32, m1
+        riscv.set_csr(vtype, 0b0001_0000);
+        riscv.set_csr(vl, 0x1000);
+    });
+
+    assert_eq!(riscv.get_gpr(a0), 1 + 2 + 3);
+}
@@ -0,0 +1,87 @@
+//! Instruction level tests for debug triggers.
+
+use super::rv32i::{create_riscv_cpu, execute_program_init_by};
+use crate::debug::DebugInterface;
+use crate::isa::abi_name::*;
+use crate::isa::csr_map::{dcsr, dpc, mcause, mepc, mtval, tdata1, tdata2};
+use cpu::model::CpuModel;
+
+#[test]
+fn execute_breakpoint() {
+    let program = vec![
+        0xb7, 0x02, 0x00, 0x20, // lui t0, 0x20000
+        0x93, 0x82, 0x42, 0x04, // addi t0, t0, 0x44
+        0x73, 0x90, 0x12, 0x7a, // csrw tdata1, t0
+        0x13, 0x03, 0x80, 0x01, // addi t1, zero, 24
+        0x73, 0x10, 0x23, 0x7a, // csrw tdata2, t1
+        0x13, 0x00, 0x00, 0x00, // nop
+        0x13, 0x05, 0x10, 0x00, // addi a0, zero, 1 <- breakpoint
+        0x73, 0x00, 0x50, 0x10, // wfi
+    ];
+
+    let riscv = execute_program_init_by(program, |riscv| riscv.set_trap_vector(28));
+
+    assert_eq!(riscv.get_gpr(a0), 0);
+    assert_eq!(riscv.get_csr(mepc), 24);
+    assert_eq!(riscv.get_csr(mcause), 3);
+    assert_eq!(riscv.get_csr(mtval), 24);
+    assert_eq!(riscv.get_csr(tdata1), 0x2010_0044, "hit bit is not set");
+}
+
+#[test]
+fn store_data_watchpoint() {
+    let program = vec![
+        0xb7, 0x02, 0x08, 0x20, // lui t0, 0x20080
+        0x93, 0x82, 0x22, 0x04, // addi t0, t0, 0x42
+        0x73, 0x90, 0x12, 0x7a, // csrw tdata1, t0
+        0x13, 0x03, 0x50, 0x05, // addi t1, zero, 0x55
+        0x73, 0x10, 0x23, 0x7a, // csrw tdata2, t1
+        0x13, 0x05, 0x10, 0x01, // addi a0, zero, 0x11
+        0x23, 0x04, 0xa0, 0x02, // sb a0, 40(zero)
+        0x23, 0x04, 0x60, 0x02, // sb t1, 40(zero) <- watchpoint
+        0x83, 0x25, 0x80, 0x02, // lw a1, 40(zero)
+        0x73, 0x00, 0x50, 0x10, // wfi
+        0x00, 0x00, 0x00, 0x00, // data
+    ];
+
+    let riscv = execute_program_init_by(program, |riscv| riscv.set_trap_vector(32));
+
+    assert_eq!(riscv.get_gpr(a1), 0x11, "store must not be performed");
+    assert_eq!(riscv.get_csr(mepc), 28);
+    assert_eq!(riscv.get_csr(mcause), 3);
+    assert_eq!(riscv.get_csr(mtval), 40);
+}
+
+#[test]
+fn load_address_enters_debug_mode() {
+    let program = vec![
+        0x03, 0x25, 0xc0, 0x00, // lw a0, 12(zero)
+        0x83, 0x25, 0x00, 0x01, // lw a1, 16(zero) <- watchpoint
+        0x73, 0x00, 0x50, 0x10, // wfi
+        0x78, 0x56, 0x34, 0x12, // data
+        0xf0, 0xde, 0xbc, 0x9a, // data
+    ];
+
+    let mut riscv = create_riscv_cpu(program);
+    // dmode, action = 1, m and load.
+    riscv.set_csr(tdata1, 0x2800_1041);
+    riscv.set_csr(tdata2, 16);
+    let result = riscv.run();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    assert!(riscv.is_debug_mode());
+    assert_eq!(riscv.get_pc(), 4);
+    assert_eq!(riscv.get_csr(dpc), 4);
+    assert_eq!((riscv.get_csr(dcsr) >> 6) & 0b111, 2);
+    assert_eq!(riscv.get_gpr(a0), 0x1234_5678);
+    assert_eq!(riscv.get_gpr(a1), 0);
+
+    // Debugger removes the trigger and resumes.
+    riscv.set_csr(tdata1, 0);
+    riscv.resume();
+    let result = riscv.run();
+    assert!(result.is_ok(), "{}", result.unwrap_err());
+
+    assert!(!riscv.is_debug_mode());
+    assert_eq!(riscv.get_gpr(a1), 0x9abc_def0);
+}
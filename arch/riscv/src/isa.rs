@@ -1,6 +1,7 @@
 pub mod abi_name;
 pub mod csr_map;
 pub mod exceptions;
+pub mod extension;
 pub mod funct;
 pub mod instr_format;
 pub mod opcode;
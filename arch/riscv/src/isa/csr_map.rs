@@ -1,7 +1,39 @@
 #![allow(dead_code, non_upper_case_globals)]
+/// Vector start position.
+pub const vstart: u32 = 0x008;
+/// Fixed-point accrued saturation flag.
+pub const vxsat: u32 = 0x009;
+/// Fixed-point rounding mode.
+pub const vxrm: u32 = 0x00a;
+/// Vector control and status register.
+pub const vcsr: u32 = 0x00f;
+/// Vector length.
+pub const vl: u32 = 0xc20;
+/// Vector data type register.
+pub const vtype: u32 = 0xc21;
+/// VLEN/8 (vector register length in bytes).
+pub const vlenb: u32 = 0xc22;
+/// ISA and extensions.
+pub const misa: u32 = 0x301;
 /// Machine trap-handler base address.
 pub const mtvec: u32 = 0x305;
 /// Machine exception program counter.
 pub const mepc: u32 = 0x341;
 /// Machine trap cause.
 pub const mcause: u32 = 0x342;
+/// Machine bad address or instruction.
+pub const mtval: u32 = 0x343;
+/// Debug/Trace trigger register select.
+pub const tselect: u32 = 0x7a0;
+/// First Debug/Trace trigger data register.
+pub const tdata1: u32 = 0x7a1;
+/// Second Debug/Trace trigger data register.
+pub const tdata2: u32 = 0x7a2;
+/// Third Debug/Trace trigger data register.
+pub const tdata3: u32 = 0x7a3;
+/// Trigger info.
+pub const tinfo: u32 = 0x7a4;
+/// Debug control and status register.
+pub const dcsr: u32 = 0x7b0;
+/// Debug PC.
+pub const dpc: u32 = 0x7b1;
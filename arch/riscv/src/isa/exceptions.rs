@@ -3,6 +3,17 @@ use crate::decode::DecodeError;
 use crate::execute::ExecuteError;
 use crate::fetch::FetchError;
 use crate::lsu::LsuError;
+use crate::vpu::VpuError;
+
+/// Exception code written to `mcause` when a trap is taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExceptionCode {
+    IllegalInstruction = 2,
+    Breakpoint = 3,
+    LoadAddressMisaligned = 4,
+    StoreAddressMisaligned = 6,
+    EnvironmentCallFromMMode = 11,
+}
 
 /// This is just an wrapper of each stage exception.
 #[derive(Debug, Fail, PartialEq)]
@@ -18,6 +29,9 @@ pub enum InternalExceptions {
 
     #[fail(display = "{}", error)]
     MemoryAccessException { error: LsuError },
+
+    #[fail(display = "{}", error)]
+    VectorException { error: VpuError },
 }
 
 impl From<FetchError> for InternalExceptions {
@@ -43,3 +57,9 @@ impl From<LsuError> for InternalExceptions {
         InternalExceptions::MemoryAccessException { error }
     }
 }
+
+impl From<VpuError> for InternalExceptions {
+    fn from(error: VpuError) -> InternalExceptions {
+        InternalExceptions::VectorException { error }
+    }
+}
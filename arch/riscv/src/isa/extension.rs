@@ -0,0 +1,296 @@
+//! ISA string and extensions enabled on a hart.
+//!
+//! An ISA string follows the naming convention of `-march`, e.g. `rv32im_zicsr_zifencei`.
+//! Version numbers such as `m2p0` are accepted and ignored.
+//! A and C are accepted so that `-march` of real cores such as `rv32imac` can be used,
+//! and reported in `misa`, although their instructions are not implemented yet.
+//! F, D and full V are rejected, and so is G, which implies F and D.
+
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+/// Extensions which can be listed in an ISA string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Extension {
+    M,
+    A,
+    C,
+    Zicsr,
+    Zifencei,
+    Zba,
+    Zbb,
+    Zbc,
+    Zbs,
+    Zve32x,
+}
+
+/// Errors in parsing an ISA string.
+#[derive(Debug, Fail, PartialEq)]
+pub enum IsaStringError {
+    #[fail(display = "only rv32 is supported: {}", isa)]
+    UnsupportedXlen { isa: String },
+
+    #[fail(display = "base integer ISA must be i or g: {}", isa)]
+    InvalidBase { isa: String },
+
+    #[fail(display = "unknown extension: {}", name)]
+    UnknownExtension { name: String },
+
+    #[fail(display = "extension is not implemented: {}", name)]
+    UnimplementedExtension { name: String },
+}
+
+/// Extensions enabled on a hart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IsaConfig {
+    extensions: u32,
+}
+
+impl IsaConfig {
+    /// RV32I only.
+    pub fn base() -> IsaConfig {
+        IsaConfig { extensions: 0 }
+    }
+
+    /// Returns true if the extension is enabled.
+    pub fn has(self, ext: Extension) -> bool {
+        self.extensions & (1 << ext as u32) != 0
+    }
+
+    /// Enables the extension and extensions it depends on.
+    pub fn enable(&mut self, ext: Extension) {
+        use self::Extension::*;
+        self.extensions |= 1 << ext as u32;
+        if ext == Zve32x {
+            self.enable(Zicsr);
+        }
+    }
+
+    /// Value of `misa`.
+    pub fn misa(self) -> u32 {
+        use self::Extension::*;
+        // MXL is 1 (32-bit) and I is always set.
+        let mut misa = (1 << 30) | misa_bit('i');
+        if self.has(M) {
+            misa |= misa_bit('m');
+        }
+        if self.has(A) {
+            misa |= misa_bit('a');
+        }
+        if self.has(C) {
+            misa |= misa_bit('c');
+        }
+        if self.has(Zba) && self.has(Zbb) && self.has(Zbs) {
+            misa |= misa_bit('b');
+        }
+        misa
+    }
+}
+
+/// All extensions implemented in the emulator are enabled.
+/// Only Zve32x subset of V is implemented, so that V is not enabled.
+impl Default for IsaConfig {
+    fn default() -> IsaConfig {
+        "rv32im_zve32x_zicsr_zifencei_zba_zbb_zbc_zbs"
+            .parse()
+            .expect("default ISA string is invalid")
+    }
+}
+
+impl FromStr for IsaConfig {
+    type Err = IsaStringError;
+
+    fn from_str(isa: &str) -> Result<IsaConfig, IsaStringError> {
+        let lower = isa.to_lowercase();
+        if !lower.starts_with("rv32") {
+            return Err(IsaStringError::UnsupportedXlen {
+                isa: isa.to_string(),
+            });
+        }
+
+        let mut config = IsaConfig::base();
+        let mut tokens = lower[4..].split('_');
+        let mut letters = tokens.next().unwrap_or("").chars().peekable();
+        match letters.next() {
+            Some('i') => (),
+            // G includes A, F and D.
+            Some('g') => {
+                return Err(IsaStringError::UnimplementedExtension {
+                    name: "g".to_string(),
+                })
+            }
+            _ => {
+                return Err(IsaStringError::InvalidBase {
+                    isa: isa.to_string(),
+                })
+            }
+        }
+        skip_version(&mut letters);
+
+        let mut extensions = Vec::new();
+        extensions.extend(single_letter_extensions(&mut letters)?);
+        for token in tokens.filter(|token| !token.is_empty()) {
+            match token.chars().next() {
+                Some('z') | Some('s') | Some('x') => {
+                    extensions.push(multi_letter_extension(token)?);
+                }
+                _ => extensions.extend(single_letter_extensions(&mut token.chars().peekable())?),
+            }
+        }
+        for ext in extensions {
+            config.enable(ext);
+        }
+        Ok(config)
+    }
+}
+
+// Parse a sequence of single-letter extensions, e.g., `mac` or `m2p0a2p1`.
+fn single_letter_extensions(
+    letters: &mut Peekable<Chars>,
+) -> Result<Vec<Extension>, IsaStringError> {
+    use self::Extension::*;
+    let mut extensions = Vec::new();
+    while let Some(letter) = letters.next() {
+        match letter {
+            'm' => extensions.push(M),
+            'a' => extensions.push(A),
+            'c' => extensions.push(C),
+            'b' => extensions.extend(&[Zba, Zbb, Zbs]),
+            'f' | 'd' | 'v' => {
+                return Err(IsaStringError::UnimplementedExtension {
+                    name: letter.to_string(),
+                })
+            }
+            _ => {
+                return Err(IsaStringError::UnknownExtension {
+                    name: letter.to_string(),
+                })
+            }
+        }
+        skip_version(letters);
+    }
+    Ok(extensions)
+}
+
+// Skip version number following a single-letter extension, e.g., `2p0`.
+fn skip_version(letters: &mut Peekable<Chars>) {
+    while letters
+        .peek()
+        .is_some_and(|c| c.is_ascii_digit() || *c == 'p')
+    {
+        letters.next();
+    }
+}
+
+// Parse a multi-letter extension such as `zicsr` or `zba1p0`.
+fn multi_letter_extension(token: &str) -> Result<Extension, IsaStringError> {
+    use self::Extension::*;
+    // Version number consists of digits and `p`, e.g., `2p0`.
+    let name = match token.find(|c: char| c.is_ascii_digit()) {
+        Some(pos) if token[pos..].chars().all(|c| c.is_ascii_digit() || c == 'p') => &token[..pos],
+        _ => token,
+    };
+    match name {
+        "zicsr" => Ok(Zicsr),
+        "zifencei" => Ok(Zifencei),
+        "zba" => Ok(Zba),
+        "zbb" => Ok(Zbb),
+        "zbc" => Ok(Zbc),
+        "zbs" => Ok(Zbs),
+        "zve32x" => Ok(Zve32x),
+        _ => Err(IsaStringError::UnknownExtension {
+            name: token.to_string(),
+        }),
+    }
+}
+
+// helper returns `misa` bit for the letter
+fn misa_bit(letter: char) -> u32 {
+    1 << (letter as u32 - 'a' as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Extension::*;
+    use super::*;
+
+    #[test]
+    fn parse_isa_string() {
+        let isa: IsaConfig = "rv32im_zicsr_zifencei".parse().unwrap();
+        assert!(isa.has(M));
+        assert!(isa.has(Zifencei));
+        assert!(!isa.has(Zbb));
+        assert!(!isa.has(Zve32x));
+        assert_eq!(0x4000_1100, isa.misa());
+    }
+
+    #[test]
+    fn implied_extensions() {
+        let isa: IsaConfig = "rv32i_zve32x".parse().unwrap();
+        assert!(isa.has(Zicsr));
+        assert!(isa.has(Zve32x));
+
+        let isa: IsaConfig = "RV32I2p1_M2p0_zba1p0_zbb_zbs".parse().unwrap();
+        assert!(isa.has(M));
+        assert_eq!(0x4000_1102, isa.misa());
+    }
+
+    #[test]
+    fn default_isa() {
+        let isa = IsaConfig::default();
+        assert!(isa.has(Zve32x));
+        // I, M and B. V is not set because only Zve32x is implemented.
+        assert_eq!(0x4000_1102, isa.misa());
+    }
+
+    #[test]
+    fn invalid_isa_string() {
+        assert_eq!(
+            Err(IsaStringError::UnsupportedXlen {
+                isa: "rv64imac".to_string()
+            }),
+            "rv64imac".parse::<IsaConfig>()
+        );
+        assert_eq!(
+            Err(IsaStringError::InvalidBase {
+                isa: "rv32e".to_string()
+            }),
+            "rv32e".parse::<IsaConfig>()
+        );
+        assert_eq!(
+            Err(IsaStringError::UnknownExtension {
+                name: "zfoo".to_string()
+            }),
+            "rv32i_zfoo".parse::<IsaConfig>()
+        );
+    }
+
+    #[test]
+    fn atomic_and_compressed() {
+        let isa: IsaConfig = "rv32imac_zicsr_zifencei".parse().unwrap();
+        assert!(isa.has(A) && isa.has(C));
+        assert_eq!(0x4000_1105, isa.misa());
+    }
+
+    #[test]
+    fn unimplemented_extension() {
+        assert_eq!(
+            Err(IsaStringError::UnimplementedExtension {
+                name: "f".to_string()
+            }),
+            "rv32imafc".parse::<IsaConfig>()
+        );
+        assert_eq!(
+            Err(IsaStringError::UnimplementedExtension {
+                name: "v".to_string()
+            }),
+            "rv32imv".parse::<IsaConfig>()
+        );
+        assert_eq!(
+            Err(IsaStringError::UnimplementedExtension {
+                name: "g".to_string()
+            }),
+            "rv32gc".parse::<IsaConfig>()
+        );
+    }
+}
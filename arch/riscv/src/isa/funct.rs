@@ -30,6 +30,25 @@ enum_from_primitive! {
     }
 }
 
+/// funct7 for OP of RV32I, RV32M and bit-manipulation extensions.
+/// Some of values are shared by several instructions which have different funct3.
+enum_from_primitive! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[allow(non_camel_case_types)]
+    pub enum OpFunct7 {
+        BASE = 0b000_0000,
+        ALT = 0b010_0000,
+        MULDIV = 0b000_0001,
+        ZEXT = 0b000_0100,
+        MINMAX_CLMUL = 0b000_0101,
+        SHADD = 0b001_0000,
+        BSET = 0b001_0100,
+        BCLR_BEXT = 0b010_0100,
+        ROTATE = 0b011_0000,
+        BINV = 0b011_0100,
+    }
+}
+
 /// funct3 for BRANCH of RV32I
 enum_from_primitive! {
     #[derive(Debug, Clone, Copy, PartialEq)]
@@ -76,3 +95,95 @@ enum_from_primitive! {
         CSRRWI = 0b101,
     }
 }
+
+/// funct3 for OP-V of V extension
+enum_from_primitive! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum OpVFunct3 {
+        OPIVV = 0b000,
+        OPFVV = 0b001,
+        OPMVV = 0b010,
+        OPIVI = 0b011,
+        OPIVX = 0b100,
+        OPFVF = 0b101,
+        OPMVX = 0b110,
+        OPCFG = 0b111,
+    }
+}
+
+/// funct6 for OPIVV/OPIVX/OPIVI of V extension
+enum_from_primitive! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum OpIFunct6 {
+        VADD = 0b00_0000,
+        VSUB = 0b00_0010,
+        VRSUB = 0b00_0011,
+        VMINU = 0b00_0100,
+        VMIN = 0b00_0101,
+        VMAXU = 0b00_0110,
+        VMAX = 0b00_0111,
+        VAND = 0b00_1001,
+        VOR = 0b00_1010,
+        VXOR = 0b00_1011,
+        VMERGE = 0b01_0111,
+        VMSEQ = 0b01_1000,
+        VMSNE = 0b01_1001,
+        VMSLTU = 0b01_1010,
+        VMSLT = 0b01_1011,
+        VMSLEU = 0b01_1100,
+        VMSLE = 0b01_1101,
+        VMSGTU = 0b01_1110,
+        VMSGT = 0b01_1111,
+        VSLL = 0b10_0101,
+        VSRL = 0b10_1000,
+        VSRA = 0b10_1001,
+    }
+}
+
+/// funct6 for OPMVV/OPMVX of V extension
+enum_from_primitive! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum OpMFunct6 {
+        VREDSUM = 0b00_0000,
+        VREDAND = 0b00_0001,
+        VREDOR = 0b00_0010,
+        VREDXOR = 0b00_0011,
+        VREDMINU = 0b00_0100,
+        VREDMIN = 0b00_0101,
+        VREDMAXU = 0b00_0110,
+        VREDMAX = 0b00_0111,
+        VWXUNARY0 = 0b01_0000,
+        VMUNARY0 = 0b01_0100,
+        VMANDN = 0b01_1000,
+        VMAND = 0b01_1001,
+        VMOR = 0b01_1010,
+        VMXOR = 0b01_1011,
+        VMORN = 0b01_1100,
+        VMNAND = 0b01_1101,
+        VMNOR = 0b01_1110,
+        VMXNOR = 0b01_1111,
+        VDIVU = 0b10_0000,
+        VDIV = 0b10_0001,
+        VREMU = 0b10_0010,
+        VREM = 0b10_0011,
+        VMULHU = 0b10_0100,
+        VMUL = 0b10_0101,
+        VMULHSU = 0b10_0110,
+        VMULH = 0b10_0111,
+        VMADD = 0b10_1001,
+        VNMSUB = 0b10_1011,
+        VMACC = 0b10_1101,
+        VNMSAC = 0b10_1111,
+    }
+}
+
+/// width for LOAD-FP/STORE-FP used by vector load/store
+enum_from_primitive! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum VectorWidth {
+        E8 = 0b000,
+        E16 = 0b101,
+        E32 = 0b110,
+        E64 = 0b111,
+    }
+}
@@ -168,6 +168,46 @@ impl JTypeInstr {
     }
 }
 
+/// OP-V format:
+/// funct6 | vm | vs2 | vs1 | funct3 | vd | opcode
+/// vs1 is replaced by rs1 or imm[4:0] according to funct3.
+bitfield! {
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct OpVInstr(u32);
+    u32;
+    pub funct6, _: 31, 26;
+    pub vm, _: 25;
+    pub vs2, _: 24, 20;
+    pub vs1, _: 19, 15;
+    pub funct3, _: 14, 12;
+    pub vd, _: 11, 7;
+    pub opcode, _: 6, 0;
+}
+
+impl OpVInstr {
+    pub fn simm5(self) -> i32 {
+        sign_extend_at(self.vs1(), 5) as i32
+    }
+}
+
+/// Vector load/store format:
+/// nf | mew | mop | vm | lumop/rs2 | rs1 | width | vd/vs3 | opcode
+/// LOAD-FP / STORE-FP
+bitfield! {
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct VMemInstr(u32);
+    u32;
+    pub nf, _: 31, 29;
+    pub mew, _: 28;
+    pub mop, _: 27, 26;
+    pub vm, _: 25;
+    pub rs2, _: 24, 20;
+    pub rs1, _: 19, 15;
+    pub width, _: 14, 12;
+    pub vd, _: 11, 7;
+    pub opcode, _: 6, 0;
+}
+
 // helper function for sign extension
 // Assumption: bits in `n` above position `sign_bit_pos` are already zero.
 #[inline(always)]
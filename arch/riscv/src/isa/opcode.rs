@@ -1,16 +1,21 @@
 //! Opcode
 
+use super::extension::Extension;
+
 /// Raw Opcode in an instruction[6:0].
 enum_from_primitive! {
     #[derive(Debug, Clone, Copy, PartialEq)]
     pub enum Opcode {
         Load     = 0b000_0011,
+        LoadFp   = 0b000_0111,
         MiscMem  = 0b000_1111,
         OpImm    = 0b001_0011,
         Auipc    = 0b001_0111,
         Store    = 0b010_0011,
+        StoreFp  = 0b010_0111,
         Op       = 0b011_0011,
         Lui      = 0b011_0111,
+        OpV      = 0b101_0111,
         Branch   = 0b110_0011,
         Jalr     = 0b110_0111,
         Jal      = 0b110_1111,
@@ -33,6 +38,46 @@ pub enum AluOp {
     SRA,
     LUI,
     AUIPC,
+    // RV32M
+    MUL,
+    MULH,
+    MULHSU,
+    MULHU,
+    DIV,
+    DIVU,
+    REM,
+    REMU,
+    // Zba
+    SH1ADD,
+    SH2ADD,
+    SH3ADD,
+    // Zbb
+    ANDN,
+    ORN,
+    XNOR,
+    CLZ,
+    CTZ,
+    CPOP,
+    MAX,
+    MAXU,
+    MIN,
+    MINU,
+    SEXTB,
+    SEXTH,
+    ZEXTH,
+    ROL,
+    ROR,
+    ORCB,
+    REV8,
+    // Zbc
+    CLMUL,
+    CLMULH,
+    CLMULR,
+    // Zbs
+    BCLR,
+    BEXT,
+    BINV,
+    BSET,
 }
 
 /// Branch type
@@ -64,6 +109,33 @@ pub enum LoadStoreType {
     SB,
 }
 
+impl AluOp {
+    /// Returns the extension which the operation belongs to, or `None` for RV32I.
+    pub fn extension(self) -> Option<Extension> {
+        use self::AluOp::*;
+        match self {
+            MUL | MULH | MULHSU | MULHU | DIV | DIVU | REM | REMU => Some(Extension::M),
+            SH1ADD | SH2ADD | SH3ADD => Some(Extension::Zba),
+            ANDN | ORN | XNOR | CLZ | CTZ | CPOP | MAX | MAXU | MIN | MINU | SEXTB | SEXTH
+            | ZEXTH | ROL | ROR | ORCB | REV8 => Some(Extension::Zbb),
+            CLMUL | CLMULH | CLMULR => Some(Extension::Zbc),
+            BCLR | BEXT | BINV | BSET => Some(Extension::Zbs),
+            _ => None,
+        }
+    }
+}
+
+impl LoadStoreType {
+    /// Returns true if the type writes memory.
+    pub fn is_store(self) -> bool {
+        use self::LoadStoreType::*;
+        match self {
+            SW | SH | SB => true,
+            LW | LH | LHU | LB | LBU => false,
+        }
+    }
+}
+
 /// Csr OP
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[allow(non_camel_case_types)]
@@ -81,3 +153,70 @@ pub enum PrivOp {
     MRET,
     ECALL,
 }
+
+/// Opcode for vector arithmetic executed in VPU
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum VectorOp {
+    // integer arithmetic
+    VADD,
+    VSUB,
+    VRSUB,
+    VMINU,
+    VMIN,
+    VMAXU,
+    VMAX,
+    VAND,
+    VOR,
+    VXOR,
+    VSLL,
+    VSRL,
+    VSRA,
+    VMUL,
+    VMULH,
+    VMULHU,
+    VMULHSU,
+    VDIVU,
+    VDIV,
+    VREMU,
+    VREM,
+    VMACC,
+    VNMSAC,
+    VMADD,
+    VNMSUB,
+    VMERGE,
+    VMV,
+    VID,
+    // integer compare
+    VMSEQ,
+    VMSNE,
+    VMSLTU,
+    VMSLT,
+    VMSLEU,
+    VMSLE,
+    VMSGTU,
+    VMSGT,
+    // reduction
+    VREDSUM,
+    VREDAND,
+    VREDOR,
+    VREDXOR,
+    VREDMINU,
+    VREDMIN,
+    VREDMAXU,
+    VREDMAX,
+    // mask
+    VMAND,
+    VMNAND,
+    VMANDN,
+    VMXOR,
+    VMOR,
+    VMNOR,
+    VMORN,
+    VMXNOR,
+    VCPOP,
+    VFIRST,
+    // scalar move
+    VMV_X_S,
+    VMV_S_X,
+}
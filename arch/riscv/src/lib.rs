@@ -12,8 +12,12 @@ mod gpr;
 mod isa;
 mod lsu;
 pub mod riscv;
+mod trigger;
+mod vpu;
 pub use self::debug::DebugInterface;
 pub use self::isa::abi_name;
+pub use self::isa::extension::{Extension, IsaConfig, IsaStringError};
+pub use self::lsu::MisalignedAccessPolicy;
 pub use self::riscv::Riscv;
 
 #[cfg(test)]
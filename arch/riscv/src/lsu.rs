@@ -1,9 +1,26 @@
 //! Load store unit.
+//!
+//! Misaligned accesses are handled according to `MisalignedAccessPolicy`.
+//!
+//! Debug triggers are checked before the access, so that an address trigger has
+//! priority over misaligned exceptions. Loaded data is checked before it is written
+//! back because reads from `MemoryAccess` never change the state of devices.
 
 use crate::execute::{LsuOp, WriteBackData};
 use crate::isa::opcode::LoadStoreType;
+use crate::trigger::{AccessType, TriggerHit, TriggerModule};
 use peripherals::memory_access::MemoryAccess;
 
+/// How a hart handles misaligned load/store.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MisalignedAccessPolicy {
+    /// Raise load/store address-misaligned exceptions.
+    #[default]
+    Trap,
+    /// Emulate misaligned access by byte accesses transparently.
+    Emulate,
+}
+
 /// Exceptions occur in load/store stage.
 #[derive(Debug, Fail, PartialEq)]
 pub enum LsuError {
@@ -12,12 +29,63 @@ pub enum LsuError {
 
     #[fail(display = "memory access error to {:08x}", addr)]
     MemoryAccessError { addr: u32 },
+
+    #[fail(display = "debug trigger fired at {:08x}", addr)]
+    Trigger { addr: u32, hit: TriggerHit },
 }
 
 pub fn load_store(
     data_mem: &mut dyn MemoryAccess,
     instr: &LsuOp,
+    policy: MisalignedAccessPolicy,
+    triggers: &mut TriggerModule,
+) -> Result<WriteBackData, LsuError> {
+    let mask = data_mask(instr.op);
+    if instr.op.is_store() {
+        check_trigger(
+            triggers,
+            AccessType::Store,
+            instr.addr,
+            Some(instr.value & mask),
+        )?;
+        return access_memory(data_mem, instr, policy);
+    }
+
+    check_trigger(triggers, AccessType::Load, instr.addr, None)?;
+    let wb = access_memory(data_mem, instr, policy)?;
+    if let WriteBackData::Gpr { value, .. } = wb {
+        check_trigger(triggers, AccessType::Load, instr.addr, Some(value & mask))?;
+    }
+    Ok(wb)
+}
+
+// helper to convert a trigger hit to an error
+fn check_trigger(
+    triggers: &mut TriggerModule,
+    access: AccessType,
+    addr: u32,
+    data: Option<u32>,
+) -> Result<(), LsuError> {
+    match triggers.check(access, addr, data) {
+        Some(hit) => Err(LsuError::Trigger { addr, hit }),
+        None => Ok(()),
+    }
+}
+
+fn access_memory(
+    data_mem: &mut dyn MemoryAccess,
+    instr: &LsuOp,
+    policy: MisalignedAccessPolicy,
 ) -> Result<WriteBackData, LsuError> {
+    if !is_aligned(instr) {
+        match policy {
+            MisalignedAccessPolicy::Trap => {
+                return Err(LsuError::Misalignment { addr: instr.addr })
+            }
+            MisalignedAccessPolicy::Emulate => return load_store_bytewise(data_mem, instr),
+        }
+    }
+
     use self::LoadStoreType::*;
     match instr.op {
         LW => {
@@ -95,6 +163,71 @@ pub fn load_store(
     }
 }
 
+// Accesses memory byte by byte so that devices which reject a wide misaligned
+// access still work.
+fn load_store_bytewise(
+    data_mem: &mut dyn MemoryAccess,
+    instr: &LsuOp,
+) -> Result<WriteBackData, LsuError> {
+    use self::LoadStoreType::*;
+    let size = access_size(instr.op);
+    if instr.op.is_store() {
+        let bytes = instr.value.to_le_bytes();
+        for (i, byte) in bytes.iter().take(size as usize).enumerate() {
+            let addr = instr.addr.wrapping_add(i as u32);
+            data_mem
+                .write_u8(addr as usize, *byte)
+                .map_err(|_| LsuError::MemoryAccessError { addr })?;
+        }
+        return Ok(WriteBackData::Gpr {
+            target: instr.dest,
+            value: 0,
+        });
+    }
+
+    let mut bytes = [0u8; 4];
+    for (i, byte) in bytes.iter_mut().take(size as usize).enumerate() {
+        let addr = instr.addr.wrapping_add(i as u32);
+        *byte = data_mem
+            .read_u8(addr as usize)
+            .map_err(|_| LsuError::MemoryAccessError { addr })?;
+    }
+    let data = u32::from_le_bytes(bytes);
+    let value = match instr.op {
+        LH => sign_extend_from_u16(data as u16),
+        LB => sign_extend_from_u8(data as u8),
+        _ => data,
+    };
+    Ok(WriteBackData::Gpr {
+        target: instr.dest,
+        value,
+    })
+}
+
+// helper for alignment check. Access size is a power of two.
+#[inline(always)]
+fn is_aligned(instr: &LsuOp) -> bool {
+    instr.addr & (access_size(instr.op) - 1) == 0
+}
+
+// helper returns the access size in bytes
+fn access_size(op: LoadStoreType) -> u32 {
+    use self::LoadStoreType::*;
+    match op {
+        LW | SW => 4,
+        LH | LHU | SH => 2,
+        LB | LBU | SB => 1,
+    }
+}
+
+// helper returns the mask of data compared by triggers
+fn data_mask(op: LoadStoreType) -> u32 {
+    match access_size(op) {
+        4 => 0xffff_ffff,
+        size => (1 << (size * 8)) - 1,
+    }
+}
+
 // helper for sign extend
 fn sign_extend_from_u16(data: u16) -> u32 {
     i32::from(data as i16) as u32
@@ -107,6 +240,9 @@ fn sign_extend_from_u8(data: u8) -> u32 {
 
 #[cfg(test)]
 mod test {
+    use super::*;
+    use peripherals::memory::Memory;
+
     #[test]
     fn sign_extend() {
         let half_word = 0xffffu16; // `-1` in singed integer
@@ -114,4 +250,52 @@ mod test {
 
         assert_eq!(signed_word, -1); // 0xffff_ffff
     }
+
+    #[test]
+    fn misaligned_load_trap() {
+        let mut dram = Memory::new(8);
+        let instr = LsuOp {
+            op: LoadStoreType::LW,
+            dest: 1,
+            addr: 2,
+            value: 0,
+        };
+
+        let result = load_store(
+            &mut dram,
+            &instr,
+            MisalignedAccessPolicy::Trap,
+            &mut TriggerModule::new(),
+        );
+        assert_eq!(Err(LsuError::Misalignment { addr: 2 }), result.map(|_| ()));
+    }
+
+    #[test]
+    fn misaligned_store_emulate() {
+        let mut dram = Memory::new(8);
+        let store = LsuOp {
+            op: LoadStoreType::SW,
+            dest: 0,
+            addr: 3,
+            value: 0x1234_5678,
+        };
+        let policy = MisalignedAccessPolicy::Emulate;
+        let mut triggers = TriggerModule::new();
+        assert!(load_store(&mut dram, &store, policy, &mut triggers).is_ok());
+        assert_eq!(0x1234_5678, dram.read_u32(3).unwrap());
+
+        let load = LsuOp {
+            op: LoadStoreType::LH,
+            dest: 1,
+            addr: 5,
+            value: 0,
+        };
+        match load_store(&mut dram, &load, policy, &mut triggers) {
+            Ok(WriteBackData::Gpr { target, value }) => {
+                assert_eq!(1, target);
+                assert_eq!(0x0000_1234, value);
+            }
+            _ => panic!(),
+        }
+    }
 }
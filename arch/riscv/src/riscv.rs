@@ -1,16 +1,19 @@
 use crate::csr::Csr;
 use crate::debug::DebugInterface;
-use crate::decode::decode;
+use crate::decode::{decode, DecodeError};
 use crate::execute::execute;
-use crate::fetch::fetch;
+use crate::fetch::{fetch, FetchError};
 use crate::gpr::Gpr;
-use crate::lsu::load_store;
+use crate::isa::extension::IsaConfig;
+use crate::lsu::{load_store, LsuError, MisalignedAccessPolicy};
+use crate::trigger::{TriggerAction, TriggerHit, TriggerModule};
+use crate::vpu::{Vpu, VpuError, VLEN};
 use cpu::model::CpuModel;
 use debug::DebugMode;
 use peripherals::interconnect::Interconnect;
 use peripherals::memory_access::MemoryAccess;
 
-use crate::isa::exceptions::InternalExceptions;
+use crate::isa::exceptions::{ExceptionCode, InternalExceptions};
 use std::result;
 pub type Result<T> = result::Result<T, InternalExceptions>;
 
@@ -24,22 +27,45 @@ pub struct Riscv<BUS: MemoryAccess> {
     debug: DebugMode,
     gpr: Gpr,
     csr: Csr,
+    isa: IsaConfig,
+    vpu: Vpu,
+    triggers: TriggerModule,
     trap_vector: u32,
+    misaligned_access: MisalignedAccessPolicy,
     halted: bool,
+    debug_mode: bool,
 }
 
 impl<BUS: MemoryAccess> Riscv<BUS> {
     /// Temporary `new`.
     /// TODO: This must be a new. It requires to modify CpuModel interface.
     pub fn fabricate(mmio: BUS, debug: DebugMode) -> Self {
+        Riscv::fabricate_with_isa(mmio, debug, IsaConfig::default())
+    }
+
+    /// Creates a hart which only implements extensions enabled in `isa`.
+    /// `isa` can be parsed from an ISA string, e.g., `"rv32imac_zicsr_zifencei".parse()`.
+    pub fn fabricate_with_isa(mmio: BUS, debug: DebugMode, isa: IsaConfig) -> Self {
+        use crate::isa::csr_map::misa;
+        use crate::isa::extension::Extension;
+        let mut csr = Csr::new();
+        csr.write_u32(misa, isa.misa());
+        if isa.has(Extension::Zve32x) {
+            Vpu::init_csr(&mut csr);
+        }
         Riscv {
             pc: 0,
             mmio,
             debug,
             gpr: Gpr::new(),
-            csr: Csr::new(),
+            csr,
+            isa,
+            vpu: Vpu::new(),
+            triggers: TriggerModule::new(),
             trap_vector: 0x8000_0004,  // default for riscv-tests.
+            misaligned_access: MisalignedAccessPolicy::default(),
             halted: true,
+            debug_mode: false,
         }
     }
 
@@ -47,6 +73,61 @@ impl<BUS: MemoryAccess> Riscv<BUS> {
     pub fn set_trap_vector(&mut self, addr: u32) {
         self.trap_vector = addr;
     }
+
+    /// Set how misaligned load/store is handled.
+    pub fn set_misaligned_access_policy(&mut self, policy: MisalignedAccessPolicy) {
+        self.misaligned_access = policy;
+    }
+
+    // Take a trap caused by the instruction at `epc`.
+    fn take_trap(&mut self, epc: u32, cause: ExceptionCode, tval: u32) {
+        use crate::isa::csr_map::{mcause, mepc, mtval};
+        self.csr.write_u32(mepc, epc);
+        self.csr.write_u32(mcause, cause as u32);
+        self.csr.write_u32(mtval, tval);
+        self.pc = self.trap_vector;
+    }
+
+    // Raise a breakpoint exception or enter debug mode according to the trigger.
+    fn handle_trigger(&mut self, epc: u32, hit: TriggerHit) {
+        match hit.action {
+            TriggerAction::Breakpoint => self.take_trap(epc, ExceptionCode::Breakpoint, hit.tval),
+            TriggerAction::DebugMode => {
+                use crate::isa::csr_map::{dcsr, dpc};
+                use bit_field::BitField;
+                // cause is 2 (trigger).
+                let mut value = self.csr.read_u32(dcsr);
+                value.set_bits(6..9, 2);
+                self.csr.write_u32(dcsr, value);
+                self.csr.write_u32(dpc, epc);
+                self.pc = epc;
+                self.debug_mode = true;
+                self.halted = true;
+            }
+        }
+    }
+
+    // Trigger CSRs are owned by the trigger module.
+    fn read_csr(&self, addr: u32) -> u32 {
+        match self.triggers.read_csr(addr) {
+            Some(value) => value,
+            None => self.csr.read_u32(addr),
+        }
+    }
+
+    // Trigger CSRs are owned by the trigger module. `misa` is read-only.
+    // `vstart` only holds bits enough to index any element.
+    fn write_csr(&mut self, addr: u32, value: u32) {
+        use crate::isa::csr_map::{misa, vstart};
+        let value = if addr == vstart {
+            value & (VLEN as u32 - 1)
+        } else {
+            value
+        };
+        if addr != misa && !self.triggers.write_csr(addr, value, self.debug_mode) {
+            self.csr.write_u32(addr, value);
+        }
+    }
 }
 
 impl<BUS: MemoryAccess> CpuModel for Riscv<BUS> {
@@ -65,13 +146,26 @@ impl<BUS: MemoryAccess> CpuModel for Riscv<BUS> {
     fn run(&mut self) -> Result<()> {
         while !self.halted {
             //println!("pc: {0:8x}", self.pc);
-            let (instr, next_pc) = fetch(&self.mmio, self.pc)?;
-            let instr = decode(instr, &self.gpr, self.pc, next_pc)?;
-            let (wb, next_pc) = execute(instr)?;
+            let (instr, next_pc) = match fetch(&self.mmio, self.pc, &mut self.triggers) {
+                Err(FetchError::Trigger { pc, hit }) => {
+                    self.handle_trigger(pc, hit);
+                    continue;
+                }
+                result => result?,
+            };
+            let decoded = match decode(instr, &self.gpr, self.pc, next_pc, self.isa) {
+                Err(DecodeError::IllegalInstr { instr }) => {
+                    self.take_trap(self.pc, ExceptionCode::IllegalInstruction, instr);
+                    continue;
+                }
+                result => result?,
+            };
+            let (wb, next_pc) = execute(decoded)?;
 
             // Change CPU state only here.
             // First, update program counter.
             // This will be updated again in case of priviledged instruction.
+            let pc = self.pc;
             self.pc = next_pc;
 
             // Next, write to general purpose register and control, status register,
@@ -80,37 +174,58 @@ impl<BUS: MemoryAccess> CpuModel for Riscv<BUS> {
             use crate::isa::opcode::PrivOp;
             match wb {
                 Gpr { target, value } => self.gpr.write_u32(target, value),
-                Lsu(ref op) => {
-                    let wb = load_store(&mut self.mmio, op)?;
-                    if let Gpr { target, value } = wb {
-                        self.gpr.write_u32(target, value);
-                    };
-                }
+                Lsu(ref op) => match load_store(
+                    &mut self.mmio,
+                    op,
+                    self.misaligned_access,
+                    &mut self.triggers,
+                ) {
+                    Ok(Gpr { target, value }) => self.gpr.write_u32(target, value),
+                    Ok(_) => (),
+                    Err(LsuError::Trigger { hit, .. }) => self.handle_trigger(pc, hit),
+                    Err(LsuError::Misalignment { addr }) => {
+                        let cause = if op.op.is_store() {
+                            ExceptionCode::StoreAddressMisaligned
+                        } else {
+                            ExceptionCode::LoadAddressMisaligned
+                        };
+                        self.take_trap(pc, cause, addr);
+                    }
+                    Err(error) => return Err(error.into()),
+                },
                 Csr(instr) => {
                     use crate::isa::opcode::CsrOp::*;
                     match instr.op {
                         WRITE => {
-                            let old = self.csr.read_u32(instr.csr_addr);
-                            self.csr.write_u32(instr.csr_addr, instr.src);
+                            let old = self.read_csr(instr.csr_addr);
+                            self.write_csr(instr.csr_addr, instr.src);
                             self.gpr.write_u32(instr.dest, old);
                         }
                         SET => {
-                            let old = self.csr.read_u32(instr.csr_addr);
-                            self.csr.write_u32(instr.csr_addr, instr.src | old);
+                            let old = self.read_csr(instr.csr_addr);
+                            self.write_csr(instr.csr_addr, instr.src | old);
                             self.gpr.write_u32(instr.dest, old);
                         }
                         CLEAR => {
-                            let old = self.csr.read_u32(instr.csr_addr);
-                            self.csr.write_u32(instr.csr_addr, (!instr.src) & old);
+                            let old = self.read_csr(instr.csr_addr);
+                            self.write_csr(instr.csr_addr, (!instr.src) & old);
                             self.gpr.write_u32(instr.dest, old);
                         }
                     }
                 }
+                // Illegal vtype and register operands are detected only at execution.
+                Vector(ref vector) => {
+                    let result = self.vpu.execute(vector, &mut self.csr, &mut self.mmio);
+                    match result {
+                        Ok(Gpr { target, value }) => self.gpr.write_u32(target, value),
+                        Ok(_) => (),
+                        Err(error @ VpuError::MemoryAccessError { .. }) => return Err(error.into()),
+                        Err(_) => self.take_trap(pc, ExceptionCode::IllegalInstruction, instr),
+                    }
+                }
                 Priv(op) => match op {
                     PrivOp::ECALL => {
-                        use crate::isa::csr_map::mcause;
-                        self.pc = self.trap_vector;
-                        self.csr.write_u32(mcause, 11);
+                        self.take_trap(pc, ExceptionCode::EnvironmentCallFromMMode, 0);
                     }
                     PrivOp::WFI => self.halted = true,
                     PrivOp::MRET => {
@@ -142,7 +257,24 @@ impl<BUS: MemoryAccess> DebugInterface for Riscv<BUS> {
     }
 
     fn get_csr(&self, index: u32) -> u32 {
-        self.csr.read_u32(index)
+        self.read_csr(index)
+    }
+
+    fn set_csr(&mut self, index: u32, value: u32) {
+        if !self.triggers.write_csr(index, value, true) {
+            self.csr.write_u32(index, value);
+        }
+    }
+
+    fn is_debug_mode(&self) -> bool {
+        self.debug_mode
+    }
+
+    fn resume(&mut self) {
+        use crate::isa::csr_map::dpc;
+        self.pc = self.csr.read_u32(dpc);
+        self.debug_mode = false;
+        self.halted = false;
     }
 }
 
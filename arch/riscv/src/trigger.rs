@@ -0,0 +1,320 @@
+//! Debug trigger module (Sdtrig).
+//!
+//! Supports mcontrol (type 2) and mcontrol6 (type 6) triggers which match on
+//! execute, load and store addresses, or on data when `select` is set.
+//! This emulator only has M-mode so that a trigger fires only if its `m` bit is set.
+//! All triggers fire before the instruction is executed.
+
+use crate::isa::csr_map;
+use bit_field::BitField;
+
+/// Number of triggers.
+pub const NUM_OF_TRIGGERS: usize = 4;
+
+// Field positions of tdata1 which are common in mcontrol and mcontrol6.
+const TYPE: std::ops::Range<usize> = 28..32;
+const DMODE: usize = 27;
+const ACTION: std::ops::Range<usize> = 12..16;
+const CHAIN: usize = 11;
+const MATCH: std::ops::Range<usize> = 7..11;
+const M: usize = 6;
+const EXECUTE: usize = 2;
+const STORE: usize = 1;
+const LOAD: usize = 0;
+
+// Field positions which are different between mcontrol and mcontrol6.
+const MCONTROL_HIT: usize = 20;
+const MCONTROL_SELECT: usize = 19;
+const MCONTROL6_HIT0: usize = 22;
+const MCONTROL6_SELECT: usize = 21;
+
+const TYPE_MCONTROL: u32 = 2;
+const TYPE_MCONTROL6: u32 = 6;
+const TYPE_DISABLED: u32 = 15;
+
+// Bits of tdata1 writable by software for each type.
+const MCONTROL_WRITABLE: u32 = 0x0819_ffdf;
+const MCONTROL6_WRITABLE: u32 = 0x0867_ffff;
+
+/// Type of an access checked by triggers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessType {
+    Execute,
+    Load,
+    Store,
+}
+
+/// What a hart does when a trigger fires.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerAction {
+    /// Raise a breakpoint exception.
+    Breakpoint,
+    /// Enter debug mode.
+    DebugMode,
+}
+
+/// A trigger fired. `tval` is the address of the access.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriggerHit {
+    pub action: TriggerAction,
+    pub tval: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Trigger {
+    tdata1: u32,
+    tdata2: u32,
+}
+
+impl Trigger {
+    fn disabled() -> Trigger {
+        Trigger {
+            tdata1: TYPE_DISABLED << 28,
+            tdata2: 0,
+        }
+    }
+
+    fn trigger_type(self) -> u32 {
+        self.tdata1.get_bits(TYPE)
+    }
+
+    // `select` chooses data instead of address.
+    fn select(self) -> bool {
+        match self.trigger_type() {
+            TYPE_MCONTROL => self.tdata1.get_bit(MCONTROL_SELECT),
+            _ => self.tdata1.get_bit(MCONTROL6_SELECT),
+        }
+    }
+
+    fn set_hit(&mut self) {
+        match self.trigger_type() {
+            TYPE_MCONTROL => self.tdata1.set_bit(MCONTROL_HIT, true),
+            _ => self.tdata1.set_bit(MCONTROL6_HIT0, true),
+        };
+    }
+
+    fn action(self) -> TriggerAction {
+        match self.tdata1.get_bits(ACTION) {
+            1 => TriggerAction::DebugMode,
+            _ => TriggerAction::Breakpoint,
+        }
+    }
+
+    // Whether the trigger matches to the access.
+    // `data` is `None` if the data is not available, e.g., for execute address.
+    fn matches(self, access: AccessType, addr: u32, data: Option<u32>) -> bool {
+        let trigger_type = self.trigger_type();
+        if trigger_type != TYPE_MCONTROL && trigger_type != TYPE_MCONTROL6 {
+            return false;
+        }
+        let enabled = match access {
+            AccessType::Execute => self.tdata1.get_bit(EXECUTE),
+            AccessType::Load => self.tdata1.get_bit(LOAD),
+            AccessType::Store => self.tdata1.get_bit(STORE),
+        };
+        if !enabled || !self.tdata1.get_bit(M) {
+            return false;
+        }
+        let value = match (self.select(), data) {
+            (false, _) => addr,
+            (true, Some(data)) => data,
+            (true, None) => return false,
+        };
+        compare(self.tdata1.get_bits(MATCH), value, self.tdata2)
+    }
+}
+
+/// Debug trigger module which has `tselect` and triggers.
+pub struct TriggerModule {
+    tselect: u32,
+    triggers: [Trigger; NUM_OF_TRIGGERS],
+}
+
+impl TriggerModule {
+    /// All triggers are disabled.
+    pub fn new() -> TriggerModule {
+        TriggerModule {
+            tselect: 0,
+            triggers: [Trigger::disabled(); NUM_OF_TRIGGERS],
+        }
+    }
+
+    /// Returns the value of trigger CSR, or `None` if `addr` is not a trigger CSR.
+    pub fn read_csr(&self, addr: u32) -> Option<u32> {
+        let trigger = &self.triggers[self.tselect as usize];
+        match addr {
+            csr_map::tselect => Some(self.tselect),
+            csr_map::tdata1 => Some(trigger.tdata1),
+            csr_map::tdata2 => Some(trigger.tdata2),
+            csr_map::tdata3 => Some(0),
+            // version 1 and supported types.
+            csr_map::tinfo => Some(1 << 24 | 1 << TYPE_MCONTROL | 1 << TYPE_MCONTROL6),
+            _ => None,
+        }
+    }
+
+    /// Writes trigger CSR. Returns false if `addr` is not a trigger CSR.
+    /// `debug_mode` is true if the write is done by a debugger.
+    pub fn write_csr(&mut self, addr: u32, value: u32, debug_mode: bool) -> bool {
+        let trigger = &mut self.triggers[self.tselect as usize];
+        // Only debugger can modify triggers owned by debug mode.
+        let writable = debug_mode || !trigger.tdata1.get_bit(DMODE);
+        match addr {
+            csr_map::tselect => {
+                if (value as usize) < NUM_OF_TRIGGERS {
+                    self.tselect = value;
+                }
+            }
+            csr_map::tdata1 if writable => trigger.tdata1 = legalize_tdata1(value, debug_mode),
+            csr_map::tdata2 if writable => trigger.tdata2 = value,
+            csr_map::tdata1 | csr_map::tdata2 | csr_map::tdata3 | csr_map::tinfo => (),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Checks all triggers and returns a hit if some trigger fires.
+    /// A chained trigger fires only if all triggers in the chain match.
+    pub fn check(
+        &mut self,
+        access: AccessType,
+        addr: u32,
+        data: Option<u32>,
+    ) -> Option<TriggerHit> {
+        let mut chain_start = 0;
+        let mut chain_matched = true;
+        for i in 0..NUM_OF_TRIGGERS {
+            let trigger = self.triggers[i];
+            chain_matched &= trigger.matches(access, addr, data);
+            if trigger.tdata1.get_bit(CHAIN) && i + 1 < NUM_OF_TRIGGERS {
+                continue;
+            }
+            if chain_matched {
+                for hit in &mut self.triggers[chain_start..=i] {
+                    hit.set_hit();
+                }
+                return Some(TriggerHit {
+                    action: trigger.action(),
+                    tval: addr,
+                });
+            }
+            chain_start = i + 1;
+            chain_matched = true;
+        }
+        None
+    }
+}
+
+// Returns tdata1 with unsupported or read-only fields are fixed.
+fn legalize_tdata1(value: u32, debug_mode: bool) -> u32 {
+    let mut tdata1 = match value.get_bits(TYPE) {
+        TYPE_MCONTROL => (TYPE_MCONTROL << 28) | (value & MCONTROL_WRITABLE),
+        TYPE_MCONTROL6 => (TYPE_MCONTROL6 << 28) | (value & MCONTROL6_WRITABLE),
+        _ => return TYPE_DISABLED << 28,
+    };
+    if !debug_mode {
+        tdata1.set_bit(DMODE, false);
+    }
+    // Entering debug mode is allowed only for triggers owned by debug mode.
+    if !tdata1.get_bit(DMODE) && tdata1.get_bits(ACTION) == 1 {
+        tdata1.set_bits(ACTION, 0);
+    }
+    tdata1
+}
+
+// Compare `value` with `tdata2` according to `match` field.
+fn compare(match_type: u32, value: u32, tdata2: u32) -> bool {
+    let low_half = |v: u32| v & 0xffff;
+    let mask = tdata2 >> 16;
+    match match_type {
+        0 => value == tdata2,
+        1 => napot(value, tdata2),
+        2 => value >= tdata2,
+        3 => value < tdata2,
+        4 => low_half(value) & mask == low_half(tdata2),
+        5 => (value >> 16) & mask == low_half(tdata2),
+        8 => value != tdata2,
+        9 => !napot(value, tdata2),
+        12 => low_half(value) & mask != low_half(tdata2),
+        13 => (value >> 16) & mask != low_half(tdata2),
+        _ => false,
+    }
+}
+
+// Naturally aligned power-of-two range. Trailing ones of `tdata2` specify the size.
+fn napot(value: u32, tdata2: u32) -> bool {
+    let ignore = tdata2 ^ tdata2.wrapping_add(1);
+    (value & !ignore) == (tdata2 & !ignore)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // mcontrol: m, execute and match equal.
+    const MCONTROL_EXECUTE: u32 = 0x2000_0044;
+
+    #[test]
+    fn disabled_at_reset() {
+        let mut triggers = TriggerModule::new();
+        assert_eq!(Some(0xf000_0000), triggers.read_csr(csr_map::tdata1));
+        assert_eq!(None, triggers.check(AccessType::Execute, 0, None));
+    }
+
+    #[test]
+    fn execute_address_match() {
+        let mut triggers = TriggerModule::new();
+        triggers.write_csr(csr_map::tdata1, MCONTROL_EXECUTE, false);
+        triggers.write_csr(csr_map::tdata2, 0x100, false);
+
+        assert_eq!(None, triggers.check(AccessType::Execute, 0xfc, None));
+        assert_eq!(None, triggers.check(AccessType::Load, 0x100, None));
+        assert_eq!(
+            Some(TriggerHit {
+                action: TriggerAction::Breakpoint,
+                tval: 0x100
+            }),
+            triggers.check(AccessType::Execute, 0x100, None)
+        );
+        assert!(triggers
+            .read_csr(csr_map::tdata1)
+            .unwrap()
+            .get_bit(MCONTROL_HIT));
+    }
+
+    #[test]
+    fn napot_match() {
+        // 0x1000 - 0x100f
+        assert!(napot(0x100f, 0x1007));
+        assert!(!napot(0x1010, 0x1007));
+    }
+
+    #[test]
+    fn chain() {
+        let mut triggers = TriggerModule::new();
+        // store address >= 0x100 and < 0x200
+        triggers.write_csr(csr_map::tdata1, 0x2000_0942, false);
+        triggers.write_csr(csr_map::tdata2, 0x100, false);
+        triggers.write_csr(csr_map::tselect, 1, false);
+        triggers.write_csr(csr_map::tdata1, 0x2000_01c2, false);
+        triggers.write_csr(csr_map::tdata2, 0x200, false);
+
+        assert!(triggers.check(AccessType::Store, 0x80, None).is_none());
+        assert!(triggers.check(AccessType::Store, 0x200, None).is_none());
+        assert!(triggers.check(AccessType::Store, 0x180, None).is_some());
+    }
+
+    #[test]
+    fn action_requires_dmode() {
+        let mut triggers = TriggerModule::new();
+        // action = 1 and dmode = 1
+        triggers.write_csr(csr_map::tdata1, 0x2800_1044, false);
+        assert_eq!(Some(0x2000_0044), triggers.read_csr(csr_map::tdata1));
+
+        triggers.write_csr(csr_map::tdata1, 0x2800_1044, true);
+        assert_eq!(Some(0x2800_1044), triggers.read_csr(csr_map::tdata1));
+        // M-mode cannot modify the trigger owned by debug mode.
+        triggers.write_csr(csr_map::tdata1, 0, false);
+        assert_eq!(Some(0x2800_1044), triggers.read_csr(csr_map::tdata1));
+    }
+}
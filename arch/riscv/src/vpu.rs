@@ -0,0 +1,604 @@
+//! Vector processing unit.
+//!
+//! Implements a subset of V extension, i.e., Zve32x (ELEN = 32) with VLEN = 128.
+//! Execution is done element by element so that it is correct but not fast.
+//! `vl`, `vtype` and `vstart` live in CSR so that they can be read by CSR instructions.
+//! `vl` and `vtype` are read-only, so that only vset{i}vl{i} changes them.
+//!
+//! Tail elements and masked-off elements are always undisturbed, which is allowed
+//! regardless of `vta` and `vma` setting.
+
+use crate::csr::Csr;
+use crate::decode::{Avl, VectorArithInstr, VectorInstr, VectorMemInstr, VectorOperand, VsetInstr};
+use crate::execute::WriteBackData;
+use crate::isa::csr_map;
+use crate::isa::opcode::VectorOp;
+use bit_field::BitField;
+use peripherals::memory_access::MemoryAccess;
+
+/// Bits of a vector register.
+pub const VLEN: usize = 128;
+/// Bytes of a vector register.
+pub const VLENB: usize = VLEN / 8;
+/// Maximum bits of an element.
+pub const ELEN: usize = 32;
+
+const NUM_OF_VREG: usize = 32;
+// vill bit in vtype.
+const VILL: u32 = 1 << 31;
+
+/// Exceptions occur in VPU.
+#[derive(Debug, Fail, PartialEq)]
+pub enum VpuError {
+    #[fail(
+        display = "vector instruction is executed with illegal vtype: {:08x}",
+        vtype
+    )]
+    IllegalVtype { vtype: u32 },
+
+    #[fail(display = "v{} is not aligned to the register group", reg)]
+    MisalignedRegisterGroup { reg: u32 },
+
+    #[fail(display = "masked instruction overwrites v0")]
+    MaskOverlap,
+
+    #[fail(display = "reduction is executed with non-zero vstart: {}", vstart)]
+    NonZeroVstart { vstart: usize },
+
+    #[fail(display = "memory access error to {:08x}", addr)]
+    MemoryAccessError { addr: u32 },
+}
+
+type Result<T> = std::result::Result<T, VpuError>;
+
+/// Decoded `vtype`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct VectorType {
+    /// SEW in bytes.
+    sew: usize,
+    /// LMUL multiplied by 8 so that fractional LMUL is an integer.
+    lmul8: usize,
+}
+
+impl VectorType {
+    // Returns `None` if the vtype is not supported.
+    fn from_u32(vtype: u32) -> Option<VectorType> {
+        if vtype.get_bits(8..32) != 0 {
+            return None;
+        }
+        let sew = match vtype.get_bits(3..6) {
+            0b000 => 1,
+            0b001 => 2,
+            0b010 => 4,
+            _ => return None,
+        };
+        let lmul8 = match vtype.get_bits(0..3) {
+            0b000 => 8,
+            0b001 => 16,
+            0b010 => 32,
+            0b011 => 64,
+            0b101 => 1,
+            0b110 => 2,
+            0b111 => 4,
+            _ => return None,
+        };
+        // SEW must be smaller than LMUL * ELEN.
+        if sew * 8 * 8 > lmul8 * ELEN {
+            return None;
+        }
+        Some(VectorType { sew, lmul8 })
+    }
+
+    // Maximum number of elements.
+    fn vlmax(self) -> usize {
+        VLENB * self.lmul8 / self.sew / 8
+    }
+
+    // Number of registers in a register group. Fractional LMUL occupies one register.
+    fn group(self) -> u32 {
+        (self.lmul8 / 8).max(1) as u32
+    }
+}
+
+/// Vector processing unit which has the vector register file.
+pub struct Vpu {
+    vrf: Vec<u8>,
+}
+
+impl Vpu {
+    /// Initialize all register as `0`.
+    pub fn new() -> Vpu {
+        Vpu {
+            vrf: vec![0u8; NUM_OF_VREG * VLENB],
+        }
+    }
+
+    /// Initialize vector CSRs. `vtype` is illegal until vset{i}vl{i} is executed.
+    pub fn init_csr(csr: &mut Csr) {
+        csr.write_u32(csr_map::vlenb, VLENB as u32);
+        csr.write_u32(csr_map::vtype, VILL);
+        csr.write_u32(csr_map::vl, 0);
+        csr.write_u32(csr_map::vstart, 0);
+    }
+
+    /// Executes a vector instruction.
+    /// Returns `WriteBackData::Gpr` for instructions writing a scalar register.
+    pub fn execute(
+        &mut self,
+        instr: &VectorInstr,
+        csr: &mut Csr,
+        data_mem: &mut dyn MemoryAccess,
+    ) -> Result<WriteBackData> {
+        let wb = match instr {
+            VectorInstr::Config(instr) => return Ok(self.set_vl(instr, csr)),
+            VectorInstr::Mem(instr) => {
+                let vtype = current_vtype(csr)?;
+                self.load_store(instr, vtype, csr, data_mem)?;
+                WriteBackData::default()
+            }
+            VectorInstr::Arith(instr) => {
+                let vtype = current_vtype(csr)?;
+                self.arith(instr, vtype, csr)?
+            }
+        };
+        csr.write_u32(csr_map::vstart, 0);
+        Ok(wb)
+    }
+
+    /// Reads an element of `sew` bytes from the register group starting at `reg`.
+    pub fn read_element(&self, reg: u32, index: usize, sew: usize) -> u32 {
+        let offset = reg as usize * VLENB + index * sew;
+        let mut bytes = [0u8; 4];
+        bytes[..sew].copy_from_slice(&self.vrf[offset..offset + sew]);
+        u32::from_le_bytes(bytes)
+    }
+
+    /// Writes an element of `sew` bytes to the register group starting at `reg`.
+    pub fn write_element(&mut self, reg: u32, index: usize, sew: usize, value: u32) {
+        let offset = reg as usize * VLENB + index * sew;
+        self.vrf[offset..offset + sew].copy_from_slice(&value.to_le_bytes()[..sew]);
+    }
+
+    // Reads a mask bit of `reg`.
+    fn mask_bit(&self, reg: u32, index: usize) -> bool {
+        self.vrf[reg as usize * VLENB + index / 8].get_bit(index % 8)
+    }
+
+    // Writes a mask bit of `reg`.
+    fn set_mask_bit(&mut self, reg: u32, index: usize, value: bool) {
+        self.vrf[reg as usize * VLENB + index / 8].set_bit(index % 8, value);
+    }
+
+    // Whether the element is active or not.
+    fn is_active(&self, masked: bool, index: usize) -> bool {
+        !masked || self.mask_bit(0, index)
+    }
+
+    // vset{i}vl{i}
+    fn set_vl(&mut self, instr: &VsetInstr, csr: &mut Csr) -> WriteBackData {
+        let (vtype, vl) = match VectorType::from_u32(instr.vtype) {
+            Some(vtype) => {
+                let vlmax = vtype.vlmax() as u32;
+                let vl = match instr.avl {
+                    Avl::Value(avl) => avl.min(vlmax),
+                    Avl::Max => vlmax,
+                    Avl::Keep => csr.read_u32(csr_map::vl).min(vlmax),
+                };
+                (instr.vtype, vl)
+            }
+            None => (VILL, 0),
+        };
+        csr.write_u32(csr_map::vtype, vtype);
+        csr.write_u32(csr_map::vl, vl);
+        csr.write_u32(csr_map::vstart, 0);
+        WriteBackData::Gpr {
+            target: instr.dest,
+            value: vl,
+        }
+    }
+
+    // unit-stride and strided load/store
+    fn load_store(
+        &mut self,
+        instr: &VectorMemInstr,
+        vtype: VectorType,
+        csr: &Csr,
+        data_mem: &mut dyn MemoryAccess,
+    ) -> Result<()> {
+        // EMUL = (EEW / SEW) * LMUL
+        let emul8 = vtype.lmul8 * instr.eew / vtype.sew;
+        if emul8 == 0 || emul8 > 64 {
+            return Err(VpuError::IllegalVtype {
+                vtype: csr.read_u32(csr_map::vtype),
+            });
+        }
+        let group = (emul8 / 8).max(1) as u32;
+        check_group(instr.vd, group)?;
+        if instr.masked && !instr.store && instr.vd == 0 {
+            return Err(VpuError::MaskOverlap);
+        }
+
+        let stride = instr.stride.unwrap_or(instr.eew as u32);
+        let (vstart, vl) = body(csr);
+        for i in vstart..vl {
+            if !self.is_active(instr.masked, i) {
+                continue;
+            }
+            let addr = instr.base.wrapping_add((i as u32).wrapping_mul(stride));
+            if instr.store {
+                let value = self.read_element(instr.vd, i, instr.eew);
+                write_memory(data_mem, addr, instr.eew, value)?;
+            } else {
+                let value = read_memory(data_mem, addr, instr.eew)?;
+                self.write_element(instr.vd, i, instr.eew, value);
+            }
+        }
+        Ok(())
+    }
+
+    // vector arithmetic
+    fn arith(
+        &mut self,
+        instr: &VectorArithInstr,
+        vtype: VectorType,
+        csr: &mut Csr,
+    ) -> Result<WriteBackData> {
+        use crate::isa::opcode::VectorOp::*;
+        match instr.op {
+            VMSEQ | VMSNE | VMSLTU | VMSLT | VMSLEU | VMSLE | VMSGTU | VMSGT => {
+                self.compare(instr, vtype, csr)
+            }
+            VREDSUM | VREDAND | VREDOR | VREDXOR | VREDMINU | VREDMIN | VREDMAXU | VREDMAX => {
+                self.reduction(instr, vtype, csr)
+            }
+            VMAND | VMNAND | VMANDN | VMXOR | VMOR | VMNOR | VMORN | VMXNOR => {
+                self.mask_logical(instr, csr);
+                Ok(WriteBackData::default())
+            }
+            VCPOP | VFIRST => Ok(self.mask_scalar(instr, csr)),
+            VMV_X_S => Ok(WriteBackData::Gpr {
+                target: instr.dest,
+                value: sign_extend(self.read_element(instr.vs2, 0, vtype.sew), vtype.sew),
+            }),
+            VMV_S_X => {
+                let (vstart, vl) = body(csr);
+                if vstart < vl {
+                    if let VectorOperand::Scalar(value) = instr.src1 {
+                        self.write_element(instr.dest, 0, vtype.sew, value);
+                    }
+                }
+                Ok(WriteBackData::default())
+            }
+            _ => self.elementwise(instr, vtype, csr),
+        }
+    }
+
+    // Operations writing each element of vd.
+    fn elementwise(
+        &mut self,
+        instr: &VectorArithInstr,
+        vtype: VectorType,
+        csr: &Csr,
+    ) -> Result<WriteBackData> {
+        let group = vtype.group();
+        check_group(instr.dest, group)?;
+        check_group(instr.vs2, group)?;
+        if let VectorOperand::Vector(vs1) = instr.src1 {
+            check_group(vs1, group)?;
+        }
+        // vmerge and masked vmv are reserved for v0 as well, though they are not masked.
+        if (instr.masked || instr.op == VectorOp::VMERGE) && instr.dest == 0 {
+            return Err(VpuError::MaskOverlap);
+        }
+
+        let sew = vtype.sew;
+        let (vstart, vl) = body(csr);
+        let mut results = Vec::new();
+        for i in vstart..vl {
+            if !self.is_active(instr.masked, i) {
+                continue;
+            }
+            let src1 = self.operand(instr.src1, i, sew);
+            let src2 = self.read_element(instr.vs2, i, sew);
+            let dest = self.read_element(instr.dest, i, sew);
+            let value = match instr.op {
+                VectorOp::VMERGE if !self.mask_bit(0, i) => src2,
+                VectorOp::VID => i as u32,
+                _ => element_op(instr.op, src2, src1, dest, sew),
+            };
+            results.push((i, value));
+        }
+        for (i, value) in results {
+            self.write_element(instr.dest, i, sew, value);
+        }
+        Ok(WriteBackData::default())
+    }
+
+    // Integer compare writing a mask register.
+    fn compare(
+        &mut self,
+        instr: &VectorArithInstr,
+        vtype: VectorType,
+        csr: &Csr,
+    ) -> Result<WriteBackData> {
+        use crate::isa::opcode::VectorOp::*;
+        let group = vtype.group();
+        check_group(instr.vs2, group)?;
+        if let VectorOperand::Vector(vs1) = instr.src1 {
+            check_group(vs1, group)?;
+        }
+
+        let sew = vtype.sew;
+        let (vstart, vl) = body(csr);
+        let mut results = Vec::new();
+        for i in vstart..vl {
+            if !self.is_active(instr.masked, i) {
+                continue;
+            }
+            let src1 = self.operand(instr.src1, i, sew);
+            let src2 = self.read_element(instr.vs2, i, sew);
+            let (signed1, signed2) = (sign_extend(src1, sew) as i32, sign_extend(src2, sew) as i32);
+            let result = match instr.op {
+                VMSEQ => src2 == src1,
+                VMSNE => src2 != src1,
+                VMSLTU => src2 < src1,
+                VMSLT => signed2 < signed1,
+                VMSLEU => src2 <= src1,
+                VMSLE => signed2 <= signed1,
+                VMSGTU => src2 > src1,
+                VMSGT => signed2 > signed1,
+                _ => unreachable!(),
+            };
+            results.push((i, result));
+        }
+        for (i, result) in results {
+            self.set_mask_bit(instr.dest, i, result);
+        }
+        Ok(WriteBackData::default())
+    }
+
+    // Single-width integer reduction. vd[0] = reduce(vs1[0], vs2[*]).
+    fn reduction(
+        &mut self,
+        instr: &VectorArithInstr,
+        vtype: VectorType,
+        csr: &Csr,
+    ) -> Result<WriteBackData> {
+        use crate::isa::opcode::VectorOp::*;
+        check_group(instr.vs2, vtype.group())?;
+
+        let sew = vtype.sew;
+        let (vstart, vl) = body(csr);
+        if vstart != 0 {
+            return Err(VpuError::NonZeroVstart { vstart });
+        }
+        if vl == 0 {
+            return Ok(WriteBackData::default());
+        }
+        let mut acc = self.operand(instr.src1, 0, sew);
+        for i in 0..vl {
+            if !self.is_active(instr.masked, i) {
+                continue;
+            }
+            let src = self.read_element(instr.vs2, i, sew);
+            let (signed_acc, signed_src) =
+                (sign_extend(acc, sew) as i32, sign_extend(src, sew) as i32);
+            acc = match instr.op {
+                VREDSUM => acc.wrapping_add(src),
+                VREDAND => acc & src,
+                VREDOR => acc | src,
+                VREDXOR => acc ^ src,
+                VREDMINU => truncate(acc, sew).min(src),
+                VREDMIN => signed_acc.min(signed_src) as u32,
+                VREDMAXU => truncate(acc, sew).max(src),
+                VREDMAX => signed_acc.max(signed_src) as u32,
+                _ => unreachable!(),
+            };
+        }
+        self.write_element(instr.dest, 0, sew, acc);
+        Ok(WriteBackData::default())
+    }
+
+    // Mask-register logical instructions. These are always unmasked.
+    fn mask_logical(&mut self, instr: &VectorArithInstr, csr: &Csr) {
+        use crate::isa::opcode::VectorOp::*;
+        let vs1 = match instr.src1 {
+            VectorOperand::Vector(vs1) => vs1,
+            VectorOperand::Scalar(_) => unreachable!(),
+        };
+        let (vstart, vl) = body(csr);
+        let results: Vec<(usize, bool)> = (vstart..vl)
+            .map(|i| {
+                let (a, b) = (self.mask_bit(instr.vs2, i), self.mask_bit(vs1, i));
+                let result = match instr.op {
+                    VMAND => a & b,
+                    VMNAND => !(a & b),
+                    VMANDN => a & !b,
+                    VMXOR => a ^ b,
+                    VMOR => a | b,
+                    VMNOR => !(a | b),
+                    VMORN => a | !b,
+                    VMXNOR => !(a ^ b),
+                    _ => unreachable!(),
+                };
+                (i, result)
+            })
+            .collect();
+        for (i, result) in results {
+            self.set_mask_bit(instr.dest, i, result);
+        }
+    }
+
+    // vcpop.m and vfirst.m which write a scalar register.
+    fn mask_scalar(&self, instr: &VectorArithInstr, csr: &Csr) -> WriteBackData {
+        let (_, vl) = body(csr);
+        let mut active =
+            (0..vl).filter(|i| self.is_active(instr.masked, *i) && self.mask_bit(instr.vs2, *i));
+        let value = match instr.op {
+            VectorOp::VCPOP => active.count() as u32,
+            _ => active.next().map_or(0xffff_ffff, |i| i as u32),
+        };
+        WriteBackData::Gpr {
+            target: instr.dest,
+            value,
+        }
+    }
+
+    // The first source operand of i-th element.
+    fn operand(&self, src: VectorOperand, index: usize, sew: usize) -> u32 {
+        match src {
+            VectorOperand::Vector(vs1) => self.read_element(vs1, index, sew),
+            VectorOperand::Scalar(value) => truncate(value, sew),
+        }
+    }
+}
+
+// Element-wise integer arithmetic at SEW. `dest` is used by multiply-add.
+fn element_op(op: VectorOp, src2: u32, src1: u32, dest: u32, sew: usize) -> u32 {
+    use crate::isa::opcode::VectorOp::*;
+    let bits = sew * 8;
+    let shamt = src1 & (bits as u32 - 1);
+    let (signed1, signed2) = (sign_extend(src1, sew) as i32, sign_extend(src2, sew) as i32);
+    let value = match op {
+        VADD => src2.wrapping_add(src1),
+        VSUB => src2.wrapping_sub(src1),
+        VRSUB => src1.wrapping_sub(src2),
+        VMINU => src2.min(src1),
+        VMIN => signed2.min(signed1) as u32,
+        VMAXU => src2.max(src1),
+        VMAX => signed2.max(signed1) as u32,
+        VAND => src2 & src1,
+        VOR => src2 | src1,
+        VXOR => src2 ^ src1,
+        VSLL => src2 << shamt,
+        VSRL => src2 >> shamt,
+        VSRA => (signed2 >> shamt) as u32,
+        VMERGE | VMV => src1,
+        VMUL => src2.wrapping_mul(src1),
+        VMULH => ((i64::from(signed2) * i64::from(signed1)) >> bits) as u32,
+        VMULHU => ((u64::from(src2) * u64::from(src1)) >> bits) as u32,
+        VMULHSU => ((i64::from(signed2) * i64::from(src1)) >> bits) as u32,
+        // Division by zero and overflow do not raise exceptions.
+        VDIVU => src2.checked_div(src1).unwrap_or(0xffff_ffff),
+        VDIV => match signed1 {
+            0 => 0xffff_ffff,
+            _ => (i64::from(signed2) / i64::from(signed1)) as u32,
+        },
+        VREMU => src2.checked_rem(src1).unwrap_or(src2),
+        VREM => match signed1 {
+            0 => src2,
+            _ => (i64::from(signed2) % i64::from(signed1)) as u32,
+        },
+        VMACC => src1.wrapping_mul(src2).wrapping_add(dest),
+        VNMSAC => dest.wrapping_sub(src1.wrapping_mul(src2)),
+        VMADD => src1.wrapping_mul(dest).wrapping_add(src2),
+        VNMSUB => src2.wrapping_sub(src1.wrapping_mul(dest)),
+        _ => unreachable!(),
+    };
+    truncate(value, sew)
+}
+
+// Returns decoded vtype or an error if vill is set.
+fn current_vtype(csr: &Csr) -> Result<VectorType> {
+    let vtype = csr.read_u32(csr_map::vtype);
+    VectorType::from_u32(vtype).ok_or(VpuError::IllegalVtype { vtype })
+}
+
+// Returns the range of body elements, i.e., (vstart, vl).
+// `vl` is clamped to VLMAX in case a debugger writes a larger value.
+fn body(csr: &Csr) -> (usize, usize) {
+    let vlmax = VectorType::from_u32(csr.read_u32(csr_map::vtype)).map_or(0, VectorType::vlmax);
+    (
+        csr.read_u32(csr_map::vstart) as usize,
+        (csr.read_u32(csr_map::vl) as usize).min(vlmax),
+    )
+}
+
+// Register number must be a multiple of the number of registers in a group,
+// which is a power of two.
+fn check_group(reg: u32, group: u32) -> Result<()> {
+    if reg & (group - 1) != 0 {
+        return Err(VpuError::MisalignedRegisterGroup { reg });
+    }
+    Ok(())
+}
+
+fn read_memory(data_mem: &dyn MemoryAccess, addr: u32, size: usize) -> Result<u32> {
+    let result = match size {
+        1 => data_mem.read_u8(addr as usize).map(u32::from),
+        2 => data_mem.read_u16(addr as usize).map(u32::from),
+        _ => data_mem.read_u32(addr as usize),
+    };
+    result.map_err(|_| VpuError::MemoryAccessError { addr })
+}
+
+fn write_memory(data_mem: &mut dyn MemoryAccess, addr: u32, size: usize, value: u32) -> Result<()> {
+    let result = match size {
+        1 => data_mem.write_u8(addr as usize, value as u8),
+        2 => data_mem.write_u16(addr as usize, value as u16),
+        _ => data_mem.write_u32(addr as usize, value),
+    };
+    result.map_err(|_| VpuError::MemoryAccessError { addr })
+}
+
+// helper to truncate a value to SEW
+fn truncate(value: u32, sew: usize) -> u32 {
+    match sew {
+        1 => value & 0xff,
+        2 => value & 0xffff,
+        _ => value,
+    }
+}
+
+// helper to sign extend a value of SEW to 32-bit
+fn sign_extend(value: u32, sew: usize) -> u32 {
+    match sew {
+        1 => i32::from(value as i8) as u32,
+        2 => i32::from(value as i16) as u32,
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_vtype() {
+        // e32, m1
+        assert_eq!(
+            Some(VectorType { sew: 4, lmul8: 8 }),
+            VectorType::from_u32(0b0001_0000)
+        );
+        // e32, mf8 is reserved because SEW > LMUL * ELEN.
+        assert_eq!(None, VectorType::from_u32(0b0001_0101));
+        // e64 is not supported in Zve32x.
+        assert_eq!(None, VectorType::from_u32(0b0001_1000));
+    }
+
+    #[test]
+    fn vlmax() {
+        let e8m8 = VectorType::from_u32(0b0000_0011).unwrap();
+        assert_eq!(128, e8m8.vlmax());
+        let e8mf2 = VectorType::from_u32(0b0000_0111).unwrap();
+        assert_eq!(8, e8mf2.vlmax());
+    }
+
+    #[test]
+    fn element_access_across_group() {
+        let mut vpu = Vpu::new();
+        // 5th element of e32 is in the next register.
+        vpu.write_element(2, 4, 4, 0x1234_5678);
+        assert_eq!(0x1234_5678, vpu.read_element(3, 0, 4));
+        assert_eq!(0x78, vpu.read_element(3, 0, 1));
+    }
+
+    #[test]
+    fn element_op_at_sew() {
+        assert_eq!(0x00, element_op(VectorOp::VADD, 0xff, 0x01, 0, 1));
+        assert_eq!(0xff, element_op(VectorOp::VSRA, 0x80, 0x07, 0, 1));
+        assert_eq!(0xff, element_op(VectorOp::VMULH, 0x80, 0x01, 0, 1));
+        // overflow: -128 / -1
+        assert_eq!(0x80, element_op(VectorOp::VDIV, 0x80, 0xff, 0, 1));
+    }
+}
@@ -1,3 +0,0 @@
-pub mod eflags;
-pub mod modrm;
-pub mod opcode;
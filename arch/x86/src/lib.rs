@@ -1,68 +1,54 @@
-#[macro_use]
-extern crate enum_primitive;
-#[macro_use]
-extern crate bitflags;
-
-mod decoder;
-mod executor;
-mod fetcher;
-mod gpr;
-mod isa;
-mod status_regs;
-
-use crate::decoder::ExecuteInst;
-use crate::executor::WriteBackType;
-use crate::fetcher::FetchedInst;
-use crate::gpr::{RegisterFile, SegmentRegister};
-use crate::isa::eflags::EFlags;
-use crate::status_regs::CpuState;
+//! x86 CPU which starts in real mode.
+//! Instructions are executed by the x86 core of the x86_64 crate,
+//! whose decoder follows the mode of the CPU and the operand and address size prefixes.
+//! So a program can switch to protected mode and long mode by itself.
+
 use cpu::model::{CpuModel, Pipeline};
 use debug::DebugMode;
-use peripherals::{interconnect::Interconnect, memory_access::MemoryAccess};
+use peripherals::interconnect::Interconnect;
 use std::result;
+use x86_64::{InternalException, X86_64};
 
-pub type Result<T> = result::Result<T, CompatibleException>;
+pub type Result<T> = result::Result<T, InternalException>;
 
-/// x86 32-bit mode.
+/// x86 CPU in the state after reset, which executes 16-bit code from address 0.
 /// Note that this does not cover all of x86 instructions.
 /// But will cover enough instructions to boot BlosOS.
-///
-/// ip: instruction pointer.
-/// mmio: memory mapped io.
-/// rf: general purpose register.
-/// state: CPU status either Run or Halted.
 pub struct X86 {
-    ip: u64,
-    mmio: Interconnect,
-    rf: RegisterFile,
-    segment: SegmentRegister,
-    eflags: EFlags,
-    state: CpuState,
+    core: X86_64,
 }
 
 impl X86 {
     /// Creates instance just after booting bios.
     /// IP starts with 0x7c00.
     pub fn boot_bios(&mut self) {
-        use self::gpr::Reg32::*;
-        self.rf.write_u64(Eax, 0xaa55u64);
-        self.rf.write_u64(Esp, 0x6f2cu64);
-        self.ip = 0x7c00u64;
+        self.core.boot_bios();
+    }
+
+    /// Starts a Multiboot kernel at `entry` in the state a Multiboot loader leaves.
+    /// The CPU is in 32-bit protected mode with flat segments, and interrupts are disabled.
+    /// EAX holds `magic`, and EBX holds the address of the boot information.
+    pub fn boot_multiboot(&mut self, entry: u64, magic: u32, info: u64) {
+        self.core.boot_multiboot(entry, magic, info);
+    }
+
+    /// The core which holds the registers and the interconnect.
+    pub fn core(&self) -> &X86_64 {
+        &self.core
+    }
+
+    pub fn core_mut(&mut self) -> &mut X86_64 {
+        &mut self.core
     }
 }
 
 impl CpuModel for X86 {
-    type Error = CompatibleException;
-
-    fn new(mmio: Interconnect, _debug: DebugMode) -> X86 {
-        X86 {
-            ip: 0,
-            mmio,
-            rf: RegisterFile::new(),
-            segment: SegmentRegister::new(),
-            eflags: EFlags::empty(),
-            state: CpuState::Running,
-        }
+    type Error = InternalException;
+
+    fn new(mmio: Interconnect, debug: DebugMode) -> X86 {
+        let mut core = X86_64::new(mmio, debug);
+        core.reset_to_real_mode();
+        X86 { core }
     }
 
     fn init(&mut self) {
@@ -70,81 +56,43 @@ impl CpuModel for X86 {
     }
 
     fn run(&mut self) -> Result<()> {
-        while self.state == CpuState::Running {
-            let inst_candidate = self.mmio.fetch_inst_candidate(self.ip);
-            self.execute_an_instruction(&inst_candidate)?;
-        }
-        Ok(())
+        self.core.run()
     }
 }
 
 impl Pipeline for X86 {
-    type Error = CompatibleException;
-    type Fetched = FetchedInst;
-    type Decoded = ExecuteInst;
-    type Executed = WriteBackType;
-
-    fn execute_an_instruction(&mut self, program: &[u8]) -> Result<()> {
-        let fetched_inst = fetcher::fetch(&program)?;
-        self.ip = fetched_inst.increment_ip(self.ip);
-        let decoded_inst = decoder::decode(&fetched_inst, &self.rf)?;
-        let write_back_packet = executor::execute(&decoded_inst)?;
-
-        self.write_back(&write_back_packet)
-    }
+    type Error = InternalException;
+    type Fetched = <X86_64 as Pipeline>::Fetched;
+    type Decoded = <X86_64 as Pipeline>::Decoded;
+    type Executed = <X86_64 as Pipeline>::Executed;
 
     fn fetch(&self, program: &[u8]) -> Result<Self::Fetched> {
-        fetcher::fetch(program)
+        self.core.fetch(program)
     }
 
     fn decode(&self, inst: &Self::Fetched) -> Result<Self::Decoded> {
-        decoder::decode(&inst, &self.rf)
+        self.core.decode(inst)
     }
 
     fn execute(&self, inst: &Self::Decoded) -> Result<Self::Executed> {
-        executor::execute(&inst)
+        self.core.execute(inst)
     }
 
     fn write_back(&mut self, inst: &Self::Executed) -> Result<()> {
-        match inst {
-            WriteBackType::Gpr(inst) => {
-                println!("{:?} <= {}", inst.index, inst.value);
-                self.rf.write_u64(inst.index, inst.value);
-            }
-            WriteBackType::Store(inst) => {
-                self.mmio
-                    .write_u64(inst.index, inst.value)
-                    .map_err(|_| CompatibleException("Invalid memory operation.".to_string()))?;
-            }
-            WriteBackType::Segment(inst) => {
-                println!("{:?} <= {}", inst.index, inst.value);
-                self.segment.write_u64(inst.index, inst.value);
-            }
-            WriteBackType::EFlags(inst) => {
-                self.eflags.set(inst.target, inst.value);
-            }
-            WriteBackType::Status(inst) => {
-                self.state = inst.state;
-            }
-        }
-        Ok(())
+        self.core.write_back(inst)
     }
 }
 
-/// Represents errors of the emulator implementation.
-/// Not CPU exception such as interrupts.
-#[derive(Debug)]
-pub struct CompatibleException(String);
-
 #[cfg(test)]
 mod test {
-    use self::gpr::Reg32::*;
-    use self::gpr::SegReg::*;
     use super::*;
     use cpu::model::cpu_factory;
-    use peripherals::interconnect::Interconnect;
     use peripherals::error::MemoryAccessError;
+    use peripherals::memory_access::MemoryAccess;
     use peripherals::uart16550::{self, Target};
+    use x86_64::RFlags;
+    use x86_64::Reg64Id::*;
+    use x86_64::SegmentRegister::*;
 
     struct FakeDisplay();
     impl MemoryAccess for FakeDisplay {
@@ -169,13 +117,13 @@ mod test {
         x86
     }
 
-    fn execute_program_after(program: Vec<u8>, initializer: fn(&mut X86)) -> X86 {
+    fn execute_program_after(program: Vec<u8>, initializer: fn(&mut X86_64)) -> X86 {
         let display: Box<dyn MemoryAccess> = Box::new(FakeDisplay());
         let serial = uart16550::uart_factory(Target::Buffer);
         let mut mmio = Interconnect::new(serial, display);
         mmio.init_memory(&program, 0);
         let mut x86 = X86::new(mmio, DebugMode::Disabled);
-        initializer(&mut x86);
+        initializer(x86.core_mut());
         let result = x86.run();
 
         assert!(result.is_ok(), "{:?}", result.err());
@@ -193,14 +141,39 @@ mod test {
         let mut x86: X86 = cpu_factory(mmio, DebugMode::Disabled);
         x86.boot_bios();
 
-        assert_eq!(x86.rf.read_u64(Eax), 0xaa55u64);
-        assert_eq!(x86.rf.read_u64(Esp), 0x6f2cu64);
-        assert_eq!(x86.ip, 0x7c00u64);
+        assert_eq!(x86.core().read_register(Rax), 0xaa55u64);
+        assert_eq!(x86.core().read_register(Rsp), 0x6f2cu64);
+        assert_eq!(x86.core().rip(), 0x7c00u64);
 
         let result = x86.run();
         assert!(result.is_ok(), "{:?}", result.err());
-        assert_eq!(x86.ip, 0x7c01u64);
-        assert_eq!(x86.state, CpuState::Halted);
+        assert_eq!(x86.core().rip(), 0x7c01u64);
+        assert!(x86.core().is_halted());
+    }
+
+    #[test]
+    fn boot_multiboot() {
+        let program = vec![
+            0xb9, 0x78, 0x56, 0x34, 0x12, // mov    ecx,0x12345678
+            0x89, 0x03, // mov    DWORD PTR [ebx],eax
+            0x66, 0xba, 0xf8, 0x03, // mov    dx,0x3f8
+            0xf4,
+        ];
+        let display: Box<dyn MemoryAccess> = Box::new(FakeDisplay());
+        let serial = uart16550::uart_factory(Target::Buffer);
+        let mut mmio = Interconnect::new(serial, display);
+        mmio.init_memory(&program, 0x1000);
+
+        let mut x86: X86 = cpu_factory(mmio, DebugMode::Disabled);
+        x86.boot_multiboot(0x1000, 0x2bad_b002, 0x9000);
+        let result = x86.run();
+        assert!(result.is_ok(), "{:?}", result.err());
+
+        let core = x86.core();
+        assert_eq!(core.read_register(Rcx), 0x1234_5678);
+        assert_eq!(core.read_register(Rdx), 0x3f8);
+        assert_eq!(core.interconnect().read_u32(0x9000).unwrap(), 0x2bad_b002);
+        assert_eq!(core.rip(), 0x100c);
     }
 
     #[test]
@@ -208,7 +181,7 @@ mod test {
         let program = vec![0xf4]; // hlt
         let x86 = execute_program(program, 0);
 
-        assert_eq!(x86.state, CpuState::Halted);
+        assert!(x86.core().is_halted());
     }
 
     #[test]
@@ -219,7 +192,7 @@ mod test {
         ];
         let x86 = execute_program(program, 0);
 
-        assert_eq!(x86.rf.read_u64(Eax), 0);
+        assert_eq!(x86.core().read_register(Rax), 0);
     }
 
     #[test]
@@ -232,15 +205,17 @@ mod test {
             0x8e, 0xe8, // mov    gs,ax
             0xf4,
         ];
-        let x86 = execute_program_after(program, |cpu: &mut X86| {
-            cpu.rf.write_u64(Eax, 0xaa55u64);
+        let x86 = execute_program_after(program, |cpu: &mut X86_64| {
+            cpu.write_register(Rax, 0xaa55u64);
         });
 
-        assert_eq!(x86.segment.read_u64(Ds), x86.rf.read_u64(Eax));
-        assert_eq!(x86.segment.read_u64(Es), x86.rf.read_u64(Eax));
-        assert_eq!(x86.segment.read_u64(Ss), x86.rf.read_u64(Eax));
-        assert_eq!(x86.segment.read_u64(Fs), x86.rf.read_u64(Eax));
-        assert_eq!(x86.segment.read_u64(Gs), x86.rf.read_u64(Eax));
+        let core = x86.core();
+        let ax = core.read_register(Rax);
+        assert_eq!(u64::from(core.selector(Ds)), ax);
+        assert_eq!(u64::from(core.selector(Es)), ax);
+        assert_eq!(u64::from(core.selector(Ss)), ax);
+        assert_eq!(u64::from(core.selector(Fs)), ax);
+        assert_eq!(u64::from(core.selector(Gs)), ax);
     }
 
     #[test]
@@ -251,7 +226,7 @@ mod test {
         ];
         let x86 = execute_program(program, 0);
 
-        assert_eq!(x86.rf.read_u64(Esp), 0x7c00u64);
+        assert_eq!(x86.core().read_register(Rsp), 0x7c00u64);
     }
 
     #[test]
@@ -260,11 +235,11 @@ mod test {
             0xfc, // cld
             0xf4,
         ];
-        let x86 = execute_program_after(program, |cpu: &mut X86| {
-            cpu.eflags.set(EFlags::DIRECTION_FLAG, true);
+        let x86 = execute_program_after(program, |cpu: &mut X86_64| {
+            cpu.write_rflags(RFlags::DIRECTION_FLAG);
         });
 
-        assert!((x86.eflags & EFlags::DIRECTION_FLAG).is_empty());
+        assert!(!x86.core().rflags().contains(RFlags::DIRECTION_FLAG));
     }
 
     #[test]
@@ -275,7 +250,91 @@ mod test {
         ];
         let x86 = execute_program(program, 0);
 
-        assert_eq!(x86.rf.read_u64(Esi), 0x00007d16);
+        assert_eq!(x86.core().read_register(Rsi), 0x00007d16);
+    }
+
+    #[test]
+    fn port_io() {
+        let program = vec![
+            0xba, 0xf8, 0x03, // mov    dx,0x3f8
+            0xb8, 0x6f, 0x00, // mov    ax,0x6f
+            0xee, // out    dx,al
+            0xf3, 0x6e, // rep outs dx,BYTE PTR ds:[si]
+            0xba, 0xfd, 0x03, // mov    dx,0x3fd
+            0xec, // in     al,dx
+            0xba, 0xf8, 0x03, // mov    dx,0x3f8
+            0xb9, 0x03, 0x00, // mov    cx,0x3
+            0xf3, 0x6c, // rep ins BYTE PTR es:[di],dx
+            0xf4,
+        ];
+        let initializer = |cpu: &mut X86_64| {
+            let mmio = cpu.interconnect_mut();
+            mmio.write_u8(0x100, b'k').unwrap();
+            mmio.write_u8(0x101, b'!').unwrap();
+            cpu.write_register(Rsi, 0x100);
+            cpu.write_register(Rdi, 0x200);
+            cpu.write_register(Rcx, 2);
+        };
+        let x86 = execute_program_after(program, initializer);
+        let core = x86.core();
+        // The line status shows received data and the empty transmitter.
+        assert_eq!(core.read_register(Rax), 0x61);
+        assert_eq!(core.interconnect().read_u8(0x200).unwrap(), b'o');
+        assert_eq!(core.interconnect().read_u8(0x201).unwrap(), b'k');
+        assert_eq!(core.interconnect().read_u8(0x202).unwrap(), b'!');
+        assert_eq!(core.read_register(Rsi), 0x102);
+        assert_eq!(core.read_register(Rdi), 0x203);
+        assert_eq!(core.read_register(Rcx), 0);
+    }
+
+    #[test]
+    fn port_io_operand_size() {
+        let program = vec![
+            0x66, 0xe5, 0x80, // in     eax,0x80
+            0xf4,
+        ];
+        let x86 = execute_program_after(program, |cpu: &mut X86_64| {
+            cpu.write_register(Rax, 0);
+        });
+        // Unmapped ports read all ones.
+        assert_eq!(x86.core().read_register(Rax), 0xffff_ffff);
+    }
+
+    #[test]
+    fn timer_interrupt() {
+        let program = vec![
+            0xb8, 0x11, 0x00, 0xe6, 0x20, // mov ax,0x11; out 0x20,al
+            0xb8, 0x40, 0x00, 0xe6, 0x21, // mov ax,0x40; out 0x21,al
+            0xb8, 0x04, 0x00, 0xe6, 0x21, // mov ax,0x4; out 0x21,al
+            0xb8, 0x01, 0x00, 0xe6, 0x21, // mov ax,0x1; out 0x21,al
+            0xb8, 0xfe, 0x00, 0xe6, 0x21, // mov ax,0xfe; out 0x21,al
+            0xb8, 0x34, 0x00, 0xe6, 0x43, // mov ax,0x34; out 0x43,al
+            0xb8, 0x00, 0x00, 0xe6, 0x40, // mov ax,0x0; out 0x40,al
+            0xb8, 0x01, 0x00, 0xe6, 0x40, // mov ax,0x1; out 0x40,al
+            0xfb, // sti
+            0xf4, // hlt
+            0xfa, // cli
+            0xf4, // hlt
+            // 0x2c: IRQ0 handler.
+            0xbb, 0x01, 0x00, // mov bx,0x1
+            0xb8, 0x20, 0x00, 0xe6, 0x20, // mov ax,0x20; out 0x20,al
+            0xcf, // iret
+        ];
+        let initializer = |cpu: &mut X86_64| {
+            cpu.write_register(Rsp, 0x8000);
+            // Vector 0x40 is at 0000:002c.
+            cpu.interconnect_mut().write_u32(0x100, 0x2c).unwrap();
+        };
+        let x86 = execute_program_after(program, initializer);
+        let core = x86.core();
+        assert_eq!(core.read_register(Rbx), 1);
+        assert_eq!(core.read_register(Rsp), 0x8000);
+        assert_eq!(core.rip(), 0x2c);
+        assert_eq!(core.selector(Cs), 0);
+        assert!(!core.rflags().contains(RFlags::INTERRUPT_FLAG));
+        // FLAGS, CS and IP were pushed.
+        assert_eq!(core.interconnect().read_u16(0x7ffa).unwrap(), 0x2a);
+        assert_eq!(core.interconnect().read_u16(0x7ffe).unwrap(), 0x202);
     }
 
     #[test]
@@ -285,12 +344,12 @@ mod test {
             //0x67, 0x8b, 0x08,  // addr32 mov ecx, [eax]
             0xf4,
         ];
-        let initializer = |cpu: &mut X86| {
-            cpu.rf.write_u64(Eax, 100);
-            cpu.rf.write_u64(Ebx, 1);
+        let initializer = |cpu: &mut X86_64| {
+            cpu.write_register(Rax, 100);
+            cpu.write_register(Rbx, 1);
         };
         let x86_64 = execute_program_after(program, initializer);
-        assert_eq!(x86_64.mmio.read_u64(100).unwrap(), 1);
+        assert_eq!(x86_64.core().interconnect().read_u64(100).unwrap(), 1);
         //assert_eq!(x86_64.rf.read_u64(Ecx), 1);
     }
 }
@@ -1,5 +0,0 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum CpuState {
-    Halted,
-    Running,
-}
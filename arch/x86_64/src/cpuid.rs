@@ -0,0 +1,188 @@
+//! Processor identification returned by CPUID.
+//! The default reports exactly the features which the emulator supports.
+//! Embedders may hide features or override whole leaves.
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+const VENDOR: &[u8; 12] = b"RustEmu86x64";
+const BRAND: &str = "Rustemu86 x86_64 processor";
+const EXTENDED_LEAF_BASE: u32 = 0x8000_0000;
+const BRAND_LEAVES: RangeInclusive<u32> = 0x8000_0002..=0x8000_0004;
+// Family 6, model 0 and stepping 0.
+const SIGNATURE: u32 = 0x0000_0600;
+// CLFLUSH line size in 8 bytes and one logical processor.
+const LEAF1_EBX: u32 = 8 << 8 | 1 << 16;
+
+bitflags! {
+    /// Feature flags of leaf 1. EDX is the lower half, and ECX is the upper half.
+    pub struct Features: u64 {
+        const TSC = 1 << 4;
+        const MSR = 1 << 5;
+        const PAE = 1 << 6;
+        const CX8 = 1 << 8;
+        const APIC = 1 << 9;
+        const PGE = 1 << 13;
+        const CMOV = 1 << 15;
+        const SSE = 1 << 25;
+        const SSE2 = 1 << 26;
+        const CX16 = 1 << (32 + 13);
+        const X2APIC = 1 << (32 + 21);
+        const TSC_DEADLINE = 1 << (32 + 24);
+    }
+}
+
+bitflags! {
+    /// Feature flags of leaf 0x8000_0001 in the same layout as `Features`.
+    pub struct ExtendedFeatures: u64 {
+        const SYSCALL = 1 << 11;
+        const NX = 1 << 20;
+        const PAGE_1GB = 1 << 26;
+        const LONG_MODE = 1 << 29;
+    }
+}
+
+pub struct Cpuid {
+    vendor: [u8; 12],
+    brand: [u8; 48],
+    features: Features,
+    extended_features: ExtendedFeatures,
+    // Leaves replaced as a whole, keyed by the leaf and the subleaf.
+    leaves: HashMap<(u32, u32), [u32; 4]>,
+}
+
+impl Cpuid {
+    pub fn new() -> Cpuid {
+        let mut cpuid = Cpuid {
+            vendor: *VENDOR,
+            brand: [0; 48],
+            features: Features::all(),
+            extended_features: ExtendedFeatures::all(),
+            leaves: HashMap::new(),
+        };
+        cpuid.set_brand(BRAND);
+        cpuid
+    }
+
+    pub fn set_vendor(&mut self, vendor: &[u8; 12]) {
+        self.vendor = *vendor;
+    }
+
+    /// The brand string is truncated to 47 bytes and terminated by NUL.
+    pub fn set_brand(&mut self, brand: &str) {
+        let bytes = brand.as_bytes();
+        let len = bytes.len().min(47);
+        self.brand = [0; 48];
+        self.brand[..len].copy_from_slice(&bytes[..len]);
+    }
+
+    /// Features reported even if the emulator lacks them are the caller's responsibility.
+    pub fn set_features(&mut self, features: Features, extended_features: ExtendedFeatures) {
+        self.features = features;
+        self.extended_features = extended_features;
+    }
+
+    /// Replaces EAX, EBX, ECX and EDX of the leaf and subleaf.
+    /// The maximum leaves in leaves 0 and 0x8000_0000 are extended to include it.
+    pub fn set_leaf(&mut self, leaf: u32, subleaf: u32, registers: [u32; 4]) {
+        self.leaves.insert((leaf, subleaf), registers);
+    }
+
+    /// Returns EAX, EBX, ECX and EDX for the leaf. Unknown leaves return zeros.
+    pub fn query(&self, leaf: u32, subleaf: u32) -> [u32; 4] {
+        if let Some(registers) = self.leaves.get(&(leaf, subleaf)) {
+            return *registers;
+        }
+        let vendor = |i: usize| {
+            u32::from_le_bytes([
+                self.vendor[i],
+                self.vendor[i + 1],
+                self.vendor[i + 2],
+                self.vendor[i + 3],
+            ])
+        };
+        match leaf {
+            // Maximum leaf and vendor.
+            0 => [self.max_leaf(0, 1), vendor(0), vendor(8), vendor(4)],
+            1 => {
+                let features = self.features.bits();
+                [
+                    SIGNATURE,
+                    LEAF1_EBX,
+                    (features >> 32) as u32,
+                    features as u32,
+                ]
+            }
+            EXTENDED_LEAF_BASE => [
+                self.max_leaf(EXTENDED_LEAF_BASE, *BRAND_LEAVES.end()),
+                0,
+                0,
+                0,
+            ],
+            0x8000_0001 => {
+                let features = self.extended_features.bits();
+                [0, 0, (features >> 32) as u32, features as u32]
+            }
+            leaf if BRAND_LEAVES.contains(&leaf) => {
+                let offset = ((leaf - BRAND_LEAVES.start()) * 16) as usize;
+                let mut registers = [0; 4];
+                for (i, register) in registers.iter_mut().enumerate() {
+                    let bytes = &self.brand[offset + i * 4..offset + i * 4 + 4];
+                    *register = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                }
+                registers
+            }
+            _ => [0; 4],
+        }
+    }
+
+    // The largest leaf in the same range as `base`, including overridden ones.
+    fn max_leaf(&self, base: u32, default: u32) -> u32 {
+        self.leaves
+            .keys()
+            .map(|(leaf, _)| *leaf)
+            .filter(|leaf| (leaf & EXTENDED_LEAF_BASE) == base && *leaf != base)
+            .fold(default, u32::max)
+    }
+}
+
+impl Default for Cpuid {
+    fn default() -> Cpuid {
+        Cpuid::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn string_of(registers: &[u32]) -> Vec<u8> {
+        registers
+            .iter()
+            .flat_map(|r| r.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn configure_leaves() {
+        let mut cpuid = Cpuid::new();
+        assert_eq!(cpuid.query(0x8000_0000, 0)[0], 0x8000_0004);
+        let brand: Vec<u8> = (0x8000_0002..=0x8000_0004)
+            .flat_map(|leaf| string_of(&cpuid.query(leaf, 0)))
+            .collect();
+        assert!(brand.starts_with(BRAND.as_bytes()));
+        assert_eq!(cpuid.query(0x8000_0001, 0)[3] & 1 << 29, 1 << 29);
+
+        cpuid.set_vendor(b"GenuineIntel");
+        cpuid.set_features(
+            Features::TSC | Features::X2APIC,
+            ExtendedFeatures::LONG_MODE,
+        );
+        cpuid.set_leaf(7, 0, [0, 1, 2, 3]);
+        let leaf0 = cpuid.query(0, 0);
+        assert_eq!(leaf0[0], 7);
+        assert_eq!(string_of(&[leaf0[1], leaf0[3], leaf0[2]]), b"GenuineIntel");
+        assert_eq!(cpuid.query(1, 0)[2..], [1 << 21, 1 << 4]);
+        assert_eq!(cpuid.query(7, 0), [0, 1, 2, 3]);
+        assert_eq!(cpuid.query(7, 1), [0; 4]);
+    }
+}
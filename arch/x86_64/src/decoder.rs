@@ -1,8 +1,15 @@
 use crate::exceptions::InternalException;
 use crate::fetcher::FetchedInst;
-use crate::isa::opcode::OperandSize;
-use crate::isa::registers::Reg64Id;
+use crate::interrupt;
+use crate::isa::condition::Condition;
+use crate::isa::modrm::{ModRm, ModRmModeField};
+use crate::isa::opcode::{Opcode, OperandSize};
+use crate::isa::prefix::LegacyPrefix;
+use crate::isa::registers::{Reg64Id, SegmentRegister};
+use crate::isa::rflags::RFlags;
+use crate::paging::{LinearMemory, Mmu};
 use crate::register_file::RegisterFile;
+use crate::segmentation::CodeSize;
 use crate::Result;
 use num::FromPrimitive;
 
@@ -13,6 +20,21 @@ pub enum ExecuteInstType {
     Branch(ExecuteInst),
     LoadStore(ExecuteInst),
     Privilege(ExecuteInst),
+    Vector(VectorInst),
+}
+
+impl ExecuteInstType {
+    /// Replaces the operands read from temporary registers with their values.
+    pub fn bind_temporaries(&self, temps: &[u64]) -> ExecuteInstType {
+        use self::ExecuteInstType::*;
+        match self {
+            ArithLogic(inst) => ArithLogic(inst.bind_temporaries(temps)),
+            Branch(inst) => Branch(inst.bind_temporaries(temps)),
+            LoadStore(inst) => LoadStore(inst.bind_temporaries(temps)),
+            Privilege(inst) => Privilege(inst.bind_temporaries(temps)),
+            Vector(inst) => Vector(inst.clone()),
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -25,6 +47,8 @@ pub struct ExecuteInst {
     op2: Option<u64>,
     op3: Option<u64>,
     op_size: Option<OperandSize>,
+    // Temporary registers from which op1, op2 and op3 are read in the execution stage.
+    temps: [Option<Reg64Id>; 3],
 }
 
 impl ExecuteInst {
@@ -43,42 +67,289 @@ impl ExecuteInst {
     pub fn get_op2(&self) -> u64 {
         self.op2.expect("Operand2 was not decoded.")
     }
-    #[allow(dead_code)]
     pub fn get_op3(&self) -> u64 {
         self.op3.expect("Operand3 was not decoded.")
     }
     pub fn get_op_size(&self) -> OperandSize {
         self.op_size.expect("Operand size was not decoded.")
     }
+
+    fn bind_temporaries(&self, temps: &[u64]) -> ExecuteInst {
+        let read = |temp: Option<Reg64Id>, op: Option<u64>| match temp {
+            Some(temp) => Some(temps[temp.temporary_index().expect("Not a temporary register.")]),
+            None => op,
+        };
+        ExecuteInst {
+            op1: read(self.temps[0], self.op1),
+            op2: read(self.temps[1], self.op2),
+            op3: read(self.temps[2], self.op3),
+            ..self.clone()
+        }
+    }
+}
+
+/// Uop of SSE instructions with 128-bit operands.
+#[derive(Clone)]
+pub struct VectorInst {
+    opcode: ExOpcode,
+    // XMM register, or general register for results in a general register.
+    dest: u8,
+    op1: u128,
+    op2: u128,
+    op_size: OperandSize,
+}
+
+impl VectorInst {
+    pub fn get_opcode(&self) -> ExOpcode {
+        self.opcode
+    }
+    pub fn get_dest(&self) -> u8 {
+        self.dest
+    }
+    pub fn get_op1(&self) -> u128 {
+        self.op1
+    }
+    pub fn get_op2(&self) -> u128 {
+        self.op2
+    }
+    pub fn get_op_size(&self) -> OperandSize {
+        self.op_size
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ExOpcode {
     Add,
+    Or,
+    Adc,
+    Sbb,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+    Test,
     Inc,
+    Dec,
+    Neg,
+    Not,
+    Imul,
+    // Multiplication and division on RDX:RAX, or AH:AL for byte.
+    Mul,
+    ImulWide,
+    Div,
+    Idiv,
+    Rol,
+    Ror,
+    Rcl,
+    Rcr,
+    Shl,
+    Shr,
+    Sar,
+    Shld,
+    Shrd,
+    Bt,
+    Bts,
+    Btr,
+    Btc,
+    Bsf,
+    Bsr,
     Mov,
+    // Sign-extends `op1` of the operand size to 64 bits.
+    SignExtend,
+    // Clears ZF unless `op1` equals `op2`, keeping the other flags,
+    // so that the uops test if all words are equal.
+    TestEqual,
+    // Writes `op2` to the destination if ZF is set. Otherwise, the old value in `op1` is
+    // written back to the destination and to the accumulator register in `op3`.
+    ExchangeIfEqual,
+    CondMove(Condition),
+    CondSet(Condition),
+    // Sets or clears the flags in `op1`.
+    SetFlags,
+    ClearFlags,
     Jump,
+    CondJump(Condition),
+    JumpIndirect,
+    // `op1` is the selector of the new CS, and `op2` is the offset.
+    JumpFar,
+    // `op1` is the linear address of the return address.
     Return,
     Load,
     Store,
+    // Reads the memory at the linear address in `op1` into the temporary register
+    // in the execution stage, so that the following uops can operate on it.
+    ReadMemory,
     Halt,
+    Cpuid,
+    Rdtsc,
+    Syscall,
+    // `op1` is RCX and `op2` is R11, which are the return address and RFLAGS.
+    SystemReturn,
+    // Exchanges the GS base and IA32_KERNEL_GS_BASE.
+    SwapGs,
+    // `op1` is the MSR, and `op2` is the value to be written.
+    ReadMsr,
+    WriteMsr,
+    // `op1` is the number of the control register.
+    ReadControlRegister,
+    WriteControlRegister,
+    InvalidatePage,
+    // `op1` is the base and `op2` is the limit of the table.
+    LoadGdt,
+    LoadIdt,
+    // `op1` is the selector of the TSS.
+    LoadTaskRegister,
+    // `op1` is the segment register, and `op2` is the selector.
+    LoadSegment,
+    // `op1` is the vector.
+    SoftwareInterrupt,
+    // `op1` is RSP, and the operand size is the size of each popped value.
+    InterruptReturn,
+    // `op1` is the port. OUT writes `op2`, and INS stores to the address in `op2`.
+    In,
+    Out,
+    Ins,
+    // SSE uops. Mov writes `op1` to the XMM register, and Add, Sub, And, Or and Xor
+    // are applied to each element of the operand size.
+    AndNot,
+    CompareEqual,
+    // Gathers the most significant bit of each byte into the general register.
+    MoveMask,
+    // Scalar floating-point operations on the lowest single or double of the operand size.
+    // The other bits of the destination are kept from `op1`.
+    FloatAdd,
+    FloatSub,
+    FloatMul,
+    FloatDiv,
+    FloatMin,
+    FloatMax,
+    FloatSqrt,
+    // Sets ZF, PF and CF like an unsigned comparison. Unordered sets all of them.
+    FloatCompare,
+    // Converts the signed integer in `op2` into the lowest element.
+    IntToFloat,
+    // Converts the double in `op1` into the general register, rounded by the rounding
+    // control in `op2` or truncated.
+    FloatToInt,
+    FloatToIntTruncate,
+    // Converts `op2` between single and double. The operand size is of the result.
+    FloatConvert,
+    // `op1` is the new MXCSR.
+    LoadMxcsr,
 }
 
-pub fn decode(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+pub fn decode(
+    rf: &RegisterFile,
+    rflags: RFlags,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
     use crate::isa::opcode::Opcode::*;
+    // An instruction reads and writes its memory operands within a step, so it is atomic
+    // to other CPUs sharing the interconnect, and LOCK only has to be validated.
+    if inst.legacy_prefix.contains(LegacyPrefix::LOCK) && !inst.opcode.is_lockable(inst.mod_rm) {
+        return Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        });
+    }
     match inst.opcode {
         // Arithmetic and Logic instructions.
-        Add => Ok(decode_add(&rf, &inst)),
-        Inc => Ok(decode_inc(&rf, &inst)),
+        AddEbGb | AddEvGv | OrEbGb | OrEvGv | AdcEbGb | AdcEvGv | SbbEbGb | SbbEvGv | AndEbGb
+        | AndEvGv | SubEbGb | SubEvGv | XorEbGb | XorEvGv | CmpEbGb | CmpEvGv => {
+            decode_alu_mr(&rf, &inst)
+        }
+        AddGbEb | AddGvEv | OrGbEb | OrGvEv | AdcGbEb | AdcGvEv | SbbGbEb | SbbGvEv | AndGbEb
+        | AndGvEv | SubGbEb | SubGvEv | XorGbEb | XorGvEv | CmpGbEb | CmpGvEv => {
+            decode_alu_rm(&rf, &inst)
+        }
+        AddAlIb | AddRaxIz | OrAlIb | OrRaxIz | AdcAlIb | AdcRaxIz | SbbAlIb | SbbRaxIz
+        | AndAlIb | AndRaxIz | SubAlIb | SubRaxIz | XorAlIb | XorRaxIz | CmpAlIb | CmpRaxIz => {
+            Ok(decode_alu_rax_imm(&rf, &inst))
+        }
+        Group1EbIb | Group1EvIz | Group1EvIb => decode_group1(&rf, &inst),
+        TestEbGb | TestEvGv => decode_test_mr(&rf, &inst),
+        TestAlIb | TestRaxIz => Ok(decode_test_rax_imm(&rf, &inst)),
+        Group3Eb | Group3Ev => decode_group3(&rf, &inst),
+        Group4 => decode_inc_dec(&rf, &inst),
+        Group5 => decode_group5(&rf, &memory, &inst),
+        IncR | DecR => Ok(decode_inc_dec_r(&rf, &inst)),
+        ImulGvEv => decode_imul(&rf, &inst),
+        ImulGvEvIz | ImulGvEvIb => decode_imul_imm(&rf, &inst),
+        ConvertRax | ConvertRdx => Ok(decode_convert(&rf, &inst)),
+        Group2EbIb | Group2EvIb | Group2Eb1 | Group2Ev1 | Group2EbCl | Group2EvCl => {
+            decode_group2(&rf, &inst)
+        }
+        ShldEvGvIb | ShldEvGvCl | ShrdEvGvIb | ShrdEvGvCl => decode_double_shift(&rf, &inst),
+        BtEvGv | BtsEvGv | BtrEvGv | BtcEvGv => decode_bit_test(&rf, &inst),
+        Group8EvIb => decode_group8(&rf, &inst),
+        BsfGvEv | BsrGvEv => decode_bit_scan(&rf, &inst),
+        CmovccGvEv => decode_cmov(&rf, &inst),
+        SetccEb => decode_setcc(&rf, &inst),
+        Clc | Stc | Cld | Std | Cli | Sti => Ok(decode_flag_control(&inst)),
         // Branch instructions.
-        JmpRel8 => Ok(decode_jmp(&inst)),
+        JmpRel8 | JmpRel32 => Ok(decode_jmp(&inst)),
+        JccRel8 | JccRel32 => Ok(decode_jcc(&inst)),
+        LoopRel8 | LoopeRel8 | LoopneRel8 => Ok(decode_loop(&rf, &inst)),
+        JrcxzRel8 => Ok(decode_jrcxz(&rf, &inst)),
+        JmpFarAp => decode_jmp_far(&inst),
         // Mov instructions may be Arithmetic/Logic, Load, or Store.
-        MovToRm => decode_mov_mr(&rf, inst),
-        MovToReg => decode_mov_rm(&rf, inst),
-        MovImm => decode_mov_oi(&inst),
+        MovToRm8 | MovToRm => decode_mov_mr(&rf, inst),
+        MovToReg8 | MovToReg => decode_mov_rm(&rf, inst),
+        MovImm8 | MovImm => decode_mov_oi(&inst),
         MovRmImm8 | MovRmImm => decode_mov_mi(&rf, &inst),
+        Lea => decode_lea(&rf, &inst),
+        MovSwEw => decode_mov_to_segment(&rf, &memory, &inst),
+        MovEwSw => decode_mov_from_segment(&rf, &inst),
+        // String instructions.
+        MovsYbXb | MovsYvXv | CmpsXbYb | CmpsXvYv | StosYbAl | StosYvRax | LodsAlXb | LodsRaxXv
+        | ScasAlYb | ScasRaxYv | InsYbDx | InsYvDx | OutsDxXb | OutsDxXv => {
+            decode_string(&rf, rflags, &memory, &inst)
+        }
+        // Port I/O instructions.
+        InAlIb | InEaxIb | InAlDx | InEaxDx => Ok(decode_in(&rf, &inst)),
+        OutIbAl | OutIbEax | OutDxAl | OutDxEax => Ok(decode_out(&rf, &inst)),
+        MovzxGvEb | MovzxGvEw | MovsxGvEb | MovsxGvEw | Movsxd => decode_mov_extend(&rf, &inst),
+        // Atomic instructions.
+        XchgR => Ok(decode_xchg_r(&rf, &inst)),
+        XchgEbGb | XchgEvGv => decode_xchg(&rf, &inst),
+        CmpxchgEbGb | CmpxchgEvGv => decode_cmpxchg(&rf, &inst),
+        XaddEbGb | XaddEvGv => decode_xadd(&rf, &inst),
+        Group9 => decode_group9(&rf, &inst),
+        // SSE instructions.
+        MovupsVW | MovupsWV | MovupdVW | MovupdWV | MovsdVW | MovsdWV | MovssVW | MovssWV
+        | MovapsVW | MovapsWV | MovapdVW | MovapdWV | MovdqaVW | MovdqaWV | MovdquVW | MovdquWV
+        | MovdVE | MovdEV | MovqVW | MovqWV => decode_sse_move(&rf, &memory, &inst),
+        AndpsVW | AndpdVW | AndnpsVW | AndnpdVW | OrpsVW | OrpdVW | XorpsVW | XorpdVW | PandVW
+        | PandnVW | PorVW | PxorVW | PaddbVW | PaddwVW | PadddVW | PaddqVW | PsubbVW | PsubwVW
+        | PsubdVW | PsubqVW | PcmpeqbVW | PcmpeqwVW | PcmpeqdVW => {
+            decode_sse_packed(&rf, &memory, &inst)
+        }
+        PmovmskbGU => decode_pmovmskb(&rf, &inst),
+        AddsdVW | AddssVW | SubsdVW | SubssVW | MulsdVW | MulssVW | DivsdVW | DivssVW | MinsdVW
+        | MinssVW | MaxsdVW | MaxssVW | SqrtsdVW | SqrtssVW | UcomisdVW | UcomissVW | ComisdVW
+        | ComissVW | Cvtsd2ssVW | Cvtss2sdVW => decode_sse_scalar(&rf, &memory, &inst),
+        Cvtsi2sdVE | Cvtsi2ssVE | Cvttsd2siGW | Cvttss2siGW | Cvtsd2siGW | Cvtss2siGW => {
+            decode_sse_convert(&rf, &memory, &inst)
+        }
+        Group15 => decode_group15(&rf, &memory, &inst),
         // Priviledged instructions.
         Halt => Ok(decode_halt(&inst)),
+        Cpuid => Ok(decode_cpuid(&rf)),
+        Rdtsc => Ok(decode_system(ExOpcode::Rdtsc, &rf)),
+        Syscall => Ok(decode_system(ExOpcode::Syscall, &rf)),
+        Sysret => Ok(decode_sysret(&rf, &inst)),
+        Rdmsr | Wrmsr => Ok(decode_msr(&rf, &inst)),
+        MovRdCd | MovCdRd => decode_mov_control_register(&rf, &inst),
+        Group6 => decode_group6(&rf, &memory, &inst),
+        Group7 => decode_group7(&rf, &memory, &inst),
+        Int3 | IntIb => Ok(decode_software_interrupt(&inst)),
+        Iret => Ok(decode_iret(&rf, &inst)),
+        // INTO is invalid in 64-bit mode.
+        Into => Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        }),
+        // NOP r/m does not access memory.
+        NopEv => Ok(vec![]),
         // Complex instructions.
         CallRel32 => Ok(decode_call(&rf, &inst)),
         PushR => Ok(decode_pushr(&rf, &inst)),
@@ -91,37 +362,564 @@ pub fn decode(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstTy
 /////////////////////////////////////////////////////////////////////////////
 // Arithmetic and Logic instructions.
 /////////////////////////////////////////////////////////////////////////////
-fn decode_add(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
-    let modrm = inst.mod_rm.unwrap();
-    let dest = modrm.rm;
-    let src = modrm.reg;
-    let op1 = rf.read64(dest);
-    let op2 = rf.read64(src);
-    let uop1 = ExecuteInst {
-        opcode: ExOpcode::Add,
+// Operations of ADD/OR/ADC/SBB/AND/SUB/XOR/CMP are encoded in the same order
+// in bits 3..6 of the opcode and in ModRM.reg of the immediate group 1.
+const ALU_OPERATIONS: [ExOpcode; 8] = [
+    ExOpcode::Add,
+    ExOpcode::Or,
+    ExOpcode::Adc,
+    ExOpcode::Sbb,
+    ExOpcode::And,
+    ExOpcode::Sub,
+    ExOpcode::Xor,
+    ExOpcode::Cmp,
+];
+
+// ALU r/m, r
+fn decode_alu_mr(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let opcode = ALU_OPERATIONS[(inst.opcode as usize >> 3) & 0x7];
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let uop = alu_uop(
+        opcode,
+        rm.register(),
+        rm.read(&rf, &inst),
+        read_register(&rf, &inst, modrm.reg),
+        &inst,
+    );
+    Ok(rm_uops(
+        rm,
+        uop,
+        Some(Operand::Op1),
+        opcode != ExOpcode::Cmp,
+    ))
+}
+
+// ALU r, r/m
+fn decode_alu_rm(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let opcode = ALU_OPERATIONS[(inst.opcode as usize >> 3) & 0x7];
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let uop = alu_uop(
+        opcode,
+        modrm.reg,
+        read_register(&rf, &inst, modrm.reg),
+        rm.read(&rf, &inst),
+        &inst,
+    );
+    Ok(rm_uops(rm, uop, Some(Operand::Op2), false))
+}
+
+// ALU al/ax/eax/rax, imm
+fn decode_alu_rax_imm(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let opcode = ALU_OPERATIONS[(inst.opcode as usize >> 3) & 0x7];
+    let uop = alu_uop(
+        opcode,
+        Reg64Id::Rax,
+        read_register(&rf, &inst, Reg64Id::Rax),
+        inst.immediate,
+        &inst,
+    );
+    vec![ExecuteInstType::ArithLogic(uop)]
+}
+
+// ALU r/m, imm
+fn decode_group1(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let opcode = ALU_OPERATIONS[modrm.reg as usize];
+    let uop = alu_uop(
+        opcode,
+        rm.register(),
+        rm.read(&rf, &inst),
+        inst.immediate,
+        &inst,
+    );
+    Ok(rm_uops(
+        rm,
+        uop,
+        Some(Operand::Op1),
+        opcode != ExOpcode::Cmp,
+    ))
+}
+
+fn decode_test_mr(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let uop = alu_uop(
+        ExOpcode::Test,
+        rm.register(),
+        rm.read(&rf, &inst),
+        read_register(&rf, &inst, modrm.reg),
+        &inst,
+    );
+    Ok(rm_uops(rm, uop, Some(Operand::Op1), false))
+}
+
+fn decode_test_rax_imm(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let uop = alu_uop(
+        ExOpcode::Test,
+        Reg64Id::Rax,
+        read_register(&rf, &inst, Reg64Id::Rax),
+        inst.immediate,
+        &inst,
+    );
+    vec![ExecuteInstType::ArithLogic(uop)]
+}
+
+// TEST/NOT/NEG/MUL/IMUL/DIV/IDIV r/m
+fn decode_group3(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let (opcode, op2) = match modrm.reg as u8 {
+        0 | 1 => (ExOpcode::Test, inst.immediate),
+        2 => (ExOpcode::Not, 0),
+        3 => (ExOpcode::Neg, 0),
+        4 => return Ok(decode_mul_div(&rf, &inst, ExOpcode::Mul, rm)),
+        5 => return Ok(decode_mul_div(&rf, &inst, ExOpcode::ImulWide, rm)),
+        6 => return Ok(decode_mul_div(&rf, &inst, ExOpcode::Div, rm)),
+        _ => return Ok(decode_mul_div(&rf, &inst, ExOpcode::Idiv, rm)),
+    };
+    let uop = alu_uop(opcode, rm.register(), rm.read(&rf, &inst), op2, &inst);
+    Ok(rm_uops(
+        rm,
+        uop,
+        Some(Operand::Op1),
+        opcode != ExOpcode::Test,
+    ))
+}
+
+// INC/DEC r/m
+fn decode_inc_dec(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let opcode = match modrm.reg as u8 {
+        0 => ExOpcode::Inc,
+        1 => ExOpcode::Dec,
+        _ => {
+            return Err(InternalException::UndefinedInstruction {
+                opcode: inst.opcode,
+            })
+        }
+    };
+    let uop = alu_uop(opcode, rm.register(), rm.read(&rf, &inst), 1, &inst);
+    Ok(rm_uops(rm, uop, Some(Operand::Op1), true))
+}
+
+// INC/DEC/CALL/JMP r/m, and JMP m16:16/32/64.
+fn decode_group5(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    let modrm = inst.mod_rm.ok_or(InternalException::ModRmRequired {
+        opcode: inst.opcode,
+    })?;
+    if modrm.reg as u8 == 5 && modrm.mode != ModRmModeField::Direct {
+        return decode_jmp_far_indirect(&rf, &memory, &inst);
+    }
+    let (_, rm) = rm_operand(&rf, &inst)?;
+    match modrm.reg as u8 {
+        0 | 1 => decode_inc_dec(&rf, &inst),
+        2 => Ok(decode_jmp_rm(&rf, &inst, rm, true)),
+        4 => Ok(decode_jmp_rm(&rf, &inst, rm, false)),
+        _ => Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        }),
+    }
+}
+
+// INC/DEC r, which are only encoded outside 64-bit mode.
+fn decode_inc_dec_r(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let reg = Reg64Id::from_u8(inst.r).expect("Invalid register number.");
+    let opcode = if inst.opcode == Opcode::IncR {
+        ExOpcode::Inc
+    } else {
+        ExOpcode::Dec
+    };
+    let uop = alu_uop(opcode, reg, read_register(&rf, &inst, reg), 1, &inst);
+    vec![ExecuteInstType::ArithLogic(uop)]
+}
+
+// IMUL r, r/m
+fn decode_imul(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let uop = alu_uop(
+        ExOpcode::Imul,
+        modrm.reg,
+        read_register(&rf, &inst, modrm.reg),
+        rm.read(&rf, &inst),
+        &inst,
+    );
+    Ok(rm_uops(rm, uop, Some(Operand::Op2), false))
+}
+
+// IMUL r, r/m, imm
+fn decode_imul_imm(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let uop = alu_uop(
+        ExOpcode::Imul,
+        modrm.reg,
+        rm.read(&rf, &inst),
+        inst.immediate,
+        &inst,
+    );
+    Ok(rm_uops(rm, uop, Some(Operand::Op1), false))
+}
+
+// MUL/IMUL/DIV/IDIV r/m. `op1` and `op3` are the lower and upper halves of RDX:RAX,
+// which are AL and AH for byte operand size.
+fn decode_mul_div(
+    rf: &RegisterFile,
+    inst: &FetchedInst,
+    opcode: ExOpcode,
+    src: RmOperand,
+) -> Vec<ExecuteInstType> {
+    let upper = match inst.op_size {
+        Some(OperandSize::Byte) => Reg64Id::Ah,
+        _ => Reg64Id::Rdx,
+    };
+    let uop = ExecuteInst {
+        opcode,
+        dest: Some(Reg64Id::Rax),
+        rip: None,
+        op1: Some(read_register(&rf, &inst, Reg64Id::Rax)),
+        op2: Some(src.read(&rf, &inst)),
+        op3: Some(read_register(&rf, &inst, upper)),
+        op_size: inst.op_size,
+        temps: [None; 3],
+    };
+    rm_uops(src, uop, Some(Operand::Op2), false)
+}
+
+// CBW/CWDE/CDQE sign-extends the lower half of RAX.
+// CWD/CDQ/CQO fills RDX with the sign of RAX.
+fn decode_convert(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let (dest, value) = match inst.opcode {
+        Opcode::ConvertRax => {
+            let half = match size {
+                OperandSize::QuadWord => OperandSize::DoubleWord,
+                OperandSize::DoubleWord => OperandSize::Word,
+                _ => OperandSize::Byte,
+            };
+            (Reg64Id::Rax, half.sign_extend(rf.read(Reg64Id::Rax, half)))
+        }
+        _ => {
+            let sign = rf.read(Reg64Id::Rax, size) & size.sign_bit() != 0;
+            (Reg64Id::Rdx, if sign { size.mask() } else { 0 })
+        }
+    };
+    let uop = ExecuteInst {
+        opcode: ExOpcode::Mov,
         dest: Some(dest),
         rip: None,
-        op1: Some(op1),
-        op2: Some(op2),
+        op1: Some(value),
+        op2: None,
+        op3: None,
+        op_size: inst.op_size,
+        temps: [None; 3],
+    };
+    vec![ExecuteInstType::ArithLogic(uop)]
+}
+
+// Operations of the shift group 2 in the order of ModRM.reg. SAL is the same as SHL.
+const SHIFT_OPERATIONS: [ExOpcode; 8] = [
+    ExOpcode::Rol,
+    ExOpcode::Ror,
+    ExOpcode::Rcl,
+    ExOpcode::Rcr,
+    ExOpcode::Shl,
+    ExOpcode::Shr,
+    ExOpcode::Shl,
+    ExOpcode::Sar,
+];
+
+// Shift/Rotate r/m by 1, CL or imm8
+fn decode_group2(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let opcode = SHIFT_OPERATIONS[modrm.reg as usize];
+    let uop = alu_uop(
+        opcode,
+        rm.register(),
+        rm.read(&rf, &inst),
+        shift_count(&rf, &inst),
+        &inst,
+    );
+    Ok(rm_uops(rm, uop, Some(Operand::Op1), true))
+}
+
+// SHLD/SHRD r/m, r, imm8 or CL. `op3` is the count.
+fn decode_double_shift(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    use crate::isa::opcode::Opcode::*;
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let opcode = match inst.opcode {
+        ShldEvGvIb | ShldEvGvCl => ExOpcode::Shld,
+        _ => ExOpcode::Shrd,
+    };
+    let mut uop = alu_uop(
+        opcode,
+        rm.register(),
+        rm.read(&rf, &inst),
+        read_register(&rf, &inst, modrm.reg),
+        &inst,
+    );
+    uop.op3 = Some(shift_count(&rf, &inst));
+    Ok(rm_uops(rm, uop, Some(Operand::Op1), true))
+}
+
+// The count is masked in the execution stage.
+fn shift_count(rf: &RegisterFile, inst: &FetchedInst) -> u64 {
+    use crate::isa::opcode::Opcode::*;
+    match inst.opcode {
+        Group2Eb1 | Group2Ev1 => 1,
+        Group2EbCl | Group2EvCl | ShldEvGvCl | ShrdEvGvCl => {
+            rf.read(Reg64Id::Rcx, OperandSize::Byte)
+        }
+        _ => inst.immediate,
+    }
+}
+
+// BT/BTS/BTR/BTC r/m, r. The signed bit offset in the register selects the operand
+// of a bit string in memory, which may be outside of the memory operand.
+fn decode_bit_test(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    use crate::isa::opcode::Opcode::*;
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let opcode = match inst.opcode {
+        BtsEvGv => ExOpcode::Bts,
+        BtrEvGv => ExOpcode::Btr,
+        BtcEvGv => ExOpcode::Btc,
+        _ => ExOpcode::Bt,
+    };
+    let offset = read_register(&rf, &inst, modrm.reg);
+    let rm = match rm {
+        RmOperand::Memory(addr) => {
+            let size = inst.op_size.expect("Operand size was not fetched.");
+            let index = (size.sign_extend(offset) as i64) >> size.bits().trailing_zeros();
+            let addr = addr.wrapping_add((index * i64::from(size.bits() / 8)) as u64);
+            RmOperand::Memory(addr)
+        }
+        rm => rm,
+    };
+    let uop = alu_uop(opcode, rm.register(), rm.read(&rf, &inst), offset, &inst);
+    Ok(rm_uops(rm, uop, Some(Operand::Op1), opcode != ExOpcode::Bt))
+}
+
+// BT/BTS/BTR/BTC r/m, imm8
+fn decode_group8(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let opcode = match modrm.reg as u8 {
+        4 => ExOpcode::Bt,
+        5 => ExOpcode::Bts,
+        6 => ExOpcode::Btr,
+        7 => ExOpcode::Btc,
+        _ => {
+            return Err(InternalException::UndefinedInstruction {
+                opcode: inst.opcode,
+            })
+        }
+    };
+    let uop = alu_uop(
+        opcode,
+        rm.register(),
+        rm.read(&rf, &inst),
+        inst.immediate,
+        &inst,
+    );
+    Ok(rm_uops(rm, uop, Some(Operand::Op1), opcode != ExOpcode::Bt))
+}
+
+// BSF/BSR r, r/m
+fn decode_bit_scan(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let opcode = match inst.opcode {
+        Opcode::BsfGvEv => ExOpcode::Bsf,
+        _ => ExOpcode::Bsr,
+    };
+    let uop = alu_uop(
+        opcode,
+        modrm.reg,
+        read_register(&rf, &inst, modrm.reg),
+        rm.read(&rf, &inst),
+        &inst,
+    );
+    Ok(rm_uops(rm, uop, Some(Operand::Op2), false))
+}
+
+// CMOVcc r, r/m
+fn decode_cmov(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let condition = inst.condition.expect("Condition was not fetched.");
+    let uop = alu_uop(
+        ExOpcode::CondMove(condition),
+        modrm.reg,
+        read_register(&rf, &inst, modrm.reg),
+        rm.read(&rf, &inst),
+        &inst,
+    );
+    Ok(rm_uops(rm, uop, Some(Operand::Op2), false))
+}
+
+// SETcc r/m8 only writes the memory operand.
+fn decode_setcc(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (_, rm) = rm_operand(&rf, &inst)?;
+    let condition = inst.condition.expect("Condition was not fetched.");
+    let mut uop = alu_uop(ExOpcode::CondSet(condition), rm.register(), 0, 0, &inst);
+    uop.op_size = Some(OperandSize::Byte);
+    Ok(rm_uops(rm, uop, None, true))
+}
+
+// CLC/STC/CLD/STD
+fn decode_flag_control(inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    use crate::isa::opcode::Opcode::*;
+    let (opcode, flags) = match inst.opcode {
+        Clc => (ExOpcode::ClearFlags, RFlags::CARRY_FLAG),
+        Stc => (ExOpcode::SetFlags, RFlags::CARRY_FLAG),
+        Cld => (ExOpcode::ClearFlags, RFlags::DIRECTION_FLAG),
+        Cli => (ExOpcode::ClearFlags, RFlags::INTERRUPT_FLAG),
+        Sti => (ExOpcode::SetFlags, RFlags::INTERRUPT_FLAG),
+        _ => (ExOpcode::SetFlags, RFlags::DIRECTION_FLAG),
+    };
+    let uop = ExecuteInst {
+        opcode,
+        dest: None,
+        rip: None,
+        op1: Some(flags.bits()),
+        op2: None,
         op3: None,
         op_size: inst.op_size,
+        temps: [None; 3],
     };
-    vec![ExecuteInstType::ArithLogic(uop1)]
+    vec![ExecuteInstType::ArithLogic(uop)]
 }
 
-fn decode_inc(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
-    let dest = inst.mod_rm.unwrap().rm;
-    let op1 = rf.read64(dest);
-    let uop1 = ExecuteInst {
-        opcode: ExOpcode::Inc,
+// Reads a register operand of the operand size.
+fn read_register(rf: &RegisterFile, inst: &FetchedInst, reg: Reg64Id) -> u64 {
+    rf.read(reg, inst.op_size.expect("Operand size was not fetched."))
+}
+
+fn alu_uop(opcode: ExOpcode, dest: Reg64Id, op1: u64, op2: u64, inst: &FetchedInst) -> ExecuteInst {
+    ExecuteInst {
+        opcode,
         dest: Some(dest),
         rip: None,
         op1: Some(op1),
-        op2: None,
+        op2: Some(op2),
         op3: None,
         op_size: inst.op_size,
+        temps: [None; 3],
+    }
+}
+
+// The temporary register which holds the memory operand of an r/m instruction.
+const MEMORY_OPERAND: Reg64Id = Reg64Id::Temp0;
+
+// The r/m operand is a register, or the memory at the linear address.
+#[derive(Clone, Copy)]
+enum RmOperand {
+    Register(Reg64Id),
+    Memory(u64),
+}
+
+impl RmOperand {
+    // The register to operate on. A memory operand is in the temporary register.
+    fn register(self) -> Reg64Id {
+        match self {
+            RmOperand::Register(reg) => reg,
+            RmOperand::Memory(_) => MEMORY_OPERAND,
+        }
+    }
+
+    // Reads a register operand. A memory operand is read in the execution stage.
+    fn read(self, rf: &RegisterFile, inst: &FetchedInst) -> u64 {
+        match self {
+            RmOperand::Register(reg) => read_register(&rf, &inst, reg),
+            RmOperand::Memory(_) => 0,
+        }
+    }
+}
+
+// Operand of a uop which is read from the temporary register.
+#[derive(Clone, Copy)]
+enum Operand {
+    Op1,
+    Op2,
+}
+
+fn rm_operand(rf: &RegisterFile, inst: &FetchedInst) -> Result<(ModRm, RmOperand)> {
+    let modrm = inst.mod_rm.ok_or(InternalException::ModRmRequired {
+        opcode: inst.opcode,
+    })?;
+    let rm = match modrm.mode {
+        ModRmModeField::Direct => RmOperand::Register(modrm.rm),
+        _ => RmOperand::Memory(effective_address(&rf, &inst)),
+    };
+    Ok((modrm, rm))
+}
+
+// A memory operand is read into the temporary register before `uop`, which takes `read`
+// from it, and the result is stored back if `write` is set. Nothing is written back
+// if any of the read, the operation or the store faults.
+fn rm_uops(
+    rm: RmOperand,
+    mut uop: ExecuteInst,
+    read: Option<Operand>,
+    write: bool,
+) -> Vec<ExecuteInstType> {
+    let addr = match rm {
+        RmOperand::Register(_) => return vec![ExecuteInstType::ArithLogic(uop)],
+        RmOperand::Memory(addr) => addr,
     };
-    vec![ExecuteInstType::ArithLogic(uop1)]
+    let size = uop.get_op_size();
+    let mut uops = Vec::new();
+    if let Some(operand) = read {
+        uops.push(read_memory_uop(MEMORY_OPERAND, addr, size));
+        uop.temps[operand as usize] = Some(MEMORY_OPERAND);
+    }
+    uops.push(ExecuteInstType::ArithLogic(uop));
+    if write {
+        uops.push(store_temporary_uop(addr, MEMORY_OPERAND, size));
+    }
+    uops
+}
+
+fn read_memory_uop(dest: Reg64Id, addr: u64, op_size: OperandSize) -> ExecuteInstType {
+    ExecuteInstType::LoadStore(ExecuteInst {
+        opcode: ExOpcode::ReadMemory,
+        dest: Some(dest),
+        rip: None,
+        op1: Some(addr),
+        op2: None,
+        op3: None,
+        op_size: Some(op_size),
+        temps: [None; 3],
+    })
+}
+
+// The uop operates on `op1` in the temporary register.
+fn temporary_uop(
+    opcode: ExOpcode,
+    dest: Reg64Id,
+    temp: Reg64Id,
+    op_size: OperandSize,
+) -> ExecuteInstType {
+    ExecuteInstType::ArithLogic(ExecuteInst {
+        opcode,
+        dest: Some(dest),
+        rip: None,
+        op1: None,
+        op2: None,
+        op3: None,
+        op_size: Some(op_size),
+        temps: [Some(temp), None, None],
+    })
+}
+
+fn store_temporary_uop(addr: u64, temp: Reg64Id, op_size: OperandSize) -> ExecuteInstType {
+    ExecuteInstType::LoadStore(ExecuteInst {
+        opcode: ExOpcode::Store,
+        dest: None,
+        rip: None,
+        op1: Some(addr),
+        op2: None,
+        op3: None,
+        op_size: Some(op_size),
+        temps: [None, Some(temp), None],
+    })
 }
 
 /////////////////////////////////////////////////////////////////////////////
@@ -133,7 +931,7 @@ fn decode_mov_mr(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteIns
         match modrm.mode {
             Direct => {
                 let dest = modrm.rm;
-                let src = rf.read64(modrm.reg);
+                let src = read_register(&rf, &inst, modrm.reg);
                 let uop = ExecuteInst {
                     opcode: ExOpcode::Mov,
                     dest: Some(dest),
@@ -142,10 +940,15 @@ fn decode_mov_mr(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteIns
                     op2: None,
                     op3: None,
                     op_size: inst.op_size,
+                    temps: [None; 3],
                 };
                 Ok(vec![ExecuteInstType::ArithLogic(uop)])
             }
-            _ => Ok(decode_store(&rf, &inst)),
+            _ => Ok(decode_store(
+                &rf,
+                &inst,
+                read_register(&rf, &inst, modrm.reg),
+            )),
         }
     } else {
         Err(InternalException::ModRmRequired {
@@ -160,7 +963,7 @@ fn decode_mov_rm(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteIns
         match modrm.mode {
             Direct => {
                 let dest = modrm.reg;
-                let src = rf.read64(modrm.rm);
+                let src = read_register(&rf, &inst, modrm.rm);
                 let uop = ExecuteInst {
                     opcode: ExOpcode::Mov,
                     dest: Some(dest),
@@ -169,6 +972,7 @@ fn decode_mov_rm(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteIns
                     op2: None,
                     op3: None,
                     op_size: inst.op_size,
+                    temps: [None; 3],
                 };
                 Ok(vec![ExecuteInstType::ArithLogic(uop)])
             }
@@ -192,6 +996,7 @@ fn decode_mov_oi(inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
         op2: None,
         op3: None,
         op_size: inst.op_size,
+        temps: [None; 3],
     };
     Ok(vec![ExecuteInstType::ArithLogic(mov)])
 }
@@ -211,10 +1016,11 @@ fn decode_mov_mi(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteIns
                     op2: None,
                     op3: None,
                     op_size: inst.op_size,
+                    temps: [None; 3],
                 };
                 Ok(vec![ExecuteInstType::ArithLogic(uop)])
             }
-            _ => Ok(decode_store(&rf, &inst)),
+            _ => Ok(decode_store(&rf, &inst, inst.immediate)),
         }
     } else {
         Err(InternalException::ModRmRequired {
@@ -223,13 +1029,122 @@ fn decode_mov_mi(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteIns
     }
 }
 
+// MOVZX/MOVSX/MOVSXD r, r/m. A register source is extended here,
+// and a memory source is extended after it is read into the temporary register.
+fn decode_mov_extend(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    use crate::isa::opcode::Opcode::*;
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let (src_size, signed) = match inst.opcode {
+        MovzxGvEb => (OperandSize::Byte, false),
+        MovzxGvEw => (OperandSize::Word, false),
+        MovsxGvEb => (OperandSize::Byte, true),
+        MovsxGvEw => (OperandSize::Word, true),
+        _ => (OperandSize::DoubleWord, true),
+    };
+    let src = match rm {
+        RmOperand::Register(reg) => rf.read(reg, src_size),
+        RmOperand::Memory(addr) => {
+            let op_size = inst.op_size.expect("Operand size was not fetched.");
+            let mut uops = vec![read_memory_uop(MEMORY_OPERAND, addr, src_size)];
+            if signed {
+                let extend = ExOpcode::SignExtend;
+                uops.push(temporary_uop(
+                    extend,
+                    MEMORY_OPERAND,
+                    MEMORY_OPERAND,
+                    src_size,
+                ));
+            }
+            uops.push(temporary_uop(
+                ExOpcode::Mov,
+                modrm.reg,
+                MEMORY_OPERAND,
+                op_size,
+            ));
+            return Ok(uops);
+        }
+    };
+    let src = if signed {
+        src_size.sign_extend(src)
+    } else {
+        src
+    };
+    let uop = ExecuteInst {
+        opcode: ExOpcode::Mov,
+        dest: Some(modrm.reg),
+        rip: None,
+        op1: Some(src),
+        op2: None,
+        op3: None,
+        op_size: inst.op_size,
+        temps: [None; 3],
+    };
+    Ok(vec![ExecuteInstType::ArithLogic(uop)])
+}
+
+// ModRM.reg of MOV Sreg selects ES, CS, SS, DS, FS or GS.
+fn segment_register_of(inst: &FetchedInst) -> Result<(ModRm, SegmentRegister)> {
+    let modrm = inst.mod_rm.ok_or(InternalException::ModRmRequired {
+        opcode: inst.opcode,
+    })?;
+    let segment = SegmentRegister::from_u8(modrm.reg as u8).ok_or(
+        InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        },
+    )?;
+    Ok((modrm, segment))
+}
+
+// MOV Sreg, r/m16. The descriptor is loaded in the write back, and CS cannot be loaded.
+fn decode_mov_to_segment(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, segment) = segment_register_of(&inst)?;
+    if segment == SegmentRegister::Cs {
+        return Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        });
+    }
+    let selector = if modrm.mode == ModRmModeField::Direct {
+        rf.read(modrm.rm, OperandSize::Word)
+    } else {
+        memory.read(effective_address(&rf, &inst), OperandSize::Word)?
+    };
+    let uop = ExecuteInst {
+        opcode: ExOpcode::LoadSegment,
+        dest: None,
+        rip: None,
+        op1: Some(segment as u64),
+        op2: Some(selector),
+        op3: None,
+        op_size: Some(OperandSize::Word),
+        temps: [None; 3],
+    };
+    Ok(vec![ExecuteInstType::Privilege(uop)])
+}
+
+// MOV r/m16, Sreg. A register destination is zero-extended to the operand size.
+fn decode_mov_from_segment(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, segment) = segment_register_of(&inst)?;
+    let selector = u64::from(rf.selector(segment));
+    if modrm.mode == ModRmModeField::Direct {
+        let size = inst.op_size.expect("Operand size was not fetched.");
+        Ok(vec![mov_uop(modrm.rm, selector, size)])
+    } else {
+        let addr = effective_address(&rf, &inst);
+        Ok(vec![store_uop(addr, selector, OperandSize::Word)])
+    }
+}
+
 /////////////////////////////////////////////////////////////////////////////
 // Load and Store instructions.
 /////////////////////////////////////////////////////////////////////////////
 fn decode_load(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
     let modrm = inst.mod_rm.unwrap();
     let dest = modrm.reg;
-    let addr = rf.read64(modrm.rm);
+    let addr = effective_address(&rf, &inst);
     let load = ExecuteInst {
         opcode: ExOpcode::Load,
         dest: Some(dest),
@@ -238,107 +1153,644 @@ fn decode_load(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
         op2: None,
         op3: None,
         op_size: inst.op_size,
+        temps: [None; 3],
     };
     vec![ExecuteInstType::LoadStore(load)]
 }
 
-fn decode_store(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
-    match inst.sib {
-        Some(_) => {
-            let addr = inst.displacement;
-            let result = inst.immediate;
-            let store = ExecuteInst {
-                opcode: ExOpcode::Store,
-                dest: None,
-                rip: None,
-                op1: Some(addr),
-                op2: Some(result),
-                op3: None,
-                op_size: inst.op_size,
-            };
-            vec![ExecuteInstType::LoadStore(store)]
-        }
-        None => {
-            let modrm = inst.mod_rm.unwrap();
-            let addr = rf.read64(modrm.rm);
-            let result = rf.read64(modrm.reg);
-            let store = ExecuteInst {
-                opcode: ExOpcode::Store,
-                dest: None,
+fn decode_store(rf: &RegisterFile, inst: &FetchedInst, data: u64) -> Vec<ExecuteInstType> {
+    let addr = effective_address(&rf, &inst);
+    let store = ExecuteInst {
+        opcode: ExOpcode::Store,
+        dest: None,
+        rip: None,
+        op1: Some(addr),
+        op2: Some(data),
+        op3: None,
+        op_size: inst.op_size,
+        temps: [None; 3],
+    };
+    vec![ExecuteInstType::LoadStore(store)]
+}
+
+// LEA only computes the address of the memory operand.
+fn decode_lea(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    match inst.mod_rm {
+        Some(modrm) if modrm.mode != ModRmModeField::Direct => {
+            let lea = ExecuteInst {
+                opcode: ExOpcode::Mov,
+                dest: Some(modrm.reg),
                 rip: None,
-                op1: Some(addr),
-                op2: Some(result),
+                op1: Some(effective_address(&rf, &inst)),
+                op2: None,
                 op3: None,
                 op_size: inst.op_size,
+                temps: [None; 3],
             };
-            vec![ExecuteInstType::LoadStore(store)]
+            Ok(vec![ExecuteInstType::ArithLogic(lea)])
         }
+        _ => Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        }),
     }
 }
 
-/////////////////////////////////////////////////////////////////////////////
-// Branch instructions.
-/////////////////////////////////////////////////////////////////////////////
-fn decode_jmp(inst: &FetchedInst) -> Vec<ExecuteInstType> {
-    let disp = inst.displacement;
-    let rip = inst.next_rip as u64;
-    let jmp = ExecuteInst {
-        opcode: ExOpcode::Jump,
-        dest: None,
-        rip: Some(rip),
-        op1: Some(disp),
-        op2: None,
-        op3: None,
-        op_size: inst.op_size,
+// Computes the linear address of the ModRM memory operand.
+// Every instruction which accesses memory through ModRM uses this.
+// The offset wraps at the address size, and the base of the segment is added.
+// The segment is SS for addresses based on RSP or RBP, and DS otherwise, unless overridden.
+fn effective_address(rf: &RegisterFile, inst: &FetchedInst) -> u64 {
+    let modrm = inst.mod_rm.expect("ModRM was not fetched.");
+    let (base, stack) = if inst.addr_size == OperandSize::Word {
+        address_16bit(&rf, modrm)
+    } else if modrm.is_rip_relative() && inst.code_size == CodeSize::Bits64 {
+        (inst.next_rip as u64, false)
+    } else if let Some(sib) = inst.sib {
+        let has_base = sib.has_base(modrm.mode);
+        let base = if has_base { rf.read64(sib.base) } else { 0 };
+        let index = sib.index_register().map_or(0, |index| {
+            rf.read64(index).wrapping_mul(u64::from(sib.scale))
+        });
+        let stack = has_base && (sib.base == Reg64Id::Rsp || sib.base == Reg64Id::Rbp);
+        (base.wrapping_add(index), stack)
+    } else if modrm.is_rip_relative() {
+        // A 32-bit displacement without a base outside 64-bit mode.
+        (0, false)
+    } else {
+        (rf.read64(modrm.rm), modrm.rm == Reg64Id::Rbp)
     };
-    vec![ExecuteInstType::Branch(jmp)]
+    let offset = base.wrapping_add(inst.displacement) & inst.addr_size.mask();
+    let default = if stack {
+        SegmentRegister::Ss
+    } else {
+        SegmentRegister::Ds
+    };
+    let segment = inst.legacy_prefix.segment().unwrap_or(default);
+    linear_address(&rf, &inst, segment, offset)
 }
 
-/////////////////////////////////////////////////////////////////////////////
-// Privileged instructions.
-/////////////////////////////////////////////////////////////////////////////
-fn decode_halt(inst: &FetchedInst) -> Vec<ExecuteInstType> {
-    let hlt = ExecuteInst {
-        opcode: ExOpcode::Halt,
-        dest: None,
-        rip: None,
-        op1: None,
-        op2: None,
-        op3: None,
-        op_size: inst.op_size,
-    };
-    vec![ExecuteInstType::Privilege(hlt)]
+// 16-bit addresses are sums of BX or BP and SI or DI. Addresses based on BP are in SS.
+fn address_16bit(rf: &RegisterFile, modrm: ModRm) -> (u64, bool) {
+    use crate::isa::registers::Reg64Id::*;
+    let read = |reg| rf.read(reg, OperandSize::Word);
+    match modrm.rm as u8 {
+        0 => (read(Rbx) + read(Rsi), false),
+        1 => (read(Rbx) + read(Rdi), false),
+        2 => (read(Rbp) + read(Rsi), true),
+        3 => (read(Rbp) + read(Rdi), true),
+        4 => (read(Rsi), false),
+        5 => (read(Rdi), false),
+        // Only the 16-bit displacement.
+        6 if modrm.mode == ModRmModeField::Indirect => (0, false),
+        6 => (read(Rbp), true),
+        _ => (read(Rbx), false),
+    }
+}
+
+// Adds the segment base to the offset. Only FS and GS have bases in 64-bit mode,
+// and linear addresses are 32-bit in other modes.
+fn linear_address(
+    rf: &RegisterFile,
+    inst: &FetchedInst,
+    segment: SegmentRegister,
+    offset: u64,
+) -> u64 {
+    let linear = offset.wrapping_add(rf.segment_base(segment));
+    match segment {
+        SegmentRegister::Fs | SegmentRegister::Gs if inst.code_size == CodeSize::Bits64 => linear,
+        _ if inst.code_size == CodeSize::Bits64 => offset,
+        _ => linear & 0xffff_ffff,
+    }
 }
 
 /////////////////////////////////////////////////////////////////////////////
-// Complex instructions that require plural micro operations.
+// String instructions.
 /////////////////////////////////////////////////////////////////////////////
-fn decode_call(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
-    let new_sp = rf.read64(Reg64Id::Rsp) - 8;
-    let update_sp = ExecuteInst {
-        opcode: ExOpcode::Mov,
-        dest: Some(Reg64Id::Rsp),
-        rip: None,
-        op1: Some(new_sp),
+// Decodes one iteration of MOVS/CMPS/STOS/LODS/SCAS/INS/OUTS. The source memory is read here,
+// and the reads do not set accessed flags because decode cannot update the CPU state.
+// With REP prefixes, RCX is decremented and the instruction jumps back to itself
+// until it terminates, so that a long repeat can be interrupted between iterations.
+// RSI, RDI and RCX are of the address size.
+fn decode_string(
+    rf: &RegisterFile,
+    rflags: RFlags,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    use crate::isa::opcode::Opcode::*;
+    let size = match inst.opcode {
+        InsYvDx | OutsDxXv => port_operand_size(&inst),
+        _ => inst.op_size.expect("Operand size was not fetched."),
+    };
+    let repeat = inst
+        .legacy_prefix
+        .intersects(LegacyPrefix::REP | LegacyPrefix::REPNE);
+    let addr_size = inst.addr_size;
+    let count = rf.read(Reg64Id::Rcx, addr_size);
+    if repeat && count == 0 {
+        return Ok(vec![]);
+    }
+
+    let step = u64::from(size.bits() / 8);
+    let step = if rflags.contains(RFlags::DIRECTION_FLAG) {
+        step.wrapping_neg()
+    } else {
+        step
+    };
+    let rsi = rf.read(Reg64Id::Rsi, addr_size);
+    let rdi = rf.read(Reg64Id::Rdi, addr_size);
+    // Only the source can be overridden, and the destination is always ES.
+    let source_segment = inst.legacy_prefix.segment().unwrap_or(SegmentRegister::Ds);
+    let source = linear_address(&rf, &inst, source_segment, rsi);
+    let destination = linear_address(&rf, &inst, SegmentRegister::Es, rdi);
+    let rax = rf.read(Reg64Id::Rax, size);
+    let (mut uops, compared) = match inst.opcode {
+        MovsYbXb | MovsYvXv => {
+            let data = memory.read(source, size)?;
+            (vec![store_uop(destination, data, size)], None)
+        }
+        StosYbAl | StosYvRax => (vec![store_uop(destination, rax, size)], None),
+        InsYbDx | InsYvDx => {
+            let port = rf.read(Reg64Id::Rdx, OperandSize::Word);
+            (vec![port_uop(ExOpcode::Ins, port, destination, size)], None)
+        }
+        OutsDxXb | OutsDxXv => {
+            let port = rf.read(Reg64Id::Rdx, OperandSize::Word);
+            let data = memory.read(source, size)?;
+            (vec![port_uop(ExOpcode::Out, port, data, size)], None)
+        }
+        LodsAlXb | LodsRaxXv => {
+            let data = memory.read(source, size)?;
+            (vec![mov_uop(Reg64Id::Rax, data, size)], None)
+        }
+        CmpsXbYb | CmpsXvYv => {
+            let (op1, op2) = (memory.read(source, size)?, memory.read(destination, size)?);
+            let cmp = alu_uop(ExOpcode::Cmp, Reg64Id::Rax, op1, op2, &inst);
+            (vec![ExecuteInstType::ArithLogic(cmp)], Some(op1 == op2))
+        }
+        _ => {
+            let op2 = memory.read(destination, size)?;
+            let cmp = alu_uop(ExOpcode::Cmp, Reg64Id::Rax, rax, op2, &inst);
+            (vec![ExecuteInstType::ArithLogic(cmp)], Some(rax == op2))
+        }
+    };
+    match inst.opcode {
+        StosYbAl | StosYvRax | ScasAlYb | ScasRaxYv | InsYbDx | InsYvDx => (),
+        _ => uops.push(mov_uop(Reg64Id::Rsi, rsi.wrapping_add(step), addr_size)),
+    }
+    match inst.opcode {
+        LodsAlXb | LodsRaxXv | OutsDxXb | OutsDxXv => (),
+        _ => uops.push(mov_uop(Reg64Id::Rdi, rdi.wrapping_add(step), addr_size)),
+    }
+
+    if repeat {
+        let count = count - 1;
+        uops.push(mov_uop(Reg64Id::Rcx, count, addr_size));
+        // REPE and REPNE also terminate by the result of CMPS and SCAS.
+        let terminated = match compared {
+            Some(equal) if inst.legacy_prefix.contains(LegacyPrefix::REPNE) => equal,
+            Some(equal) => !equal,
+            None => false,
+        };
+        if count != 0 && !terminated {
+            uops.extend(decode_jmp_indirect(inst.rip as u64));
+        }
+    }
+    Ok(uops)
+}
+
+fn mov_uop(dest: Reg64Id, value: u64, op_size: OperandSize) -> ExecuteInstType {
+    ExecuteInstType::ArithLogic(ExecuteInst {
+        opcode: ExOpcode::Mov,
+        dest: Some(dest),
+        rip: None,
+        op1: Some(value),
         op2: None,
         op3: None,
-        op_size: inst.op_size,
-    };
+        op_size: Some(op_size),
+        temps: [None; 3],
+    })
+}
 
-    let ret_addr = inst.next_rip as u64;
-    let push = ExecuteInst {
+fn store_uop(addr: u64, data: u64, op_size: OperandSize) -> ExecuteInstType {
+    ExecuteInstType::LoadStore(ExecuteInst {
         opcode: ExOpcode::Store,
         dest: None,
         rip: None,
-        op1: Some(new_sp),
-        op2: Some(ret_addr),
+        op1: Some(addr),
+        op2: Some(data),
         op3: None,
-        op_size: inst.op_size,
+        op_size: Some(op_size),
+        temps: [None; 3],
+    })
+}
+
+/////////////////////////////////////////////////////////////////////////////
+// Port I/O instructions.
+/////////////////////////////////////////////////////////////////////////////
+// Port accesses are done in write back because reading a port may change the device.
+// The operand size is at most 32-bit even with REX.W.
+fn port_operand_size(inst: &FetchedInst) -> OperandSize {
+    match inst.op_size.expect("Operand size was not fetched.") {
+        OperandSize::QuadWord => OperandSize::DoubleWord,
+        size => size,
+    }
+}
+
+// The port is the 8-bit immediate or DX.
+fn port_of(rf: &RegisterFile, inst: &FetchedInst) -> u64 {
+    use crate::isa::opcode::Opcode::*;
+    match inst.opcode {
+        InAlIb | InEaxIb | OutIbAl | OutIbEax => inst.immediate,
+        _ => rf.read(Reg64Id::Rdx, OperandSize::Word),
+    }
+}
+
+fn port_uop(opcode: ExOpcode, port: u64, op2: u64, op_size: OperandSize) -> ExecuteInstType {
+    ExecuteInstType::LoadStore(ExecuteInst {
+        opcode,
+        dest: None,
+        rip: None,
+        op1: Some(port),
+        op2: Some(op2),
+        op3: None,
+        op_size: Some(op_size),
+        temps: [None; 3],
+    })
+}
+
+fn decode_in(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let uop = ExecuteInst {
+        opcode: ExOpcode::In,
+        dest: Some(Reg64Id::Rax),
+        rip: None,
+        op1: Some(port_of(&rf, &inst)),
+        op2: None,
+        op3: None,
+        op_size: Some(port_operand_size(&inst)),
+        temps: [None; 3],
+    };
+    vec![ExecuteInstType::LoadStore(uop)]
+}
+
+fn decode_out(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let size = port_operand_size(&inst);
+    let data = rf.read(Reg64Id::Rax, size);
+    vec![port_uop(ExOpcode::Out, port_of(&rf, &inst), data, size)]
+}
+
+/////////////////////////////////////////////////////////////////////////////
+// SSE instructions.
+/////////////////////////////////////////////////////////////////////////////
+// XMM registers are numbered by ModRM including REX.R and REX.B.
+// Memory operands are read here like string instructions. 128-bit memory operands
+// must be aligned to 16 bytes, except for MOVUPS/MOVUPD/MOVDQU.
+// MXCSR exceptions are always masked, and the rounding control applies only to CVTSS2SI
+// and CVTSD2SI. Other results are rounded to nearest.
+fn decode_sse_move(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    use crate::isa::opcode::Opcode::*;
+    let modrm = inst.mod_rm.expect("ModRM was not fetched.");
+    let reg = modrm.reg as u8;
+    let rm = modrm.rm as u8;
+    let direct = modrm.mode == ModRmModeField::Direct;
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    match inst.opcode {
+        MovupsVW | MovupdVW | MovapsVW | MovapdVW | MovdqaVW | MovdquVW => {
+            let value = read_xmm_operand(&rf, &memory, &inst, 128)?;
+            Ok(vec![xmm_move_uop(reg, value)])
+        }
+        MovupsWV | MovupdWV | MovapsWV | MovapdWV | MovdqaWV | MovdquWV if direct => {
+            Ok(vec![xmm_move_uop(rm, rf.read_xmm(reg))])
+        }
+        MovupsWV | MovupdWV | MovapsWV | MovapdWV | MovdqaWV | MovdquWV => {
+            store_xmm_operand(&rf, &inst, rf.read_xmm(reg), 128)
+        }
+        // Moves between registers merge the lowest element, and loads clear the upper bits.
+        MovssVW | MovsdVW => {
+            let bits = if inst.opcode == MovssVW { 32 } else { 64 };
+            let value = read_xmm_operand(&rf, &memory, &inst, bits)?;
+            if direct {
+                let merged = rf.read_xmm(reg) & !element_mask(bits) | value;
+                Ok(vec![xmm_move_uop(reg, merged)])
+            } else {
+                Ok(vec![xmm_move_uop(reg, value)])
+            }
+        }
+        MovssWV | MovsdWV => {
+            let bits = if inst.opcode == MovssWV { 32 } else { 64 };
+            let value = rf.read_xmm(reg) & element_mask(bits);
+            if direct {
+                let merged = rf.read_xmm(rm) & !element_mask(bits) | value;
+                Ok(vec![xmm_move_uop(rm, merged)])
+            } else {
+                store_xmm_operand(&rf, &inst, value, bits)
+            }
+        }
+        // MOVD and MOVQ zero-extend into the XMM register.
+        MovdVE => {
+            let value = if direct {
+                rf.read(modrm.rm, size)
+            } else {
+                memory.read(effective_address(&rf, &inst), size)?
+            };
+            Ok(vec![xmm_move_uop(reg, u128::from(value))])
+        }
+        MovdEV => {
+            let value = rf.read_xmm(reg) as u64 & size.mask();
+            if direct {
+                Ok(vec![mov_uop(modrm.rm, value, size)])
+            } else {
+                Ok(vec![store_uop(effective_address(&rf, &inst), value, size)])
+            }
+        }
+        MovqVW => {
+            let value = read_xmm_operand(&rf, &memory, &inst, 64)?;
+            Ok(vec![xmm_move_uop(reg, value)])
+        }
+        _ => {
+            let value = rf.read_xmm(reg) & element_mask(64);
+            if direct {
+                Ok(vec![xmm_move_uop(rm, value)])
+            } else {
+                store_xmm_operand(&rf, &inst, value, 64)
+            }
+        }
+    }
+}
+
+// Packed logical and integer operations. The logical operations of single and double
+// are the same bitwise operations as the integer ones.
+fn decode_sse_packed(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    use crate::isa::opcode::Opcode::*;
+    let (opcode, size) = match inst.opcode {
+        AndpsVW | AndpdVW | PandVW => (ExOpcode::And, OperandSize::QuadWord),
+        AndnpsVW | AndnpdVW | PandnVW => (ExOpcode::AndNot, OperandSize::QuadWord),
+        OrpsVW | OrpdVW | PorVW => (ExOpcode::Or, OperandSize::QuadWord),
+        XorpsVW | XorpdVW | PxorVW => (ExOpcode::Xor, OperandSize::QuadWord),
+        PaddbVW => (ExOpcode::Add, OperandSize::Byte),
+        PaddwVW => (ExOpcode::Add, OperandSize::Word),
+        PadddVW => (ExOpcode::Add, OperandSize::DoubleWord),
+        PaddqVW => (ExOpcode::Add, OperandSize::QuadWord),
+        PsubbVW => (ExOpcode::Sub, OperandSize::Byte),
+        PsubwVW => (ExOpcode::Sub, OperandSize::Word),
+        PsubdVW => (ExOpcode::Sub, OperandSize::DoubleWord),
+        PsubqVW => (ExOpcode::Sub, OperandSize::QuadWord),
+        PcmpeqbVW => (ExOpcode::CompareEqual, OperandSize::Byte),
+        PcmpeqwVW => (ExOpcode::CompareEqual, OperandSize::Word),
+        _ => (ExOpcode::CompareEqual, OperandSize::DoubleWord),
+    };
+    let reg = inst.mod_rm.expect("ModRM was not fetched.").reg as u8;
+    let op2 = read_xmm_operand(&rf, &memory, &inst, 128)?;
+    Ok(vec![vector_uop(opcode, reg, rf.read_xmm(reg), op2, size)])
+}
+
+fn decode_pmovmskb(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    match inst.mod_rm {
+        Some(modrm) if modrm.mode == ModRmModeField::Direct => {
+            let src = rf.read_xmm(modrm.rm as u8);
+            let uop = vector_uop(
+                ExOpcode::MoveMask,
+                modrm.reg as u8,
+                src,
+                0,
+                OperandSize::DoubleWord,
+            );
+            Ok(vec![uop])
+        }
+        _ => Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        }),
+    }
+}
+
+// Scalar floating-point arithmetic, comparisons and conversions between single and double.
+// COMISS/COMISD are the same as UCOMISS/UCOMISD because exceptions are masked.
+fn decode_sse_scalar(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    use crate::isa::opcode::Opcode::*;
+    let (opcode, size) = match inst.opcode {
+        AddssVW | AddsdVW => (ExOpcode::FloatAdd, scalar_size(inst.opcode == AddssVW)),
+        SubssVW | SubsdVW => (ExOpcode::FloatSub, scalar_size(inst.opcode == SubssVW)),
+        MulssVW | MulsdVW => (ExOpcode::FloatMul, scalar_size(inst.opcode == MulssVW)),
+        DivssVW | DivsdVW => (ExOpcode::FloatDiv, scalar_size(inst.opcode == DivssVW)),
+        MinssVW | MinsdVW => (ExOpcode::FloatMin, scalar_size(inst.opcode == MinssVW)),
+        MaxssVW | MaxsdVW => (ExOpcode::FloatMax, scalar_size(inst.opcode == MaxssVW)),
+        SqrtssVW | SqrtsdVW => (ExOpcode::FloatSqrt, scalar_size(inst.opcode == SqrtssVW)),
+        UcomissVW | ComissVW => (ExOpcode::FloatCompare, OperandSize::DoubleWord),
+        UcomisdVW | ComisdVW => (ExOpcode::FloatCompare, OperandSize::QuadWord),
+        Cvtsd2ssVW => (ExOpcode::FloatConvert, OperandSize::DoubleWord),
+        _ => (ExOpcode::FloatConvert, OperandSize::QuadWord),
+    };
+    // The source of conversions has the other size.
+    let source_size = match (opcode, size) {
+        (ExOpcode::FloatConvert, OperandSize::DoubleWord) => OperandSize::QuadWord,
+        (ExOpcode::FloatConvert, _) => OperandSize::DoubleWord,
+        _ => size,
     };
+    let reg = inst.mod_rm.expect("ModRM was not fetched.").reg as u8;
+    let op2 = read_xmm_operand(&rf, &memory, &inst, source_size.bits())?;
+    Ok(vec![vector_uop(opcode, reg, rf.read_xmm(reg), op2, size)])
+}
+
+// CVTSI2SS/CVTSI2SD from a general register or memory of the operand size,
+// and CVT(T)SS2SI/CVT(T)SD2SI into a general register.
+fn decode_sse_convert(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    use crate::isa::opcode::Opcode::*;
+    let modrm = inst.mod_rm.expect("ModRM was not fetched.");
+    let reg = modrm.reg as u8;
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let uop = match inst.opcode {
+        Cvtsi2ssVE | Cvtsi2sdVE => {
+            let value = if modrm.mode == ModRmModeField::Direct {
+                rf.read(modrm.rm, size)
+            } else {
+                memory.read(effective_address(&rf, &inst), size)?
+            };
+            vector_uop(
+                ExOpcode::IntToFloat,
+                reg,
+                rf.read_xmm(reg),
+                u128::from(size.sign_extend(value)),
+                scalar_size(inst.opcode == Cvtsi2ssVE),
+            )
+        }
+        _ => {
+            // Singles are converted to doubles exactly.
+            let value = match inst.opcode {
+                Cvttss2siGW | Cvtss2siGW => {
+                    let single = read_xmm_operand(&rf, &memory, &inst, 32)? as u32;
+                    f64::from(f32::from_bits(single))
+                }
+                _ => f64::from_bits(read_xmm_operand(&rf, &memory, &inst, 64)? as u64),
+            };
+            let opcode = match inst.opcode {
+                Cvttss2siGW | Cvttsd2siGW => ExOpcode::FloatToIntTruncate,
+                _ => ExOpcode::FloatToInt,
+            };
+            let rounding = (rf.mxcsr() >> 13) & 0x3;
+            vector_uop(
+                opcode,
+                reg,
+                u128::from(value.to_bits()),
+                u128::from(rounding),
+                size,
+            )
+        }
+    };
+    Ok(vec![uop])
+}
+
+// LDMXCSR and STMXCSR. Fences are no-ops as memory is accessed in program order.
+fn decode_group15(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    let modrm = inst.mod_rm.ok_or(InternalException::ModRmRequired {
+        opcode: inst.opcode,
+    })?;
+    let direct = modrm.mode == ModRmModeField::Direct;
+    match (direct, modrm.reg as u8) {
+        (true, 5..=7) => Ok(vec![]),
+        (false, 2) => {
+            let value = memory.read(effective_address(&rf, &inst), OperandSize::DoubleWord)?;
+            let uop = vector_uop(
+                ExOpcode::LoadMxcsr,
+                0,
+                u128::from(value),
+                0,
+                OperandSize::DoubleWord,
+            );
+            Ok(vec![uop])
+        }
+        (false, 3) => Ok(vec![store_uop(
+            effective_address(&rf, &inst),
+            u64::from(rf.mxcsr()),
+            OperandSize::DoubleWord,
+        )]),
+        _ => Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        }),
+    }
+}
+
+// Single for `single`, otherwise double.
+fn scalar_size(single: bool) -> OperandSize {
+    if single {
+        OperandSize::DoubleWord
+    } else {
+        OperandSize::QuadWord
+    }
+}
+
+fn element_mask(bits: u32) -> u128 {
+    if bits >= 128 {
+        !0
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+// Reads the lower `bits` of the XMM register or the memory of ModRM r/m.
+fn read_xmm_operand(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+    bits: u32,
+) -> Result<u128> {
+    let modrm = inst.mod_rm.expect("ModRM was not fetched.");
+    if modrm.mode == ModRmModeField::Direct {
+        return Ok(rf.read_xmm(modrm.rm as u8) & element_mask(bits));
+    }
+    let addr = effective_address(&rf, &inst);
+    match bits {
+        32 => Ok(u128::from(memory.read(addr, OperandSize::DoubleWord)?)),
+        64 => Ok(u128::from(memory.read(addr, OperandSize::QuadWord)?)),
+        _ => {
+            check_alignment(&inst, addr)?;
+            let low = memory.read(addr, OperandSize::QuadWord)?;
+            let high = memory.read(addr.wrapping_add(8), OperandSize::QuadWord)?;
+            Ok(u128::from(high) << 64 | u128::from(low))
+        }
+    }
+}
+
+fn store_xmm_operand(
+    rf: &RegisterFile,
+    inst: &FetchedInst,
+    value: u128,
+    bits: u32,
+) -> Result<Vec<ExecuteInstType>> {
+    let addr = effective_address(&rf, &inst);
+    match bits {
+        32 => Ok(vec![store_uop(addr, value as u64, OperandSize::DoubleWord)]),
+        64 => Ok(vec![store_uop(addr, value as u64, OperandSize::QuadWord)]),
+        _ => {
+            check_alignment(&inst, addr)?;
+            Ok(vec![
+                store_uop(addr, value as u64, OperandSize::QuadWord),
+                store_uop(
+                    addr.wrapping_add(8),
+                    (value >> 64) as u64,
+                    OperandSize::QuadWord,
+                ),
+            ])
+        }
+    }
+}
+
+// Misaligned 128-bit memory operands raise #GP(0).
+fn check_alignment(inst: &FetchedInst, addr: u64) -> Result<()> {
+    use crate::isa::opcode::Opcode::*;
+    match inst.opcode {
+        MovupsVW | MovupsWV | MovupdVW | MovupdWV | MovdquVW | MovdquWV => Ok(()),
+        _ if addr % 16 != 0 => Err(InternalException::GeneralProtection { error_code: 0 }),
+        _ => Ok(()),
+    }
+}
 
+fn vector_uop(
+    opcode: ExOpcode,
+    dest: u8,
+    op1: u128,
+    op2: u128,
+    op_size: OperandSize,
+) -> ExecuteInstType {
+    ExecuteInstType::Vector(VectorInst {
+        opcode,
+        dest,
+        op1,
+        op2,
+        op_size,
+    })
+}
+
+fn xmm_move_uop(dest: u8, value: u128) -> ExecuteInstType {
+    vector_uop(ExOpcode::Mov, dest, value, 0, OperandSize::QuadWord)
+}
+
+/////////////////////////////////////////////////////////////////////////////
+// Branch instructions.
+/////////////////////////////////////////////////////////////////////////////
+fn decode_jmp(inst: &FetchedInst) -> Vec<ExecuteInstType> {
     let disp = inst.displacement;
     let rip = inst.next_rip as u64;
-    let call = ExecuteInst {
+    let jmp = ExecuteInst {
         opcode: ExOpcode::Jump,
         dest: None,
         rip: Some(rip),
@@ -346,99 +1798,681 @@ fn decode_call(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
         op2: None,
         op3: None,
         op_size: inst.op_size,
+        temps: [None; 3],
     };
+    vec![ExecuteInstType::Branch(jmp)]
+}
 
-    vec![
-        ExecuteInstType::ArithLogic(update_sp),
-        ExecuteInstType::LoadStore(push),
-        ExecuteInstType::Branch(call),
-    ]
+fn decode_jcc(inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let condition = inst.condition.expect("Condition was not fetched.");
+    let jcc = ExecuteInst {
+        opcode: ExOpcode::CondJump(condition),
+        dest: None,
+        rip: Some(inst.next_rip as u64),
+        op1: Some(inst.displacement),
+        op2: None,
+        op3: None,
+        op_size: inst.op_size,
+        temps: [None; 3],
+    };
+    vec![ExecuteInstType::Branch(jcc)]
 }
 
-fn decode_pushr(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
-    let new_sp = rf.read64(Reg64Id::Rsp) - 8;
-    let update_sp = ExecuteInst {
+fn decode_jmp_indirect(target: u64) -> Vec<ExecuteInstType> {
+    let jmp = ExecuteInst {
+        opcode: ExOpcode::JumpIndirect,
+        dest: None,
+        rip: None,
+        op1: Some(target),
+        op2: None,
+        op3: None,
+        op_size: Some(OperandSize::QuadWord),
+        temps: [None; 3],
+    };
+    vec![ExecuteInstType::Branch(jmp)]
+}
+
+// LOOP/LOOPE/LOOPNE decrement RCX of the address size without changing flags.
+// The branch is resolved here because RCX after the decrement is already known.
+fn decode_loop(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    use crate::isa::opcode::Opcode::*;
+    let size = inst.addr_size;
+    let count = rf.read(Reg64Id::Rcx, size).wrapping_sub(1) & size.mask();
+    let update_count = ExecuteInst {
         opcode: ExOpcode::Mov,
-        dest: Some(Reg64Id::Rsp),
+        dest: Some(Reg64Id::Rcx),
         rip: None,
-        op1: Some(new_sp),
+        op1: Some(count),
         op2: None,
         op3: None,
-        op_size: inst.op_size,
+        op_size: Some(size),
+        temps: [None; 3],
     };
+    let mut uops = vec![ExecuteInstType::ArithLogic(update_count)];
+    if count != 0 {
+        let opcode = match inst.opcode {
+            LoopeRel8 => ExOpcode::CondJump(Condition::Equal),
+            LoopneRel8 => ExOpcode::CondJump(Condition::NotEqual),
+            _ => ExOpcode::Jump,
+        };
+        let branch = ExecuteInst {
+            opcode,
+            dest: None,
+            rip: Some(inst.next_rip as u64),
+            op1: Some(inst.displacement),
+            op2: None,
+            op3: None,
+            op_size: inst.op_size,
+            temps: [None; 3],
+        };
+        uops.push(ExecuteInstType::Branch(branch));
+    }
+    uops
+}
 
-    let data = rf.read64(Reg64Id::from_u8(inst.r).unwrap());
-    let push = ExecuteInst {
-        opcode: ExOpcode::Store,
+fn decode_jrcxz(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    if rf.read(Reg64Id::Rcx, inst.addr_size) == 0 {
+        decode_jmp(&inst)
+    } else {
+        vec![]
+    }
+}
+
+// JMP ptr16:16/32 has the selector above the offset in the immediate.
+fn decode_jmp_far(inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    if inst.code_size == CodeSize::Bits64 {
+        return Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        });
+    }
+    let selector = inst.immediate >> 32;
+    let offset = inst.immediate & 0xffff_ffff;
+    Ok(vec![far_jump_uop(selector, offset)])
+}
+
+// JMP m16:16/32/64 reads the offset of the operand size followed by the selector.
+fn decode_jmp_far_indirect(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let addr = effective_address(&rf, &inst);
+    let offset = memory.read(addr, size)?;
+    let selector_addr = addr.wrapping_add(u64::from(size.bits() / 8));
+    let selector = memory.read(selector_addr, OperandSize::Word)?;
+    Ok(vec![far_jump_uop(selector, offset)])
+}
+
+fn far_jump_uop(selector: u64, offset: u64) -> ExecuteInstType {
+    ExecuteInstType::Branch(ExecuteInst {
+        opcode: ExOpcode::JumpFar,
         dest: None,
         rip: None,
-        op1: Some(new_sp),
-        op2: Some(data),
+        op1: Some(selector),
+        op2: Some(offset),
+        op3: None,
+        op_size: None,
+        temps: [None; 3],
+    })
+}
+
+/////////////////////////////////////////////////////////////////////////////
+// Privileged instructions.
+/////////////////////////////////////////////////////////////////////////////
+fn decode_halt(inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let hlt = ExecuteInst {
+        opcode: ExOpcode::Halt,
+        dest: None,
+        rip: None,
+        op1: None,
+        op2: None,
         op3: None,
         op_size: inst.op_size,
+        temps: [None; 3],
     };
+    vec![ExecuteInstType::Privilege(hlt)]
+}
 
-    vec![
-        ExecuteInstType::ArithLogic(update_sp),
-        ExecuteInstType::LoadStore(push),
-    ]
+// CPUID takes the leaf in EAX and the sub-leaf in ECX.
+fn decode_cpuid(rf: &RegisterFile) -> Vec<ExecuteInstType> {
+    let cpuid = ExecuteInst {
+        opcode: ExOpcode::Cpuid,
+        dest: None,
+        rip: None,
+        op1: Some(rf.read(Reg64Id::Rax, OperandSize::DoubleWord)),
+        op2: Some(rf.read(Reg64Id::Rcx, OperandSize::DoubleWord)),
+        op3: None,
+        op_size: Some(OperandSize::DoubleWord),
+        temps: [None; 3],
+    };
+    vec![ExecuteInstType::Privilege(cpuid)]
 }
 
-fn decode_popr(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
-    let dest = Reg64Id::from_u8(inst.r).unwrap();
-    let sp = rf.read64(Reg64Id::Rsp);
-    let pop = ExecuteInst {
-        opcode: ExOpcode::Load,
-        dest: Some(dest),
+// System instructions which are completed in the write back stage.
+// `op1` is RAX, e.g., the system call number.
+fn decode_system(opcode: ExOpcode, rf: &RegisterFile) -> Vec<ExecuteInstType> {
+    let uop = ExecuteInst {
+        opcode,
+        dest: None,
         rip: None,
-        op1: Some(sp),
+        op1: Some(rf.read64(Reg64Id::Rax)),
         op2: None,
         op3: None,
+        op_size: Some(OperandSize::QuadWord),
+        temps: [None; 3],
+    };
+    vec![ExecuteInstType::Privilege(uop)]
+}
+
+// SYSRET returns to 64-bit mode with REX.W, otherwise to compatibility mode.
+fn decode_sysret(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let uop = ExecuteInst {
+        opcode: ExOpcode::SystemReturn,
+        dest: None,
+        rip: None,
+        op1: Some(rf.read64(Reg64Id::Rcx)),
+        op2: Some(rf.read64(Reg64Id::R11)),
+        op3: None,
         op_size: inst.op_size,
+        temps: [None; 3],
     };
+    vec![ExecuteInstType::Privilege(uop)]
+}
 
-    let new_sp = rf.read64(Reg64Id::Rsp) + 8;
-    let update_sp = ExecuteInst {
-        opcode: ExOpcode::Mov,
-        dest: Some(Reg64Id::Rsp),
+// RDMSR and WRMSR. The value is split into EDX:EAX.
+fn decode_msr(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let (opcode, value) = match inst.opcode {
+        Opcode::Rdmsr => (ExOpcode::ReadMsr, None),
+        _ => {
+            let high = rf.read(Reg64Id::Rdx, OperandSize::DoubleWord);
+            let low = rf.read(Reg64Id::Rax, OperandSize::DoubleWord);
+            (ExOpcode::WriteMsr, Some(high << 32 | low))
+        }
+    };
+    let uop = ExecuteInst {
+        opcode,
+        dest: None,
+        rip: None,
+        op1: Some(rf.read(Reg64Id::Rcx, OperandSize::DoubleWord)),
+        op2: value,
+        op3: None,
+        op_size: Some(OperandSize::QuadWord),
+        temps: [None; 3],
+    };
+    vec![ExecuteInstType::Privilege(uop)]
+}
+
+// MOV r, CRn and MOV CRn, r. The operand size is 64-bit in 64-bit mode and 32-bit otherwise,
+// and ModRM.mod is ignored as if it were a register operand.
+fn decode_mov_control_register(
+    rf: &RegisterFile,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    let modrm = inst.mod_rm.ok_or(InternalException::ModRmRequired {
+        opcode: inst.opcode,
+    })?;
+    let number = modrm.reg as u8;
+    if !Mmu::is_control_register(number) {
+        return Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        });
+    }
+    let size = match inst.code_size {
+        CodeSize::Bits64 => OperandSize::QuadWord,
+        _ => OperandSize::DoubleWord,
+    };
+    let uop = if inst.opcode == Opcode::MovRdCd {
+        ExecuteInst {
+            opcode: ExOpcode::ReadControlRegister,
+            dest: Some(modrm.rm),
+            rip: None,
+            op1: Some(u64::from(number)),
+            op2: None,
+            op3: None,
+            op_size: Some(size),
+            temps: [None; 3],
+        }
+    } else {
+        ExecuteInst {
+            opcode: ExOpcode::WriteControlRegister,
+            dest: None,
+            rip: None,
+            op1: Some(u64::from(number)),
+            op2: Some(rf.read(modrm.rm, size)),
+            op3: None,
+            op_size: Some(size),
+            temps: [None; 3],
+        }
+    };
+    Ok(vec![ExecuteInstType::Privilege(uop)])
+}
+
+// Only LTR r/m16 is supported in group 6.
+fn decode_group6(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    let modrm = inst.mod_rm.ok_or(InternalException::ModRmRequired {
+        opcode: inst.opcode,
+    })?;
+    if modrm.reg as u8 != 3 {
+        return Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        });
+    }
+    let selector = if modrm.mode == ModRmModeField::Direct {
+        rf.read(modrm.rm, OperandSize::Word)
+    } else {
+        memory.read(effective_address(&rf, &inst), OperandSize::Word)?
+    };
+    let ltr = ExecuteInst {
+        opcode: ExOpcode::LoadTaskRegister,
+        dest: None,
+        rip: None,
+        op1: Some(selector),
+        op2: None,
+        op3: None,
+        op_size: Some(OperandSize::Word),
+        temps: [None; 3],
+    };
+    Ok(vec![ExecuteInstType::Privilege(ltr)])
+}
+
+// LGDT m, LIDT m and INVLPG m are supported in group 7.
+// The memory operand of LGDT and LIDT is a 16-bit limit followed by a 64-bit base
+// in 64-bit mode. Otherwise, the base is 32-bit, of which 24 bits are used with 16-bit operands.
+fn decode_group7(
+    rf: &RegisterFile,
+    memory: &LinearMemory,
+    inst: &FetchedInst,
+) -> Result<Vec<ExecuteInstType>> {
+    let modrm = inst.mod_rm.ok_or(InternalException::ModRmRequired {
+        opcode: inst.opcode,
+    })?;
+    // Register forms are other instructions, and only SWAPGS is implemented.
+    if modrm.mode == ModRmModeField::Direct {
+        if modrm.reg as u8 == 7 && modrm.rm == Reg64Id::Rax {
+            let swapgs = ExecuteInst {
+                opcode: ExOpcode::SwapGs,
+                dest: None,
+                rip: None,
+                op1: None,
+                op2: None,
+                op3: None,
+                op_size: None,
+                temps: [None; 3],
+            };
+            return Ok(vec![ExecuteInstType::Privilege(swapgs)]);
+        }
+        return Err(InternalException::UndefinedInstruction {
+            opcode: inst.opcode,
+        });
+    }
+    let addr = effective_address(&rf, &inst);
+    let (opcode, op1, op2) = match modrm.reg as u8 {
+        2 | 3 => {
+            let limit = memory.read(addr, OperandSize::Word)?;
+            let base = match (inst.code_size, inst.op_size) {
+                (CodeSize::Bits64, _) => {
+                    memory.read(addr.wrapping_add(2), OperandSize::QuadWord)?
+                }
+                (_, Some(OperandSize::Word)) => {
+                    memory.read(addr.wrapping_add(2), OperandSize::DoubleWord)? & 0xff_ffff
+                }
+                _ => memory.read(addr.wrapping_add(2), OperandSize::DoubleWord)?,
+            };
+            let opcode = if modrm.reg as u8 == 2 {
+                ExOpcode::LoadGdt
+            } else {
+                ExOpcode::LoadIdt
+            };
+            (opcode, base, Some(limit))
+        }
+        7 => (ExOpcode::InvalidatePage, addr, None),
+        _ => {
+            return Err(InternalException::UndefinedInstruction {
+                opcode: inst.opcode,
+            })
+        }
+    };
+    let uop = ExecuteInst {
+        opcode,
+        dest: None,
+        rip: None,
+        op1: Some(op1),
+        op2,
+        op3: None,
+        op_size: Some(OperandSize::QuadWord),
+        temps: [None; 3],
+    };
+    Ok(vec![ExecuteInstType::Privilege(uop)])
+}
+
+// INT3 is INT 3 in one byte.
+fn decode_software_interrupt(inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let vector = if inst.opcode == Opcode::Int3 {
+        u64::from(interrupt::BREAKPOINT)
+    } else {
+        inst.immediate
+    };
+    let int = ExecuteInst {
+        opcode: ExOpcode::SoftwareInterrupt,
+        dest: None,
+        rip: None,
+        op1: Some(vector),
+        op2: None,
+        op3: None,
+        op_size: None,
+        temps: [None; 3],
+    };
+    vec![ExecuteInstType::Privilege(int)]
+}
+
+// IRET pops RIP, CS, RFLAGS, RSP and SS of the operand size.
+fn decode_iret(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let sp = rf.read(Reg64Id::Rsp, inst.code_size.address_size());
+    let iret = ExecuteInst {
+        opcode: ExOpcode::InterruptReturn,
+        dest: None,
         rip: None,
-        op1: Some(new_sp),
+        op1: Some(linear_address(&rf, &inst, SegmentRegister::Ss, sp)),
         op2: None,
         op3: None,
         op_size: inst.op_size,
+        temps: [None; 3],
     };
+    vec![ExecuteInstType::Privilege(iret)]
+}
 
+/////////////////////////////////////////////////////////////////////////////
+// Atomic instructions.
+/////////////////////////////////////////////////////////////////////////////
+// XCHG r, rAX. 0x90 is NOP, which does not clear the upper half of RAX.
+fn decode_xchg_r(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let reg = Reg64Id::from_u8(inst.r).expect("Invalid register number.");
+    if reg == Reg64Id::Rax {
+        return vec![];
+    }
+    let size = inst.op_size.expect("Operand size was not fetched.");
     vec![
-        ExecuteInstType::LoadStore(pop),
-        ExecuteInstType::ArithLogic(update_sp),
+        mov_uop(reg, rf.read(Reg64Id::Rax, size), size),
+        mov_uop(Reg64Id::Rax, rf.read(reg, size), size),
     ]
 }
 
-fn decode_ret(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
-    let sp = rf.read64(Reg64Id::Rsp);
-    let ret = ExecuteInst {
-        opcode: ExOpcode::Return,
+// XCHG r/m, r. The memory operand is read before the register is stored to it.
+fn decode_xchg(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let src = read_register(&rf, &inst, modrm.reg);
+    match rm {
+        RmOperand::Register(reg) => Ok(vec![
+            mov_uop(reg, src, size),
+            mov_uop(modrm.reg, read_register(&rf, &inst, reg), size),
+        ]),
+        RmOperand::Memory(addr) => Ok(vec![
+            read_memory_uop(MEMORY_OPERAND, addr, size),
+            store_uop(addr, src, size),
+            temporary_uop(ExOpcode::Mov, modrm.reg, MEMORY_OPERAND, size),
+        ]),
+    }
+}
+
+// CMPXCHG r/m, r compares the accumulator with the destination like CMP. If they are equal,
+// the source is written to the destination. Otherwise, the destination is loaded into
+// the accumulator, and a memory destination is written back unchanged.
+fn decode_cmpxchg(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let mut cmp = alu_uop(
+        ExOpcode::Cmp,
+        Reg64Id::Rax,
+        read_register(&rf, &inst, Reg64Id::Rax),
+        rm.read(&rf, &inst),
+        &inst,
+    );
+    let mut exchange = ExecuteInst {
+        opcode: ExOpcode::ExchangeIfEqual,
+        dest: Some(rm.register()),
+        rip: None,
+        op1: Some(rm.read(&rf, &inst)),
+        op2: Some(read_register(&rf, &inst, modrm.reg)),
+        op3: Some(Reg64Id::Rax as u64),
+        op_size: Some(size),
+        temps: [None; 3],
+    };
+    let addr = match rm {
+        RmOperand::Register(_) => {
+            let uops = vec![cmp, exchange];
+            return Ok(uops.into_iter().map(ExecuteInstType::ArithLogic).collect());
+        }
+        RmOperand::Memory(addr) => addr,
+    };
+    cmp.temps[1] = Some(MEMORY_OPERAND);
+    exchange.temps[0] = Some(MEMORY_OPERAND);
+    Ok(vec![
+        read_memory_uop(MEMORY_OPERAND, addr, size),
+        ExecuteInstType::ArithLogic(cmp),
+        ExecuteInstType::ArithLogic(exchange),
+        store_temporary_uop(addr, MEMORY_OPERAND, size),
+    ])
+}
+
+// XADD r/m, r loads the destination into the source, and writes the sum to the destination.
+fn decode_xadd(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let mut add = alu_uop(
+        ExOpcode::Add,
+        rm.register(),
+        rm.read(&rf, &inst),
+        read_register(&rf, &inst, modrm.reg),
+        &inst,
+    );
+    match rm {
+        RmOperand::Register(reg) => Ok(vec![
+            mov_uop(modrm.reg, read_register(&rf, &inst, reg), size),
+            ExecuteInstType::ArithLogic(add),
+        ]),
+        RmOperand::Memory(addr) => {
+            add.temps[0] = Some(MEMORY_OPERAND);
+            Ok(vec![
+                read_memory_uop(MEMORY_OPERAND, addr, size),
+                temporary_uop(ExOpcode::Mov, modrm.reg, MEMORY_OPERAND, size),
+                ExecuteInstType::ArithLogic(add),
+                store_temporary_uop(addr, MEMORY_OPERAND, size),
+            ])
+        }
+    }
+}
+
+// CMPXCHG8B m64 compares EDX:EAX with the memory, and writes ECX:EBX to it if equal.
+// CMPXCHG16B m128 with REX.W does the same with RDX:RAX and RCX:RBX, and the memory
+// must be aligned to 16 bytes. Both halves are compared before either is written,
+// and only ZF is changed.
+fn decode_group9(rf: &RegisterFile, inst: &FetchedInst) -> Result<Vec<ExecuteInstType>> {
+    let (modrm, rm) = rm_operand(&rf, &inst)?;
+    let addr = match rm {
+        RmOperand::Memory(addr) if modrm.reg as u8 == 1 => addr,
+        _ => {
+            return Err(InternalException::UndefinedInstruction {
+                opcode: inst.opcode,
+            })
+        }
+    };
+    let size = match inst.op_size {
+        Some(OperandSize::QuadWord) => OperandSize::QuadWord,
+        _ => OperandSize::DoubleWord,
+    };
+    if size == OperandSize::QuadWord && addr % 16 != 0 {
+        return Err(InternalException::GeneralProtection { error_code: 0 });
+    }
+    let high = addr.wrapping_add(u64::from(size.bits() / 8));
+    // The temporary register, the address, the accumulator and the source of each half.
+    let halves = [
+        (Reg64Id::Temp0, addr, Reg64Id::Rax, Reg64Id::Rbx),
+        (Reg64Id::Temp1, high, Reg64Id::Rdx, Reg64Id::Rcx),
+    ];
+    let mut uops = Vec::new();
+    for (temp, addr, _, _) in halves.iter() {
+        uops.push(read_memory_uop(*temp, *addr, size));
+    }
+    uops.push(ExecuteInstType::ArithLogic(ExecuteInst {
+        opcode: ExOpcode::SetFlags,
         dest: None,
         rip: None,
-        op1: Some(sp),
+        op1: Some(RFlags::ZERO_FLAG.bits()),
         op2: None,
         op3: None,
-        op_size: inst.op_size,
+        op_size: Some(size),
+        temps: [None; 3],
+    }));
+    for (temp, _, accumulator, _) in halves.iter() {
+        uops.push(ExecuteInstType::ArithLogic(ExecuteInst {
+            opcode: ExOpcode::TestEqual,
+            dest: None,
+            rip: None,
+            op1: None,
+            op2: Some(rf.read(*accumulator, size)),
+            op3: None,
+            op_size: Some(size),
+            temps: [Some(*temp), None, None],
+        }));
+    }
+    for (temp, _, accumulator, src) in halves.iter() {
+        uops.push(ExecuteInstType::ArithLogic(ExecuteInst {
+            opcode: ExOpcode::ExchangeIfEqual,
+            dest: Some(*temp),
+            rip: None,
+            op1: None,
+            op2: Some(rf.read(*src, size)),
+            op3: Some(*accumulator as u64),
+            op_size: Some(size),
+            temps: [Some(*temp), None, None],
+        }));
+    }
+    for (temp, addr, _, _) in halves.iter() {
+        uops.push(store_temporary_uop(*addr, *temp, size));
+    }
+    Ok(uops)
+}
+
+/////////////////////////////////////////////////////////////////////////////
+// Complex instructions that require plural micro operations.
+/////////////////////////////////////////////////////////////////////////////
+fn decode_call(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let mut uops = push_uops(&rf, &inst, inst.next_rip as u64, size);
+    let call = ExecuteInst {
+        opcode: ExOpcode::Jump,
+        dest: None,
+        rip: Some(inst.next_rip as u64),
+        op1: Some(inst.displacement),
+        op2: None,
+        op3: None,
+        op_size: Some(size),
+        temps: [None; 3],
     };
+    uops.push(ExecuteInstType::Branch(call));
+    uops
+}
 
-    let new_sp = rf.read64(Reg64Id::Rsp) + 8;
-    let update_sp = ExecuteInst {
-        opcode: ExOpcode::Mov,
-        dest: Some(Reg64Id::Rsp),
+// CALL/JMP r/m jumps to the absolute address, and CALL pushes the return address.
+// A target in memory is read into the temporary register before the push.
+fn decode_jmp_rm(
+    rf: &RegisterFile,
+    inst: &FetchedInst,
+    target: RmOperand,
+    call: bool,
+) -> Vec<ExecuteInstType> {
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let mut uops = Vec::new();
+    let mut jmp = ExecuteInst {
+        opcode: ExOpcode::JumpIndirect,
+        dest: None,
         rip: None,
-        op1: Some(new_sp),
+        op1: Some(target.read(&rf, &inst)),
         op2: None,
         op3: None,
-        op_size: inst.op_size,
+        op_size: Some(OperandSize::QuadWord),
+        temps: [None; 3],
+    };
+    if let RmOperand::Memory(addr) = target {
+        uops.push(read_memory_uop(MEMORY_OPERAND, addr, size));
+        jmp.temps[0] = Some(MEMORY_OPERAND);
+    }
+    if call {
+        uops.extend(push_uops(&rf, &inst, inst.next_rip as u64, size));
+    }
+    uops.push(ExecuteInstType::Branch(jmp));
+    uops
+}
+
+fn decode_pushr(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let data = rf.read(Reg64Id::from_u8(inst.r).unwrap(), size);
+    push_uops(&rf, &inst, data, size)
+}
+
+fn decode_popr(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let (addr, update_sp) = pop_uops(&rf, &inst, size);
+    let pop = ExecuteInst {
+        opcode: ExOpcode::Load,
+        dest: Some(Reg64Id::from_u8(inst.r).unwrap()),
+        rip: None,
+        op1: Some(addr),
+        op2: None,
+        op3: None,
+        op_size: Some(size),
+        temps: [None; 3],
     };
+    vec![ExecuteInstType::LoadStore(pop), update_sp]
+}
 
+fn decode_ret(rf: &RegisterFile, inst: &FetchedInst) -> Vec<ExecuteInstType> {
+    let size = inst.op_size.expect("Operand size was not fetched.");
+    let (addr, update_sp) = pop_uops(&rf, &inst, size);
+    let ret = ExecuteInst {
+        opcode: ExOpcode::Return,
+        dest: None,
+        rip: None,
+        op1: Some(addr),
+        op2: None,
+        op3: None,
+        op_size: Some(size),
+        temps: [None; 3],
+    };
+    vec![ExecuteInstType::Branch(ret), update_sp]
+}
+
+// The stack is in SS, and the stack pointer wraps at the address size of the mode.
+// Pushing decrements the stack pointer and stores `data` of `size` there.
+fn push_uops(
+    rf: &RegisterFile,
+    inst: &FetchedInst,
+    data: u64,
+    size: OperandSize,
+) -> Vec<ExecuteInstType> {
+    let sp_size = inst.code_size.address_size();
+    let new_sp = rf
+        .read(Reg64Id::Rsp, sp_size)
+        .wrapping_sub(u64::from(size.bits() / 8))
+        & sp_size.mask();
+    let addr = linear_address(&rf, &inst, SegmentRegister::Ss, new_sp);
     vec![
-        ExecuteInstType::Branch(ret),
-        ExecuteInstType::ArithLogic(update_sp),
+        mov_uop(Reg64Id::Rsp, new_sp, sp_size),
+        store_uop(addr, data, size),
     ]
 }
+
+// Returns the address of the top of the stack, and the uop to increment the stack pointer.
+fn pop_uops(rf: &RegisterFile, inst: &FetchedInst, size: OperandSize) -> (u64, ExecuteInstType) {
+    let sp_size = inst.code_size.address_size();
+    let sp = rf.read(Reg64Id::Rsp, sp_size);
+    let addr = linear_address(&rf, &inst, SegmentRegister::Ss, sp);
+    let new_sp = sp.wrapping_add(u64::from(size.bits() / 8)) & sp_size.mask();
+    (addr, mov_uop(Reg64Id::Rsp, new_sp, sp_size))
+}
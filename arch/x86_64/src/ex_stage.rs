@@ -1,17 +1,60 @@
 use crate::decoder::ExOpcode;
 use crate::decoder::ExecuteInst;
 use crate::decoder::ExecuteInstType;
+use crate::decoder::VectorInst;
+use crate::exceptions::InternalException;
+use crate::interrupt::DescriptorTableRegister;
+use crate::isa::condition::Condition;
 use crate::isa::opcode::OperandSize;
-use crate::isa::registers::Reg64Id;
+use crate::isa::registers::{Reg64Id, SegmentRegister};
+use crate::isa::rflags::RFlags;
+use crate::paging::LinearMemory;
 use crate::CpuState;
+use crate::Result;
+use num::{Float, FromPrimitive};
 
 pub enum WriteBack {
     Rip(u64),
-    GeneralRegister(Reg64Id, u64),
+    GeneralRegister(Reg64Id, OperandSize, u64),
+    Xmm(u8, u128),
+    Mxcsr(u32),
+    Flags(RFlags),
     CpuState(CpuState),
     Store(u64, WriteBackData),
-    Load(Reg64Id, u64),
-    Return(u64),
+    Load(Reg64Id, OperandSize, u64),
+    // Address and size of the return address.
+    Return(u64, OperandSize),
+    // Selector of the new CS and the offset.
+    FarJump(u16, u64),
+    // Loads the selector into the segment register.
+    Segment(SegmentRegister, u16),
+    // EDX:EAX = time-stamp counter.
+    TimeStampCounter,
+    Syscall,
+    // RCX, R11 and the operand size.
+    SystemReturn(u64, u64, OperandSize),
+    SwapGs,
+    // Leaf and subleaf.
+    Cpuid(u32, u32),
+    // Reads the MSR into EDX:EAX.
+    ReadMsr(u32),
+    WriteMsr(u32, u64),
+    // Control register number and the value to be written.
+    ControlRegister(u8, u64),
+    // Reads the control register into the destination.
+    ReadControlRegister(Reg64Id, u8),
+    InvalidatePage(u64),
+    Gdtr(DescriptorTableRegister),
+    Idtr(DescriptorTableRegister),
+    // Selector of the TSS descriptor in the GDT.
+    TaskRegister(u16),
+    SoftwareInterrupt(u8),
+    // Stack pointer and the size of each popped value.
+    InterruptReturn(u64, OperandSize),
+    PortIn(Reg64Id, OperandSize, u16),
+    PortOut(u16, OperandSize, u64),
+    // Stores the data from the port to the address.
+    PortInToMemory(u64, OperandSize, u16),
 }
 
 pub enum WriteBackData {
@@ -21,72 +64,563 @@ pub enum WriteBackData {
     QWord(u64),
 }
 
-pub fn execute(inst: &ExecuteInstType) -> Result<WriteBack, ()> {
+impl WriteBackData {
+    pub fn size_and_value(&self) -> (OperandSize, u64) {
+        match self {
+            WriteBackData::Byte(data) => (OperandSize::Byte, u64::from(*data)),
+            WriteBackData::Word(data) => (OperandSize::Word, u64::from(*data)),
+            WriteBackData::DWord(data) => (OperandSize::DoubleWord, u64::from(*data)),
+            WriteBackData::QWord(data) => (OperandSize::QuadWord, *data),
+        }
+    }
+}
+
+// Temporary registers which pass values between the uops of an instruction.
+const NUM_OF_TEMPORARIES: usize = 2;
+
+/// Executes the uops of an instruction in order. The following uops read the results
+/// in temporary registers and see the status flags written by the previous uops.
+/// Temporary registers are not written back, and nothing is written back if a uop faults.
+pub fn execute_uops(
+    uops: &[ExecuteInstType],
+    rflags: RFlags,
+    memory: &LinearMemory,
+) -> Result<Vec<WriteBack>> {
+    let mut temps = [0; NUM_OF_TEMPORARIES];
+    let mut rflags = rflags;
+    let mut results = Vec::new();
+    for uop in uops {
+        for wb in execute(&uop.bind_temporaries(&temps), rflags, memory)? {
+            match wb {
+                WriteBack::GeneralRegister(dest, size, value)
+                    if dest.temporary_index().is_some() =>
+                {
+                    temps[dest.temporary_index().unwrap()] = value & size.mask();
+                }
+                WriteBack::Flags(flags) => {
+                    rflags = flags;
+                    results.push(wb);
+                }
+                wb => results.push(wb),
+            }
+        }
+    }
+    Ok(results)
+}
+
+fn execute(
+    inst: &ExecuteInstType,
+    rflags: RFlags,
+    memory: &LinearMemory,
+) -> Result<Vec<WriteBack>> {
     match inst.clone() {
-        ExecuteInstType::ArithLogic(inst) => execute_arith_logic(inst),
-        ExecuteInstType::Branch(inst) => execute_branch(inst),
-        ExecuteInstType::LoadStore(inst) => execute_load_store(inst),
+        ExecuteInstType::ArithLogic(inst) => execute_arith_logic(inst, rflags),
+        ExecuteInstType::Branch(inst) => execute_branch(inst, rflags),
+        ExecuteInstType::LoadStore(inst) => execute_load_store(inst, memory).map(|wb| vec![wb]),
         ExecuteInstType::Privilege(inst) => execute_privilege(inst),
+        ExecuteInstType::Vector(inst) => execute_vector(inst, rflags),
     }
 }
 
-fn execute_arith_logic(inst: ExecuteInst) -> Result<WriteBack, ()> {
+fn execute_arith_logic(inst: ExecuteInst, rflags: RFlags) -> Result<Vec<WriteBack>> {
     match inst.get_opcode() {
-        ExOpcode::Inc => Ok(execute_inc(inst)),
-        ExOpcode::Add => Ok(execute_add(inst)),
-        ExOpcode::Mov => Ok(execute_mov(inst)),
-        _ => Err(()),
+        ExOpcode::Mov => Ok(vec![execute_mov(inst)]),
+        ExOpcode::SignExtend => Ok(vec![execute_sign_extend(inst)]),
+        ExOpcode::TestEqual => Ok(vec![execute_test_equal(inst, rflags)]),
+        ExOpcode::ExchangeIfEqual => Ok(execute_exchange_if_equal(inst, rflags)),
+        ExOpcode::Not => Ok(vec![execute_not(inst)]),
+        ExOpcode::CondMove(condition) => Ok(vec![execute_cmov(inst, condition, rflags)]),
+        ExOpcode::CondSet(condition) => Ok(vec![execute_setcc(inst, condition, rflags)]),
+        ExOpcode::SetFlags => Ok(vec![WriteBack::Flags(rflags | flags_of(inst))]),
+        ExOpcode::ClearFlags => Ok(vec![WriteBack::Flags(rflags - flags_of(inst))]),
+        ExOpcode::Imul => Ok(execute_imul(inst, rflags)),
+        ExOpcode::Mul | ExOpcode::ImulWide => Ok(execute_multiply(inst, rflags)),
+        ExOpcode::Div | ExOpcode::Idiv => execute_divide(inst),
+        ExOpcode::Shl | ExOpcode::Shr | ExOpcode::Sar => Ok(execute_shift(inst, rflags)),
+        ExOpcode::Rol | ExOpcode::Ror | ExOpcode::Rcl | ExOpcode::Rcr => {
+            Ok(execute_rotate(inst, rflags))
+        }
+        ExOpcode::Shld | ExOpcode::Shrd => Ok(execute_double_shift(inst, rflags)),
+        ExOpcode::Bt | ExOpcode::Bts | ExOpcode::Btr | ExOpcode::Btc => {
+            Ok(execute_bit_test(inst, rflags))
+        }
+        ExOpcode::Bsf | ExOpcode::Bsr => Ok(execute_bit_scan(inst, rflags)),
+        _ => execute_alu(inst, rflags),
     }
 }
 
-fn execute_inc(inst: ExecuteInst) -> WriteBack {
-    let result = inst.get_op1() + 1;
-    let dest = inst.get_dest();
-    WriteBack::GeneralRegister(dest, result)
+// Executes an ALU operation which updates the status flags.
+fn execute_alu(inst: ExecuteInst, rflags: RFlags) -> Result<Vec<WriteBack>> {
+    let op1 = inst.get_op1();
+    let op2 = inst.get_op2();
+    let size = inst.get_op_size();
+    let carry = rflags.contains(RFlags::CARRY_FLAG);
+    let (result, mut flags) = match inst.get_opcode() {
+        ExOpcode::Add => add_with_flags(op1, op2, false, size),
+        ExOpcode::Adc => add_with_flags(op1, op2, carry, size),
+        ExOpcode::Sub | ExOpcode::Cmp => sub_with_flags(op1, op2, false, size),
+        ExOpcode::Sbb => sub_with_flags(op1, op2, carry, size),
+        ExOpcode::Neg => sub_with_flags(0, op1, false, size),
+        ExOpcode::And | ExOpcode::Test => logic_with_flags(op1 & op2, size),
+        ExOpcode::Or => logic_with_flags(op1 | op2, size),
+        ExOpcode::Xor => logic_with_flags(op1 ^ op2, size),
+        ExOpcode::Inc => add_with_flags(op1, 1, false, size),
+        ExOpcode::Dec => sub_with_flags(op1, 1, false, size),
+        opcode => return Err(unexpected_uop(opcode)),
+    };
+    // INC and DEC do not affect CF.
+    if let ExOpcode::Inc | ExOpcode::Dec = inst.get_opcode() {
+        flags.set(RFlags::CARRY_FLAG, carry);
+    }
+
+    let mut wbs = vec![WriteBack::Flags((rflags - RFlags::STATUS_FLAGS) | flags)];
+    match inst.get_opcode() {
+        ExOpcode::Cmp | ExOpcode::Test => (),
+        _ => wbs.push(WriteBack::GeneralRegister(inst.get_dest(), size, result)),
+    }
+    Ok(wbs)
+}
+
+fn flags_of(inst: ExecuteInst) -> RFlags {
+    RFlags::from_bits_truncate(inst.get_op1())
+}
+
+fn execute_sign_extend(inst: ExecuteInst) -> WriteBack {
+    let value = inst.get_op_size().sign_extend(inst.get_op1());
+    WriteBack::GeneralRegister(inst.get_dest(), OperandSize::QuadWord, value)
+}
+
+fn execute_test_equal(inst: ExecuteInst, rflags: RFlags) -> WriteBack {
+    let mask = inst.get_op_size().mask();
+    let mut flags = rflags;
+    if inst.get_op1() & mask != inst.get_op2() & mask {
+        flags.remove(RFlags::ZERO_FLAG);
+    }
+    WriteBack::Flags(flags)
+}
+
+fn execute_exchange_if_equal(inst: ExecuteInst, rflags: RFlags) -> Vec<WriteBack> {
+    let size = inst.get_op_size();
+    if rflags.contains(RFlags::ZERO_FLAG) {
+        return vec![WriteBack::GeneralRegister(
+            inst.get_dest(),
+            size,
+            inst.get_op2(),
+        )];
+    }
+    let accumulator = Reg64Id::from_u64(inst.get_op3()).expect("Invalid register number.");
+    vec![
+        WriteBack::GeneralRegister(inst.get_dest(), size, inst.get_op1()),
+        WriteBack::GeneralRegister(accumulator, size, inst.get_op1()),
+    ]
+}
+
+fn execute_not(inst: ExecuteInst) -> WriteBack {
+    let op1 = inst.get_op1();
+    let size = inst.get_op_size();
+    let result = !op1 & size.mask();
+    WriteBack::GeneralRegister(inst.get_dest(), size, result)
+}
+
+// The destination is written even if the condition is false,
+// so that the upper half of 64-bit register is cleared with 32-bit operand size.
+fn execute_cmov(inst: ExecuteInst, condition: Condition, rflags: RFlags) -> WriteBack {
+    let op1 = inst.get_op1();
+    let size = inst.get_op_size();
+    let src = if condition.is_satisfied(rflags) {
+        inst.get_op2()
+    } else {
+        op1
+    };
+    WriteBack::GeneralRegister(inst.get_dest(), size, src)
 }
 
-fn execute_add(inst: ExecuteInst) -> WriteBack {
-    let result = inst.get_op1() + inst.get_op2();
+fn execute_setcc(inst: ExecuteInst, condition: Condition, rflags: RFlags) -> WriteBack {
+    let result = condition.is_satisfied(rflags) as u64;
+    WriteBack::GeneralRegister(inst.get_dest(), OperandSize::Byte, result)
+}
+
+// CF and OF are set if the signed result is truncated.
+fn execute_imul(inst: ExecuteInst, rflags: RFlags) -> Vec<WriteBack> {
+    let size = inst.get_op_size();
+    let op1 = size.sign_extend(inst.get_op1()) as i64 as i128;
+    let op2 = size.sign_extend(inst.get_op2()) as i64 as i128;
+    let product = op1 * op2;
+    let result = product as u64 & size.mask();
+    let mut flags = result_flags(result, size);
+    let overflow = size.sign_extend(result) as i64 as i128 != product;
+    flags.set(RFlags::CARRY_FLAG | RFlags::OVERFLOW_FLAG, overflow);
+    vec![
+        WriteBack::Flags((rflags - RFlags::STATUS_FLAGS) | flags),
+        WriteBack::GeneralRegister(inst.get_dest(), size, result),
+    ]
+}
+
+// The upper half of the double sized operand is RDX, or AH for byte.
+fn upper_half_register(size: OperandSize) -> Reg64Id {
+    match size {
+        OperandSize::Byte => Reg64Id::Ah,
+        _ => Reg64Id::Rdx,
+    }
+}
+
+// RDX:RAX = RAX * r/m. CF and OF are set if the upper half is significant.
+fn execute_multiply(inst: ExecuteInst, rflags: RFlags) -> Vec<WriteBack> {
+    let size = inst.get_op_size();
+    let (op1, op2) = (inst.get_op1() & size.mask(), inst.get_op2() & size.mask());
+    let (product, overflow) = match inst.get_opcode() {
+        ExOpcode::Mul => {
+            let product = op1 as u128 * op2 as u128;
+            (product, product > size.mask() as u128)
+        }
+        _ => {
+            let op1 = size.sign_extend(op1) as i64 as i128;
+            let op2 = size.sign_extend(op2) as i64 as i128;
+            let product = op1 * op2;
+            let lower = size.sign_extend(product as u64) as i64 as i128;
+            (product as u128, lower != product)
+        }
+    };
+    let lower = product as u64 & size.mask();
+    let upper = (product >> size.bits()) as u64 & size.mask();
+    let mut flags = result_flags(lower, size);
+    flags.set(RFlags::CARRY_FLAG | RFlags::OVERFLOW_FLAG, overflow);
+    vec![
+        WriteBack::Flags((rflags - RFlags::STATUS_FLAGS) | flags),
+        WriteBack::GeneralRegister(Reg64Id::Rax, size, lower),
+        WriteBack::GeneralRegister(upper_half_register(size), size, upper),
+    ]
+}
+
+// RAX = RDX:RAX / r/m, RDX = RDX:RAX % r/m. The status flags are unchanged.
+// #DE is raised if the divisor is zero or the quotient does not fit in the operand size.
+fn execute_divide(inst: ExecuteInst) -> Result<Vec<WriteBack>> {
+    let size = inst.get_op_size();
+    let bits = size.bits();
+    let dividend =
+        (((inst.get_op3() & size.mask()) as u128) << bits) | (inst.get_op1() & size.mask()) as u128;
+    let divisor = inst.get_op2() & size.mask();
+    let result = match inst.get_opcode() {
+        ExOpcode::Div => {
+            let divisor = divisor as u128;
+            dividend
+                .checked_div(divisor)
+                .filter(|quotient| *quotient <= size.mask() as u128)
+                .map(|quotient| (quotient as u64, (dividend % divisor) as u64))
+        }
+        _ => {
+            let shift = 128 - 2 * bits;
+            let dividend = ((dividend << shift) as i128) >> shift;
+            let divisor = size.sign_extend(divisor) as i64 as i128;
+            dividend
+                .checked_div(divisor)
+                .filter(|quotient| size.sign_extend(*quotient as u64) as i64 as i128 == *quotient)
+                .map(|quotient| (quotient as u64, (dividend % divisor) as u64))
+        }
+    };
+    let (quotient, remainder) = result.ok_or(InternalException::DivideError)?;
+    Ok(vec![
+        WriteBack::GeneralRegister(Reg64Id::Rax, size, quotient & size.mask()),
+        WriteBack::GeneralRegister(upper_half_register(size), size, remainder & size.mask()),
+    ])
+}
+
+// The count is masked to 6 bits for 64-bit operand size, otherwise to 5 bits.
+fn shift_count(count: u64, size: OperandSize) -> u32 {
+    match size {
+        OperandSize::QuadWord => (count & 0x3f) as u32,
+        _ => (count & 0x1f) as u32,
+    }
+}
+
+// A zero count changes neither the flags nor the destination, but a 32-bit register
+// is still written, which clears its upper half.
+fn zero_count_write_back(inst: &ExecuteInst, op1: u64, size: OperandSize) -> Vec<WriteBack> {
     let dest = inst.get_dest();
-    WriteBack::GeneralRegister(dest, result)
+    match size {
+        OperandSize::DoubleWord if dest.temporary_index().is_none() => {
+            vec![WriteBack::GeneralRegister(dest, size, op1)]
+        }
+        _ => vec![],
+    }
+}
+
+// SHL/SHR/SAR. CF is the last bit shifted out. OF is defined only for 1-bit shifts,
+// but is computed for every count.
+fn execute_shift(inst: ExecuteInst, rflags: RFlags) -> Vec<WriteBack> {
+    let size = inst.get_op_size();
+    let op1 = inst.get_op1() & size.mask();
+    let count = shift_count(inst.get_op2(), size);
+    if count == 0 {
+        return zero_count_write_back(&inst, op1, size);
+    }
+    let (result, carry, overflow) = match inst.get_opcode() {
+        ExOpcode::Shl => {
+            let shifted = (op1 as u128) << count;
+            let result = shifted as u64 & size.mask();
+            let carry = (shifted >> size.bits()) & 1 != 0;
+            (result, carry, (result & size.sign_bit() != 0) != carry)
+        }
+        ExOpcode::Shr => (
+            op1 >> count,
+            (op1 >> (count - 1)) & 1 != 0,
+            op1 & size.sign_bit() != 0,
+        ),
+        _ => {
+            let signed = size.sign_extend(op1) as i64;
+            let result = (signed >> count) as u64 & size.mask();
+            (result, (signed >> (count - 1)) & 1 != 0, false)
+        }
+    };
+    let mut flags = result_flags(result, size);
+    flags.set(RFlags::CARRY_FLAG, carry);
+    flags.set(RFlags::OVERFLOW_FLAG, overflow);
+    vec![
+        WriteBack::Flags((rflags - RFlags::STATUS_FLAGS) | flags),
+        WriteBack::GeneralRegister(inst.get_dest(), size, result),
+    ]
+}
+
+// ROL/ROR/RCL/RCR affect only CF and OF.
+// RCL and RCR rotate through CF, so the count is modulo (size + 1).
+fn execute_rotate(inst: ExecuteInst, rflags: RFlags) -> Vec<WriteBack> {
+    let size = inst.get_op_size();
+    let bits = size.bits();
+    let op1 = inst.get_op1() & size.mask();
+    let count = shift_count(inst.get_op2(), size);
+    if count == 0 {
+        return zero_count_write_back(&inst, op1, size);
+    }
+    let msb = |value: u64| value & size.sign_bit() != 0;
+    let mut carry = rflags.contains(RFlags::CARRY_FLAG);
+    let result = match inst.get_opcode() {
+        ExOpcode::Rol | ExOpcode::Ror => {
+            let n = count % bits;
+            let result = match (inst.get_opcode(), n) {
+                (_, 0) => op1,
+                (ExOpcode::Rol, n) => ((op1 << n) | (op1 >> (bits - n))) & size.mask(),
+                (_, n) => ((op1 >> n) | (op1 << (bits - n))) & size.mask(),
+            };
+            carry = match inst.get_opcode() {
+                ExOpcode::Rol => result & 1 != 0,
+                _ => msb(result),
+            };
+            result
+        }
+        ExOpcode::Rcl => (0..count % (bits + 1)).fold(op1, |value, _| {
+            let next = ((value << 1) | carry as u64) & size.mask();
+            carry = msb(value);
+            next
+        }),
+        _ => (0..count % (bits + 1)).fold(op1, |value, _| {
+            let next = (value >> 1) | if carry { size.sign_bit() } else { 0 };
+            carry = value & 1 != 0;
+            next
+        }),
+    };
+    let overflow = match inst.get_opcode() {
+        ExOpcode::Rol | ExOpcode::Rcl => msb(result) != carry,
+        _ => msb(result) != msb(result << 1),
+    };
+    let mut flags = rflags;
+    flags.set(RFlags::CARRY_FLAG, carry);
+    flags.set(RFlags::OVERFLOW_FLAG, overflow);
+    vec![
+        WriteBack::Flags(flags),
+        WriteBack::GeneralRegister(inst.get_dest(), size, result),
+    ]
+}
+
+// SHLD/SHRD shift the destination and fill the vacated bits from the source.
+fn execute_double_shift(inst: ExecuteInst, rflags: RFlags) -> Vec<WriteBack> {
+    let size = inst.get_op_size();
+    let bits = size.bits();
+    let dest = inst.get_op1() & size.mask();
+    let src = inst.get_op2() & size.mask();
+    let count = shift_count(inst.get_op3(), size);
+    if count == 0 {
+        return zero_count_write_back(&inst, dest, size);
+    }
+    let (result, carry) = match inst.get_opcode() {
+        ExOpcode::Shld => {
+            let concat = ((dest as u128) << bits) | src as u128;
+            let result = ((concat << count) >> bits) as u64 & size.mask();
+            (result, (concat >> (2 * bits - count)) & 1 != 0)
+        }
+        _ => {
+            let concat = ((src as u128) << bits) | dest as u128;
+            let result = (concat >> count) as u64 & size.mask();
+            (result, (concat >> (count - 1)) & 1 != 0)
+        }
+    };
+    let mut flags = result_flags(result, size);
+    flags.set(RFlags::CARRY_FLAG, carry);
+    flags.set(
+        RFlags::OVERFLOW_FLAG,
+        (result ^ dest) & size.sign_bit() != 0,
+    );
+    vec![
+        WriteBack::Flags((rflags - RFlags::STATUS_FLAGS) | flags),
+        WriteBack::GeneralRegister(inst.get_dest(), size, result),
+    ]
+}
+
+// CF is the selected bit. The bit offset of register operand is modulo operand size.
+fn execute_bit_test(inst: ExecuteInst, rflags: RFlags) -> Vec<WriteBack> {
+    let size = inst.get_op_size();
+    let op1 = inst.get_op1();
+    let bit = 1 << (inst.get_op2() % u64::from(size.bits()));
+    let mut flags = rflags;
+    flags.set(RFlags::CARRY_FLAG, op1 & bit != 0);
+    let mut wbs = vec![WriteBack::Flags(flags)];
+    let result = match inst.get_opcode() {
+        ExOpcode::Bts => op1 | bit,
+        ExOpcode::Btr => op1 & !bit,
+        ExOpcode::Btc => op1 ^ bit,
+        _ => return wbs,
+    };
+    wbs.push(WriteBack::GeneralRegister(inst.get_dest(), size, result));
+    wbs
+}
+
+// If the source is zero, ZF is set and the destination is unchanged.
+fn execute_bit_scan(inst: ExecuteInst, rflags: RFlags) -> Vec<WriteBack> {
+    let size = inst.get_op_size();
+    let src = inst.get_op2() & size.mask();
+    let mut flags = rflags;
+    flags.set(RFlags::ZERO_FLAG, src == 0);
+    let mut wbs = vec![WriteBack::Flags(flags)];
+    if src != 0 {
+        let index = match inst.get_opcode() {
+            ExOpcode::Bsf => src.trailing_zeros(),
+            _ => 63 - src.leading_zeros(),
+        };
+        wbs.push(WriteBack::GeneralRegister(
+            inst.get_dest(),
+            size,
+            u64::from(index),
+        ));
+    }
+    wbs
+}
+
+fn add_with_flags(op1: u64, op2: u64, carry: bool, size: OperandSize) -> (u64, RFlags) {
+    let (op1, op2) = (op1 & size.mask(), op2 & size.mask());
+    let full = op1 as u128 + op2 as u128 + carry as u128;
+    let result = full as u64 & size.mask();
+    let mut flags = result_flags(result, size);
+    flags.set(RFlags::CARRY_FLAG, full > size.mask() as u128);
+    flags.set(
+        RFlags::AUXILIARY_CARRY_FLAG,
+        (op1 ^ op2 ^ result) & 0x10 != 0,
+    );
+    flags.set(
+        RFlags::OVERFLOW_FLAG,
+        (op1 ^ result) & (op2 ^ result) & size.sign_bit() != 0,
+    );
+    (result, flags)
+}
+
+fn sub_with_flags(op1: u64, op2: u64, borrow: bool, size: OperandSize) -> (u64, RFlags) {
+    let (op1, op2) = (op1 & size.mask(), op2 & size.mask());
+    let result = op1.wrapping_sub(op2).wrapping_sub(borrow as u64) & size.mask();
+    let mut flags = result_flags(result, size);
+    flags.set(
+        RFlags::CARRY_FLAG,
+        (op1 as u128) < op2 as u128 + borrow as u128,
+    );
+    flags.set(
+        RFlags::AUXILIARY_CARRY_FLAG,
+        (op1 ^ op2 ^ result) & 0x10 != 0,
+    );
+    flags.set(
+        RFlags::OVERFLOW_FLAG,
+        (op1 ^ op2) & (op1 ^ result) & size.sign_bit() != 0,
+    );
+    (result, flags)
+}
+
+// Logical operations clear CF and OF. AF is undefined and cleared.
+fn logic_with_flags(result: u64, size: OperandSize) -> (u64, RFlags) {
+    let result = result & size.mask();
+    (result, result_flags(result, size))
+}
+
+// ZF, SF and PF which depend only on the result.
+fn result_flags(result: u64, size: OperandSize) -> RFlags {
+    let mut flags = RFlags::empty();
+    flags.set(RFlags::ZERO_FLAG, result == 0);
+    flags.set(RFlags::SIGN_FLAG, result & size.sign_bit() != 0);
+    flags.set(RFlags::PARITY_FLAG, (result as u8).count_ones() % 2 == 0);
+    flags
 }
 
 fn execute_mov(inst: ExecuteInst) -> WriteBack {
     let result = inst.get_op1();
     let dest = inst.get_dest();
-    WriteBack::GeneralRegister(dest, result)
+    WriteBack::GeneralRegister(dest, inst.get_op_size(), result)
 }
 
-fn execute_branch(inst: ExecuteInst) -> Result<WriteBack, ()> {
+fn execute_branch(inst: ExecuteInst, rflags: RFlags) -> Result<Vec<WriteBack>> {
     match inst.get_opcode() {
-        ExOpcode::Jump => Ok(execute_jump(inst)),
-        ExOpcode::Return => Ok(execute_return(inst)),
-        _ => Err(()),
+        ExOpcode::Jump => Ok(vec![execute_jump(inst)]),
+        ExOpcode::CondJump(condition) if condition.is_satisfied(rflags) => {
+            Ok(vec![execute_jump(inst)])
+        }
+        ExOpcode::CondJump(_) => Ok(vec![]),
+        ExOpcode::JumpIndirect => Ok(vec![WriteBack::Rip(inst.get_op1())]),
+        ExOpcode::JumpFar => Ok(vec![WriteBack::FarJump(
+            inst.get_op1() as u16,
+            inst.get_op2(),
+        )]),
+        ExOpcode::Return => Ok(vec![execute_return(inst)]),
+        opcode => Err(unexpected_uop(opcode)),
     }
 }
 
+// The target wraps at the operand size.
 fn execute_jump(inst: ExecuteInst) -> WriteBack {
-    let result = inst.get_rip() + inst.get_op1();
-    WriteBack::Rip(result)
+    let result = inst.get_rip().wrapping_add(inst.get_op1());
+    WriteBack::Rip(result & inst.get_op_size().mask())
 }
 
 fn execute_return(inst: ExecuteInst) -> WriteBack {
     let sp = inst.get_op1();
-    WriteBack::Return(sp)
+    WriteBack::Return(sp, inst.get_op_size())
 }
 
-fn execute_load_store(inst: ExecuteInst) -> Result<WriteBack, ()> {
+fn execute_load_store(inst: ExecuteInst, memory: &LinearMemory) -> Result<WriteBack> {
     match inst.get_opcode() {
         ExOpcode::Load => Ok(execute_load(inst)),
+        ExOpcode::ReadMemory => {
+            let size = inst.get_op_size();
+            let value = memory.read(inst.get_op1(), size)?;
+            Ok(WriteBack::GeneralRegister(inst.get_dest(), size, value))
+        }
         ExOpcode::Store => Ok(execute_store(inst)),
-        _ => Err(()),
+        ExOpcode::In => Ok(WriteBack::PortIn(
+            inst.get_dest(),
+            inst.get_op_size(),
+            inst.get_op1() as u16,
+        )),
+        ExOpcode::Out => Ok(WriteBack::PortOut(
+            inst.get_op1() as u16,
+            inst.get_op_size(),
+            inst.get_op2(),
+        )),
+        ExOpcode::Ins => Ok(WriteBack::PortInToMemory(
+            inst.get_op2(),
+            inst.get_op_size(),
+            inst.get_op1() as u16,
+        )),
+        opcode => Err(unexpected_uop(opcode)),
     }
 }
 
 fn execute_load(inst: ExecuteInst) -> WriteBack {
     let addr = inst.get_op1();
     let dest = inst.get_dest();
-    WriteBack::Load(dest, addr)
+    WriteBack::Load(dest, inst.get_op_size(), addr)
 }
 
 fn execute_store(inst: ExecuteInst) -> WriteBack {
@@ -101,9 +635,224 @@ fn execute_store(inst: ExecuteInst) -> WriteBack {
     WriteBack::Store(addr, data)
 }
 
-fn execute_privilege(inst: ExecuteInst) -> Result<WriteBack, ()> {
+// Bits 16..32 of MXCSR are reserved, and DAZ is supported.
+const MXCSR_RESERVED: u32 = 0xffff_0000;
+
+fn execute_vector(inst: VectorInst, rflags: RFlags) -> Result<Vec<WriteBack>> {
+    let (op1, op2) = (inst.get_op1(), inst.get_op2());
+    let size = inst.get_op_size();
+    let dest = inst.get_dest();
+    let xmm = |result| Ok(vec![WriteBack::Xmm(dest, result)]);
+    match inst.get_opcode() {
+        ExOpcode::Mov => xmm(op1),
+        ExOpcode::And => xmm(op1 & op2),
+        ExOpcode::AndNot => xmm(!op1 & op2),
+        ExOpcode::Or => xmm(op1 | op2),
+        ExOpcode::Xor => xmm(op1 ^ op2),
+        ExOpcode::Add => xmm(map_elements(op1, op2, size, u64::wrapping_add)),
+        ExOpcode::Sub => xmm(map_elements(op1, op2, size, u64::wrapping_sub)),
+        ExOpcode::CompareEqual => xmm(map_elements(op1, op2, size, compare_equal)),
+        ExOpcode::MoveMask => {
+            let mask = (0..16).fold(0, |mask, i| mask | ((op1 >> (i * 8 + 7)) as u64 & 1) << i);
+            Ok(vec![general_register_of(dest, size, mask)])
+        }
+        ExOpcode::FloatCompare => {
+            let ordering = match size {
+                OperandSize::DoubleWord => single_of(op1).partial_cmp(&single_of(op2)),
+                _ => double_of(op1).partial_cmp(&double_of(op2)),
+            };
+            let flags = match ordering {
+                Some(std::cmp::Ordering::Less) => RFlags::CARRY_FLAG,
+                Some(std::cmp::Ordering::Equal) => RFlags::ZERO_FLAG,
+                Some(std::cmp::Ordering::Greater) => RFlags::empty(),
+                None => RFlags::ZERO_FLAG | RFlags::PARITY_FLAG | RFlags::CARRY_FLAG,
+            };
+            let flags = (rflags - RFlags::STATUS_FLAGS) | flags;
+            Ok(vec![WriteBack::Flags(flags)])
+        }
+        ExOpcode::IntToFloat => {
+            let value = op2 as i64;
+            xmm(merge_float(op1, size, value as f32, value as f64))
+        }
+        ExOpcode::FloatConvert => {
+            let (single, double) = (double_of(op2) as f32, f64::from(single_of(op2)));
+            xmm(merge_float(op1, size, single, double))
+        }
+        ExOpcode::FloatToInt | ExOpcode::FloatToIntTruncate => {
+            let value = double_of(op1);
+            let rounded = match (inst.get_opcode(), op2) {
+                (ExOpcode::FloatToIntTruncate, _) | (_, 3) => value.trunc(),
+                (_, 1) => value.floor(),
+                (_, 2) => value.ceil(),
+                _ => round_half_even(value),
+            };
+            let result = float_to_int(rounded, size);
+            Ok(vec![general_register_of(dest, size, result)])
+        }
+        ExOpcode::LoadMxcsr => {
+            let mxcsr = op1 as u32;
+            if mxcsr & MXCSR_RESERVED != 0 {
+                return Err(InternalException::GeneralProtection { error_code: 0 });
+            }
+            Ok(vec![WriteBack::Mxcsr(mxcsr)])
+        }
+        opcode => {
+            let result = match size {
+                OperandSize::DoubleWord => {
+                    let result = float_operation(opcode, single_of(op1), single_of(op2))?;
+                    u128::from(result.to_bits())
+                }
+                _ => {
+                    let result = float_operation(opcode, double_of(op1), double_of(op2))?;
+                    u128::from(result.to_bits())
+                }
+            };
+            xmm(op1 & !element_mask(size) | result)
+        }
+    }
+}
+
+// Applies `f` to each pair of elements of the size.
+fn map_elements<F>(op1: u128, op2: u128, size: OperandSize, f: F) -> u128
+where
+    F: Fn(u64, u64) -> u64,
+{
+    let bits = size.bits();
+    let mask = u128::from(size.mask());
+    (0..128 / bits).fold(0, |result, i| {
+        let shift = i * bits;
+        let a = ((op1 >> shift) & mask) as u64;
+        let b = ((op2 >> shift) & mask) as u64;
+        result | (u128::from(f(a, b)) & mask) << shift
+    })
+}
+
+// All bits of the element are set if equal.
+fn compare_equal(a: u64, b: u64) -> u64 {
+    if a == b {
+        !0
+    } else {
+        0
+    }
+}
+
+// MINSS/MAXSS return the second operand if either is NaN or both are zero.
+fn float_operation<F: Float>(opcode: ExOpcode, a: F, b: F) -> Result<F> {
+    let result = match opcode {
+        ExOpcode::FloatAdd => a + b,
+        ExOpcode::FloatSub => a - b,
+        ExOpcode::FloatMul => a * b,
+        ExOpcode::FloatDiv => a / b,
+        ExOpcode::FloatMin if a < b => a,
+        ExOpcode::FloatMax if a > b => a,
+        ExOpcode::FloatMin | ExOpcode::FloatMax => b,
+        ExOpcode::FloatSqrt => b.sqrt(),
+        opcode => return Err(unexpected_uop(opcode)),
+    };
+    Ok(result)
+}
+
+fn single_of(value: u128) -> f32 {
+    f32::from_bits(value as u32)
+}
+
+fn double_of(value: u128) -> f64 {
+    f64::from_bits(value as u64)
+}
+
+fn element_mask(size: OperandSize) -> u128 {
+    u128::from(size.mask())
+}
+
+// Writes the single or double of the size into the lowest element of `op1`.
+fn merge_float(op1: u128, size: OperandSize, single: f32, double: f64) -> u128 {
+    let result = match size {
+        OperandSize::DoubleWord => u128::from(single.to_bits()),
+        _ => u128::from(double.to_bits()),
+    };
+    op1 & !element_mask(size) | result
+}
+
+fn round_half_even(value: f64) -> f64 {
+    let rounded = value.round();
+    if (rounded - value).abs() == 0.5 && rounded % 2.0 != 0.0 {
+        rounded - value.signum()
+    } else {
+        rounded
+    }
+}
+
+// NaN and values out of range are converted to the integer indefinite,
+// which is the most negative integer.
+fn float_to_int(value: f64, size: OperandSize) -> u64 {
+    let limit = size.sign_bit() as f64;
+    if value.is_nan() || value >= limit || value < -limit {
+        size.sign_bit()
+    } else {
+        value as i64 as u64 & size.mask()
+    }
+}
+
+fn general_register_of(reg: u8, size: OperandSize, value: u64) -> WriteBack {
+    let reg = Reg64Id::from_u8(reg).expect("Invalid register number.");
+    WriteBack::GeneralRegister(reg, size, value)
+}
+
+fn execute_privilege(inst: ExecuteInst) -> Result<Vec<WriteBack>> {
     match inst.get_opcode() {
-        ExOpcode::Halt => Ok(WriteBack::CpuState(CpuState::Halt)),
-        _ => Err(()),
+        ExOpcode::Halt => Ok(vec![WriteBack::CpuState(CpuState::Halt)]),
+        ExOpcode::Cpuid => Ok(vec![WriteBack::Cpuid(
+            inst.get_op1() as u32,
+            inst.get_op2() as u32,
+        )]),
+        ExOpcode::Rdtsc => Ok(vec![WriteBack::TimeStampCounter]),
+        ExOpcode::Syscall => Ok(vec![WriteBack::Syscall]),
+        ExOpcode::SystemReturn => Ok(vec![WriteBack::SystemReturn(
+            inst.get_op1(),
+            inst.get_op2(),
+            inst.get_op_size(),
+        )]),
+        ExOpcode::SwapGs => Ok(vec![WriteBack::SwapGs]),
+        ExOpcode::ReadMsr => Ok(vec![WriteBack::ReadMsr(inst.get_op1() as u32)]),
+        ExOpcode::WriteMsr => Ok(vec![WriteBack::WriteMsr(
+            inst.get_op1() as u32,
+            inst.get_op2(),
+        )]),
+        ExOpcode::ReadControlRegister => Ok(vec![WriteBack::ReadControlRegister(
+            inst.get_dest(),
+            inst.get_op1() as u8,
+        )]),
+        ExOpcode::WriteControlRegister => Ok(vec![WriteBack::ControlRegister(
+            inst.get_op1() as u8,
+            inst.get_op2(),
+        )]),
+        ExOpcode::InvalidatePage => Ok(vec![WriteBack::InvalidatePage(inst.get_op1())]),
+        ExOpcode::LoadGdt => Ok(vec![WriteBack::Gdtr(descriptor_table_of(inst))]),
+        ExOpcode::LoadIdt => Ok(vec![WriteBack::Idtr(descriptor_table_of(inst))]),
+        ExOpcode::LoadTaskRegister => Ok(vec![WriteBack::TaskRegister(inst.get_op1() as u16)]),
+        ExOpcode::LoadSegment => {
+            let segment = SegmentRegister::from_u64(inst.get_op1())
+                .expect("Segment register was not decoded.");
+            Ok(vec![WriteBack::Segment(segment, inst.get_op2() as u16)])
+        }
+        ExOpcode::SoftwareInterrupt => Ok(vec![WriteBack::SoftwareInterrupt(inst.get_op1() as u8)]),
+        ExOpcode::InterruptReturn => Ok(vec![WriteBack::InterruptReturn(
+            inst.get_op1(),
+            inst.get_op_size(),
+        )]),
+        opcode => Err(unexpected_uop(opcode)),
+    }
+}
+
+fn descriptor_table_of(inst: ExecuteInst) -> DescriptorTableRegister {
+    DescriptorTableRegister {
+        base: inst.get_op1(),
+        limit: inst.get_op2() as u16,
+    }
+}
+
+fn unexpected_uop(opcode: ExOpcode) -> InternalException {
+    InternalException::UnexpectedMicroOperation {
+        uop: format!("{:?}", opcode),
     }
 }
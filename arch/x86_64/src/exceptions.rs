@@ -2,8 +2,8 @@ use crate::isa::opcode::Opcode;
 
 #[derive(Debug, Fail)]
 pub enum InternalException {
-    #[fail(display = "fetcher: Fetch error, unknown opcode {}", opcode)]
-    FetchError { opcode: u8 },
+    #[fail(display = "fetcher: Fetch error, unknown opcode {:#x}", opcode)]
+    FetchError { opcode: u32 },
     #[fail(display = "decoder: Undefined instruction: {:?}", opcode)]
     UndefinedInstruction { opcode: Opcode },
     #[fail(
@@ -11,4 +11,21 @@ pub enum InternalException {
         opcode
     )]
     ModRmRequired { opcode: Opcode },
+    #[fail(display = "executor: {} is not executed in this unit.", uop)]
+    UnexpectedMicroOperation { uop: String },
+    #[fail(display = "#DE: Divide error")]
+    DivideError,
+    #[fail(
+        display = "#PF: Page fault at {:#x}, error code {:#x}",
+        addr, error_code
+    )]
+    PageFault { addr: u64, error_code: u64 },
+    #[fail(display = "#NP: Segment not present, error code {:#x}", error_code)]
+    SegmentNotPresent { error_code: u64 },
+    #[fail(display = "#SS: Stack fault, error code {:#x}", error_code)]
+    StackFault { error_code: u64 },
+    #[fail(display = "#GP: General protection, error code {:#x}", error_code)]
+    GeneralProtection { error_code: u64 },
+    #[fail(display = "Triple fault")]
+    TripleFault,
 }
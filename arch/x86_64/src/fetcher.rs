@@ -1,7 +1,10 @@
+use crate::isa::condition::Condition;
 use crate::isa::modrm::{ModRm, ModRmModeField, Sib};
-use crate::isa::opcode::{self, Opcode, OperandSize};
+use crate::isa::opcode::{self, ImmediateSize, Opcode, OperandSize};
 use crate::isa::opcode::{REX, REX_WRXB};
+use crate::isa::prefix::LegacyPrefix;
 use crate::isa::registers::Reg64Id;
+use crate::segmentation::CodeSize;
 use crate::{InternalException, Result};
 use bit_field::BitField;
 use byteorder::{LittleEndian, ReadBytesExt};
@@ -17,16 +20,20 @@ impl FetchUnit {
         FetchUnit { rip: 0 }
     }
 
-    pub fn fetch(&mut self, program: &[u8]) -> Result<FetchedInst> {
-        let inst = FetchedInstBuilder::new(self.rip as usize, &program)
-            .parse_mandatory_prefix()
+    /// Fetches an instruction of the code size, which determines the default operand
+    /// and address sizes. REX prefixes are only recognized in 64-bit mode.
+    pub fn fetch(&mut self, program: &[u8], code_size: CodeSize) -> Result<FetchedInst> {
+        let inst = FetchedInstBuilder::new(self.rip as usize, &program, code_size)
+            .parse_legacy_prefix()
             .parse_rex_prefix()
             .parse_opcode()?
             .parse_modrm()
+            .parse_addr_size()
             .parse_sib()
+            .parse_op_size()
             .parse_disp()
+            .resolve_byte_registers()
             .parse_imm()
-            .parse_op_size()
             .build();
         self.rip = inst.next_rip;
         Ok(inst)
@@ -42,66 +49,75 @@ impl FetchUnit {
 }
 
 pub struct FetchedInst {
-    pub lecacy_prefix: u32,
+    pub rip: usize,
+    pub legacy_prefix: LegacyPrefix,
     pub rex_prefix: Option<u8>,
     pub opcode: Opcode,
     pub r: u8,
+    pub condition: Option<Condition>,
     pub mod_rm: Option<ModRm>,
     pub sib: Option<Sib>,
     pub displacement: u64,
     pub immediate: u64,
     pub next_rip: usize,
     pub op_size: Option<OperandSize>,
+    pub addr_size: OperandSize,
+    pub code_size: CodeSize,
 }
 
 struct FetchedInstBuilder<'a> {
-    lecacy_prefix: u32,
-    mandatory_prefix: Option<u8>,
+    legacy_prefix: LegacyPrefix,
     rex_prefix: Option<u8>,
     opcode: Opcode, // Opcode enum.
     r: u8,
+    condition: Option<Condition>,
     mod_rm: Option<ModRm>,
     sib: Option<Sib>,
     displacement: u64,
     immediate: u64,
     op_size: Option<OperandSize>,
+    addr_size: OperandSize,
+    code_size: CodeSize,
     rip_base: usize,
     rip_offset: usize,
     program: &'a [u8],
 }
 
 impl<'a> FetchedInstBuilder<'a> {
-    fn new(rip: usize, program: &[u8]) -> FetchedInstBuilder {
+    fn new(rip: usize, program: &[u8], code_size: CodeSize) -> FetchedInstBuilder {
         FetchedInstBuilder {
-            lecacy_prefix: 0,
-            mandatory_prefix: None,
+            legacy_prefix: LegacyPrefix::empty(),
             rex_prefix: None,
             opcode: Opcode::Invalid,
             r: 0,
+            condition: None,
             mod_rm: None,
             sib: None,
             displacement: 0,
             immediate: 0,
             op_size: None,
+            addr_size: code_size.address_size(),
+            code_size,
             rip_base: rip,
             rip_offset: 0,
             program,
         }
     }
 
-    fn parse_mandatory_prefix(&mut self) -> &mut FetchedInstBuilder<'a> {
-        let candidate = self.program[self.rip_offset];
-        match candidate {
-            opcode::OVERRIDE_OP_SIZE => {
-                self.mandatory_prefix = Some(candidate);
-                self.rip_offset += 1
-            }
-            _ => (),
+    // Legacy prefixes may appear in any order. The last one wins within a group.
+    fn parse_legacy_prefix(&mut self) -> &mut FetchedInstBuilder<'a> {
+        while let Some((prefix, group)) = LegacyPrefix::from_byte(self.program[self.rip_offset]) {
+            self.legacy_prefix = (self.legacy_prefix - group) | prefix;
+            self.rip_offset += 1;
         }
         self
     }
 
+    // 0x40..0x4f are INC and DEC outside 64-bit mode.
     fn parse_rex_prefix(&mut self) -> &mut FetchedInstBuilder<'a> {
+        if self.code_size != CodeSize::Bits64 {
+            return self;
+        }
         let candidate = self.program[self.rip_offset];
         match candidate {
             REX...REX_WRXB => {
@@ -114,117 +130,249 @@ impl<'a> FetchedInstBuilder<'a> {
     }
 
     fn parse_opcode(&mut self) -> Result<&mut FetchedInstBuilder<'a>> {
-        let candidate = self.program[self.rip_offset];
-        let mut r: u8 = 0;
-        {
-            let extract_r = |opcode| {
-                r = candidate.get_bits(0..3);
-                Some(opcode)
-            };
-            let plus_r_opcode = || Opcode::from_u8(candidate & 0xf8).and_then(extract_r);
-            self.opcode = Opcode::from_u8(candidate)
-                .or_else(plus_r_opcode)
-                .ok_or(InternalException::FetchError { opcode: candidate })?
-        }
-        self.r = r;
+        let mut candidate = u32::from(self.program[self.rip_offset]);
+        if candidate == u32::from(opcode::TWO_BYTE_ESCAPE) {
+            self.rip_offset += 1;
+            candidate = (candidate << 8) | u32::from(self.program[self.rip_offset]);
+            // Three-byte opcode maps 0x0f38 and 0x0f3a.
+            if candidate == opcode::THREE_BYTE_ESCAPE_38
+                || candidate == opcode::THREE_BYTE_ESCAPE_3A
+            {
+                self.rip_offset += 1;
+                candidate = (candidate << 8) | u32::from(self.program[self.rip_offset]);
+            }
+        }
+        let plus_r_opcode =
+            || Opcode::from_u32(candidate & !0x7).filter(|opcode| opcode.is_plus_r());
+        let condition_opcode =
+            || Opcode::from_u32(candidate & !0xf).filter(|opcode| opcode.has_condition());
+        self.opcode = self
+            .parse_mandatory_prefix(candidate)
+            .or_else(|| Opcode::from_u32(candidate))
+            .or_else(plus_r_opcode)
+            .or_else(condition_opcode)
+            .ok_or(InternalException::FetchError { opcode: candidate })?;
+        if self.opcode.is_plus_r() {
+            let rex_b = self.rex_prefix.map_or(false, |rex| rex.get_bit(0));
+            self.r = candidate.get_bits(0..3) as u8 | (rex_b as u8) << 3;
+        }
+        if self.opcode.has_condition() {
+            self.condition = Condition::from_u32(candidate.get_bits(0..4));
+        }
         self.rip_offset += 1;
         Ok(self)
     }
 
+    // SSE instructions are selected by a mandatory prefix 0x66, 0xf2 or 0xf3 before the escape.
+    // F2 and F3 take precedence over 66. The prefix is consumed if it selects an SSE opcode,
+    // so that 0x66 does not change the operand size and 0xf3 does not repeat.
+    fn parse_mandatory_prefix(&mut self, candidate: u32) -> Option<Opcode> {
+        if candidate <= 0xff {
+            return None;
+        }
+        let (prefix, byte) = if self.legacy_prefix.contains(LegacyPrefix::REPNE) {
+            (LegacyPrefix::REPNE, 0xf2)
+        } else if self.legacy_prefix.contains(LegacyPrefix::REP) {
+            (LegacyPrefix::REP, 0xf3)
+        } else if self.legacy_prefix.contains(LegacyPrefix::OPERAND_SIZE) {
+            (LegacyPrefix::OPERAND_SIZE, 0x66)
+        } else {
+            return None;
+        };
+        let shift = if candidate > 0xffff { 24 } else { 16 };
+        let opcode = Opcode::from_u32(byte << shift | candidate)?;
+        self.legacy_prefix.remove(prefix);
+        Some(opcode)
+    }
+
     fn parse_modrm(&mut self) -> &mut FetchedInstBuilder<'a> {
         let candidate = self.program[self.rip_offset];
-        self.mod_rm = self.opcode.modrm_if_required(candidate);
+        let rex = self.rex_prefix.unwrap_or(0);
+        self.mod_rm = self
+            .opcode
+            .modrm_if_required(candidate)
+            .map(|modrm| modrm.extend_by_rex(rex));
         if self.mod_rm.is_some() {
             self.rip_offset += 1;
         }
         self
     }
 
+    // 0x67 selects 32-bit addresses in 64-bit mode, and toggles 16-bit and 32-bit otherwise.
+    fn parse_addr_size(&mut self) -> &mut FetchedInstBuilder<'a> {
+        if !self.legacy_prefix.contains(LegacyPrefix::ADDRESS_SIZE) {
+            return self;
+        }
+        self.addr_size = match self.code_size {
+            CodeSize::Bits16 => OperandSize::DoubleWord,
+            CodeSize::Bits32 => OperandSize::Word,
+            CodeSize::Bits64 => OperandSize::DoubleWord,
+        };
+        self
+    }
+
+    // 16-bit addressing has no SIB.
     fn parse_sib(&mut self) -> &mut FetchedInstBuilder<'a> {
+        if self.addr_size == OperandSize::Word {
+            return self;
+        }
         if let Some(modrm) = self.mod_rm.as_ref() {
             if modrm.mode != ModRmModeField::Direct {
-                match modrm.rm /* Or R12 */ {
-          Reg64Id::Rsp => {
-            self.sib = Some(Sib::new(self.program[self.rip_offset]));
-            self.rip_offset += 1;
-          }
-          _ => (),
-        }
+                match modrm.rm {
+                    Reg64Id::Rsp | Reg64Id::R12 => {
+                        let rex = self.rex_prefix.unwrap_or(0);
+                        let sib = Sib::new(self.program[self.rip_offset]).extend_by_rex(rex);
+                        self.sib = Some(sib);
+                        self.rip_offset += 1;
+                    }
+                    _ => (),
+                }
             }
         }
         self
     }
 
     fn parse_disp(&mut self) -> &mut FetchedInstBuilder<'a> {
-        match self.opcode {
-            Opcode::JmpRel8 => {
-                self.displacement = self.program[self.rip_offset] as u64;
-                self.rip_offset += 1
-            }
-            Opcode::CallRel32 | Opcode::MovRmImm8 => {
-                let mut disp = &self.program[self.rip_offset..self.rip_offset + 4];
-                self.displacement = sign_extend_from_u32(disp.read_u32::<LittleEndian>().unwrap());
-                self.rip_offset += 4;
-            }
-            Opcode::MovRmImm if self.rex_prefix.is_none() => {
-                let mut disp = &self.program[self.rip_offset..self.rip_offset + 4];
-                self.displacement = sign_extend_from_u32(disp.read_u32::<LittleEndian>().unwrap());
-                self.rip_offset += 4;
-            }
+        use crate::isa::opcode::Opcode::*;
+        let disp_size = match self.opcode {
+            JmpRel8 | JccRel8 | LoopRel8 | LoopeRel8 | LoopneRel8 | JrcxzRel8 => 1,
+            CallRel32 | JmpRel32 | JccRel32 if self.op_size == Some(OperandSize::Word) => 2,
+            CallRel32 | JmpRel32 | JccRel32 => 4,
+            _ if self.addr_size == OperandSize::Word => self
+                .mod_rm
+                .map_or(0, |modrm| modrm.displacement_size_16bit()),
+            _ => self
+                .mod_rm
+                .map_or(0, |modrm| modrm.displacement_size(self.sib)),
+        };
+        let mut disp = &self.program[self.rip_offset..];
+        match disp_size {
+            1 => self.displacement = disp.read_i8().unwrap() as u64,
+            2 => self.displacement = disp.read_i16::<LittleEndian>().unwrap() as u64,
+            4 => self.displacement = sign_extend_from_u32(disp.read_u32::<LittleEndian>().unwrap()),
             _ => (),
         }
+        self.rip_offset += disp_size;
         self
     }
 
     fn parse_imm(&mut self) -> &mut FetchedInstBuilder<'a> {
-        match self.opcode {
-            Opcode::MovRmImm8 => {
-                self.immediate = self.program[self.rip_offset] as u64;
+        let op_size = self
+            .op_size
+            .expect("Operand size must be parsed before immediate.");
+        let imm_size = self.opcode.immediate_size(self.mod_rm);
+        let mut imm = &self.program[self.rip_offset..];
+        match (imm_size, op_size) {
+            (Some(ImmediateSize::Ib), _) => {
+                self.immediate = imm.read_i8().unwrap() as u64;
                 self.rip_offset += 1
             }
-            Opcode::MovRmImm if self.mandatory_prefix.is_some() => {
-                let mut imm = &self.program[self.rip_offset..self.rip_offset + 2];
+            (Some(ImmediateSize::Iz), OperandSize::Word) => {
+                self.immediate = imm.read_i16::<LittleEndian>().unwrap() as u64;
+                self.rip_offset += 2
+            }
+            (Some(ImmediateSize::Iz), _) => {
+                self.immediate = imm.read_i32::<LittleEndian>().unwrap() as u64;
+                self.rip_offset += 4
+            }
+            // `mov r, imm` is the only instruction which has a 64-bit immediate.
+            (Some(ImmediateSize::Iv), OperandSize::QuadWord) => {
+                self.immediate = imm.read_u64::<LittleEndian>().unwrap();
+                self.rip_offset += 8
+            }
+            (Some(ImmediateSize::Iv), OperandSize::Word) => {
                 self.immediate = imm.read_u16::<LittleEndian>().unwrap().into();
                 self.rip_offset += 2
             }
-            Opcode::MovImm | Opcode::MovRmImm => {
-                let mut imm = &self.program[self.rip_offset..self.rip_offset + 4];
+            (Some(ImmediateSize::Iv), _) => {
                 self.immediate = imm.read_u32::<LittleEndian>().unwrap().into();
                 self.rip_offset += 4
             }
-            _ => (),
+            // The selector is placed above the 32-bit offset.
+            (Some(ImmediateSize::Ap), OperandSize::Word) => {
+                let offset = imm.read_u16::<LittleEndian>().unwrap();
+                let selector = imm.read_u16::<LittleEndian>().unwrap();
+                self.immediate = u64::from(selector) << 32 | u64::from(offset);
+                self.rip_offset += 4
+            }
+            (Some(ImmediateSize::Ap), _) => {
+                let offset = imm.read_u32::<LittleEndian>().unwrap();
+                let selector = imm.read_u16::<LittleEndian>().unwrap();
+                self.immediate = u64::from(selector) << 32 | u64::from(offset);
+                self.rip_offset += 6
+            }
+            (None, _) => (),
         }
         self
     }
 
+    // 0x66 toggles 16-bit and 32-bit operands. In 64-bit mode, REX.W selects 64-bit operands,
+    // and near branches and stack operations are 64-bit unless 0x66 is given.
     fn parse_op_size(&mut self) -> &mut FetchedInstBuilder<'a> {
-        match self.opcode {
-            Opcode::MovRmImm8 => self.op_size = Some(OperandSize::Byte),
-            _ => {
-                if let Some(_) = self.rex_prefix {
-                    self.op_size = Some(OperandSize::QuadWord);
-                } else if let Some(opcode::OVERRIDE_OP_SIZE) = self.mandatory_prefix {
-                    self.op_size = Some(OperandSize::Word);
-                } else {
-                    self.op_size = Some(OperandSize::DoubleWord);
+        let rex_w = self.rex_prefix.map_or(false, |rex| rex.get_bit(3));
+        let toggled = self.legacy_prefix.contains(LegacyPrefix::OPERAND_SIZE);
+        let default_64bit =
+            self.code_size == CodeSize::Bits64 && self.opcode.is_64bit_by_default(self.mod_rm);
+        if self.opcode.is_byte_operation() {
+            self.op_size = Some(OperandSize::Byte);
+        } else if rex_w {
+            self.op_size = Some(OperandSize::QuadWord);
+        } else if toggled && self.code_size == CodeSize::Bits16 {
+            self.op_size = Some(OperandSize::DoubleWord);
+        } else if toggled {
+            self.op_size = Some(OperandSize::Word);
+        } else if default_64bit {
+            self.op_size = Some(OperandSize::QuadWord);
+        } else {
+            self.op_size = Some(self.code_size.operand_size());
+        }
+        self
+    }
+
+    // Byte operands 4..7 are AH, CH, DH and BH without REX prefix.
+    fn resolve_byte_registers(&mut self) -> &mut FetchedInstBuilder<'a> {
+        let rex = self.rex_prefix.is_some();
+        if self.opcode.has_byte_source() {
+            if let Some(modrm) = self.mod_rm.as_mut() {
+                if modrm.mode == ModRmModeField::Direct {
+                    modrm.rm = modrm.rm.byte_register(rex);
                 }
             }
         }
+        if self.op_size != Some(OperandSize::Byte) {
+            return self;
+        }
+        let has_opcode_extension = self.opcode.has_opcode_extension();
+        if let Some(modrm) = self.mod_rm.as_mut() {
+            if !has_opcode_extension {
+                modrm.reg = modrm.reg.byte_register(rex);
+            }
+            if modrm.mode == ModRmModeField::Direct {
+                modrm.rm = modrm.rm.byte_register(rex);
+            }
+        }
+        if self.opcode.is_plus_r() {
+            self.r = Reg64Id::from_u8(self.r).unwrap().byte_register(rex) as u8;
+        }
         self
     }
 
     fn build(&self) -> FetchedInst {
         FetchedInst {
-            lecacy_prefix: self.lecacy_prefix,
+            rip: self.rip_base,
+            legacy_prefix: self.legacy_prefix,
             rex_prefix: self.rex_prefix,
             opcode: self.opcode,
             r: self.r,
+            condition: self.condition,
             mod_rm: self.mod_rm,
             sib: self.sib,
             displacement: self.displacement,
             immediate: self.immediate,
             next_rip: self.rip_base + self.rip_offset,
             op_size: self.op_size,
+            addr_size: self.addr_size,
+            code_size: self.code_size,
         }
     }
 }
@@ -252,4 +400,29 @@ mod test {
         assert_eq!(sign_extend, 0xFFFF_FFFF_FFFF_FFFF);
         assert_eq!(zero_extend, 0x0000_0000_FFFF_FFFF);
     }
+
+    #[test]
+    fn mandatory_prefix() {
+        let mut fetch_unit = FetchUnit::new();
+        // pxor xmm0, xmm0; nop word [rax + rax]; movq rax, xmm1
+        let program = [
+            0x66, 0x0f, 0xef, 0xc0, 0x66, 0x0f, 0x1f, 0x44, 0x00, 0x00, 0x66, 0x48, 0x0f, 0x7e,
+            0xc8,
+        ];
+        // The program is given from RIP.
+        let pxor = fetch_unit.fetch(&program, CodeSize::Bits64).unwrap();
+        assert_eq!(pxor.opcode, Opcode::PxorVW);
+        assert!(pxor.legacy_prefix.is_empty());
+        let nop = fetch_unit
+            .fetch(&program[pxor.next_rip..], CodeSize::Bits64)
+            .unwrap();
+        assert_eq!(nop.opcode, Opcode::NopEv);
+        assert_eq!(nop.op_size, Some(OperandSize::Word));
+        let movq = fetch_unit
+            .fetch(&program[nop.next_rip..], CodeSize::Bits64)
+            .unwrap();
+        assert_eq!(movq.opcode, Opcode::MovdEV);
+        assert_eq!(movq.op_size, Some(OperandSize::QuadWord));
+        assert_eq!(movq.next_rip, program.len());
+    }
 }
@@ -0,0 +1,277 @@
+//! Interrupt and exception delivery through the IDT in long mode.
+//! An IDT entry is a 16-byte gate which holds the entry point of the handler,
+//! the code segment selector, and the IST slot of the handler's stack.
+//! In 32-bit protected mode, the IDT holds 8-byte gates with 32-bit entry points.
+//! In real mode, the IVT holds a 4-byte far pointer to the handler instead.
+use crate::exceptions::InternalException;
+use bit_field::BitField;
+
+pub const DIVIDE_ERROR: u8 = 0;
+pub const BREAKPOINT: u8 = 3;
+pub const INVALID_OPCODE: u8 = 6;
+pub const DOUBLE_FAULT: u8 = 8;
+pub const SEGMENT_NOT_PRESENT: u8 = 11;
+pub const STACK_FAULT: u8 = 12;
+pub const GENERAL_PROTECTION: u8 = 13;
+pub const PAGE_FAULT: u8 = 14;
+
+// Offsets of stack pointers in the 64-bit TSS.
+const TSS_RSP0: u64 = 0x04;
+const TSS_IST1: u64 = 0x24;
+
+// Types of system descriptors.
+const INTERRUPT_GATE: u64 = 0xe;
+const TRAP_GATE: u64 = 0xf;
+const AVAILABLE_TSS: u64 = 0x9;
+const BUSY_TSS: u64 = 0xb;
+
+/// Base and limit of a descriptor table, loaded by LGDT or LIDT.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DescriptorTableRegister {
+    pub base: u64,
+    pub limit: u16,
+}
+
+/// The IVT of real mode is at address 0 after reset.
+pub const REAL_MODE_IVT: DescriptorTableRegister = DescriptorTableRegister {
+    base: 0,
+    limit: 0x3ff,
+};
+
+impl DescriptorTableRegister {
+    /// True if the table contains `len` bytes from `offset`.
+    pub fn contains(&self, offset: u64, len: u64) -> bool {
+        offset + len - 1 <= u64::from(self.limit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventSource {
+    /// Exceptions detected by the processor.
+    Exception,
+    /// INT n and INT3, which are restricted by the DPL of the gate.
+    Software,
+    /// Interrupts requested by devices.
+    External,
+}
+
+/// An interrupt or exception to be delivered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Event {
+    pub vector: u8,
+    pub error_code: Option<u64>,
+    pub source: EventSource,
+}
+
+impl Event {
+    pub fn exception(vector: u8, error_code: Option<u64>) -> Event {
+        Event {
+            vector,
+            error_code,
+            source: EventSource::Exception,
+        }
+    }
+
+    pub fn software(vector: u8) -> Event {
+        Event {
+            vector,
+            error_code: None,
+            source: EventSource::Software,
+        }
+    }
+
+    pub fn external(vector: u8) -> Event {
+        Event {
+            vector,
+            error_code: None,
+            source: EventSource::External,
+        }
+    }
+
+    /// Converts an exception visible to the guest into an event.
+    /// Errors of the emulator itself are not delivered.
+    pub fn from_exception(exception: &InternalException) -> Option<Event> {
+        match exception {
+            InternalException::DivideError => Some(Event::exception(DIVIDE_ERROR, None)),
+            InternalException::FetchError { .. }
+            | InternalException::UndefinedInstruction { .. } => {
+                Some(Event::exception(INVALID_OPCODE, None))
+            }
+            InternalException::SegmentNotPresent { error_code } => {
+                Some(Event::exception(SEGMENT_NOT_PRESENT, Some(*error_code)))
+            }
+            InternalException::StackFault { error_code } => {
+                Some(Event::exception(STACK_FAULT, Some(*error_code)))
+            }
+            InternalException::GeneralProtection { error_code } => {
+                Some(Event::exception(GENERAL_PROTECTION, Some(*error_code)))
+            }
+            InternalException::PageFault { error_code, .. } => {
+                Some(Event::exception(PAGE_FAULT, Some(*error_code)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Error code of faults caused by the IDT entry of this event.
+    pub fn idt_error_code(&self) -> u64 {
+        let external = self.source != EventSource::Software;
+        u64::from(self.vector) << 3 | 0b10 | u64::from(external)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExceptionClass {
+    Benign,
+    Contributory,
+    PageFault,
+}
+
+fn exception_class(event: &Event) -> ExceptionClass {
+    match (event.source, event.vector) {
+        (EventSource::Exception, PAGE_FAULT) => ExceptionClass::PageFault,
+        (EventSource::Exception, 0) | (EventSource::Exception, 10..=13) => {
+            ExceptionClass::Contributory
+        }
+        _ => ExceptionClass::Benign,
+    }
+}
+
+/// True if `second` occurring while delivering `first` causes a double fault.
+pub fn is_double_fault(first: &Event, second: &Event) -> bool {
+    use self::ExceptionClass::*;
+    match (exception_class(first), exception_class(second)) {
+        (Contributory, Contributory) | (PageFault, Contributory) | (PageFault, PageFault) => true,
+        _ => false,
+    }
+}
+
+/// An interrupt gate or a trap gate in the IDT.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GateDescriptor {
+    pub offset: u64,
+    pub selector: u16,
+    /// Index of the interrupt stack table, or 0 not to use it.
+    pub ist: u8,
+    /// Trap gates do not clear RFLAGS.IF.
+    pub is_trap: bool,
+    pub dpl: u8,
+    pub present: bool,
+    valid_type: bool,
+}
+
+impl GateDescriptor {
+    pub fn new(low: u64, high: u64) -> GateDescriptor {
+        let gate_type = low.get_bits(40..44);
+        GateDescriptor {
+            offset: low.get_bits(0..16) | low.get_bits(48..64) << 16 | high.get_bits(0..32) << 32,
+            selector: low.get_bits(16..32) as u16,
+            ist: low.get_bits(32..35) as u8,
+            is_trap: gate_type == TRAP_GATE,
+            dpl: low.get_bits(45..47) as u8,
+            present: low.get_bit(47),
+            valid_type: gate_type == INTERRUPT_GATE || gate_type == TRAP_GATE,
+        }
+    }
+
+    /// Parses an 8-byte gate of the IDT in 32-bit protected mode, which has no IST slot.
+    pub fn new_legacy(entry: u64) -> GateDescriptor {
+        GateDescriptor {
+            ist: 0,
+            ..GateDescriptor::new(entry, 0)
+        }
+    }
+
+    /// Task gates and 32-bit gates are not allowed in the IDT of long mode.
+    /// In 32-bit protected mode, task gates and 16-bit gates are not supported.
+    pub fn is_valid(&self) -> bool {
+        self.valid_type
+    }
+}
+
+/// A 16-byte TSS descriptor in the GDT.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TssDescriptor {
+    pub base: u64,
+    pub present: bool,
+    pub busy: bool,
+    valid_type: bool,
+}
+
+impl TssDescriptor {
+    pub fn new(low: u64, high: u64) -> TssDescriptor {
+        let descriptor_type = low.get_bits(40..44);
+        TssDescriptor {
+            base: low.get_bits(16..40) | low.get_bits(56..64) << 24 | high.get_bits(0..32) << 32,
+            present: low.get_bit(47),
+            busy: descriptor_type == BUSY_TSS,
+            valid_type: descriptor_type == AVAILABLE_TSS || descriptor_type == BUSY_TSS,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.valid_type
+    }
+
+    /// The lower half of the descriptor marked busy by LTR.
+    pub fn mark_busy(low: u64) -> u64 {
+        let mut low = low;
+        low.set_bits(40..44, BUSY_TSS);
+        low
+    }
+}
+
+/// Offset of the stack pointer in the TSS for the privilege level.
+pub fn tss_rsp_offset(cpl: u8) -> u64 {
+    TSS_RSP0 + u64::from(cpl) * 8
+}
+
+/// Offset of the stack pointer in the TSS for the IST slot from 1 to 7.
+pub fn tss_ist_offset(ist: u8) -> u64 {
+    TSS_IST1 + u64::from(ist - 1) * 8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_descriptors() {
+        // Interrupt gate to 0x1234_5678_9abc_def0 in CS 0x08 with IST 2 and DPL 3.
+        let gate = GateDescriptor::new(0x9abc_ee02_0008_def0, 0x1234_5678);
+        assert_eq!(gate.offset, 0x1234_5678_9abc_def0);
+        assert_eq!(gate.selector, 0x08);
+        assert_eq!((gate.ist, gate.dpl), (2, 3));
+        assert!(gate.present && gate.is_valid() && !gate.is_trap);
+        // 32-bit call gate.
+        assert!(!GateDescriptor::new(0x0000_8c00_0008_0000, 0).is_valid());
+        // 32-bit trap gate to 0x1234_5678 in CS 0x10.
+        let gate = GateDescriptor::new_legacy(0x1234_8f00_0010_5678);
+        assert_eq!(
+            (gate.offset, gate.selector, gate.ist),
+            (0x1234_5678, 0x10, 0)
+        );
+        assert!(gate.present && gate.is_valid() && gate.is_trap);
+
+        let low = 0x1200_8934_5678_0067;
+        let tss = TssDescriptor::new(low, 0xffff_ffff);
+        assert_eq!(tss.base, 0xffff_ffff_1234_5678);
+        assert!(tss.present && tss.is_valid() && !tss.busy);
+        assert!(TssDescriptor::new(TssDescriptor::mark_busy(low), 0).busy);
+    }
+
+    #[test]
+    fn double_fault_conditions() {
+        let page_fault = Event::exception(PAGE_FAULT, Some(0));
+        let protection = Event::exception(GENERAL_PROTECTION, Some(0));
+        let divide = Event::exception(DIVIDE_ERROR, None);
+        let invalid = Event::exception(INVALID_OPCODE, None);
+        assert!(is_double_fault(&divide, &protection));
+        assert!(is_double_fault(&page_fault, &page_fault));
+        assert!(!is_double_fault(&protection, &page_fault));
+        assert!(!is_double_fault(&invalid, &protection));
+        assert!(!is_double_fault(&Event::software(13), &protection));
+        assert_eq!(Event::software(0x80).idt_error_code(), 0x402);
+        assert_eq!(Event::external(0x20).idt_error_code(), 0x103);
+    }
+}
@@ -0,0 +1,70 @@
+//! Condition codes of Jcc, SETcc and CMOVcc which are encoded in the lower 4 bits of the opcode.
+
+use crate::isa::rflags::RFlags;
+
+enum_from_primitive! {
+  #[derive(Debug, Clone, Copy, PartialEq)]
+  pub enum Condition {
+    Overflow       = 0x0,
+    NotOverflow    = 0x1,
+    Below          = 0x2,
+    AboveOrEqual   = 0x3,
+    Equal          = 0x4,
+    NotEqual       = 0x5,
+    BelowOrEqual   = 0x6,
+    Above          = 0x7,
+    Sign           = 0x8,
+    NotSign        = 0x9,
+    Parity         = 0xa,
+    NotParity      = 0xb,
+    Less           = 0xc,
+    GreaterOrEqual = 0xd,
+    LessOrEqual    = 0xe,
+    Greater        = 0xf,
+  }
+}
+
+impl Condition {
+    /// Evaluates the condition with the status flags.
+    pub fn is_satisfied(self, rflags: RFlags) -> bool {
+        use self::Condition::*;
+        let cf = rflags.contains(RFlags::CARRY_FLAG);
+        let zf = rflags.contains(RFlags::ZERO_FLAG);
+        let sf = rflags.contains(RFlags::SIGN_FLAG);
+        let of = rflags.contains(RFlags::OVERFLOW_FLAG);
+        let pf = rflags.contains(RFlags::PARITY_FLAG);
+        match self {
+            Overflow => of,
+            NotOverflow => !of,
+            Below => cf,
+            AboveOrEqual => !cf,
+            Equal => zf,
+            NotEqual => !zf,
+            BelowOrEqual => cf || zf,
+            Above => !cf && !zf,
+            Sign => sf,
+            NotSign => !sf,
+            Parity => pf,
+            NotParity => !pf,
+            Less => sf != of,
+            GreaterOrEqual => sf == of,
+            LessOrEqual => zf || sf != of,
+            Greater => !zf && sf == of,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn signed_and_unsigned_conditions() {
+        // Flags of `cmp -1, 1`
+        let rflags = RFlags::SIGN_FLAG;
+        assert!(Condition::Less.is_satisfied(rflags));
+        assert!(Condition::Above.is_satisfied(rflags));
+        assert!(!Condition::Greater.is_satisfied(rflags));
+        assert!(!Condition::BelowOrEqual.is_satisfied(rflags));
+    }
+}
@@ -0,0 +1,53 @@
+//! Control registers which determine the operating mode of the processor.
+
+bitflags! {
+    /// The CR0 register.
+    pub struct Cr0: u64 {
+        /// Enables protected mode.
+        const PROTECTION_ENABLE = 1 << 0;
+        const MONITOR_COPROCESSOR = 1 << 1;
+        const EMULATION = 1 << 2;
+        const TASK_SWITCHED = 1 << 3;
+        const EXTENSION_TYPE = 1 << 4;
+        const NUMERIC_ERROR = 1 << 5;
+        /// Supervisor accesses also respect read-only pages.
+        const WRITE_PROTECT = 1 << 16;
+        const ALIGNMENT_MASK = 1 << 18;
+        const NOT_WRITE_THROUGH = 1 << 29;
+        const CACHE_DISABLE = 1 << 30;
+        /// Enables paging.
+        const PAGING = 1 << 31;
+    }
+}
+
+bitflags! {
+    /// The CR4 register.
+    pub struct Cr4: u64 {
+        const VIRTUAL_8086_MODE_EXTENSIONS = 1 << 0;
+        const PROTECTED_MODE_VIRTUAL_INTERRUPTS = 1 << 1;
+        const TIME_STAMP_DISABLE = 1 << 2;
+        const DEBUGGING_EXTENSIONS = 1 << 3;
+        const PAGE_SIZE_EXTENSION = 1 << 4;
+        /// Physical address extension, which is required by 4-level paging.
+        const PHYSICAL_ADDRESS_EXTENSION = 1 << 5;
+        const MACHINE_CHECK = 1 << 6;
+        /// Global pages survive reloads of CR3.
+        const PAGE_GLOBAL = 1 << 7;
+        const PERFORMANCE_MONITOR_COUNTER = 1 << 8;
+        const OSFXSR = 1 << 9;
+        const OSXMMEXCPT = 1 << 10;
+    }
+}
+
+bitflags! {
+    /// The IA32_EFER MSR.
+    pub struct Efer: u64 {
+        /// Enables SYSCALL and SYSRET.
+        const SYSTEM_CALL_EXTENSIONS = 1 << 0;
+        const LONG_MODE_ENABLE = 1 << 8;
+        /// Set by the processor while long mode is active.
+        const LONG_MODE_ACTIVE = 1 << 10;
+        /// Enables the NX bit of paging-structure entries.
+        const NO_EXECUTE_ENABLE = 1 << 11;
+    }
+}
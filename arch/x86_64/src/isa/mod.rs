@@ -1,3 +1,7 @@
+pub mod condition;
+pub mod control_registers;
 pub mod modrm;
 pub mod opcode;
+pub mod prefix;
 pub mod registers;
+pub mod rflags;
@@ -31,6 +31,46 @@ impl ModRm {
             rm: Reg64Id::from_u8(rm).unwrap(),
         }
     }
+
+    /// `mod` = 00 and `r/m` = 101 is RIP-relative addressing in 64-bit mode.
+    pub fn is_rip_relative(&self) -> bool {
+        self.mode == ModRmModeField::Indirect && (self.rm as u8).get_bits(0..3) == 0b101
+    }
+
+    /// Returns the size of the displacement which follows ModRM and SIB.
+    pub fn displacement_size(&self, sib: Option<Sib>) -> usize {
+        match self.mode {
+            ModRmModeField::Direct => 0,
+            ModRmModeField::OneByteDisp => 1,
+            ModRmModeField::FourByteDisp => 4,
+            ModRmModeField::Indirect if self.is_rip_relative() => 4,
+            ModRmModeField::Indirect => match sib {
+                Some(sib) if !sib.has_base(self.mode) => 4,
+                _ => 0,
+            },
+        }
+    }
+
+    /// Returns the size of the displacement with 16-bit addressing, which has no SIB.
+    /// `mod` = 00 and `r/m` = 110 is a 16-bit displacement without registers.
+    pub fn displacement_size_16bit(&self) -> usize {
+        match self.mode {
+            ModRmModeField::Direct => 0,
+            ModRmModeField::OneByteDisp => 1,
+            ModRmModeField::FourByteDisp => 2,
+            ModRmModeField::Indirect if self.rm == Reg64Id::Rsi => 2,
+            ModRmModeField::Indirect => 0,
+        }
+    }
+
+    /// Extends `reg` by REX.R and `rm` by REX.B.
+    pub fn extend_by_rex(self, rex: u8) -> ModRm {
+        ModRm {
+            reg: extend_register(self.reg, rex.get_bit(2)),
+            rm: extend_register(self.rm, rex.get_bit(0)),
+            ..self
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -41,15 +81,41 @@ pub struct Sib {
 }
 
 impl Sib {
-    pub fn new(modrm: u8) -> Sib {
-        let scale = modrm.get_bits(6..8);
-        let index = modrm.get_bits(3..6);
-        let base = modrm.get_bits(0..3);
+    pub fn new(sib: u8) -> Sib {
+        let scale = sib.get_bits(6..8);
+        let index = sib.get_bits(3..6);
+        let base = sib.get_bits(0..3);
 
         Sib {
-            scale: 2 ^ scale,
+            scale: 1 << scale,
             index: Reg64Id::from_u8(index).unwrap(),
             base: Reg64Id::from_u8(base).unwrap(),
         }
     }
+
+    /// `base` = 101 with `mod` = 00 means no base register and 32-bit displacement.
+    pub fn has_base(&self, mode: ModRmModeField) -> bool {
+        mode != ModRmModeField::Indirect || (self.base as u8).get_bits(0..3) != 0b101
+    }
+
+    /// `index` = 100 means no index. R12 can be an index with REX.X.
+    pub fn index_register(&self) -> Option<Reg64Id> {
+        match self.index {
+            Reg64Id::Rsp => None,
+            index => Some(index),
+        }
+    }
+
+    /// Extends `index` by REX.X and `base` by REX.B.
+    pub fn extend_by_rex(self, rex: u8) -> Sib {
+        Sib {
+            index: extend_register(self.index, rex.get_bit(1)),
+            base: extend_register(self.base, rex.get_bit(0)),
+            ..self
+        }
+    }
+}
+
+fn extend_register(reg: Reg64Id, extension: bool) -> Reg64Id {
+    Reg64Id::from_u8(reg as u8 | (extension as u8) << 3).unwrap()
 }
@@ -1,23 +1,170 @@
-use crate::isa::modrm::ModRm;
+use crate::isa::modrm::{ModRm, ModRmModeField};
 
 pub const REX: u8 = 0x40;
 pub const REX_WRXB: u8 = 0x4F;
-pub const OVERRIDE_OP_SIZE: u8 = 0x66;
+pub const TWO_BYTE_ESCAPE: u8 = 0x0f;
+pub const THREE_BYTE_ESCAPE_38: u32 = 0x0f38;
+pub const THREE_BYTE_ESCAPE_3A: u32 = 0x0f3a;
 
+// Naming convention of operands follows Intel SDM opcode map:
+//   Eb/Ev: ModRM r/m operand (byte / operand size).
+//   Gb/Gv: ModRM reg operand (byte / operand size).
+//   Ib/Iz: immediate (byte / word or double word).
+//   Xb/Xv: memory addressed by RSI, Yb/Yv: memory addressed by RDI.
+//   V: ModRM reg XMM register, W: ModRM r/m XMM register or memory,
+//   U: ModRM r/m XMM register, E/G: general register or memory / general register.
+// Opcodes in the two-byte map are prefixed by 0x0f, e.g., 0x0f80.
+// SSE opcodes with a mandatory prefix 0x66, 0xf2 or 0xf3 have it in bits 16..24, e.g., 0x66_0f6f.
+// Three-byte maps are prefixed by 0x0f38 or 0x0f3a, but no opcode is defined yet.
 enum_from_primitive! {
   #[derive(Debug, Clone, Copy, PartialEq)]
   pub enum Opcode {
-    Add       = 0x01,
+    AddEbGb   = 0x00,
+    AddEvGv   = 0x01,
+    AddGbEb   = 0x02,
+    AddGvEv   = 0x03,
+    AddAlIb   = 0x04,
+    AddRaxIz  = 0x05,
+    Invalid   = 0x06,
+    OrEbGb    = 0x08,
+    OrEvGv    = 0x09,
+    OrGbEb    = 0x0a,
+    OrGvEv    = 0x0b,
+    OrAlIb    = 0x0c,
+    OrRaxIz   = 0x0d,
+    AdcEbGb   = 0x10,
+    AdcEvGv   = 0x11,
+    AdcGbEb   = 0x12,
+    AdcGvEv   = 0x13,
+    AdcAlIb   = 0x14,
+    AdcRaxIz  = 0x15,
+    SbbEbGb   = 0x18,
+    SbbEvGv   = 0x19,
+    SbbGbEb   = 0x1a,
+    SbbGvEv   = 0x1b,
+    SbbAlIb   = 0x1c,
+    SbbRaxIz  = 0x1d,
+    AndEbGb   = 0x20,
+    AndEvGv   = 0x21,
+    AndGbEb   = 0x22,
+    AndGvEv   = 0x23,
+    AndAlIb   = 0x24,
+    AndRaxIz  = 0x25,
+    SubEbGb   = 0x28,
+    SubEvGv   = 0x29,
+    SubGbEb   = 0x2a,
+    SubGvEv   = 0x2b,
+    SubAlIb   = 0x2c,
+    SubRaxIz  = 0x2d,
+    XorEbGb   = 0x30,
+    XorEvGv   = 0x31,
+    XorGbEb   = 0x32,
+    XorGvEv   = 0x33,
+    XorAlIb   = 0x34,
+    XorRaxIz  = 0x35,
+    CmpEbGb   = 0x38,
+    CmpEvGv   = 0x39,
+    CmpGbEb   = 0x3a,
+    CmpGvEv   = 0x3b,
+    CmpAlIb   = 0x3c,
+    CmpRaxIz  = 0x3d,
+    // INC/DEC r, which are REX prefixes in 64-bit mode.
+    IncR      = 0x40,
+    DecR      = 0x48,
+    // Operand encoding: RM, sign-extends a double word.
+    Movsxd    = 0x63,
+    // IMUL r, r/m, imm
+    ImulGvEvIz = 0x69,
+    ImulGvEvIb = 0x6b,
+    // Immediate group 1: ADD/OR/ADC/SBB/AND/SUB/XOR/CMP selected by ModRM.reg.
+    Group1EbIb = 0x80,
+    Group1EvIz = 0x81,
+    Group1EvIb = 0x83,
+    // Condition code in the lower 4 bits.
+    JccRel8   = 0x70,
+    TestEbGb  = 0x84,
+    TestEvGv  = 0x85,
+    TestAlIb  = 0xa8,
+    TestRaxIz = 0xa9,
+    // String instructions which may have REP/REPE/REPNE prefix.
+    MovsYbXb  = 0xa4,
+    MovsYvXv  = 0xa5,
+    CmpsXbYb  = 0xa6,
+    CmpsXvYv  = 0xa7,
+    StosYbAl  = 0xaa,
+    StosYvRax = 0xab,
+    LodsAlXb  = 0xac,
+    LodsRaxXv = 0xad,
+    ScasAlYb  = 0xae,
+    ScasRaxYv = 0xaf,
+    // Port I/O. The port is DX or an 8-bit immediate.
+    InsYbDx   = 0x6c,
+    InsYvDx   = 0x6d,
+    OutsDxXb  = 0x6e,
+    OutsDxXv  = 0x6f,
+    InAlIb    = 0xe4,
+    InEaxIb   = 0xe5,
+    OutIbAl   = 0xe6,
+    OutIbEax  = 0xe7,
+    InAlDx    = 0xec,
+    InEaxDx   = 0xed,
+    OutDxAl   = 0xee,
+    OutDxEax  = 0xef,
+    LoopneRel8 = 0xe0,
+    LoopeRel8 = 0xe1,
+    LoopRel8  = 0xe2,
+    JrcxzRel8 = 0xe3,
+    // XCHG r, rAX. 0x90 is NOP unless REX.B selects R8.
+    XchgR     = 0x90,
+    // XCHG r/m, r, which is locked with a memory operand.
+    XchgEbGb  = 0x86,
+    XchgEvGv  = 0x87,
+    // CBW/CWDE/CDQE and CWD/CDQ/CQO by the operand size.
+    ConvertRax = 0x98,
+    ConvertRdx = 0x99,
     CallRel32 = 0xe8,
+    JmpRel32  = 0xe9,
+    // Shift group 2: ROL/ROR/RCL/RCR/SHL/SHR/SAL/SAR selected by ModRM.reg.
+    Group2EbIb = 0xc0,
+    Group2EvIb = 0xc1,
+    Group2Eb1 = 0xd0,
+    Group2Ev1 = 0xd1,
+    Group2EbCl = 0xd2,
+    Group2EvCl = 0xd3,
+    Int3      = 0xcc,
+    IntIb     = 0xcd,
+    Into      = 0xce,
+    // IRET, or IRETQ with REX.W.
+    Iret      = 0xcf,
     Halt      = 0xf4,
-    Inc       = 0xff,
-    Invalid   = 0x06,
+    Clc       = 0xf8,
+    Stc       = 0xf9,
+    Cli       = 0xfa,
+    Sti       = 0xfb,
+    Cld       = 0xfc,
+    Std       = 0xfd,
+    // Unary group 3: TEST/NOT/NEG/MUL/IMUL/DIV/IDIV selected by ModRM.reg.
+    Group3Eb  = 0xf6,
+    Group3Ev  = 0xf7,
+    // INC/DEC Eb
+    Group4    = 0xfe,
+    // INC/DEC/CALL/JMP Ev
+    Group5    = 0xff,
     JmpRel8   = 0xeb,
+    // JMP ptr16:16/32, which is invalid in 64-bit mode.
+    JmpFarAp  = 0xea,
     // Operand encoding: MR
+    MovToRm8  = 0x88,
     MovToRm   = 0x89,
     // Operand encoding: RM
+    MovToReg8 = 0x8a,
     MovToReg  = 0x8b,
+    Lea       = 0x8d,
+    // MOV r/m16, Sreg and MOV Sreg, r/m16. ModRM.reg is the segment register.
+    MovEwSw   = 0x8c,
+    MovSwEw   = 0x8e,
     // Operand encoding: OI
+    MovImm8   = 0xb0,
     MovImm    = 0xb8,
     // Operand encoding: MI
     MovRmImm8 = 0xc6,
@@ -25,6 +172,123 @@ enum_from_primitive! {
     PushR     = 0x50,
     PopR      = 0x58,
     Ret       = 0xc3,
+    // SLDT/STR/LLDT/LTR/VERR/VERW selected by ModRM.reg.
+    Group6    = 0x0f00,
+    // SGDT/SIDT/LGDT/LIDT/INVLPG selected by ModRM.reg, and SWAPGS.
+    Group7    = 0x0f01,
+    Syscall   = 0x0f05,
+    Sysret    = 0x0f07,
+    Ud2       = 0x0f0b,
+    MovupsVW  = 0x0f10,
+    MovupsWV  = 0x0f11,
+    MovupdVW  = 0x66_0f10,
+    MovupdWV  = 0x66_0f11,
+    MovsdVW   = 0xf2_0f10,
+    MovsdWV   = 0xf2_0f11,
+    MovssVW   = 0xf3_0f10,
+    MovssWV   = 0xf3_0f11,
+    NopEv     = 0x0f1f,
+    // MOV from and to control registers. ModRM.reg is the control register.
+    MovRdCd   = 0x0f20,
+    MovCdRd   = 0x0f22,
+    MovapsVW  = 0x0f28,
+    MovapsWV  = 0x0f29,
+    MovapdVW  = 0x66_0f28,
+    MovapdWV  = 0x66_0f29,
+    Cvtsi2sdVE = 0xf2_0f2a,
+    Cvtsi2ssVE = 0xf3_0f2a,
+    Cvttsd2siGW = 0xf2_0f2c,
+    Cvttss2siGW = 0xf3_0f2c,
+    Cvtsd2siGW = 0xf2_0f2d,
+    Cvtss2siGW = 0xf3_0f2d,
+    UcomissVW = 0x0f2e,
+    UcomisdVW = 0x66_0f2e,
+    ComissVW  = 0x0f2f,
+    ComisdVW  = 0x66_0f2f,
+    // ECX is the MSR, and EDX:EAX is the value.
+    Wrmsr     = 0x0f30,
+    Rdtsc     = 0x0f31,
+    Rdmsr     = 0x0f32,
+    // Condition code in the lower 4 bits.
+    CmovccGvEv = 0x0f40,
+    SqrtsdVW  = 0xf2_0f51,
+    SqrtssVW  = 0xf3_0f51,
+    AndpsVW   = 0x0f54,
+    AndpdVW   = 0x66_0f54,
+    AndnpsVW  = 0x0f55,
+    AndnpdVW  = 0x66_0f55,
+    OrpsVW    = 0x0f56,
+    OrpdVW    = 0x66_0f56,
+    XorpsVW   = 0x0f57,
+    XorpdVW   = 0x66_0f57,
+    AddsdVW   = 0xf2_0f58,
+    AddssVW   = 0xf3_0f58,
+    MulsdVW   = 0xf2_0f59,
+    MulssVW   = 0xf3_0f59,
+    Cvtsd2ssVW = 0xf2_0f5a,
+    Cvtss2sdVW = 0xf3_0f5a,
+    SubsdVW   = 0xf2_0f5c,
+    SubssVW   = 0xf3_0f5c,
+    MinsdVW   = 0xf2_0f5d,
+    MinssVW   = 0xf3_0f5d,
+    DivsdVW   = 0xf2_0f5e,
+    DivssVW   = 0xf3_0f5e,
+    MaxsdVW   = 0xf2_0f5f,
+    MaxssVW   = 0xf3_0f5f,
+    // MOVD, or MOVQ with REX.W.
+    MovdVE    = 0x66_0f6e,
+    MovdqaVW  = 0x66_0f6f,
+    MovdquVW  = 0xf3_0f6f,
+    PcmpeqbVW = 0x66_0f74,
+    PcmpeqwVW = 0x66_0f75,
+    PcmpeqdVW = 0x66_0f76,
+    MovdEV    = 0x66_0f7e,
+    MovqVW    = 0xf3_0f7e,
+    MovdqaWV  = 0x66_0f7f,
+    MovdquWV  = 0xf3_0f7f,
+    JccRel32  = 0x0f80,
+    SetccEb   = 0x0f90,
+    Cpuid     = 0x0fa2,
+    BtEvGv    = 0x0fa3,
+    ShldEvGvIb = 0x0fa4,
+    ShldEvGvCl = 0x0fa5,
+    BtsEvGv   = 0x0fab,
+    ShrdEvGvIb = 0x0fac,
+    ShrdEvGvCl = 0x0fad,
+    // LDMXCSR/STMXCSR and fences selected by ModRM.reg.
+    Group15   = 0x0fae,
+    ImulGvEv  = 0x0faf,
+    // CMPXCHG r/m, r compares with AL/AX/EAX/RAX.
+    CmpxchgEbGb = 0x0fb0,
+    CmpxchgEvGv = 0x0fb1,
+    BtrEvGv   = 0x0fb3,
+    MovzxGvEb = 0x0fb6,
+    MovzxGvEw = 0x0fb7,
+    // BT/BTS/BTR/BTC Ev, Ib selected by ModRM.reg.
+    Group8EvIb = 0x0fba,
+    BtcEvGv   = 0x0fbb,
+    BsfGvEv   = 0x0fbc,
+    BsrGvEv   = 0x0fbd,
+    MovsxGvEb = 0x0fbe,
+    MovsxGvEw = 0x0fbf,
+    XaddEbGb  = 0x0fc0,
+    XaddEvGv  = 0x0fc1,
+    // CMPXCHG8B, or CMPXCHG16B with REX.W, selected by ModRM.reg.
+    Group9    = 0x0fc7,
+    PaddqVW   = 0x66_0fd4,
+    MovqWV    = 0x66_0fd6,
+    PmovmskbGU = 0x66_0fd7,
+    PandVW    = 0x66_0fdb,
+    PandnVW   = 0x66_0fdf,
+    PorVW     = 0x66_0feb,
+    PxorVW    = 0x66_0fef,
+    PsubbVW   = 0x66_0ff8,
+    PsubwVW   = 0x66_0ff9,
+    PsubdVW   = 0x66_0ffa,
+    PsubqVW   = 0x66_0ffb,
+    PaddbVW   = 0x66_0ffc,
+    PaddwVW   = 0x66_0ffd,
+    PadddVW   = 0x66_0ffe,
   }
 }
 
@@ -36,15 +300,213 @@ pub enum OperandSize {
     QuadWord,
 }
 
+impl OperandSize {
+    /// Number of bits.
+    pub fn bits(self) -> u32 {
+        match self {
+            OperandSize::Byte => 8,
+            OperandSize::Word => 16,
+            OperandSize::DoubleWord => 32,
+            OperandSize::QuadWord => 64,
+        }
+    }
+
+    /// Mask to truncate a value to the size.
+    pub fn mask(self) -> u64 {
+        match self {
+            OperandSize::QuadWord => 0xffff_ffff_ffff_ffff,
+            size => (1 << size.bits()) - 1,
+        }
+    }
+
+    /// The most significant bit of the size.
+    pub fn sign_bit(self) -> u64 {
+        1 << (self.bits() - 1)
+    }
+
+    /// Sign-extends the lower bits of the size to 64 bits.
+    pub fn sign_extend(self, value: u64) -> u64 {
+        let shift = 64 - self.bits();
+        (((value << shift) as i64) >> shift) as u64
+    }
+}
+
+/// Size of an immediate which follows ModRM, SIB and displacement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImmediateSize {
+    /// 8-bit immediate.
+    Ib,
+    /// 16-bit immediate for 16-bit operand size, otherwise 32-bit immediate.
+    Iz,
+    /// Immediate of the operand size including 64-bit.
+    Iv,
+    /// Far pointer of an offset of the operand size followed by a 16-bit selector.
+    Ap,
+}
+
 impl Opcode {
     pub fn modrm_if_required(&self, candidate: u8) -> Option<ModRm> {
+        use self::Opcode::*;
+        match self {
+            AddEbGb | AddEvGv | AddGbEb | AddGvEv | OrEbGb | OrEvGv | OrGbEb | OrGvEv | AdcEbGb
+            | AdcEvGv | AdcGbEb | AdcGvEv | SbbEbGb | SbbEvGv | SbbGbEb | SbbGvEv | AndEbGb
+            | AndEvGv | AndGbEb | AndGvEv | SubEbGb | SubEvGv | SubGbEb | SubGvEv | XorEbGb
+            | XorEvGv | XorGbEb | XorGvEv | CmpEbGb | CmpEvGv | CmpGbEb | CmpGvEv | Group1EbIb
+            | Group1EvIz | Group1EvIb | TestEbGb | TestEvGv | Group3Eb | Group3Ev | Group4
+            | Group5 | MovToRm8 | MovToRm | MovToReg8 | MovToReg | Lea | MovRmImm | MovRmImm8
+            | CmovccGvEv | SetccEb | Movsxd | NopEv | BtEvGv | BtsEvGv | BtrEvGv | BtcEvGv
+            | Group8EvIb | ImulGvEv | MovzxGvEb | MovzxGvEw | MovsxGvEb | MovsxGvEw | BsfGvEv
+            | BsrGvEv | ImulGvEvIz | ImulGvEvIb | Group2EbIb | Group2EvIb | Group2Eb1
+            | Group2Ev1 | Group2EbCl | Group2EvCl | ShldEvGvIb | ShldEvGvCl | ShrdEvGvIb
+            | ShrdEvGvCl | Group6 | Group7 | MovRdCd | MovCdRd | Group15 | MovEwSw | MovSwEw
+            | XchgEbGb | XchgEvGv | CmpxchgEbGb | CmpxchgEvGv | XaddEbGb | XaddEvGv | Group9 => {
+                Some(ModRm::new(candidate))
+            }
+            opcode if opcode.is_sse() => Some(ModRm::new(candidate)),
+            _ => None,
+        }
+    }
+
+    /// `plus r` opcodes have a register number in the lower 3 bits.
+    pub fn is_plus_r(self) -> bool {
+        use self::Opcode::*;
+        match self {
+            MovImm8 | MovImm | PushR | PopR | IncR | DecR | XchgR => true,
+            _ => false,
+        }
+    }
+
+    /// Opcodes which have a condition code in the lower 4 bits.
+    pub fn has_condition(self) -> bool {
+        use self::Opcode::*;
+        match self {
+            JccRel8 | JccRel32 | CmovccGvEv | SetccEb => true,
+            _ => false,
+        }
+    }
+
+    /// True if ModRM.reg is an opcode extension instead of a register.
+    pub fn has_opcode_extension(self) -> bool {
+        use self::Opcode::*;
+        match self {
+            Group1EbIb | Group1EvIz | Group1EvIb | Group2EbIb | Group2EvIb | Group2Eb1
+            | Group2Ev1 | Group2EbCl | Group2EvCl | Group3Eb | Group3Ev | Group4 | Group5
+            | Group6 | Group7 | Group8EvIb | Group9 | Group15 | MovRmImm8 | MovRmImm => true,
+            _ => false,
+        }
+    }
+
+    /// SSE instructions, all of which have ModRM.
+    pub fn is_sse(self) -> bool {
+        use self::Opcode::*;
+        match self {
+            MovupsVW | MovupsWV | MovupdVW | MovupdWV | MovsdVW | MovsdWV | MovssVW | MovssWV
+            | MovapsVW | MovapsWV | MovapdVW | MovapdWV | MovdqaVW | MovdqaWV | MovdquVW
+            | MovdquWV | MovdVE | MovdEV | MovqVW | MovqWV => true,
+            AndpsVW | AndpdVW | AndnpsVW | AndnpdVW | OrpsVW | OrpdVW | XorpsVW | XorpdVW
+            | PandVW | PandnVW | PorVW | PxorVW | PaddbVW | PaddwVW | PadddVW | PaddqVW
+            | PsubbVW | PsubwVW | PsubdVW | PsubqVW | PcmpeqbVW | PcmpeqwVW | PcmpeqdVW
+            | PmovmskbGU => true,
+            AddsdVW | AddssVW | SubsdVW | SubssVW | MulsdVW | MulssVW | DivsdVW | DivssVW
+            | MinsdVW | MinssVW | MaxsdVW | MaxssVW | SqrtsdVW | SqrtssVW | UcomisdVW
+            | UcomissVW | ComisdVW | ComissVW | Cvtsi2sdVE | Cvtsi2ssVE | Cvttsd2siGW
+            | Cvttss2siGW | Cvtsd2siGW | Cvtss2siGW | Cvtsd2ssVW | Cvtss2sdVW => true,
+            _ => false,
+        }
+    }
+
+    /// True if the operand size is 64-bit by default in 64-bit mode,
+    /// which is the case for near branches and stack operations.
+    pub fn is_64bit_by_default(self, modrm: Option<ModRm>) -> bool {
+        use self::Opcode::*;
+        match self {
+            JmpRel8 | JmpRel32 | JccRel8 | JccRel32 | CallRel32 | Ret | PushR | PopR | LoopRel8
+            | LoopeRel8 | LoopneRel8 | JrcxzRel8 => true,
+            // Near CALL and JMP r/m.
+            Group5 => modrm.map_or(false, |modrm| modrm.reg as u8 == 2 || modrm.reg as u8 == 4),
+            _ => false,
+        }
+    }
+
+    /// True if the instruction raises #GP unless CPL is 0.
+    /// CLI, STI and port I/O are included as IOPL is always 0,
+    /// and the I/O permission bitmap in the TSS is not consulted.
+    /// Only privileged instructions are implemented in groups 6 and 7.
+    pub fn is_privileged(self) -> bool {
+        use self::Opcode::*;
+        match self {
+            Halt | Cli | Sti | Group6 | Group7 | MovRdCd | MovCdRd | Sysret | Rdmsr | Wrmsr => true,
+            InsYbDx | InsYvDx | OutsDxXb | OutsDxXv | InAlIb | InEaxIb | OutIbAl | OutIbEax
+            | InAlDx | InEaxDx | OutDxAl | OutDxEax => true,
+            _ => false,
+        }
+    }
+
+    /// True if the source operand is byte while the destination is not.
+    pub fn has_byte_source(self) -> bool {
+        match self {
+            Opcode::MovzxGvEb | Opcode::MovsxGvEb => true,
+            _ => false,
+        }
+    }
+
+    /// True if the operand size is always byte.
+    pub fn is_byte_operation(self) -> bool {
+        use self::Opcode::*;
+        match self {
+            AddEbGb | AddGbEb | AddAlIb | OrEbGb | OrGbEb | OrAlIb | AdcEbGb | AdcGbEb
+            | AdcAlIb | SbbEbGb | SbbGbEb | SbbAlIb | AndEbGb | AndGbEb | AndAlIb | SubEbGb
+            | SubGbEb | SubAlIb | XorEbGb | XorGbEb | XorAlIb | CmpEbGb | CmpGbEb | CmpAlIb
+            | Group1EbIb | TestEbGb | TestAlIb | Group3Eb | Group4 | MovRmImm8 | SetccEb
+            | MovToRm8 | MovToReg8 | MovImm8 | Group2EbIb | Group2Eb1 | Group2EbCl | MovsYbXb
+            | CmpsXbYb | StosYbAl | LodsAlXb | ScasAlYb | InsYbDx | OutsDxXb | InAlIb | OutIbAl
+            | InAlDx | OutDxAl | XchgEbGb | CmpxchgEbGb | XaddEbGb => true,
+            _ => false,
+        }
+    }
+
+    /// True if the LOCK prefix is allowed, which requires a read-modify-write instruction
+    /// with a memory destination. `modrm` is required for group opcodes.
+    pub fn is_lockable(self, modrm: Option<ModRm>) -> bool {
+        use self::Opcode::*;
+        let reg = match modrm {
+            Some(modrm) if modrm.mode != ModRmModeField::Direct => modrm.reg as u8 & 7,
+            _ => return false,
+        };
+        match self {
+            AddEbGb | AddEvGv | OrEbGb | OrEvGv | AdcEbGb | AdcEvGv | SbbEbGb | SbbEvGv
+            | AndEbGb | AndEvGv | SubEbGb | SubEvGv | XorEbGb | XorEvGv | BtsEvGv | BtrEvGv
+            | BtcEvGv | XchgEbGb | XchgEvGv | CmpxchgEbGb | CmpxchgEvGv | XaddEbGb | XaddEvGv => {
+                true
+            }
+            // All but CMP of group 1, NOT and NEG of group 3, INC and DEC of groups 4 and 5,
+            // BTS, BTR and BTC of group 8, and CMPXCHG8B/16B of group 9.
+            Group1EbIb | Group1EvIz | Group1EvIb => reg != 7,
+            Group3Eb | Group3Ev => reg == 2 || reg == 3,
+            Group4 | Group5 => reg == 0 || reg == 1,
+            Group8EvIb => reg >= 5,
+            Group9 => reg == 1,
+            _ => false,
+        }
+    }
+
+    /// Returns the size of the immediate. `modrm` is required for group opcodes.
+    pub fn immediate_size(self, modrm: Option<ModRm>) -> Option<ImmediateSize> {
+        use self::ImmediateSize::*;
+        use self::Opcode::*;
+        // TEST is encoded in ModRM.reg 0 and 1 of group 3.
+        let test = modrm.map_or(false, |modrm| (modrm.reg as u8) < 2);
         match self {
-            Opcode::Add
-            | Opcode::Inc
-            | Opcode::MovToRm
-            | Opcode::MovToReg
-            | Opcode::MovRmImm
-            | Opcode::MovRmImm8 => Some(ModRm::new(candidate)),
+            AddAlIb | OrAlIb | AdcAlIb | SbbAlIb | AndAlIb | SubAlIb | XorAlIb | CmpAlIb
+            | TestAlIb | Group1EbIb | Group1EvIb | MovRmImm8 | MovImm8 | Group8EvIb
+            | ImulGvEvIb | Group2EbIb | Group2EvIb | ShldEvGvIb | ShrdEvGvIb | IntIb | InAlIb
+            | InEaxIb | OutIbAl | OutIbEax => Some(Ib),
+            AddRaxIz | OrRaxIz | AdcRaxIz | SbbRaxIz | AndRaxIz | SubRaxIz | XorRaxIz
+            | CmpRaxIz | TestRaxIz | Group1EvIz | MovRmImm | ImulGvEvIz => Some(Iz),
+            Group3Eb if test => Some(Ib),
+            Group3Ev if test => Some(Iz),
+            MovImm => Some(Iv),
+            JmpFarAp => Some(Ap),
             _ => None,
         }
     }
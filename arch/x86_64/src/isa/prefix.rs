@@ -0,0 +1,82 @@
+//! Legacy prefixes which precede the REX prefix and the opcode.
+use crate::isa::registers::SegmentRegister;
+
+bitflags! {
+    /// Legacy prefixes of an instruction. Prefixes of the same group overwrite each other.
+    pub struct LegacyPrefix: u32 {
+        const LOCK = 1 << 0;
+        /// REPNE/REPNZ, or a mandatory prefix of SSE instructions.
+        const REPNE = 1 << 1;
+        /// REP/REPE/REPZ, or a mandatory prefix of SSE instructions.
+        const REP = 1 << 2;
+        const SEGMENT_FS = 1 << 3;
+        const SEGMENT_GS = 1 << 4;
+        const OPERAND_SIZE = 1 << 5;
+        const ADDRESS_SIZE = 1 << 6;
+        const SEGMENT_ES = 1 << 7;
+        const SEGMENT_CS = 1 << 8;
+        const SEGMENT_SS = 1 << 9;
+        const SEGMENT_DS = 1 << 10;
+
+        /// Group 1 prefixes.
+        const LOCK_REPEAT = Self::LOCK.bits | Self::REPNE.bits | Self::REP.bits;
+        /// Group 2 prefixes. CS, SS, DS and ES overrides are ignored in 64-bit mode.
+        const SEGMENT = Self::SEGMENT_ES.bits
+            | Self::SEGMENT_CS.bits
+            | Self::SEGMENT_SS.bits
+            | Self::SEGMENT_DS.bits
+            | Self::SEGMENT_FS.bits
+            | Self::SEGMENT_GS.bits;
+    }
+}
+
+impl LegacyPrefix {
+    /// Returns the prefix and its group if `byte` is a legacy prefix.
+    pub fn from_byte(byte: u8) -> Option<(LegacyPrefix, LegacyPrefix)> {
+        match byte {
+            0xf0 => Some((LegacyPrefix::LOCK, LegacyPrefix::LOCK_REPEAT)),
+            0xf2 => Some((LegacyPrefix::REPNE, LegacyPrefix::LOCK_REPEAT)),
+            0xf3 => Some((LegacyPrefix::REP, LegacyPrefix::LOCK_REPEAT)),
+            0x26 => Some((LegacyPrefix::SEGMENT_ES, LegacyPrefix::SEGMENT)),
+            0x2e => Some((LegacyPrefix::SEGMENT_CS, LegacyPrefix::SEGMENT)),
+            0x36 => Some((LegacyPrefix::SEGMENT_SS, LegacyPrefix::SEGMENT)),
+            0x3e => Some((LegacyPrefix::SEGMENT_DS, LegacyPrefix::SEGMENT)),
+            0x64 => Some((LegacyPrefix::SEGMENT_FS, LegacyPrefix::SEGMENT)),
+            0x65 => Some((LegacyPrefix::SEGMENT_GS, LegacyPrefix::SEGMENT)),
+            0x66 => Some((LegacyPrefix::OPERAND_SIZE, LegacyPrefix::OPERAND_SIZE)),
+            0x67 => Some((LegacyPrefix::ADDRESS_SIZE, LegacyPrefix::ADDRESS_SIZE)),
+            _ => None,
+        }
+    }
+
+    /// The segment register selected by the segment override prefix.
+    pub fn segment(self) -> Option<SegmentRegister> {
+        [
+            (LegacyPrefix::SEGMENT_ES, SegmentRegister::Es),
+            (LegacyPrefix::SEGMENT_CS, SegmentRegister::Cs),
+            (LegacyPrefix::SEGMENT_SS, SegmentRegister::Ss),
+            (LegacyPrefix::SEGMENT_DS, SegmentRegister::Ds),
+            (LegacyPrefix::SEGMENT_FS, SegmentRegister::Fs),
+            (LegacyPrefix::SEGMENT_GS, SegmentRegister::Gs),
+        ]
+        .iter()
+        .find(|(prefix, _)| self.contains(*prefix))
+        .map(|(_, segment)| *segment)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prefix_groups() {
+        let (rep, group) = LegacyPrefix::from_byte(0xf3).unwrap();
+        let prefix = LegacyPrefix::REPNE | LegacyPrefix::OPERAND_SIZE;
+        assert_eq!(
+            (prefix - group) | rep,
+            LegacyPrefix::REP | LegacyPrefix::OPERAND_SIZE
+        );
+        assert!(LegacyPrefix::from_byte(0x48).is_none());
+    }
+}
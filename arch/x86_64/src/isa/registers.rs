@@ -9,6 +9,72 @@ enum_from_primitive! {
     Rbp = 0x05,
     Rsi = 0x06,
     Rdi = 0x07,
+    R8  = 0x08,
+    R9  = 0x09,
+    R10 = 0x0a,
+    R11 = 0x0b,
+    R12 = 0x0c,
+    R13 = 0x0d,
+    R14 = 0x0e,
+    R15 = 0x0f,
+    // Legacy high byte registers, bits 8..16 of rax, rcx, rdx and rbx.
+    Ah  = 0x14,
+    Ch  = 0x15,
+    Dh  = 0x16,
+    Bh  = 0x17,
+    // Temporary registers of uops, which are not visible to the software.
+    Temp0 = 0x18,
+    Temp1 = 0x19,
     Unknown = 0xff,
   }
 }
+
+impl Reg64Id {
+    /// Byte register encoded as 4..7 is AH, CH, DH or BH without REX prefix,
+    /// otherwise SPL, BPL, SIL or DIL.
+    pub fn byte_register(self, rex: bool) -> Reg64Id {
+        use self::Reg64Id::*;
+        match self {
+            Rsp if !rex => Ah,
+            Rbp if !rex => Ch,
+            Rsi if !rex => Dh,
+            Rdi if !rex => Bh,
+            reg => reg,
+        }
+    }
+
+    /// The 64-bit register which contains the high byte register.
+    pub fn high_byte_of(self) -> Option<Reg64Id> {
+        use self::Reg64Id::*;
+        match self {
+            Ah => Some(Rax),
+            Ch => Some(Rcx),
+            Dh => Some(Rdx),
+            Bh => Some(Rbx),
+            _ => None,
+        }
+    }
+
+    /// Index of a temporary register, which holds a value between the uops of an instruction.
+    pub fn temporary_index(self) -> Option<usize> {
+        use self::Reg64Id::*;
+        match self {
+            Temp0 => Some(0),
+            Temp1 => Some(1),
+            _ => None,
+        }
+    }
+}
+
+enum_from_primitive! {
+  /// Segment registers in the order of the `sreg` field of MOV Sreg.
+  #[derive(Debug, Clone, Copy, PartialEq)]
+  pub enum SegmentRegister {
+    Es = 0,
+    Cs = 1,
+    Ss = 2,
+    Ds = 3,
+    Fs = 4,
+    Gs = 5,
+  }
+}
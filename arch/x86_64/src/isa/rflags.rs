@@ -0,0 +1,54 @@
+//! Processor state stored in the RFLAGS register.
+
+bitflags! {
+    /// The RFLAGS register.
+    pub struct RFlags: u64 {
+        /// Set by arithmetic instructions if a carry or borrow occurs out of the MSB.
+        const CARRY_FLAG = 1 << 0;
+        /// Set if the least significant byte of the result has even number of 1s.
+        const PARITY_FLAG = 1 << 2;
+        /// Set if a carry or borrow occurs out of bit 3.
+        const AUXILIARY_CARRY_FLAG = 1 << 4;
+        /// Set if the result is zero.
+        const ZERO_FLAG = 1 << 6;
+        /// Copy of the MSB of the result.
+        const SIGN_FLAG = 1 << 7;
+        /// Enables single-step mode.
+        const TRAP_FLAG = 1 << 8;
+        /// Enables maskable external interrupts.
+        const INTERRUPT_FLAG = 1 << 9;
+        /// Determines the order in which strings are processed.
+        const DIRECTION_FLAG = 1 << 10;
+        /// Set if the signed result does not fit in the destination.
+        const OVERFLOW_FLAG = 1 << 11;
+        /// Privilege level required to execute I/O instructions and to change IF.
+        const IO_PRIVILEGE_LEVEL = 3 << 12;
+
+        /// Flags updated by arithmetic instructions.
+        const STATUS_FLAGS = Self::CARRY_FLAG.bits
+            | Self::PARITY_FLAG.bits
+            | Self::AUXILIARY_CARRY_FLAG.bits
+            | Self::ZERO_FLAG.bits
+            | Self::SIGN_FLAG.bits
+            | Self::OVERFLOW_FLAG.bits;
+    }
+}
+
+impl RFlags {
+    /// Returns IOPL.
+    pub fn iopl(self) -> u8 {
+        ((self.bits & Self::IO_PRIVILEGE_LEVEL.bits) >> 12) as u8
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn status_flags() {
+        let mut rflags = RFlags::DIRECTION_FLAG | RFlags::CARRY_FLAG | RFlags::ZERO_FLAG;
+        rflags.remove(RFlags::STATUS_FLAGS);
+        assert_eq!(rflags, RFlags::DIRECTION_FLAG);
+    }
+}
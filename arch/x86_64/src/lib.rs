@@ -1,35 +1,89 @@
 #[macro_use]
+extern crate bitflags;
+#[macro_use]
 extern crate failure;
 #[macro_use]
 extern crate enum_primitive;
 
+pub mod cpuid;
 mod decoder;
 mod ex_stage;
 mod exceptions;
 mod fetcher;
+mod interrupt;
 mod isa;
+pub mod linux;
+mod msr;
+mod paging;
 mod register_file;
+mod segmentation;
 
+use self::cpuid::Cpuid;
 use self::decoder::ExecuteInstType;
-use self::ex_stage::{WriteBack, WriteBackData};
-use self::exceptions::InternalException;
+use self::ex_stage::WriteBack;
 use self::fetcher::{FetchUnit, FetchedInst};
+use self::interrupt::{
+    DescriptorTableRegister, Event, GateDescriptor, TssDescriptor, REAL_MODE_IVT,
+};
+use self::isa::control_registers::{Cr0, Efer};
+use self::isa::opcode::{Opcode, OperandSize};
+use self::isa::registers::Reg64Id::{Rax, Rbx, Rcx, Rdx, Rsp, R11};
+use self::linux::{LinuxProcess, ProgramImage};
+use self::msr::SystemCallMsrs;
+use self::paging::{AccessKind, LinearMemory, Mmu, PAGE_SIZE};
 use self::register_file::RegisterFile;
+use self::segmentation::{CodeSize, SegmentDescriptor};
 use cpu::model::{CpuModel, Pipeline};
 use debug::DebugMode;
 use peripherals::interconnect::Interconnect;
+use peripherals::io_bus::IoDevice;
 use peripherals::memory_access::MemoryAccess;
+use std::collections::VecDeque;
 use std::fmt;
 use std::result;
 
+// From x86_64 specification.
+const MAX_INSTRUCTION_LENGTH: u64 = 15;
+// Bit 1 of RFLAGS is reserved and always read as 1.
+const RFLAGS_RESERVED: u64 = 1 << 1;
+// SYSRET loads only these bits from R11, which clears RF and VM.
+const SYSRET_RFLAGS_MASK: u64 = 0x3c_7fd7;
+// Flat segments which a Multiboot loader leaves.
+const MULTIBOOT_CODE_SELECTOR: u16 = 0x08;
+const MULTIBOOT_DATA_SELECTOR: u16 = 0x10;
+const SEGMENT_REGISTERS: [SegmentRegister; 6] = [
+    SegmentRegister::Es,
+    SegmentRegister::Cs,
+    SegmentRegister::Ss,
+    SegmentRegister::Ds,
+    SegmentRegister::Fs,
+    SegmentRegister::Gs,
+];
+
+pub use self::exceptions::InternalException;
+pub use self::isa::registers::{Reg64Id, SegmentRegister};
+pub use self::isa::rflags::RFlags;
+
 pub type Result<T> = result::Result<T, InternalException>;
 pub struct X86_64 {
     rf: RegisterFile,
+    rflags: RFlags,
     fetch_unit: FetchUnit,
     executed_insts: u64,
     mmio: Interconnect,
+    mmu: Mmu,
+    // Set when CS is loaded from the D and L flags of the descriptor.
+    code_size: CodeSize,
+    gdtr: DescriptorTableRegister,
+    // Exceptions abort the emulation until LIDT is executed.
+    idtr: Option<DescriptorTableRegister>,
+    tss_base: u64,
+    msrs: SystemCallMsrs,
+    cpuid: Cpuid,
+    pending_interrupts: VecDeque<u8>,
     state: CpuState,
     debug: DebugMode,
+    process: Option<LinuxProcess>,
 }
 
 impl CpuModel for X86_64 {
@@ -38,11 +92,21 @@ impl CpuModel for X86_64 {
     fn new(mmio: Interconnect, debug: DebugMode) -> X86_64 {
         X86_64 {
             rf: RegisterFile::new(),
+            rflags: RFlags::empty(),
             fetch_unit: FetchUnit::new(),
             executed_insts: 0,
             mmio,
+            mmu: Mmu::new(),
+            code_size: CodeSize::Bits64,
+            gdtr: DescriptorTableRegister::default(),
+            idtr: None,
+            tss_base: 0,
+            msrs: SystemCallMsrs::default(),
+            cpuid: Cpuid::new(),
+            pending_interrupts: VecDeque::new(),
             state: CpuState::Running,
             debug,
+            process: None,
         }
     }
 
@@ -51,21 +115,826 @@ impl CpuModel for X86_64 {
     }
 
     fn run(&mut self) -> Result<()> {
-        while self.state == CpuState::Running {
-            let inst_candidate = self.mmio.fetch_inst_candidate(self.fetch_unit.get_rip());
-            let inst = self.fetch_unit.fetch(&inst_candidate)?;
-            let uops = self.decode(&inst)?;
-            let wbs = self.execute(&uops)?;
-            self.write_back(&wbs)?;
-            self.executed_insts += 1;
+        loop {
+            self.accept_interrupt()?;
+            if self.state == CpuState::Halt && self.wait_for_interrupt() {
+                continue;
+            }
+            if self.state != CpuState::Running {
+                break;
+            }
+            let rip = self.fetch_unit.get_rip();
+            if let Err(exception) = self.step() {
+                self.handle_exception(exception, rip)?;
+            }
+            // One instruction takes one clock of the PIT.
+            self.mmio.tick(1);
             self.debug.do_cycle_end_action(&self);
         }
-        println!(
-            "Finish emulation. {} instructions executed.",
-            self.executed_insts
-        );
+        // Output of a user process should not be mixed with the emulator's.
+        if self.process.is_none() {
+            println!(
+                "Finish emulation. {} instructions executed.",
+                self.executed_insts
+            );
+        }
+        Ok(())
+    }
+}
+
+impl X86_64 {
+    /// Creates a CPU which runs a Linux process in user mode.
+    /// The program must be loaded in the user space of `mmio` already.
+    pub fn new_user_process(
+        mut mmio: Interconnect,
+        debug: DebugMode,
+        mut process: LinuxProcess,
+        image: &ProgramImage,
+    ) -> X86_64 {
+        let rsp = process.start(&mut mmio, image);
+        let mut x86_64 = X86_64::new(mmio, debug);
+        x86_64.rf.write64(Rsp, rsp);
+        x86_64.fetch_unit.set_rip(image.entry);
+        x86_64
+            .rf
+            .write_selector(SegmentRegister::Cs, linux::USER_CODE_SELECTOR);
+        x86_64
+            .rf
+            .write_selector(SegmentRegister::Ss, linux::USER_STACK_SELECTOR);
+        x86_64.process = Some(process);
+        x86_64
+    }
+
+    /// Status of the exited user process.
+    pub fn exit_status(&self) -> Option<i32> {
+        self.process
+            .as_ref()
+            .and_then(|process| process.exit_status())
+    }
+
+    /// Resets the CPU to real mode, where all segments are at 0 and IP is 0.
+    pub fn reset_to_real_mode(&mut self) {
+        self.mmu = Mmu::new_real_mode();
+        self.code_size = CodeSize::Bits16;
+        for segment in SEGMENT_REGISTERS.iter() {
+            self.rf.load_segment(*segment, 0, 0);
+        }
+        self.rflags = RFlags::empty();
+        self.fetch_unit.set_rip(0);
+    }
+
+    /// Resets the CPU to real mode as a BIOS leaves it before the boot sector at 0x7c00.
+    /// The boot sector can switch to protected mode and long mode by itself.
+    pub fn boot_bios(&mut self) {
+        self.reset_to_real_mode();
+        self.rf.write64(Rax, 0xaa55);
+        self.rf.write64(Rsp, 0x6f2c);
+        self.fetch_unit.set_rip(0x7c00);
+    }
+
+    /// Starts a Multiboot kernel at `entry` in the state a Multiboot loader leaves.
+    /// The CPU is in 32-bit protected mode with flat segments, and interrupts are disabled.
+    /// EAX holds `magic`, and EBX holds the address of the boot information.
+    /// GDTR is not valid until the kernel loads its own GDT.
+    pub fn boot_multiboot(&mut self, entry: u64, magic: u32, info: u64) {
+        self.reset_to_real_mode();
+        let cr0 = self.mmu.read_control_register(0);
+        self.mmu
+            .write_control_register(0, cr0 | Cr0::PROTECTION_ENABLE.bits());
+        for segment in SEGMENT_REGISTERS.iter() {
+            let selector = match segment {
+                SegmentRegister::Cs => MULTIBOOT_CODE_SELECTOR,
+                _ => MULTIBOOT_DATA_SELECTOR,
+            };
+            self.rf.load_segment(*segment, selector, 0);
+        }
+        self.code_size = CodeSize::Bits32;
+        self.rf.write64(Rax, u64::from(magic));
+        self.rf.write64(Rbx, info);
+        self.fetch_unit.set_rip(entry);
+    }
+
+    /// Requests an external interrupt, which is accepted at an instruction boundary
+    /// while RFLAGS.IF is set. A halted CPU resumes to handle the interrupt.
+    /// Interrupts requested here take priority over those from the PIC.
+    pub fn request_interrupt(&mut self, vector: u8) {
+        self.pending_interrupts.push_back(vector);
+    }
+
+    /// Leaves returned by CPUID, which may be configured before running.
+    pub fn cpuid_mut(&mut self) -> &mut Cpuid {
+        &mut self.cpuid
+    }
+
+    /// RIP, which is an offset in CS outside 64-bit mode.
+    pub fn rip(&self) -> u64 {
+        self.fetch_unit.get_rip()
+    }
+
+    pub fn read_register(&self, reg: Reg64Id) -> u64 {
+        self.rf.read64(reg)
+    }
+
+    pub fn write_register(&mut self, reg: Reg64Id, value: u64) {
+        self.rf.write64(reg, value)
+    }
+
+    pub fn selector(&self, segment: SegmentRegister) -> u16 {
+        self.rf.selector(segment)
+    }
+
+    pub fn rflags(&self) -> RFlags {
+        self.rflags
+    }
+
+    pub fn write_rflags(&mut self, rflags: RFlags) {
+        self.rflags = rflags;
+    }
+
+    /// True after HLT until an interrupt wakes the CPU up.
+    pub fn is_halted(&self) -> bool {
+        self.state == CpuState::Halt
+    }
+
+    pub fn interconnect(&self) -> &Interconnect {
+        &self.mmio
+    }
+
+    pub fn interconnect_mut(&mut self) -> &mut Interconnect {
+        &mut self.mmio
+    }
+
+    fn step(&mut self) -> Result<()> {
+        let inst_candidate = self.fetch_inst_candidate()?;
+        let inst = self.fetch_unit.fetch(&inst_candidate, self.code_size)?;
+        if inst.opcode.is_privileged() && self.cpl() != 0 {
+            return Err(InternalException::GeneralProtection { error_code: 0 });
+        }
+        let uops = self.decode(&inst)?;
+        let wbs = self.execute(&uops)?;
+        self.write_back(&wbs)?;
+        self.executed_insts += 1;
+        Ok(())
+    }
+
+    // CPL is always 0 in real mode.
+    fn cpl(&self) -> u8 {
+        if !self.mmu.is_protected_mode() {
+            return 0;
+        }
+        (self.rf.selector(SegmentRegister::Cs) & 3) as u8
+    }
+
+    // Accesses at CPL 3 are user-mode accesses.
+    fn is_user_mode(&self) -> bool {
+        self.cpl() == 3
+    }
+
+    // Bytes in the next page are fetched only if it is mapped,
+    // because the instruction may end before the page boundary.
+    // RIP is an offset in CS outside 64-bit mode.
+    fn fetch_inst_candidate(&mut self) -> Result<Vec<u8>> {
+        let rip = match self.code_size {
+            CodeSize::Bits64 => self.fetch_unit.get_rip(),
+            _ => {
+                let base = self.rf.segment_base(SegmentRegister::Cs);
+                (self.fetch_unit.get_rip() + base) & 0xffff_ffff
+            }
+        };
+        if !self.mmu.is_paging_enabled() {
+            return Ok(self.mmio.fetch_inst_candidate(rip));
+        }
+        let user = self.is_user_mode();
+        let first = (PAGE_SIZE - rip % PAGE_SIZE).min(MAX_INSTRUCTION_LENGTH);
+        let mut ranges = self.mmu.translate(
+            &mut self.mmio,
+            rip,
+            first as usize,
+            AccessKind::Execute,
+            user,
+        )?;
+        if first < MAX_INSTRUCTION_LENGTH {
+            let rest = (MAX_INSTRUCTION_LENGTH - first) as usize;
+            let next = rip.wrapping_add(first);
+            let next = self
+                .mmu
+                .translate(&mut self.mmio, next, rest, AccessKind::Execute, user);
+            ranges.extend(next.unwrap_or_default());
+        }
+        Ok(ranges
+            .iter()
+            .flat_map(|(addr, len)| *addr..*addr + *len)
+            .map(|addr| self.mmio.read_u8(addr))
+            .take_while(|byte| byte.is_ok())
+            .map(|byte| byte.unwrap())
+            .collect())
+    }
+
+    // Physical ranges of the memory accessed by the write back.
+    // Loads and stores of an instruction which updates RSP, such as PUSH and POP, are stack accesses.
+    fn translate_write_back(
+        &mut self,
+        wb: &WriteBack,
+        stack: bool,
+    ) -> Result<Option<Vec<(usize, usize)>>> {
+        let (addr, size, kind, stack) = match wb {
+            WriteBack::Load(_, size, addr) => (*addr, *size, AccessKind::Read, stack),
+            WriteBack::Store(addr, data) => {
+                (*addr, data.size_and_value().0, AccessKind::Write, stack)
+            }
+            WriteBack::PortInToMemory(addr, size, _) => (*addr, *size, AccessKind::Write, false),
+            WriteBack::Return(addr, size) => (*addr, *size, AccessKind::Read, true),
+            WriteBack::InterruptReturn(rsp, size) => {
+                let slots = if self.mmu.is_long_mode_active() { 5 } else { 3 };
+                let len = (size.bits() / 8 * slots) as usize;
+                let user = self.is_user_mode();
+                let ranges = self.translate_stack(*rsp, len, AccessKind::Read, user)?;
+                return Ok(Some(ranges));
+            }
+            _ => return Ok(None),
+        };
+        let len = (size.bits() / 8) as usize;
+        let user = self.is_user_mode();
+        let ranges = if stack {
+            self.translate_stack(addr, len, kind, user)?
+        } else {
+            self.mmu.translate(&mut self.mmio, addr, len, kind, user)?
+        };
+        Ok(Some(ranges))
+    }
+
+    // Translates an access to the stack, where a non-canonical address raises #SS instead of #GP.
+    fn translate_stack(
+        &mut self,
+        addr: u64,
+        len: usize,
+        kind: AccessKind,
+        user: bool,
+    ) -> Result<Vec<(usize, usize)>> {
+        self.mmu
+            .translate(&mut self.mmio, addr, len, kind, user)
+            .map_err(|exception| match exception {
+                InternalException::GeneralProtection { error_code } => {
+                    InternalException::StackFault { error_code }
+                }
+                exception => exception,
+            })
+    }
+
+    // Faults are delivered to the guest once the IDT is loaded.
+    // `rip` points to the faulting instruction, which restarts after the handler returns.
+    fn handle_exception(&mut self, exception: InternalException, rip: u64) -> Result<()> {
+        self.record_page_fault(&exception);
+        match Event::from_exception(&exception) {
+            Some(event) if self.can_deliver_exception() => self.deliver_exception(event, rip),
+            _ => Err(exception),
+        }
+    }
+
+    // Interrupts in protected mode are accepted once the IDT is loaded.
+    fn can_accept_interrupt(&self) -> bool {
+        !self.mmu.is_protected_mode() || self.idtr.is_some()
+    }
+
+    // Exceptions abort the emulation until LIDT is executed,
+    // so that a fault in real mode does not jump through the uninitialized IVT.
+    fn can_deliver_exception(&self) -> bool {
+        self.idtr.is_some() && self.can_accept_interrupt()
+    }
+
+    // An exception during the delivery is delivered serially, or escalates to #DF.
+    // An exception during the delivery of #DF shuts down the processor.
+    fn deliver_exception(&mut self, event: Event, rip: u64) -> Result<()> {
+        let exception = match self.deliver(event, rip) {
+            Ok(()) => return Ok(()),
+            Err(exception) => exception,
+        };
+        self.record_page_fault(&exception);
+        let second = Event::from_exception(&exception).ok_or(exception)?;
+        if event.vector == interrupt::DOUBLE_FAULT {
+            return Err(InternalException::TripleFault);
+        }
+        if interrupt::is_double_fault(&event, &second) {
+            self.deliver_exception(Event::exception(interrupt::DOUBLE_FAULT, Some(0)), rip)
+        } else {
+            self.deliver_exception(second, rip)
+        }
+    }
+
+    // CR2 holds the linear address which caused the page fault.
+    fn record_page_fault(&mut self, exception: &InternalException) {
+        if let InternalException::PageFault { addr, .. } = exception {
+            self.mmu.write_control_register(2, *addr);
+        }
+    }
+
+    fn accept_interrupt(&mut self) -> Result<()> {
+        if !self.rflags.contains(RFlags::INTERRUPT_FLAG) || !self.can_accept_interrupt() {
+            return Ok(());
+        }
+        let vector = self
+            .pending_interrupts
+            .pop_front()
+            .or_else(|| self.mmio.acknowledge_interrupt());
+        match vector {
+            Some(vector) => {
+                let rip = self.fetch_unit.get_rip();
+                self.deliver_exception(Event::external(vector), rip)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // A halted CPU stops emulation unless an interrupt wakes it up.
+    fn wait_for_interrupt(&mut self) -> bool {
+        self.rflags.contains(RFlags::INTERRUPT_FLAG)
+            && self.can_accept_interrupt()
+            && self.mmio.wait_for_interrupt()
+    }
+
+    // Enters the handler through the IDT gate of the vector. `rip` is the return address.
+    // The stack is switched to the IST slot or, on a privilege change, to RSP of the new CPL in the TSS.
+    // The new CPL is taken from RPL of the gate's selector, as code segment descriptors are not emulated.
+    fn deliver(&mut self, event: Event, rip: u64) -> Result<()> {
+        if !self.mmu.is_protected_mode() {
+            return self.deliver_real_mode(event, rip);
+        }
+        if !self.mmu.is_long_mode_active() {
+            return self.deliver_protected_mode(event, rip);
+        }
+        let idtr = self.idtr.unwrap_or_default();
+        let offset = u64::from(event.vector) * 16;
+        let error_code = event.idt_error_code();
+        if !idtr.contains(offset, 16) {
+            return Err(InternalException::GeneralProtection { error_code });
+        }
+        let low = self.read_system(idtr.base.wrapping_add(offset))?;
+        let high = self.read_system(idtr.base.wrapping_add(offset + 8))?;
+        let gate = GateDescriptor::new(low, high);
+        if !gate.is_valid()
+            || (event.source == interrupt::EventSource::Software && gate.dpl < self.cpl())
+        {
+            return Err(InternalException::GeneralProtection { error_code });
+        }
+        if !gate.present {
+            return Err(InternalException::SegmentNotPresent { error_code });
+        }
+
+        let new_cpl = (gate.selector & 3) as u8;
+        let privilege_change = new_cpl < self.cpl();
+        let rsp = if gate.ist != 0 {
+            self.read_system(self.tss_base + interrupt::tss_ist_offset(gate.ist))?
+        } else if privilege_change {
+            self.read_system(self.tss_base + interrupt::tss_rsp_offset(new_cpl))?
+        } else {
+            self.rf.read64(Rsp)
+        };
+        // The stack frame is aligned to 16 bytes.
+        let mut frame = vec![
+            rip,
+            u64::from(self.rf.selector(SegmentRegister::Cs)),
+            self.saved_rflags(),
+            self.rf.read64(Rsp),
+            u64::from(self.rf.selector(SegmentRegister::Ss)),
+        ];
+        if let Some(code) = event.error_code {
+            frame.insert(0, code);
+        }
+        let new_rsp = (rsp & !0xf).wrapping_sub(frame.len() as u64 * 8);
+        let bytes: Vec<u8> = frame
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect();
+        let ranges = self.translate_stack(new_rsp, bytes.len(), AccessKind::Write, new_cpl == 3)?;
+        paging::write_physical_bytes(&mut self.mmio, &ranges, &bytes);
+
+        if privilege_change {
+            // SS is a null selector with RPL of the new CPL.
+            self.rf
+                .write_selector(SegmentRegister::Ss, u16::from(new_cpl));
+        }
+        self.rf.write_selector(SegmentRegister::Cs, gate.selector);
+        self.code_size = CodeSize::Bits64;
+        self.rf.write64(Rsp, new_rsp);
+        self.rflags.remove(RFlags::TRAP_FLAG);
+        if !gate.is_trap {
+            self.rflags.remove(RFlags::INTERRUPT_FLAG);
+        }
+        self.fetch_unit.set_rip(gate.offset);
+        self.state = CpuState::Running;
+        Ok(())
+    }
+
+    // Enters the handler through the 8-byte gate of the IDT in 32-bit protected mode.
+    // EFLAGS, CS, EIP and the error code are pushed as 32-bit values on the current stack.
+    // Privilege changes are not supported, so the handler runs at the current CPL as after a far JMP.
+    fn deliver_protected_mode(&mut self, event: Event, rip: u64) -> Result<()> {
+        let idtr = self.idtr.unwrap_or_default();
+        let offset = u64::from(event.vector) * 8;
+        let error_code = event.idt_error_code();
+        if !idtr.contains(offset, 8) {
+            return Err(InternalException::GeneralProtection { error_code });
+        }
+        let gate = GateDescriptor::new_legacy(self.read_system(idtr.base.wrapping_add(offset))?);
+        if !gate.is_valid()
+            || (event.source == interrupt::EventSource::Software && gate.dpl < self.cpl())
+        {
+            return Err(InternalException::GeneralProtection { error_code });
+        }
+        if !gate.present {
+            return Err(InternalException::SegmentNotPresent { error_code });
+        }
+
+        let mut frame = vec![
+            rip as u32,
+            u32::from(self.rf.selector(SegmentRegister::Cs)),
+            self.saved_rflags() as u32,
+        ];
+        if let Some(code) = event.error_code {
+            frame.insert(0, code as u32);
+        }
+        let sp_size = self.code_size.address_size();
+        let sp = self
+            .rf
+            .read(Rsp, sp_size)
+            .wrapping_sub(frame.len() as u64 * 4)
+            & sp_size.mask();
+        let bytes: Vec<u8> = frame
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect();
+        let addr = self.rf.segment_base(SegmentRegister::Ss) + sp;
+        let user = self.is_user_mode();
+        let ranges =
+            self.mmu
+                .translate(&mut self.mmio, addr, bytes.len(), AccessKind::Write, user)?;
+        paging::write_physical_bytes(&mut self.mmio, &ranges, &bytes);
+
+        self.far_jump(gate.selector, gate.offset)?;
+        self.rf.write(Rsp, sp_size, sp);
+        self.rflags.remove(RFlags::TRAP_FLAG);
+        if !gate.is_trap {
+            self.rflags.remove(RFlags::INTERRUPT_FLAG);
+        }
+        self.state = CpuState::Running;
+        Ok(())
+    }
+
+    // Enters the handler through the IVT, whose entries are far pointers of 4 bytes.
+    // FLAGS, CS and IP are pushed, and error codes are not.
+    fn deliver_real_mode(&mut self, event: Event, rip: u64) -> Result<()> {
+        let ivt = self.idtr.unwrap_or(REAL_MODE_IVT);
+        let offset = u64::from(event.vector) * 4;
+        if !ivt.contains(offset, 4) {
+            return Err(InternalException::GeneralProtection { error_code: 0 });
+        }
+        let entry = self.read_system(ivt.base.wrapping_add(offset))?;
+        let (handler, selector) = (entry & 0xffff, (entry >> 16) as u16);
+
+        let frame = [
+            rip as u16,
+            self.rf.selector(SegmentRegister::Cs),
+            self.saved_rflags() as u16,
+        ];
+        let sp = self
+            .rf
+            .read(Rsp, OperandSize::Word)
+            .wrapping_sub(frame.len() as u64 * 2)
+            & 0xffff;
+        let bytes: Vec<u8> = frame
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect();
+        let addr = self.rf.segment_base(SegmentRegister::Ss) + sp;
+        let ranges =
+            self.mmu
+                .translate(&mut self.mmio, addr, bytes.len(), AccessKind::Write, false)?;
+        paging::write_physical_bytes(&mut self.mmio, &ranges, &bytes);
+
+        self.rf.write(Rsp, OperandSize::Word, sp);
+        self.far_jump(selector, handler)?;
+        self.rflags
+            .remove(RFlags::TRAP_FLAG | RFlags::INTERRUPT_FLAG);
+        self.state = CpuState::Running;
+        Ok(())
+    }
+
+    // IRET returns to the same or an outer privilege level.
+    // Outside long mode, only IP, CS and FLAGS are popped, as privilege changes are not supported.
+    fn interrupt_return(&mut self, ranges: &[(usize, usize)], size: OperandSize) -> Result<()> {
+        let width = (size.bits() / 8) as usize;
+        let bytes = paging::read_physical_bytes(&self.mmio, ranges);
+        let values: Vec<u64> = bytes
+            .chunks(width)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .rev()
+                    .fold(0, |value, byte| value << 8 | u64::from(*byte))
+            })
+            .collect();
+        if !self.mmu.is_long_mode_active() {
+            return self.legacy_interrupt_return(values[0], values[1] as u16, values[2], size);
+        }
+        let (rip, cs, rflags, rsp, ss) = (values[0], values[1], values[2], values[3], values[4]);
+        if ((cs & 3) as u8) < self.cpl() {
+            return Err(InternalException::GeneralProtection {
+                error_code: cs & !3,
+            });
+        }
+        self.rflags = self.merge_rflags(rflags, !0);
+        self.fetch_unit.set_rip(rip);
+        self.rf.write_selector(SegmentRegister::Cs, cs as u16);
+        self.rf.write64(Rsp, rsp);
+        self.rf.write_selector(SegmentRegister::Ss, ss as u16);
+        Ok(())
+    }
+
+    // The stack pointer is of the size before CS is reloaded.
+    fn legacy_interrupt_return(
+        &mut self,
+        ip: u64,
+        cs: u16,
+        flags: u64,
+        size: OperandSize,
+    ) -> Result<()> {
+        let sp_size = self.code_size.address_size();
+        let sp = self
+            .rf
+            .read(Rsp, sp_size)
+            .wrapping_add(u64::from(size.bits() / 8) * 3)
+            & sp_size.mask();
+        let rflags = self.merge_rflags(flags, size.mask());
+        self.far_jump(cs, ip)?;
+        self.rflags = rflags;
+        self.rf.write(Rsp, sp_size, sp);
+        Ok(())
+    }
+
+    // RFLAGS as saved by interrupts and SYSCALL, with the reserved bit 1 set.
+    fn saved_rflags(&self) -> u64 {
+        self.rflags.bits() | RFLAGS_RESERVED
+    }
+
+    // RFLAGS whose bits in `mask` are loaded from `value` with the privilege of the current CPL.
+    // IOPL is changed only at CPL 0, and IF only if CPL <= IOPL.
+    // Flags which are not emulated are ignored, and the reserved bit 1 is set when RFLAGS is saved.
+    fn merge_rflags(&self, value: u64, mask: u64) -> RFlags {
+        let mut mask = mask;
+        if self.cpl() > 0 {
+            mask &= !RFlags::IO_PRIVILEGE_LEVEL.bits();
+        }
+        if self.cpl() > self.rflags.iopl() {
+            mask &= !RFlags::INTERRUPT_FLAG.bits();
+        }
+        RFlags::from_bits_truncate(self.rflags.bits() & !mask | value & mask)
+    }
+
+    // LTR loads the TSS descriptor from the GDT and marks it busy.
+    fn load_task_register(&mut self, selector: u16) -> Result<()> {
+        let error_code = u64::from(selector & !3);
+        let index = u64::from(selector & !7);
+        if index == 0 || !self.gdtr.contains(index, 16) {
+            return Err(InternalException::GeneralProtection { error_code });
+        }
+        let addr = self.gdtr.base.wrapping_add(index);
+        let low = self.read_system(addr)?;
+        let high = self.read_system(addr + 8)?;
+        let tss = TssDescriptor::new(low, high);
+        if !tss.is_valid() || tss.busy {
+            return Err(InternalException::GeneralProtection { error_code });
+        }
+        if !tss.present {
+            return Err(InternalException::SegmentNotPresent { error_code });
+        }
+        let ranges = self
+            .mmu
+            .translate(&mut self.mmio, addr, 8, AccessKind::Write, false)?;
+        let busy = TssDescriptor::mark_busy(low);
+        paging::write_physical(&mut self.mmio, &ranges, OperandSize::QuadWord, busy);
+        self.tss_base = tss.base;
+        Ok(())
+    }
+
+    fn read_port(&self, port: u16, size: OperandSize) -> u64 {
+        let io_bus = self.mmio.io_bus();
+        match size {
+            OperandSize::Byte => u64::from(io_bus.read_u8(port)),
+            OperandSize::Word => u64::from(io_bus.read_u16(port)),
+            _ => u64::from(io_bus.read_u32(port)),
+        }
+    }
+
+    fn write_port(&mut self, port: u16, size: OperandSize, data: u64) {
+        let io_bus = self.mmio.io_bus_mut();
+        match size {
+            OperandSize::Byte => io_bus.write_u8(port, data as u8),
+            OperandSize::Word => io_bus.write_u16(port, data as u16),
+            _ => io_bus.write_u32(port, data as u32),
+        }
+    }
+
+    // Unknown MSRs and invalid values raise #GP(0).
+    // MSRs other than those of the CPU are looked up in the interconnect.
+    fn read_msr(&self, msr: u32) -> Result<u64> {
+        let value = match msr {
+            msr::IA32_TSC => self.mmio.time_stamp_counter(),
+            msr::IA32_EFER => self.mmu.efer().bits(),
+            msr::IA32_STAR => self.msrs.star,
+            msr::IA32_LSTAR => self.msrs.lstar,
+            msr::IA32_CSTAR => self.msrs.cstar,
+            msr::IA32_FMASK => self.msrs.fmask,
+            msr::IA32_FS_BASE => self.rf.segment_base(SegmentRegister::Fs),
+            msr::IA32_GS_BASE => self.rf.segment_base(SegmentRegister::Gs),
+            msr::IA32_KERNEL_GS_BASE => self.msrs.kernel_gs_base,
+            _ => self
+                .mmio
+                .read_msr(msr)
+                .ok_or(InternalException::GeneralProtection { error_code: 0 })?,
+        };
+        Ok(value)
+    }
+
+    fn write_msr(&mut self, msr: u32, value: u64) -> Result<()> {
+        let valid = match msr {
+            msr::IA32_TSC => {
+                self.mmio.set_time_stamp_counter(value);
+                true
+            }
+            msr::IA32_EFER => self.mmu.write_efer(value),
+            msr::IA32_STAR => {
+                self.msrs.star = value;
+                true
+            }
+            msr::IA32_FMASK => {
+                self.msrs.fmask = value & 0xffff_ffff;
+                true
+            }
+            // Addresses must be canonical.
+            msr::IA32_LSTAR
+            | msr::IA32_CSTAR
+            | msr::IA32_FS_BASE
+            | msr::IA32_GS_BASE
+            | msr::IA32_KERNEL_GS_BASE
+                if !msr::is_canonical(value) =>
+            {
+                false
+            }
+            msr::IA32_LSTAR => {
+                self.msrs.lstar = value;
+                true
+            }
+            msr::IA32_CSTAR => {
+                self.msrs.cstar = value;
+                true
+            }
+            msr::IA32_FS_BASE => {
+                self.rf.write_segment_base(SegmentRegister::Fs, value);
+                true
+            }
+            msr::IA32_GS_BASE => {
+                self.rf.write_segment_base(SegmentRegister::Gs, value);
+                true
+            }
+            msr::IA32_KERNEL_GS_BASE => {
+                self.msrs.kernel_gs_base = value;
+                true
+            }
+            _ => self.mmio.write_msr(msr, value),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(InternalException::GeneralProtection { error_code: 0 })
+        }
+    }
+
+    // SYSCALL enters CPL 0 at LSTAR. CS and SS are taken from STAR.
+    // RCX and R11 hold the return address and RFLAGS, and FMASK clears RFLAGS bits.
+    fn system_call(&mut self) -> Result<()> {
+        if !self.mmu.efer().contains(Efer::SYSTEM_CALL_EXTENSIONS) {
+            return Err(InternalException::UndefinedInstruction {
+                opcode: Opcode::Syscall,
+            });
+        }
+        self.rf.write64(Rcx, self.fetch_unit.get_rip());
+        self.rf.write64(R11, self.saved_rflags());
+        self.rflags = RFlags::from_bits_truncate(self.rflags.bits() & !self.msrs.fmask);
+        let selector = (self.msrs.star >> 32) as u16 & !3;
+        self.rf.write_selector(SegmentRegister::Cs, selector);
+        self.rf.write_selector(SegmentRegister::Ss, selector + 8);
+        self.fetch_unit.set_rip(self.msrs.lstar);
+        Ok(())
+    }
+
+    // SYSRET returns to CPL 3 at RCX with RFLAGS from R11. CS and SS are taken from STAR.
+    // Without REX.W, it returns to compatibility mode, which is executed as 64-bit code.
+    fn system_return(&mut self, rcx: u64, r11: u64, size: OperandSize) -> Result<()> {
+        if !self.mmu.efer().contains(Efer::SYSTEM_CALL_EXTENSIONS) {
+            return Err(InternalException::UndefinedInstruction {
+                opcode: Opcode::Sysret,
+            });
+        }
+        let selector = (self.msrs.star >> 48) as u16;
+        let (rip, cs) = if size == OperandSize::QuadWord {
+            if !msr::is_canonical(rcx) {
+                return Err(InternalException::GeneralProtection { error_code: 0 });
+            }
+            (rcx, selector.wrapping_add(16))
+        } else {
+            (rcx & 0xffff_ffff, selector)
+        };
+        self.fetch_unit.set_rip(rip);
+        self.rflags = RFlags::from_bits_truncate(r11 & SYSRET_RFLAGS_MASK | RFLAGS_RESERVED);
+        self.rf.write_selector(SegmentRegister::Cs, cs | 3);
+        self.rf
+            .write_selector(SegmentRegister::Ss, selector.wrapping_add(8) | 3);
+        Ok(())
+    }
+
+    // Reads the code or data segment descriptor of the selector from the GDT.
+    // A null selector has no descriptor.
+    fn read_segment_descriptor(&mut self, selector: u16) -> Result<Option<SegmentDescriptor>> {
+        let error_code = u64::from(selector & !3);
+        let index = u64::from(selector & !7);
+        if index == 0 {
+            return Ok(None);
+        }
+        // The LDT is not supported.
+        if selector & 4 != 0 || !self.gdtr.contains(index, 8) {
+            return Err(InternalException::GeneralProtection { error_code });
+        }
+        let descriptor = self.read_system(self.gdtr.base.wrapping_add(index))?;
+        Ok(Some(SegmentDescriptor::new(descriptor)))
+    }
+
+    // MOV Sreg loads the base of the selector, which is the selector shifted by 4 in real mode.
+    // A null selector may be loaded into data segments, and also into SS in 64-bit mode.
+    fn load_segment(&mut self, segment: SegmentRegister, selector: u16) -> Result<()> {
+        if !self.mmu.is_protected_mode() {
+            self.rf
+                .load_segment(segment, selector, u64::from(selector) << 4);
+            return Ok(());
+        }
+        let error_code = u64::from(selector & !3);
+        let descriptor = match self.read_segment_descriptor(selector)? {
+            Some(descriptor) => descriptor,
+            None if segment != SegmentRegister::Ss || self.code_size == CodeSize::Bits64 => {
+                self.rf.load_segment(segment, selector, 0);
+                return Ok(());
+            }
+            None => return Err(InternalException::GeneralProtection { error_code }),
+        };
+        let valid = match segment {
+            SegmentRegister::Ss => descriptor.is_valid_stack(),
+            _ => descriptor.is_valid_data(),
+        };
+        if !valid {
+            return Err(InternalException::GeneralProtection { error_code });
+        }
+        if !descriptor.present {
+            return Err(InternalException::SegmentNotPresent { error_code });
+        }
+        self.rf.load_segment(segment, selector, descriptor.base);
+        Ok(())
+    }
+
+    // A far JMP loads CS, whose D and L flags switch the code size.
+    // The CPL does not change, and it replaces RPL of the selector.
+    fn far_jump(&mut self, selector: u16, offset: u64) -> Result<()> {
+        if !self.mmu.is_protected_mode() {
+            self.rf
+                .load_segment(SegmentRegister::Cs, selector, u64::from(selector) << 4);
+            self.fetch_unit.set_rip(offset & 0xffff);
+            return Ok(());
+        }
+        let error_code = u64::from(selector & !3);
+        let descriptor = self
+            .read_segment_descriptor(selector)?
+            .filter(|descriptor| descriptor.is_valid_code())
+            .ok_or(InternalException::GeneralProtection { error_code })?;
+        if !descriptor.present {
+            return Err(InternalException::SegmentNotPresent { error_code });
+        }
+        let selector = selector & !3 | u16::from(self.cpl());
+        self.rf
+            .load_segment(SegmentRegister::Cs, selector, descriptor.base);
+        self.code_size = descriptor.code_size(self.mmu.is_long_mode_active());
+        self.fetch_unit.set_rip(offset);
         Ok(())
     }
+
+    // System structures such as IDT, GDT and TSS are accessed as supervisor.
+    fn read_system(&mut self, addr: u64) -> Result<u64> {
+        let ranges = self
+            .mmu
+            .translate(&mut self.mmio, addr, 8, AccessKind::Read, false)?;
+        Ok(paging::read_physical(
+            &self.mmio,
+            &ranges,
+            OperandSize::QuadWord,
+        ))
+    }
 }
 
 impl Pipeline for X86_64 {
@@ -80,39 +949,120 @@ impl Pipeline for X86_64 {
     }
 
     fn decode(&self, inst: &Self::Fetched) -> Result<Self::Decoded> {
-        decoder::decode(&self.rf, &inst)
+        let memory = LinearMemory::new(&self.mmu, &self.mmio, self.is_user_mode());
+        decoder::decode(&self.rf, self.rflags, &memory, &inst)
     }
 
     fn execute(&self, insts: &Self::Decoded) -> Result<Self::Executed> {
-        let results: Self::Executed = (&insts)
-            .into_iter()
-            .map(|inst| ex_stage::execute(&inst).unwrap())
-            .collect();
-        Ok(results)
+        let memory = LinearMemory::new(&self.mmu, &self.mmio, self.is_user_mode());
+        ex_stage::execute_uops(insts, self.rflags, &memory)
     }
 
     fn write_back(&mut self, inst: &Self::Executed) -> Result<()> {
-        for wb in inst {
+        // Memory accesses are translated first, so that a page fault leaves the state unchanged.
+        let stack = inst
+            .iter()
+            .any(|wb| matches!(wb, WriteBack::GeneralRegister(Rsp, _, _)));
+        let ranges = inst
+            .iter()
+            .map(|wb| self.translate_write_back(wb, stack))
+            .collect::<Result<Vec<_>>>()?;
+        for (wb, ranges) in inst.iter().zip(ranges) {
             match wb {
-                WriteBack::GeneralRegister(dest, value) => self.rf.write64(*dest, *value),
+                WriteBack::GeneralRegister(dest, size, value) => {
+                    self.rf.write(*dest, *size, *value)
+                }
+                WriteBack::Xmm(dest, value) => self.rf.write_xmm(*dest, *value),
+                WriteBack::Mxcsr(value) => self.rf.write_mxcsr(*value),
                 WriteBack::Rip(next_rip) => self.fetch_unit.set_rip(*next_rip),
-                WriteBack::Load(dest, addr) => self
-                    .rf
-                    .write64(*dest, self.mmio.read_u64(*addr as usize).unwrap()),
-                WriteBack::Store(addr, data) => {
-                    let addr = *addr as usize;
-                    use self::WriteBackData::*;
-                    match data {
-                        Byte(data) => self.mmio.write_u8(addr, *data).unwrap(),
-                        Word(data) => self.mmio.write_u16(addr, *data).unwrap(),
-                        DWord(data) => self.mmio.write_u32(addr, *data).unwrap(),
-                        QWord(data) => self.mmio.write_u64(addr, *data).unwrap(),
-                    }
+                WriteBack::Flags(rflags) => self.rflags = *rflags,
+                WriteBack::Load(dest, size, _) => {
+                    let value = paging::read_physical(&self.mmio, &ranges.unwrap(), *size);
+                    self.rf.write(*dest, *size, value)
+                }
+                WriteBack::Store(_, data) => {
+                    let (size, data) = data.size_and_value();
+                    paging::write_physical(&mut self.mmio, &ranges.unwrap(), size, data)
                 }
                 WriteBack::CpuState(next_state) => self.state = *next_state,
-                WriteBack::Return(addr) => self
-                    .fetch_unit
-                    .set_rip(self.mmio.read_u64(*addr as usize).unwrap()),
+                WriteBack::Return(_, size) => {
+                    let ranges = ranges.unwrap();
+                    let rip = paging::read_physical(&self.mmio, &ranges, *size);
+                    self.fetch_unit.set_rip(rip)
+                }
+                WriteBack::FarJump(selector, offset) => self.far_jump(*selector, *offset)?,
+                WriteBack::Segment(segment, selector) => self.load_segment(*segment, *selector)?,
+                WriteBack::TimeStampCounter => {
+                    let tsc = self.mmio.time_stamp_counter();
+                    self.rf.write(Rax, OperandSize::DoubleWord, tsc);
+                    self.rf.write(Rdx, OperandSize::DoubleWord, tsc >> 32);
+                }
+                WriteBack::Syscall => {
+                    let rflags = self.saved_rflags();
+                    match self.process.as_mut() {
+                        Some(process) => {
+                            // SYSCALL saves RIP and RFLAGS to RCX and R11.
+                            self.rf.write64(Rcx, self.fetch_unit.get_rip());
+                            self.rf.write64(R11, rflags);
+                            process.syscall(&mut self.rf, &mut self.mmio);
+                            if process.exit_status().is_some() {
+                                self.state = CpuState::Halt;
+                            }
+                        }
+                        None => self.system_call()?,
+                    }
+                }
+                WriteBack::SystemReturn(rcx, r11, size) => self.system_return(*rcx, *r11, *size)?,
+                WriteBack::SwapGs => {
+                    let gs_base = self.rf.segment_base(SegmentRegister::Gs);
+                    let kernel_gs_base = self.msrs.kernel_gs_base;
+                    self.rf
+                        .write_segment_base(SegmentRegister::Gs, kernel_gs_base);
+                    self.msrs.kernel_gs_base = gs_base;
+                }
+                WriteBack::Cpuid(leaf, subleaf) => {
+                    let values = self.cpuid.query(*leaf, *subleaf);
+                    for (reg, value) in [Rax, Rbx, Rcx, Rdx].iter().zip(values.iter()) {
+                        self.rf
+                            .write(*reg, OperandSize::DoubleWord, u64::from(*value));
+                    }
+                }
+                WriteBack::ReadMsr(msr) => {
+                    let value = self.read_msr(*msr)?;
+                    self.rf.write(Rax, OperandSize::DoubleWord, value);
+                    self.rf.write(Rdx, OperandSize::DoubleWord, value >> 32);
+                }
+                WriteBack::WriteMsr(msr, value) => self.write_msr(*msr, *value)?,
+                WriteBack::ControlRegister(number, value) => {
+                    if !self.mmu.write_control_register(*number, *value) {
+                        return Err(InternalException::GeneralProtection { error_code: 0 });
+                    }
+                }
+                WriteBack::ReadControlRegister(dest, number) => {
+                    let value = self.mmu.read_control_register(*number);
+                    self.rf.write64(*dest, value)
+                }
+                WriteBack::InvalidatePage(addr) => self.mmu.invalidate_page(*addr),
+                WriteBack::Gdtr(gdtr) => self.gdtr = *gdtr,
+                WriteBack::Idtr(idtr) => self.idtr = Some(*idtr),
+                WriteBack::TaskRegister(selector) => self.load_task_register(*selector)?,
+                // RIP has been advanced to the next instruction, which is the return address.
+                WriteBack::SoftwareInterrupt(vector) => {
+                    let rip = self.fetch_unit.get_rip();
+                    self.deliver(Event::software(*vector), rip)?
+                }
+                WriteBack::InterruptReturn(_, size) => {
+                    self.interrupt_return(&ranges.unwrap(), *size)?
+                }
+                WriteBack::PortIn(dest, size, port) => {
+                    let value = self.read_port(*port, *size);
+                    self.rf.write(*dest, *size, value)
+                }
+                WriteBack::PortOut(port, size, data) => self.write_port(*port, *size, *data),
+                WriteBack::PortInToMemory(_, size, port) => {
+                    let value = self.read_port(*port, *size);
+                    paging::write_physical(&mut self.mmio, &ranges.unwrap(), *size, value)
+                }
             };
         }
         Ok(())
@@ -123,9 +1073,10 @@ impl fmt::Debug for X86_64 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "=== CPU status ({} instructions executed.)===\nRIP: {}\nRegisters:\n{}",
+            "=== CPU status ({} instructions executed.)===\nRIP: {}\nRFLAGS: {:?}\nRegisters:\n{}",
             self.executed_insts,
             self.fetch_unit.get_rip(),
+            self.rflags,
             self.rf
         )
     }
@@ -135,9 +1086,10 @@ impl fmt::Display for X86_64 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "=== CPU status ({} instructions executed.)===\nRIP: 0x{:>08X}\nRegisters:\n{}",
+            "=== CPU status ({} instructions executed.)===\nRIP: 0x{:>08X}\nRFLAGS: 0x{:>08X}\nRegisters:\n{}",
             self.executed_insts,
             self.fetch_unit.get_rip(),
+            self.rflags.bits(),
             self.rf
         )
     }
@@ -153,10 +1105,18 @@ pub enum CpuState {
 mod test {
     use super::*;
     use debug::DebugMode;
-    use crate::isa::registers::Reg64Id::{Rax, Rbx, Rcx, Rsp};
+    use crate::isa::registers::Reg64Id::{
+        Rax, Rbp, Rbx, Rcx, Rdi, Rdx, Rsi, Rsp, R10, R11, R12, R13, R15, R8, R9,
+    };
+    use crate::isa::rflags::RFlags;
+    use crate::linux::{LinuxProcess, ProgramImage};
     use peripherals::interconnect::Interconnect;
     use peripherals::error::MemoryAccessError;
     use peripherals::uart16550::{self, Target};
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::path::Path;
+    use std::rc::Rc;
 
     struct FakeDisplay();
     impl MemoryAccess for FakeDisplay {
@@ -169,11 +1129,30 @@ mod test {
         }
     }
 
-    fn execute_program(program: Vec<u8>) -> X86_64 {
+    // Creates a machine whose memory has the program at `start`.
+    fn create_interconnect(program: &[u8], start: usize) -> Interconnect {
         let display: Box<dyn MemoryAccess> = Box::new(FakeDisplay());
         let serial = uart16550::uart_factory(Target::Buffer);
         let mut mmio = Interconnect::new(serial, display);
-        mmio.init_memory(&program, 0);
+        mmio.init_memory(program, start);
+        mmio
+    }
+
+    // PML4 at 0x8000, PDPT at 0x9000, PD at 0xa000 and PT at 0xb000.
+    // The first 64KiB are identity mapped, and 0x40_0000 is a read-only 2MiB page at 0.
+    fn write_page_tables(mmio: &mut Interconnect) {
+        mmio.write_u64(0x8000, 0x9003).unwrap();
+        mmio.write_u64(0x9000, 0xa003).unwrap();
+        mmio.write_u64(0xa000, 0xb003).unwrap();
+        mmio.write_u64(0xa010, 0x81).unwrap();
+        for page in 0..0x10 {
+            mmio.write_u64(0xb000 + page * 8, (page as u64) << 12 | 0x3)
+                .unwrap();
+        }
+    }
+
+    fn execute_program(program: Vec<u8>) -> X86_64 {
+        let mmio = create_interconnect(&program, 0);
         let mut x86_64 = X86_64::new(mmio, DebugMode::Disabled);
         let result = x86_64.run();
 
@@ -182,10 +1161,7 @@ mod test {
     }
 
     fn execute_program_after_init(program: Vec<u8>, initializer: &Fn(&mut X86_64)) -> X86_64 {
-        let display: Box<dyn MemoryAccess> = Box::new(FakeDisplay());
-        let serial = uart16550::uart_factory(Target::Buffer);
-        let mut mmio = Interconnect::new(serial, display);
-        mmio.init_memory(&program, 0);
+        let mmio = create_interconnect(&program, 0);
         let mut x86_64 = X86_64::new(mmio, DebugMode::Disabled);
         initializer(&mut x86_64);
         let result = x86_64.run();
@@ -194,6 +1170,17 @@ mod test {
         x86_64
     }
 
+    // Runs the program, which is expected to stop with an exception.
+    fn execute_program_with_exception(
+        program: Vec<u8>,
+        initializer: &Fn(&mut X86_64),
+    ) -> InternalException {
+        let mmio = create_interconnect(&program, 0);
+        let mut x86_64 = X86_64::new(mmio, DebugMode::Disabled);
+        initializer(&mut x86_64);
+        x86_64.run().expect_err("An exception is expected.")
+    }
+
     #[test]
     fn execute_two_instructions() {
         let program = vec![
@@ -296,4 +1283,1295 @@ mod test {
         assert_eq!(x86_64.mmio.read_u64(0x100).unwrap(), 0x0e48);
     }
 
+    #[test]
+    fn execute_add_flags() {
+        let program = vec![
+            0x48, 0x01, 0xc8, // add rax, rcx
+            0x00, 0xda, // add dl, bl
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 0x7fff_ffff_ffff_ffff);
+            x86_64.rf.write64(Rcx, 1);
+            x86_64.rf.write64(Rdx, 0x1234_56ff);
+            x86_64.rf.write64(Rbx, 1);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rax), 0x8000_0000_0000_0000);
+        assert_eq!(x86_64.rf.read64(Rdx), 0x1234_5600);
+        assert_eq!(
+            x86_64.rflags,
+            RFlags::CARRY_FLAG
+                | RFlags::ZERO_FLAG
+                | RFlags::PARITY_FLAG
+                | RFlags::AUXILIARY_CARRY_FLAG
+        );
+    }
+
+    #[test]
+    fn execute_sub_sbb_cmp() {
+        let program = vec![
+            0x29, 0xc8, // sub eax, ecx
+            0x48, 0x19, 0xd3, // sbb rbx, rdx
+            0x48, 0x83, 0xf9, 0xff, // cmp rcx, -1
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 0xffff_ffff_0000_0000);
+            x86_64.rf.write64(Rcx, 1);
+            x86_64.rf.write64(Rbx, 10);
+            x86_64.rf.write64(Rdx, 3);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        // 32-bit result is zero-extended.
+        assert_eq!(x86_64.rf.read64(Rax), 0xffff_ffff);
+        assert_eq!(x86_64.rf.read64(Rbx), 6);
+        assert_eq!(x86_64.rf.read64(Rcx), 1);
+        assert!(x86_64.rflags.contains(RFlags::CARRY_FLAG));
+        assert!(!x86_64.rflags.contains(RFlags::ZERO_FLAG));
+    }
+
+    #[test]
+    fn execute_signed_overflow() {
+        let program = vec![
+            0x66, 0x05, 0x00, 0x80, // add ax, 0x8000
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| x86_64.rf.write64(Rax, 0x1_8000);
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rax), 0x1_0000);
+        assert_eq!(
+            x86_64.rflags,
+            RFlags::CARRY_FLAG | RFlags::ZERO_FLAG | RFlags::PARITY_FLAG | RFlags::OVERFLOW_FLAG
+        );
+    }
+
+    #[test]
+    fn execute_logic() {
+        let program = vec![
+            0x48, 0x31, 0xc0, // xor rax, rax
+            0x81, 0xcb, 0x00, 0x00, 0x00, 0x80, // or ebx, 0x8000_0000
+            0x24, 0x0f, // and al, 0x0f
+            0x48, 0x85, 0xdb, // test rbx, rbx
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 0x1234);
+            x86_64.rflags = RFlags::CARRY_FLAG | RFlags::DIRECTION_FLAG;
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rax), 0);
+        assert_eq!(x86_64.rf.read64(Rbx), 0x8000_0000);
+        assert_eq!(x86_64.rflags, RFlags::DIRECTION_FLAG | RFlags::PARITY_FLAG);
+    }
+
+    #[test]
+    fn execute_unary() {
+        let program = vec![
+            0x48, 0xf7, 0xd8, // neg rax
+            0xf7, 0xd3, // not ebx
+            0xfe, 0xc9, // dec cl
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 1);
+            x86_64.rf.write64(Rbx, 0xffff_ffff_0000_ffff);
+            x86_64.rf.write64(Rcx, 0x100);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rax), 0xffff_ffff_ffff_ffff);
+        assert_eq!(x86_64.rf.read64(Rbx), 0xffff_0000);
+        assert_eq!(x86_64.rf.read64(Rcx), 0x1ff);
+        // DEC keeps CF set by NEG.
+        assert!(x86_64.rflags.contains(RFlags::CARRY_FLAG | RFlags::SIGN_FLAG));
+    }
+
+    #[test]
+    fn execute_jcc_loop() {
+        let program = vec![
+            0x31, 0xc0, // xor eax, eax
+            0x48, 0x01, 0xd8, // add rax, rbx <- loop
+            0x48, 0xff, 0xcb, // dec rbx
+            0x75, 0xf8, // jnz loop
+            0x0f, 0x84, 0x01, 0x00, 0x00, 0x00, // jz skip
+            0xf4, // hlt
+            0xe2, 0xfe, // loop skip <- skip
+            0xf4, // hlt
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rbx, 4);
+            x86_64.rf.write64(Rcx, 2);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rax), 10);
+        assert_eq!(x86_64.rf.read64(Rcx), 0);
+        assert_eq!(x86_64.fetch_unit.get_rip(), 20);
+    }
+
+    #[test]
+    fn execute_setcc_cmovcc() {
+        let program = vec![
+            0x48, 0x39, 0xd8, // cmp rax, rbx
+            0x0f, 0x9c, 0xc1, // setl cl
+            0x48, 0x0f, 0x4c, 0xd3, // cmovl rdx, rbx
+            0x0f, 0x42, 0xc3, // cmovb eax, ebx
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 0xffff_ffff_ffff_ffff);
+            x86_64.rf.write64(Rbx, 1);
+            x86_64.rf.write64(Rcx, 0xff00);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rcx) & 0xff, 1);
+        assert_eq!(x86_64.rf.read64(Rdx), 1);
+        // Upper half is cleared even though the condition is false.
+        assert_eq!(x86_64.rf.read64(Rax), 0xffff_ffff);
+    }
+
+    #[test]
+    fn execute_indirect_call_jmp() {
+        let program = vec![
+            0xff, 0xd0, // call rax
+            0xf4, // hlt
+            0xe9, 0xfa, 0xff, 0xff, 0xff, // jmp -6
+            0xff, 0xe3, // jmp rbx <- rax
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rsp, 0x100);
+            x86_64.rf.write64(Rax, 8);
+            x86_64.rf.write64(Rbx, 3);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.mmio.read_u64(0x100 - 8).unwrap(), 2);
+        assert_eq!(x86_64.rf.read64(Rsp), 0xf8);
+        assert_eq!(x86_64.executed_insts, 4);
+    }
+
+    #[test]
+    fn execute_jrcxz() {
+        let program = vec![
+            0xe3, 0x01, // jrcxz +1
+            0xf4, 0xf4,
+        ];
+        let x86_64 = execute_program(program);
+        assert_eq!(x86_64.fetch_unit.get_rip(), 4);
+    }
+
+    #[test]
+    fn execute_rex_extended_registers() {
+        let program = vec![
+            0x49, 0xc7, 0xc0, 0x01, 0x00, 0x00, 0x00, // mov r8, 1
+            0x4d, 0x01, 0xc1, // add r9, r8
+            0x4d, 0x89, 0xcf, // mov r15, r9
+            0x41, 0x54, // push r12
+            0x5f, // pop rdi
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rsp, 0x100);
+            x86_64.rf.write64(R9, 2);
+            x86_64.rf.write64(R12, 0x1234_5678_9abc_def0);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(R8), 1);
+        assert_eq!(x86_64.rf.read64(R15), 3);
+        assert_eq!(x86_64.rf.read64(Rdi), 0x1234_5678_9abc_def0);
+    }
+
+    #[test]
+    fn execute_partial_registers() {
+        let program = vec![
+            0xb4, 0x12, // mov ah, 0x12
+            0x40, 0xb4, 0x34, // mov spl, 0x34
+            0x88, 0xe3, // mov bl, ah
+            0x66, 0x89, 0xc1, // mov cx, ax
+            0x89, 0xc2, // mov edx, eax
+            0x41, 0x80, 0xc0, 0x01, // add r8b, 1
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 0xffff_ffff_ffff_ffff);
+            x86_64.rf.write64(Rbx, 0xffff_ffff_ffff_ffff);
+            x86_64.rf.write64(Rcx, 0xffff_ffff_ffff_ffff);
+            x86_64.rf.write64(Rsp, 0xffff_ffff_ffff_ffff);
+            x86_64.rf.write64(R8, 0x1ff);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rax), 0xffff_ffff_ffff_12ff);
+        assert_eq!(x86_64.rf.read64(Rsp), 0xffff_ffff_ffff_ff34);
+        assert_eq!(x86_64.rf.read64(Rbx), 0xffff_ffff_ffff_ff12);
+        assert_eq!(x86_64.rf.read64(Rcx), 0xffff_ffff_ffff_12ff);
+        assert_eq!(x86_64.rf.read64(Rdx), 0xffff_12ff);
+        assert_eq!(x86_64.rf.read64(R8), 0x100);
+    }
+
+    #[test]
+    fn execute_effective_address() {
+        let program = vec![
+            0x48, 0x89, 0x44, 0x8b, 0x08, // mov [rbx + rcx * 4 + 8], rax
+            0x49, 0x89, 0x44, 0x24, 0xf8, // mov [r12 - 8], rax
+            0x41, 0x88, 0x45, 0x10, // mov [r13 + 0x10], al
+            0x48, 0x8b, 0x15, 0x05, 0x00, 0x00, 0x00, // mov rdx, [rip + 5]
+            0x48, 0x8d, 0x34, 0x49, // lea rsi, [rcx + rcx * 2]
+            0xf4,
+            0x78, 0x56, 0x34, 0x12, // data
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 0xaabb);
+            x86_64.rf.write64(Rbx, 0x100);
+            x86_64.rf.write64(Rcx, 2);
+            x86_64.rf.write64(R12, 0x208);
+            x86_64.rf.write64(R13, 0x300);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.mmio.read_u64(0x110).unwrap(), 0xaabb);
+        assert_eq!(x86_64.mmio.read_u64(0x200).unwrap(), 0xaabb);
+        assert_eq!(x86_64.mmio.read_u64(0x310).unwrap(), 0xbb);
+        assert_eq!(x86_64.rf.read64(Rdx), 0x1234_5678);
+        assert_eq!(x86_64.rf.read64(Rsi), 6);
+    }
+
+    #[test]
+    fn execute_two_byte_opcodes() {
+        let program = vec![
+            0x0f, 0xb6, 0xc7, // movzx eax, bh
+            0x48, 0x0f, 0xbe, 0xcb, // movsx rcx, bl
+            0x48, 0x63, 0xd3, // movsxd rdx, ebx
+            0x48, 0x0f, 0xaf, 0xc1, // imul rax, rcx
+            0x0f, 0x1f, 0x44, 0x00, 0x00, // nop dword [rax + rax]
+            0x90, // nop
+            0x0f, 0xbd, 0xf3, // bsr esi, ebx
+            0x0f, 0xba, 0xeb, 0x00, // bts ebx, 0
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| x86_64.rf.write64(Rbx, 0x8000_12fe);
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rcx), 0xffff_ffff_ffff_fffe);
+        assert_eq!(x86_64.rf.read64(Rax), 0xffff_ffff_ffff_ffdc);
+        assert_eq!(x86_64.rf.read64(Rdx), 0xffff_ffff_8000_12fe);
+        assert_eq!(x86_64.rf.read64(Rsi), 31);
+        assert_eq!(x86_64.rf.read64(Rbx), 0x8000_12ff);
+        assert!(!x86_64.rflags.contains(RFlags::CARRY_FLAG));
+    }
+
+    #[test]
+    fn execute_imul_overflow() {
+        let program = vec![
+            0x0f, 0xaf, 0xc3, // imul eax, ebx
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 0x1_0000);
+            x86_64.rf.write64(Rbx, 0x1_0000);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rax), 0);
+        assert!(x86_64
+            .rflags
+            .contains(RFlags::CARRY_FLAG | RFlags::OVERFLOW_FLAG));
+    }
+
+    #[test]
+    fn execute_cpuid_rdtsc() {
+        let program = vec![
+            0x0f, 0xa2, // cpuid
+            0x0f, 0x31, // rdtsc
+            0xf4,
+        ];
+        let x86_64 = execute_program(program);
+        assert_eq!(x86_64.rf.read64(Rbx), u64::from(u32::from_le_bytes(*b"Rust")));
+        assert_eq!(x86_64.rf.read64(Rax), 1);
+        assert_eq!(x86_64.rf.read64(Rdx), 0);
+    }
+
+    #[test]
+    fn execute_multiply_divide() {
+        let program = vec![
+            0x48, 0xf7, 0xe3, // mul rbx
+            0x48, 0x99, // cqo
+            0x48, 0xf7, 0xf1, // div rcx
+            0x6b, 0xc0, 0xfd, // imul eax, eax, -3
+            0x48, 0x98, // cdqe
+            0x48, 0x99, // cqo
+            0x48, 0xf7, 0xf9, // idiv rcx
+            0x69, 0xd9, 0x00, 0x01, 0x00, 0x00, // imul ebx, ecx, 0x100
+            0xf6, 0xe1, // mul cl
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 0x10);
+            x86_64.rf.write64(Rbx, 0x20);
+            x86_64.rf.write64(Rcx, 7);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rax), 0xffff_ffff_ffff_0627);
+        assert_eq!(x86_64.rf.read64(Rdx), 0xffff_ffff_ffff_fffe);
+        assert_eq!(x86_64.rf.read64(Rbx), 0x700);
+        assert!(x86_64
+            .rflags
+            .contains(RFlags::CARRY_FLAG | RFlags::OVERFLOW_FLAG));
+    }
+
+    #[test]
+    fn execute_divide_error() {
+        let program = vec![
+            0x48, 0xf7, 0xf1, // div rcx
+            0xf4,
+        ];
+        let mmio = create_interconnect(&program, 0);
+        let mut x86_64 = X86_64::new(mmio, DebugMode::Disabled);
+        match x86_64.run() {
+            Err(InternalException::DivideError) => (),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn execute_shift_rotate() {
+        let program = vec![
+            0xd0, 0xc3, // rol bl, 1
+            0xd0, 0xdb, // rcr bl, 1
+            0xd2, 0xe3, // shl bl, cl
+            0x48, 0xc1, 0xfa, 0x3c, // sar rdx, 60
+            0xc1, 0xce, 0x08, // ror esi, 8
+            0x48, 0x0f, 0xa4, 0xc7, 0x04, // shld rdi, rax, 4
+            0x0f, 0xad, 0xc5, // shrd ebp, eax, cl
+            0xb1, 0x20, // mov cl, 32
+            0x41, 0xd3, 0xe1, // shl r9d, cl
+            0x0f, 0xa5, 0xd8, // shld eax, ebx, cl
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 0xf000_0000_0000_0000);
+            x86_64.rf.write64(Rbx, 0x81);
+            x86_64.rf.write64(Rcx, 4);
+            x86_64.rf.write64(Rdx, 0x8000_0000_0000_0000);
+            x86_64.rf.write64(Rsi, 0x1234_5678);
+            x86_64.rf.write64(Rdi, 0x1);
+            x86_64.rf.write64(Rbp, 0x1234_5678);
+            x86_64.rf.write64(R9, 0xffff_ffff_1234_5678);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rbx), 0x10);
+        assert_eq!(x86_64.rf.read64(Rdx), 0xffff_ffff_ffff_fff8);
+        assert_eq!(x86_64.rf.read64(Rsi), 0x7812_3456);
+        assert_eq!(x86_64.rf.read64(Rdi), 0x1f);
+        assert_eq!(x86_64.rf.read64(Rbp), 0x0123_4567);
+        assert!(x86_64.rflags.contains(RFlags::CARRY_FLAG));
+        // A masked count of zero keeps the flags, but clears the upper half of a 32-bit register.
+        assert_eq!(x86_64.rf.read64(R9), 0x1234_5678);
+        assert_eq!(x86_64.rf.read64(Rax), 0);
+    }
+
+    #[test]
+    fn execute_rep_movs_stos() {
+        let program = vec![
+            0xf3, 0xa4, // rep movsb
+            0x48, 0xc7, 0xc1, 0x02, 0x00, 0x00, 0x00, // mov rcx, 2
+            0xb8, 0x41, 0x41, 0x41, 0x41, // mov eax, 0x41414141
+            0xf3, 0x48, 0xab, // rep stosq
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            for (i, byte) in b"hello".iter().enumerate() {
+                x86_64.mmio.write_u8(0x100 + i, *byte).unwrap();
+            }
+            x86_64.rf.write64(Rsi, 0x100);
+            x86_64.rf.write64(Rdi, 0x200);
+            x86_64.rf.write64(Rcx, 5);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        let copied: Vec<u8> = (0x200..0x205)
+            .map(|addr| x86_64.mmio.read_u8(addr).unwrap())
+            .collect();
+        assert_eq!(&copied, b"hello");
+        assert_eq!(x86_64.mmio.read_u64(0x205).unwrap(), 0x4141_4141);
+        assert_eq!(x86_64.mmio.read_u64(0x20d).unwrap(), 0x4141_4141);
+        assert_eq!(x86_64.rf.read64(Rsi), 0x105);
+        assert_eq!(x86_64.rf.read64(Rdi), 0x215);
+        assert_eq!(x86_64.rf.read64(Rcx), 0);
+        // Each iteration is executed as an instruction.
+        assert_eq!(x86_64.executed_insts, 10);
+    }
+
+    #[test]
+    fn execute_repe_repne_and_direction() {
+        let program = vec![
+            0xf2, 0xae, // repne scasb
+            0x48, 0xc7, 0xc6, 0x00, 0x01, 0x00, 0x00, // mov rsi, 0x100
+            0x48, 0xc7, 0xc7, 0x00, 0x02, 0x00, 0x00, // mov rdi, 0x200
+            0xb9, 0x0a, 0x00, 0x00, 0x00, // mov ecx, 10
+            0xf3, 0xa6, // repe cmpsb
+            0xfd, // std
+            0xac, // lodsb
+            0xac, // lodsb
+            0xfc, // cld
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            for (i, byte) in b"abc\0".iter().enumerate() {
+                x86_64.mmio.write_u8(0x100 + i, *byte).unwrap();
+            }
+            for (i, byte) in b"abd".iter().enumerate() {
+                x86_64.mmio.write_u8(0x200 + i, *byte).unwrap();
+            }
+            x86_64.rf.write64(Rdi, 0x100);
+            x86_64.rf.write64(Rcx, 0xffff_ffff_ffff_ffff);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rcx), 7);
+        assert_eq!(x86_64.rf.read64(Rsi), 0x101);
+        assert_eq!(x86_64.rf.read64(Rdi), 0x203);
+        assert_eq!(x86_64.rf.read64(Rax), u64::from(b'c'));
+        assert!(x86_64.rflags.contains(RFlags::CARRY_FLAG));
+        assert!(!x86_64.rflags.contains(RFlags::DIRECTION_FLAG));
+    }
+
+    #[test]
+    fn execute_port_io() {
+        let program = vec![
+            0x66, 0xba, 0xf8, 0x03, // mov dx, 0x3f8
+            0xb0, 0x6f, // mov al, 0x6f
+            0xee, // out dx, al
+            0xf3, 0x6e, // rep outsb
+            0xb2, 0xfd, // mov dl, 0xfd
+            0xec, // in al, dx
+            0x88, 0xc3, // mov bl, al
+            0xb2, 0xf8, // mov dl, 0xf8
+            0xb9, 0x03, 0x00, 0x00, 0x00, // mov ecx, 3
+            0xf3, 0x6c, // rep insb
+            0xe4, 0x80, // in al, 0x80
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.mmio.write_u8(0x100, b'k').unwrap();
+            x86_64.mmio.write_u8(0x101, b'!').unwrap();
+            x86_64.rf.write64(Rsi, 0x100);
+            x86_64.rf.write64(Rdi, 0x200);
+            x86_64.rf.write64(Rcx, 2);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        // The line status shows received data and the empty transmitter.
+        assert_eq!(x86_64.rf.read64(Rbx), 0x61);
+        let received: Vec<u8> = (0x200..0x203)
+            .map(|addr| x86_64.mmio.read_u8(addr).unwrap())
+            .collect();
+        assert_eq!(&received, b"ok!");
+        assert_eq!(x86_64.rf.read64(Rsi), 0x102);
+        assert_eq!(x86_64.rf.read64(Rdi), 0x203);
+        // Unmapped ports read all ones.
+        assert_eq!(x86_64.rf.read64(Rax), 0xff);
+    }
+
+    #[test]
+    fn execute_paging() {
+        let program = vec![
+            0xb8, 0x00, 0x80, 0x00, 0x00, // mov eax, 0x8000
+            0x0f, 0x22, 0xd8, // mov cr3, rax
+            0xb8, 0x20, 0x00, 0x00, 0x00, // mov eax, 0x20 (PAE)
+            0x0f, 0x22, 0xe0, // mov cr4, rax
+            0xb8, 0x11, 0x00, 0x01, 0x80, // mov eax, 0x80010011 (PG, WP, ET and PE)
+            0x0f, 0x22, 0xc0, // mov cr0, rax
+            0x48, 0x8b, 0x1c, 0x25, 0x00, 0x01, 0x40, 0x00, // mov rbx, [0x400100]
+            0x0f, 0x20, 0xc1, // mov rcx, cr0
+            0x0f, 0x01, 0x3c, 0x25, 0x00, 0x00, 0x40, 0x00, // invlpg [0x400000]
+            0x48, 0x89, 0x1c, 0x25, 0x00, 0x00, 0x40, 0x00, // mov [0x400000], rbx
+            0xf4,
+        ];
+        let mut mmio = create_interconnect(&program, 0);
+        write_page_tables(&mut mmio);
+        mmio.write_u64(0x100, 0x1234).unwrap();
+        let mut x86_64 = X86_64::new(mmio, DebugMode::Disabled);
+        let result = x86_64.run();
+
+        match result {
+            Err(InternalException::PageFault { addr, error_code }) => {
+                assert_eq!((addr, error_code), (0x40_0000, 0x3))
+            }
+            _ => panic!("#PF is expected."),
+        }
+        assert_eq!(x86_64.mmu.read_control_register(2), 0x40_0000);
+        assert_eq!(x86_64.rf.read64(Rbx), 0x1234);
+        assert_eq!(x86_64.rf.read64(Rcx), 0x8001_0011);
+        // The code page is accessed but not dirty.
+        assert_eq!(x86_64.mmio.read_u64(0xb000).unwrap(), 0x23);
+    }
+
+    #[test]
+    fn execute_memory_operands() {
+        let program = vec![
+            0x48, 0x01, 0x18, // add [rax], rbx
+            0x48, 0xff, 0x04, 0x24, // inc qword [rsp]
+            0x48, 0x83, 0x38, 0x33, // cmp qword [rax], 0x33
+            0x0f, 0x94, 0x40, 0x10, // sete [rax + 0x10]
+            0x48, 0x2b, 0x08, // sub rcx, [rax]
+            0xc1, 0x60, 0x08, 0x04, // shl dword [rax + 8], 4
+            0x48, 0x0f, 0xab, 0x10, // bts [rax], rdx
+            0x48, 0x0f, 0xbe, 0x70, 0x18, // movsx rsi, byte [rax + 0x18]
+            0x48, 0x6b, 0x78, 0x08, 0x03, // imul rdi, [rax + 8], 3
+            0x66, 0xf7, 0x58, 0x1a, // neg word [rax + 0x1a]
+            0xff, 0x50, 0x20, // call [rax + 0x20]
+            0xf4, // hlt
+            0xf4, // hlt
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rax, 0x200);
+            x86_64.rf.write64(Rbx, 0x22);
+            x86_64.rf.write64(Rcx, 0x40);
+            // The bit offset 66 selects bit 2 of the next quadword.
+            x86_64.rf.write64(Rdx, 66);
+            x86_64.rf.write64(Rsp, 0x300);
+            x86_64.mmio.write_u64(0x200, 0x11).unwrap();
+            x86_64.mmio.write_u64(0x208, 0x1234_5678_0000_0001).unwrap();
+            x86_64.mmio.write_u64(0x218, 0x0001_0080).unwrap();
+            x86_64.mmio.write_u64(0x220, 0x2c).unwrap();
+            x86_64.mmio.write_u64(0x300, 0xff).unwrap();
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.mmio.read_u64(0x200).unwrap(), 0x33);
+        assert_eq!(x86_64.mmio.read_u64(0x300).unwrap(), 0x100);
+        assert_eq!(x86_64.mmio.read_u8(0x210).unwrap(), 1);
+        assert_eq!(x86_64.rf.read64(Rcx), 0xd);
+        assert_eq!(x86_64.mmio.read_u64(0x208).unwrap(), 0x1234_5678_0000_0014);
+        assert_eq!(x86_64.rf.read64(Rsi), 0xffff_ffff_ffff_ff80);
+        assert_eq!(x86_64.rf.read64(Rdi), 0x369d_0368_0000_003c);
+        assert_eq!(x86_64.mmio.read_u64(0x218).unwrap(), 0xffff_0080);
+        // CALL r/m pushes the return address and jumps to the target read from memory.
+        assert_eq!(x86_64.mmio.read_u64(0x2f8).unwrap(), 0x2b);
+        assert_eq!(x86_64.fetch_unit.get_rip(), 0x2d);
+    }
+
+    #[test]
+    fn execute_memory_operand_fault() {
+        let program = vec![
+            0xb8, 0x00, 0x80, 0x00, 0x00, // mov eax, 0x8000
+            0x0f, 0x22, 0xd8, // mov cr3, rax
+            0xb8, 0x20, 0x00, 0x00, 0x00, // mov eax, 0x20 (PAE)
+            0x0f, 0x22, 0xe0, // mov cr4, rax
+            0xb8, 0x11, 0x00, 0x01, 0x80, // mov eax, 0x80010011 (PG, WP, ET and PE)
+            0x0f, 0x22, 0xc0, // mov cr0, rax
+            0xbb, 0x01, 0x00, 0x00, 0x00, // mov ebx, 1
+            0x48, 0x39, 0x1c, 0x25, 0x00, 0x01, 0x40, 0x00, // cmp [0x400100], rbx
+            0xf9, // stc
+            0x48, 0x01, 0x1c, 0x25, 0x00, 0x01, 0x40, 0x00, // add [0x400100], rbx
+            0xf4,
+        ];
+        let mut mmio = create_interconnect(&program, 0);
+        write_page_tables(&mut mmio);
+        mmio.write_u64(0x100, 0x1234).unwrap();
+        let mut x86_64 = X86_64::new(mmio, DebugMode::Disabled);
+        let result = x86_64.run();
+
+        // The read of the memory operand succeeds, but the store faults.
+        match result {
+            Err(InternalException::PageFault { addr, error_code }) => {
+                assert_eq!((addr, error_code), (0x40_0100, 0x3))
+            }
+            _ => panic!("#PF is expected."),
+        }
+        // Neither the memory nor the flags are updated by the faulting instruction.
+        assert_eq!(x86_64.mmio.read_u64(0x100).unwrap(), 0x1234);
+        assert!(x86_64.rflags.contains(RFlags::CARRY_FLAG));
+        assert!(!x86_64.rflags.contains(RFlags::ZERO_FLAG));
+    }
+
+    #[test]
+    fn execute_non_canonical_address() {
+        let paging = [
+            0xb8, 0x00, 0x80, 0x00, 0x00, // mov eax, 0x8000
+            0x0f, 0x22, 0xd8, // mov cr3, rax
+            0xb8, 0x20, 0x00, 0x00, 0x00, // mov eax, 0x20 (PAE)
+            0x0f, 0x22, 0xe0, // mov cr4, rax
+            0xb8, 0x11, 0x00, 0x01, 0x80, // mov eax, 0x80010011 (PG, WP, ET and PE)
+            0x0f, 0x22, 0xc0, // mov cr0, rax
+            0x48, 0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, // mov rax, 1 << 47
+        ];
+        let run = |access: &[u8]| {
+            let program = [&paging[..], access, &[0xf4]].concat();
+            let mut mmio = create_interconnect(&program, 0);
+            write_page_tables(&mut mmio);
+            let mut x86_64 = X86_64::new(mmio, DebugMode::Disabled);
+            let result = x86_64.run();
+            (x86_64, result)
+        };
+
+        let (x86_64, result) = run(&[0x48, 0x8b, 0x18]); // mov rbx, [rax]
+        match result {
+            Err(InternalException::GeneralProtection { error_code: 0 }) => (),
+            _ => panic!("#GP(0) is expected."),
+        }
+        assert_eq!(x86_64.rf.read64(Rbx), 0);
+
+        // A stack access raises #SS and leaves RSP unchanged.
+        let (x86_64, result) = run(&[0x48, 0x89, 0xc4, 0x5b]); // mov rsp, rax; pop rbx
+        match result {
+            Err(InternalException::StackFault { error_code: 0 }) => (),
+            _ => panic!("#SS(0) is expected."),
+        }
+        assert_eq!(x86_64.rf.read64(Rsp), 0x8000_0000_0000);
+    }
+
+    #[test]
+    fn execute_atomic_instructions() {
+        let program = vec![
+            0xf0, 0x48, 0x0f, 0xc1, 0x1f, // lock xadd [rdi], rbx
+            0x48, 0x87, 0x4f, 0x08, // xchg [rdi + 8], rcx
+            0xb8, 0x05, 0x00, 0x00, 0x00, // mov eax, 5
+            0xf0, 0x48, 0x0f, 0xb1, 0x57, 0x10, // lock cmpxchg [rdi + 0x10], rdx
+            0xf0, 0x0f, 0xb1, 0x57, 0x18, // lock cmpxchg [rdi + 0x18], edx
+            0x41, 0x89, 0xc0, // mov r8d, eax
+            0xb8, 0x01, 0x00, 0x00, 0x00, // mov eax, 1
+            0xba, 0x02, 0x00, 0x00, 0x00, // mov edx, 2
+            0xbb, 0x03, 0x00, 0x00, 0x00, // mov ebx, 3
+            0xb9, 0x04, 0x00, 0x00, 0x00, // mov ecx, 4
+            0xf0, 0x0f, 0xc7, 0x4f, 0x20, // lock cmpxchg8b [rdi + 0x20]
+            0x40, 0x0f, 0x94, 0xc6, // sete sil
+            0x31, 0xc0, // xor eax, eax
+            0x31, 0xd2, // xor edx, edx
+            0xf0, 0x48, 0x0f, 0xc7, 0x4f, 0x30, // lock cmpxchg16b [rdi + 0x30]
+            0x49, 0x91, // xchg r9, rax
+            0x90, // nop
+            0xf4,
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rdi, 0x200);
+            x86_64.rf.write64(Rbx, 7);
+            x86_64.rf.write64(Rcx, 0x2222);
+            x86_64.rf.write64(Rdx, 0x55);
+            x86_64.rf.write64(R9, 0x99);
+            x86_64.mmio.write_u64(0x200, 10).unwrap();
+            x86_64.mmio.write_u64(0x208, 0x1111).unwrap();
+            x86_64.mmio.write_u64(0x210, 5).unwrap();
+            x86_64.mmio.write_u64(0x218, 0xaaaa_0000_0009).unwrap();
+            x86_64.mmio.write_u64(0x220, 0x2_0000_0001).unwrap();
+            x86_64.mmio.write_u64(0x230, 5).unwrap();
+            x86_64.mmio.write_u64(0x238, 6).unwrap();
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        // XADD and XCHG
+        assert_eq!(x86_64.mmio.read_u64(0x200).unwrap(), 17);
+        assert_eq!(x86_64.mmio.read_u64(0x208).unwrap(), 0x2222);
+        // CMPXCHG writes the source if equal, and loads the accumulator otherwise.
+        assert_eq!(x86_64.mmio.read_u64(0x210).unwrap(), 0x55);
+        assert_eq!(x86_64.mmio.read_u64(0x218).unwrap(), 0xaaaa_0000_0009);
+        assert_eq!(x86_64.rf.read64(R8), 9);
+        // CMPXCHG8B succeeds, and CMPXCHG16B loads RDX:RAX.
+        assert_eq!(x86_64.mmio.read_u64(0x220).unwrap(), 0x4_0000_0003);
+        assert_eq!(x86_64.rf.read64(Rsi), 1);
+        assert_eq!(x86_64.mmio.read_u64(0x230).unwrap(), 5);
+        assert_eq!(x86_64.rf.read64(Rdx), 6);
+        assert!(!x86_64.rflags.contains(RFlags::ZERO_FLAG));
+        // XCHG r, rAX
+        assert_eq!(x86_64.rf.read64(Rax), 0x99);
+        assert_eq!(x86_64.rf.read64(R9), 5);
+    }
+
+    #[test]
+    fn execute_invalid_lock_prefix() {
+        let programs = vec![
+            vec![0xf0, 0x48, 0x01, 0xd8],       // lock add rax, rbx
+            vec![0xf0, 0x48, 0x39, 0x07],       // lock cmp [rdi], rax
+            vec![0xf0, 0x48, 0x8b, 0x07],       // lock mov rax, [rdi]
+            vec![0xf0, 0x48, 0x0f, 0xc1, 0xc3], // lock xadd rbx, rax
+            vec![0xf0, 0x48, 0x0f, 0xb1, 0xd3], // lock cmpxchg rbx, rdx
+        ];
+        for program in programs {
+            match execute_program_with_exception(program, &|_| ()) {
+                InternalException::UndefinedInstruction { .. } => (),
+                exception => panic!("#UD is expected, but {:?}", exception),
+            }
+        }
+
+        // The memory operand of CMPXCHG16B must be aligned to 16 bytes.
+        let program = vec![0xf0, 0x48, 0x0f, 0xc7, 0x4f, 0x08]; // lock cmpxchg16b [rdi + 8]
+        let initializer = |x86_64: &mut X86_64| x86_64.rf.write64(Rdi, 0x200);
+        match execute_program_with_exception(program, &initializer) {
+            InternalException::GeneralProtection { error_code: 0 } => (),
+            exception => panic!("#GP(0) is expected, but {:?}", exception),
+        }
+    }
+
+    #[test]
+    fn execute_boot_to_long_mode() {
+        let program = vec![
+            // Real mode at 0x7c00.
+            0xfa, // cli
+            0x31, 0xc0, // xor ax, ax
+            0x8e, 0xd8, // mov ds, ax
+            0x8e, 0xd0, // mov ss, ax
+            0xbc, 0x00, 0x7c, // mov sp, 0x7c00
+            0xb8, 0x00, 0x05, // mov ax, 0x500
+            0x8e, 0xc0, // mov es, ax
+            0x26, 0xc7, 0x06, 0x10, 0x00, 0x34, 0x12, // mov word es:[0x10], 0x1234
+            0xb8, 0x78, 0x56, // mov ax, 0x5678
+            0x50, // push ax
+            0x5b, // pop bx
+            0x0f, 0x01, 0x16, 0xd0, 0x7c, // lgdt [0x7cd0]
+            0x0f, 0x20, 0xc0, // mov eax, cr0
+            0x66, 0x83, 0xc8, 0x01, // or eax, 0x1 (PE)
+            0x0f, 0x22, 0xc0, // mov cr0, eax
+            0xea, 0x2f, 0x7c, 0x08, 0x00, // jmp 0x08:0x7c2f
+            // 32-bit protected mode.
+            0x66, 0xb8, 0x10, 0x00, // mov ax, 0x10
+            0x8e, 0xd8, // mov ds, ax
+            0x8e, 0xc0, // mov es, ax
+            0x8e, 0xd0, // mov ss, ax
+            0xbc, 0x00, 0x70, 0x00, 0x00, // mov esp, 0x7000
+            0x43, // inc ebx
+            0xe8, 0x31, 0x00, 0x00, 0x00, // call 0x7c75
+            0x0f, 0x20, 0xe0, // mov eax, cr4
+            0x83, 0xc8, 0x20, // or eax, 0x20 (PAE)
+            0x0f, 0x22, 0xe0, // mov cr4, eax
+            0xb8, 0x00, 0x10, 0x00, 0x00, // mov eax, 0x1000
+            0x0f, 0x22, 0xd8, // mov cr3, eax
+            0xb9, 0x80, 0x00, 0x00, 0xc0, // mov ecx, 0xc0000080 (EFER)
+            0x0f, 0x32, // rdmsr
+            0x0d, 0x00, 0x01, 0x00, 0x00, // or eax, 0x100 (LME)
+            0x0f, 0x30, // wrmsr
+            0x0f, 0x20, 0xc0, // mov eax, cr0
+            0x0d, 0x00, 0x00, 0x00, 0x80, // or eax, 0x80000000 (PG)
+            0x0f, 0x22, 0xc0, // mov cr0, eax
+            0xea, 0xa2, 0x7c, 0x00, 0x00, 0x18, 0x00, // jmp 0x18:0x7ca2
+            // Identity maps the first 2MiB at 0x7c75.
+            0xbf, 0x00, 0x10, 0x00, 0x00, // mov edi, 0x1000
+            0x31, 0xc0, // xor eax, eax
+            0xb9, 0x00, 0x0c, 0x00, 0x00, // mov ecx, 0xc00
+            0xf3, 0xab, // rep stosd
+            0xc7, 0x05, 0x00, 0x10, 0x00, 0x00, 0x03, 0x20, 0x00, 0x00, // mov [0x1000], 0x2003
+            0xc7, 0x05, 0x00, 0x20, 0x00, 0x00, 0x03, 0x30, 0x00, 0x00, // mov [0x2000], 0x3003
+            0xc7, 0x05, 0x00, 0x30, 0x00, 0x00, 0x83, 0x00, 0x00, 0x00, // mov [0x3000], 0x83
+            0xc3, // ret
+            // 64-bit mode at 0x7ca2.
+            0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // mov rax, 0x1122334455667788
+            0x50, // push rax
+            0x59, // pop rcx
+            0xf4, // hlt
+            0x90, // nop
+            // GDT at 0x7cb0: null, 32-bit code, data and 64-bit code.
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xff, 0xff, 0x00, 0x00, 0x00, 0x9a, 0xcf, 0x00,
+            0xff, 0xff, 0x00, 0x00, 0x00, 0x92, 0xcf, 0x00,
+            0xff, 0xff, 0x00, 0x00, 0x00, 0x9a, 0xaf, 0x00,
+            // GDT pseudo-descriptor at 0x7cd0.
+            0x1f, 0x00, 0xb0, 0x7c, 0x00, 0x00,
+        ];
+        let mmio = create_interconnect(&program, 0x7c00);
+        let mut x86_64 = X86_64::new(mmio, DebugMode::Disabled);
+        x86_64.boot_bios();
+        let result = x86_64.run();
+
+        assert!(result.is_ok(), "{:?}", result.err());
+        assert_eq!(x86_64.code_size, CodeSize::Bits64);
+        assert!(x86_64.mmu.is_long_mode_active());
+        assert_eq!(x86_64.rf.selector(SegmentRegister::Cs), 0x18);
+        assert_eq!(x86_64.rf.selector(SegmentRegister::Ss), 0x10);
+        assert_eq!(x86_64.rf.read64(Rax), 0x1122_3344_5566_7788);
+        assert_eq!(x86_64.rf.read64(Rcx), 0x1122_3344_5566_7788);
+        assert_eq!(x86_64.rf.read64(Rbx), 0x5679);
+        assert_eq!(x86_64.rf.read64(Rsp), 0x7000);
+        assert_eq!(x86_64.mmio.read_u64(0x5010).unwrap() & 0xffff, 0x1234);
+        assert_eq!(x86_64.mmio.read_u64(0x6ff8).unwrap(), 0x1122_3344_5566_7788);
+        // The PML4 entry is accessed by the walk.
+        assert_eq!(x86_64.mmio.read_u64(0x1000).unwrap(), 0x2023);
+    }
+
+    #[test]
+    fn execute_real_mode_interrupt() {
+        let program = vec![
+            0xbc, 0x00, 0x80, // mov sp, 0x8000
+            0xcd, 0x21, // int 0x21
+            0xf4, // hlt
+        ];
+        let handler = vec![
+            0x8c, 0xcb, // mov bx, cs
+            0xcf, // iret
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.reset_to_real_mode();
+            // Vector 0x21 is at 0006:0000.
+            x86_64.mmio.write_u32(0x84, 0x0006_0000).unwrap();
+            x86_64.mmio.init_memory(&handler, 0x60);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rbx), 0x6);
+        assert_eq!(x86_64.rf.read64(Rsp), 0x8000);
+        assert_eq!(x86_64.rf.selector(SegmentRegister::Cs), 0);
+        assert_eq!(x86_64.fetch_unit.get_rip(), 0x6);
+        // FLAGS, CS and IP were pushed.
+        assert_eq!(x86_64.mmio.read_u64(0x7ffa).unwrap() & 0xffff_ffff_ffff, 0x0002_0000_0005);
+    }
+
+    // Writes a 64-bit gate whose code segment selector is 0x08.
+    fn write_gate(x86_64: &mut X86_64, vector: u64, handler: u64, ist: u64, attributes: u64) {
+        let low = handler & 0xffff
+            | 0x08 << 16
+            | ist << 32
+            | attributes << 40
+            | (handler >> 16 & 0xffff) << 48;
+        let addr = (0x1000 + vector * 16) as usize;
+        x86_64.mmio.write_u64(addr, low).unwrap();
+        x86_64.mmio.write_u64(addr + 8, handler >> 32).unwrap();
+    }
+
+    #[test]
+    fn execute_interrupts_and_exceptions() {
+        let program = vec![
+            0x0f, 0x01, 0x1c, 0x25, 0x00, 0x40, 0x00, 0x00, // lidt [0x4000]
+            0x0f, 0x01, 0x14, 0x25, 0x10, 0x40, 0x00, 0x00, // lgdt [0x4010]
+            0x66, 0xb8, 0x10, 0x00, // mov ax, 0x10
+            0x0f, 0x00, 0xd8, // ltr ax
+            0xfb, // sti
+            0x31, 0xd2, // xor edx, edx
+            0xb8, 0x0a, 0x00, 0x00, 0x00, // mov eax, 10
+            0x31, 0xc9, // xor ecx, ecx
+            0xf7, 0xf1, // div ecx
+            0x49, 0x89, 0xc1, // mov r9, rax
+            0xcd, 0x80, // int 0x80
+            0x0f, 0x0b, // ud2
+            0xcd, 0x81, // int 0x81
+            0xf4, // hlt
+            // 0x2d: #DE handler on IST1 makes the divisor 2.
+            0x49, 0x89, 0xe0, // mov r8, rsp
+            0xb9, 0x02, 0x00, 0x00, 0x00, // mov ecx, 2
+            0x48, 0xcf, // iretq
+            // 0x37: #UD handler skips UD2.
+            0x41, 0x5b, // pop r11
+            0x49, 0x83, 0xc3, 0x02, // add r11, 2
+            0x41, 0x53, // push r11
+            0x48, 0xcf, // iretq
+            // 0x41: INT 0x80 handler reads the saved RFLAGS.
+            0x4c, 0x8b, 0x54, 0x24, 0x10, // mov r10, [rsp + 16]
+            0x48, 0xcf, // iretq
+            // 0x48: #GP handler pops the error code.
+            0x41, 0x5c, // pop r12
+            0xf4, // hlt
+            // 0x4b: External interrupt handler.
+            0x41, 0xbd, 0x20, 0x00, 0x00, 0x00, // mov r13d, 0x20
+            0x48, 0xcf, // iretq
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rsp, 0x8000);
+            // IDT at 0x1000 up to vector 0x80, GDT at 0x2000 and TSS at 0x3000.
+            x86_64.mmio.write_u16(0x4000, 0x80f).unwrap();
+            x86_64.mmio.write_u64(0x4002, 0x1000).unwrap();
+            x86_64.mmio.write_u16(0x4010, 0x1f).unwrap();
+            x86_64.mmio.write_u64(0x4012, 0x2000).unwrap();
+            x86_64
+                .mmio
+                .write_u64(0x2010, 0x0000_8900_3000_0067)
+                .unwrap();
+            x86_64.mmio.write_u64(0x3024, 0x7000).unwrap();
+            write_gate(x86_64, 0, 0x2d, 1, 0x8e);
+            write_gate(x86_64, 6, 0x37, 0, 0x8e);
+            write_gate(x86_64, 13, 0x48, 0, 0x8e);
+            write_gate(x86_64, 0x20, 0x4b, 0, 0x8e);
+            // Trap gate callable from user mode.
+            write_gate(x86_64, 0x80, 0x41, 0, 0xef);
+            x86_64.request_interrupt(0x20);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(R13), 0x20);
+        // Five values are pushed on the IST stack.
+        assert_eq!(x86_64.rf.read64(R8), 0x7000 - 40);
+        assert_eq!(x86_64.rf.read64(R9), 5);
+        assert_eq!(x86_64.rf.read64(R10) & 0x202, 0x202);
+        assert_eq!(x86_64.rf.read64(R11), 0x2a);
+        // INT 0x81 is beyond the IDT limit.
+        assert_eq!(x86_64.rf.read64(R12), 0x81 << 3 | 0b10);
+        assert_eq!(x86_64.rf.read64(Rsp), 0x8000 - 40);
+        assert!(!x86_64.rflags.contains(RFlags::INTERRUPT_FLAG));
+        // LTR marks the TSS busy.
+        assert_eq!(x86_64.mmio.read_u64(0x2010).unwrap(), 0x0000_8b00_3000_0067);
+    }
+
+    #[test]
+    fn execute_timer_interrupt() {
+        let program = vec![
+            0x0f, 0x01, 0x1c, 0x25, 0x00, 0x40, 0x00, 0x00, // lidt [0x4000]
+            0xb0, 0x11, 0xe6, 0x20, // mov al, 0x11; out 0x20, al
+            0xb0, 0x20, 0xe6, 0x21, // mov al, 0x20; out 0x21, al
+            0xb0, 0x04, 0xe6, 0x21, // mov al, 0x04; out 0x21, al
+            0xb0, 0x01, 0xe6, 0x21, // mov al, 0x01; out 0x21, al
+            0xb0, 0xfe, 0xe6, 0x21, // mov al, 0xfe; out 0x21, al
+            0xb0, 0x34, 0xe6, 0x43, // mov al, 0x34; out 0x43, al
+            0xb0, 0x00, 0xe6, 0x40, // mov al, 0x00; out 0x40, al
+            0xb0, 0x01, 0xe6, 0x40, // mov al, 0x01; out 0x40, al
+            0xfb, // sti
+            0xf4, // hlt
+            0xf4, // hlt
+            0xfa, // cli
+            0xf4, // hlt
+            // 0x2d: IRQ0 handler.
+            0x49, 0xff, 0xc5, // inc r13
+            0xb0, 0x20, 0xe6, 0x20, // mov al, 0x20; out 0x20, al
+            0x48, 0xcf, // iretq
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rsp, 0x8000);
+            x86_64.rf.write64(R13, 0);
+            x86_64.mmio.write_u16(0x4000, 0x20f).unwrap();
+            x86_64.mmio.write_u64(0x4002, 0x1000).unwrap();
+            write_gate(x86_64, 0x20, 0x2d, 0, 0x8e);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        // Each HLT is woken up by the timer, and the last one stops with IF cleared.
+        assert_eq!(x86_64.rf.read64(R13), 2);
+        assert_eq!(x86_64.fetch_unit.get_rip(), 0x2d);
+        assert_eq!(x86_64.rf.read64(Rsp), 0x8000);
+    }
+
+    #[test]
+    fn execute_protected_mode_timer_interrupt() {
+        let program = vec![
+            0x0f, 0x01, 0x1d, 0x00, 0x40, 0x00, 0x00, // lidt [0x4000]
+            0x0f, 0x01, 0x15, 0x10, 0x40, 0x00, 0x00, // lgdt [0x4010]
+            0xb0, 0x11, 0xe6, 0x20, // mov al, 0x11; out 0x20, al
+            0xb0, 0x20, 0xe6, 0x21, // mov al, 0x20; out 0x21, al
+            0xb0, 0x04, 0xe6, 0x21, // mov al, 0x04; out 0x21, al
+            0xb0, 0x01, 0xe6, 0x21, // mov al, 0x01; out 0x21, al
+            0xb0, 0xfe, 0xe6, 0x21, // mov al, 0xfe; out 0x21, al
+            0xb0, 0x34, 0xe6, 0x43, // mov al, 0x34; out 0x43, al
+            0xb0, 0x00, 0xe6, 0x40, // mov al, 0x00; out 0x40, al
+            0xb0, 0x01, 0xe6, 0x40, // mov al, 0x01; out 0x40, al
+            0xfb, // sti
+            0xf4, // hlt
+            0xf4, // hlt
+            0xfa, // cli
+            0xf4, // hlt
+            // 0x33: IRQ0 handler.
+            0x47, // inc edi
+            0xb0, 0x20, 0xe6, 0x20, // mov al, 0x20; out 0x20, al
+            0xcf, // iretd
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.boot_multiboot(0, 0x2bad_b002, 0);
+            x86_64.rf.write64(Rsp, 0x8000);
+            x86_64.rf.write64(Rdi, 0);
+            // 32-bit interrupt gate to 0x33 in CS 0x08.
+            x86_64.mmio.write_u16(0x4000, 0x107).unwrap();
+            x86_64.mmio.write_u32(0x4002, 0x1000).unwrap();
+            x86_64
+                .mmio
+                .write_u64(0x1100, 0x0000_8e00_0008_0033)
+                .unwrap();
+            // Flat 32-bit code and data segments.
+            x86_64.mmio.write_u16(0x4010, 0x17).unwrap();
+            x86_64.mmio.write_u32(0x4012, 0x2000).unwrap();
+            x86_64
+                .mmio
+                .write_u64(0x2008, 0x00cf_9a00_0000_ffff)
+                .unwrap();
+            x86_64
+                .mmio
+                .write_u64(0x2010, 0x00cf_9200_0000_ffff)
+                .unwrap();
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(Rdi), 2);
+        assert_eq!(x86_64.fetch_unit.get_rip(), 0x33);
+        assert_eq!(x86_64.rf.read64(Rsp), 0x8000);
+        assert_eq!(x86_64.rf.selector(SegmentRegister::Cs), 0x08);
+        // EFLAGS, CS and EIP after the second HLT were pushed.
+        assert_eq!(x86_64.mmio.read_u32(0x7ff4).unwrap(), 0x31);
+        assert_eq!(x86_64.mmio.read_u32(0x7ff8).unwrap(), 0x08);
+        assert_eq!(x86_64.mmio.read_u32(0x7ffc).unwrap(), 0x202);
+    }
+
+    #[test]
+    fn execute_local_apic() {
+        let program = vec![
+            0x0f, 0x01, 0x1c, 0x25, 0x00, 0x40, 0x00, 0x00, // lidt [0x4000]
+            0xbb, 0x00, 0x00, 0xe0, 0xfe, // mov ebx, 0xfee00000
+            0xc7, 0x83, 0xf0, 0x00, 0x00, 0x00, // mov dword [rbx + 0xf0],
+            0xff, 0x01, 0x00, 0x00, // 0x1ff
+            0xc7, 0x83, 0xe0, 0x03, 0x00, 0x00, // mov dword [rbx + 0x3e0],
+            0x0b, 0x00, 0x00, 0x00, // 0xb
+            0xc7, 0x83, 0x20, 0x03, 0x00, 0x00, // mov dword [rbx + 0x320],
+            0x40, 0x00, 0x00, 0x00, // 0x40
+            0xc7, 0x83, 0x80, 0x03, 0x00, 0x00, // mov dword [rbx + 0x380],
+            0x00, 0x01, 0x00, 0x00, // 0x100
+            0xfb, // sti
+            0xf4, // hlt
+            0xfa, // cli
+            0xb9, 0x1b, 0x00, 0x00, 0x00, // mov ecx, 0x1b (IA32_APIC_BASE)
+            0x0f, 0x32, // rdmsr
+            0x0d, 0x00, 0x04, 0x00, 0x00, // or eax, 0x400
+            0x0f, 0x30, // wrmsr
+            0xb9, 0x3f, 0x08, 0x00, 0x00, // mov ecx, 0x83f (SELF_IPI)
+            0xb8, 0x50, 0x00, 0x00, 0x00, // mov eax, 0x50
+            0x0f, 0x30, // wrmsr
+            0xb9, 0x22, 0x08, 0x00, 0x00, // mov ecx, 0x822 (IRR of 0x40-0x5f)
+            0x0f, 0x32, // rdmsr
+            0xf4, // hlt
+            // 0x5a: timer handler.
+            0x49, 0xff, 0xc5, // inc r13
+            0xc7, 0x83, 0xb0, 0x00, 0x00, 0x00, // mov dword [rbx + 0xb0],
+            0x00, 0x00, 0x00, 0x00, // 0
+            0x48, 0xcf, // iretq
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rsp, 0x8000);
+            x86_64.rf.write64(R13, 0);
+            x86_64.mmio.write_u16(0x4000, 0x50f).unwrap();
+            x86_64.mmio.write_u64(0x4002, 0x1000).unwrap();
+            write_gate(x86_64, 0x40, 0x5a, 0, 0x8e);
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        // The one-shot timer fires once, and the self IPI waits with IF cleared.
+        assert_eq!(x86_64.rf.read64(R13), 1);
+        assert_eq!(x86_64.rf.read64(Rax), 0x1_0000);
+        assert_eq!(x86_64.rf.read64(Rdx), 0);
+        assert!(x86_64.mmio.clock() >= 0x100);
+    }
+
+    #[test]
+    fn execute_interrupt_return_in_user_mode() {
+        let program = vec![
+            0x48, 0xcf, // iretq
+            0x0f, 0x05, // syscall
+            0xf4, // hlt
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            let efer = x86_64.read_msr(msr::IA32_EFER).unwrap();
+            x86_64.write_msr(msr::IA32_EFER, efer | 1).unwrap();
+            x86_64.write_msr(msr::IA32_STAR, 0x0023_0010 << 32).unwrap();
+            x86_64.write_msr(msr::IA32_LSTAR, 4).unwrap();
+            x86_64.rf.write_selector(SegmentRegister::Cs, 0x2b);
+            x86_64.rf.write64(Rsp, 0x7000);
+            // IOPL 3, IF and CF are requested at CPL 3.
+            x86_64.mmio.write_u64(0x7000, 2).unwrap();
+            x86_64.mmio.write_u64(0x7008, 0x2b).unwrap();
+            x86_64.mmio.write_u64(0x7010, 0x3201).unwrap();
+            x86_64.mmio.write_u64(0x7018, 0x6000).unwrap();
+            x86_64.mmio.write_u64(0x7020, 0x23).unwrap();
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        // Only CF is loaded, and SYSCALL saves RFLAGS of user mode.
+        assert_eq!(x86_64.rf.read64(R11), 0x3);
+        assert_eq!(x86_64.rf.read64(Rcx), 4);
+        assert_eq!(x86_64.rf.read64(Rsp), 0x6000);
+        assert_eq!(x86_64.rflags.iopl(), 0);
+    }
+
+    #[test]
+    fn execute_system_call_msrs() {
+        let program = vec![
+            0xb9, 0x80, 0x00, 0x00, 0xc0, // mov ecx, 0xc0000080 (EFER)
+            0x0f, 0x32, // rdmsr
+            0x83, 0xc8, 0x01, // or eax, 1
+            0x0f, 0x30, // wrmsr
+            0xb9, 0x82, 0x00, 0x00, 0xc0, // mov ecx, 0xc0000082 (LSTAR)
+            0xb8, 0x69, 0x00, 0x00, 0x00, // mov eax, 0x69
+            0x31, 0xd2, // xor edx, edx
+            0x0f, 0x30, // wrmsr
+            0xb9, 0x81, 0x00, 0x00, 0xc0, // mov ecx, 0xc0000081 (STAR)
+            0x31, 0xc0, // xor eax, eax
+            0xba, 0x10, 0x00, 0x23, 0x00, // mov edx, 0x00230010
+            0x0f, 0x30, // wrmsr
+            0x31, 0xd2, // xor edx, edx
+            0xb9, 0x84, 0x00, 0x00, 0xc0, // mov ecx, 0xc0000084 (FMASK)
+            0xb8, 0x00, 0x02, 0x00, 0x00, // mov eax, 0x200
+            0x0f, 0x30, // wrmsr
+            0xb9, 0x00, 0x01, 0x00, 0xc0, // mov ecx, 0xc0000100 (FS_BASE)
+            0xb8, 0x00, 0x50, 0x00, 0x00, // mov eax, 0x5000
+            0x0f, 0x30, // wrmsr
+            0xb9, 0x02, 0x01, 0x00, 0xc0, // mov ecx, 0xc0000102 (KERNEL_GS_BASE)
+            0xb8, 0x00, 0x60, 0x00, 0x00, // mov eax, 0x6000
+            0x0f, 0x30, // wrmsr
+            0x0f, 0x01, 0xf8, // swapgs
+            0x65, 0x4c, 0x8b, 0x04, 0x25, 0x00, 0x00, 0x00, 0x00, // mov r8, gs:[0]
+            0xbe, 0x08, 0x00, 0x00, 0x00, // mov esi, 8
+            0x64, 0x48, 0xad, // lodsq rax, fs:[rsi]
+            0x49, 0x89, 0xc1, // mov r9, rax
+            0x0f, 0x05, // syscall
+            0x0f, 0x05, // syscall
+            // 0x69: SYSCALL handler returns once, and halts at the second call.
+            0x49, 0xff, 0xc5, // inc r13
+            0x49, 0x83, 0xfd, 0x02, // cmp r13, 2
+            0x74, 0x0d, // je +13
+            0x49, 0x89, 0xca, // mov r10, rcx
+            0x49, 0xc7, 0xc3, 0x02, 0x02, 0x03, 0x00, // mov r11, 0x30202
+            0x48, 0x0f, 0x07, // sysretq
+            0xf4, // hlt
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(R13, 0);
+            x86_64.mmio.write_u64(0x5008, 0x1111).unwrap();
+            x86_64.mmio.write_u64(0x6000, 0x2222).unwrap();
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.rf.read64(R8), 0x2222);
+        assert_eq!(x86_64.rf.read64(R9), 0x1111);
+        assert_eq!(x86_64.rf.read64(R10), 0x67);
+        assert_eq!(x86_64.rf.read64(R13), 2);
+        // The second SYSCALL is from user mode with RFLAGS restored by SYSRET without RF and VM.
+        assert_eq!(x86_64.rf.read64(Rcx), 0x69);
+        assert_eq!(x86_64.rf.read64(R11), 0x202);
+        assert!(!x86_64.rflags.contains(RFlags::INTERRUPT_FLAG));
+        assert_eq!(
+            (
+                x86_64.rf.selector(SegmentRegister::Cs),
+                x86_64.rf.selector(SegmentRegister::Ss)
+            ),
+            (0x10, 0x18)
+        );
+        assert_eq!(x86_64.msrs.kernel_gs_base, 0);
+        assert_eq!(x86_64.read_msr(msr::IA32_GS_BASE).unwrap(), 0x6000);
+        assert!(x86_64.read_msr(0xc000_0103).is_err());
+    }
+
+    #[test]
+    fn execute_sse() {
+        let program = vec![
+            0x0f, 0x01, 0x1c, 0x25, 0x00, 0x40, 0x00, 0x00, // lidt [0x4000]
+            0x66, 0x0f, 0x6f, 0x04, 0x25, // movdqa xmm0,
+            0x00, 0x30, 0x00, 0x00, // [0x3000]
+            0xf3, 0x0f, 0x6f, 0x0c, 0x25, // movdqu xmm1,
+            0x10, 0x30, 0x00, 0x00, // [0x3010]
+            0x66, 0x0f, 0xef, 0xd2, // pxor xmm2, xmm2
+            0x66, 0x0f, 0xfe, 0xc1, // paddd xmm0, xmm1
+            0x0f, 0x11, 0x04, 0x25, 0x20, 0x30, 0x00, 0x00, // movups [0x3020], xmm0
+            0x66, 0x0f, 0x74, 0xc9, // pcmpeqb xmm1, xmm1
+            0x66, 0x0f, 0xd7, 0xc1, // pmovmskb eax, xmm1
+            0x48, 0xc7, 0xc3, 0xfd, 0xff, 0xff, 0xff, // mov rbx, -3
+            0xf2, 0x48, 0x0f, 0x2a, 0xdb, // cvtsi2sd xmm3, rbx
+            0xf2, 0x0f, 0x10, 0x24, 0x25, // movsd xmm4,
+            0x30, 0x30, 0x00, 0x00, // [0x3030]
+            0xf2, 0x0f, 0x59, 0xdc, // mulsd xmm3, xmm4
+            0xf2, 0x0f, 0x58, 0xdc, // addsd xmm3, xmm4
+            0xf2, 0x48, 0x0f, 0x2c, 0xcb, // cvttsd2si rcx, xmm3
+            0x66, 0x48, 0x0f, 0x7e, 0xda, // movq rdx, xmm3
+            0x66, 0x0f, 0x2e, 0xdc, // ucomisd xmm3, xmm4
+            0x41, 0x0f, 0x92, 0xc0, // setb r8b
+            0xf2, 0x0f, 0x51, 0xec, // sqrtsd xmm5, xmm4
+            0xf2, 0x0f, 0x5a, 0xf5, // cvtsd2ss xmm6, xmm5
+            0x66, 0x41, 0x0f, 0x7e, 0xf1, // movd r9d, xmm6
+            0x0f, 0xae, 0x1c, 0x25, 0x40, 0x30, 0x00, 0x00, // stmxcsr [0x3040]
+            0x44, 0x8b, 0x1c, 0x25, 0x40, 0x30, 0x00, 0x00, // mov r11d, [0x3040]
+            0x41, 0x81, 0xcb, 0x00, 0x20, 0x00, 0x00, // or r11d, 0x2000 (round down)
+            0x44, 0x89, 0x1c, 0x25, 0x40, 0x30, 0x00, 0x00, // mov [0x3040], r11d
+            0x0f, 0xae, 0x14, 0x25, 0x40, 0x30, 0x00, 0x00, // ldmxcsr [0x3040]
+            0xf2, 0x4c, 0x0f, 0x2d, 0xd5, // cvtsd2si r10, xmm5
+            0x66, 0x48, 0x0f, 0x6e, 0xf9, // movq xmm7, rcx
+            0x66, 0x0f, 0xd6, 0x3c, 0x25, // movq [0x3048],
+            0x48, 0x30, 0x00, 0x00, // xmm7
+            0x0f, 0xae, 0xf0, // mfence
+            0x0f, 0x28, 0x04, 0x25, 0x08, 0x30, 0x00, 0x00, // movaps xmm0, [0x3008]
+            0xf4, // hlt
+            // 0xb4: #GP handler for the misaligned MOVAPS.
+            0x41, 0x5c, // pop r12
+            0xf4, // hlt
+        ];
+        let initializer = |x86_64: &mut X86_64| {
+            x86_64.rf.write64(Rsp, 0x8000);
+            x86_64.rf.write64(R12, 0xff);
+            x86_64.mmio.write_u16(0x4000, 0xff).unwrap();
+            x86_64.mmio.write_u64(0x4002, 0x1000).unwrap();
+            write_gate(x86_64, 13, 0xb4, 0, 0x8e);
+            x86_64.mmio.write_u64(0x3000, 0x2_0000_0001).unwrap();
+            x86_64.mmio.write_u64(0x3008, 0x4_0000_0003).unwrap();
+            x86_64.mmio.write_u64(0x3010, 0x14_0000_000a).unwrap();
+            x86_64.mmio.write_u64(0x3018, 0x28_0000_001e).unwrap();
+            x86_64.mmio.write_u64(0x3030, 2.5f64.to_bits()).unwrap();
+        };
+        let x86_64 = execute_program_after_init(program, &initializer);
+        assert_eq!(x86_64.mmio.read_u64(0x3020).unwrap(), 0x0000_0016_0000_000b);
+        assert_eq!(x86_64.mmio.read_u64(0x3028).unwrap(), 0x0000_002c_0000_0021);
+        assert_eq!(x86_64.rf.read_xmm(2), 0);
+        assert_eq!(x86_64.rf.read64(Rax), 0xffff);
+        // -3.0 * 2.5 + 2.5
+        assert_eq!(x86_64.rf.read64(Rcx), -5i64 as u64);
+        assert_eq!(x86_64.rf.read64(Rdx), (-5.0f64).to_bits());
+        assert_eq!(x86_64.rf.read64(R8), 1);
+        let single = 2.5f64.sqrt() as f32;
+        assert_eq!(x86_64.rf.read64(R9), u64::from(single.to_bits()));
+        assert_eq!(x86_64.rf.mxcsr(), 0x3f80);
+        // sqrt(2.5) is rounded down.
+        assert_eq!(x86_64.rf.read64(R10), 1);
+        assert_eq!(x86_64.mmio.read_u64(0x3048).unwrap(), -5i64 as u64);
+        assert_eq!(x86_64.rf.read64(R12), 0);
+        assert_eq!(x86_64.fetch_unit.get_rip(), 0xb7);
+    }
+
+    #[test]
+    fn execute_triple_fault() {
+        // #DE finds an empty IDT entry, and #DF is beyond the IDT limit.
+        let program = vec![
+            0x0f, 0x01, 0x1c, 0x25, 0x00, 0x40, 0x00, 0x00, // lidt [0x4000]
+            0x31, 0xc9, // xor ecx, ecx
+            0xf7, 0xf1, // div ecx
+        ];
+        let mut mmio = create_interconnect(&program, 0);
+        mmio.write_u16(0x4000, 0xf).unwrap();
+        mmio.write_u64(0x4002, 0x1000).unwrap();
+        let mut x86_64 = X86_64::new(mmio, DebugMode::Disabled);
+        match x86_64.run() {
+            Err(InternalException::TripleFault) => (),
+            result => panic!("Triple fault is expected: {:?}", result),
+        }
+    }
+
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn execute_user_process() {
+        let program = vec![
+            0xb8, 0x01, 0x00, 0x00, 0x00, // mov eax, 1 (write)
+            0xbf, 0x01, 0x00, 0x00, 0x00, // mov edi, 1
+            0x48, 0x8b, 0x74, 0x24, 0x10, // mov rsi, [rsp + 16] (argv[1])
+            0xba, 0x02, 0x00, 0x00, 0x00, // mov edx, 2
+            0x0f, 0x05, // syscall
+            0xb8, 0x9e, 0x00, 0x00, 0x00, // mov eax, 158 (arch_prctl)
+            0xbf, 0x02, 0x10, 0x00, 0x00, // mov edi, 0x1002 (ARCH_SET_FS)
+            0x48, 0x89, 0xe6, // mov rsi, rsp
+            0x0f, 0x05, // syscall
+            0x64, 0x48, 0x8b, 0x1c, 0x25, 0x08, 0x00, 0x00, 0x00, // mov rbx, fs:[8]
+            0xb8, 0x0c, 0x00, 0x00, 0x00, // mov eax, 12 (brk)
+            0x31, 0xff, // xor edi, edi
+            0x0f, 0x05, // syscall
+            0x48, 0x89, 0xc5, // mov rbp, rax
+            0x48, 0x8b, 0x3c, 0x24, // mov rdi, [rsp] (argc)
+            0xb8, 0xe7, 0x00, 0x00, 0x00, // mov eax, 231 (exit_group)
+            0x0f, 0x05, // syscall
+        ];
+        let mut mmio = Interconnect::new_user_space();
+        mmio.init_memory(&program, 0x40_1000);
+        let args = vec!["prog".to_string(), "ok".to_string()];
+        let mut process = LinuxProcess::new(Path::new("."), &args, &[]);
+        let stdout = Rc::new(RefCell::new(Vec::new()));
+        process.set_stdout(Box::new(SharedBuffer(stdout.clone())));
+        let image = ProgramImage {
+            entry: 0x40_1000,
+            program_headers: 0x40_0040,
+            program_header_size: 56,
+            program_header_num: 1,
+            brk: 0x40_1045,
+        };
+        let mut x86_64 = X86_64::new_user_process(mmio, DebugMode::Disabled, process, &image);
+        let result = x86_64.run();
+
+        assert!(result.is_ok(), "{:?}", result.err());
+        assert_eq!(x86_64.exit_status(), Some(2));
+        assert_eq!(*stdout.borrow(), b"ok");
+        let rsp = x86_64.rf.read64(Rsp);
+        assert_eq!(rsp % 16, 0);
+        // FS base points argc, so that fs:[8] is argv[0].
+        let argv0 = x86_64.mmio.read_u64(rsp as usize + 8).unwrap();
+        assert_eq!(x86_64.rf.read64(Rbx), argv0);
+        assert_eq!(x86_64.mmio.read_u32(argv0 as usize).unwrap(), 0x676f_7270);
+        assert_eq!(x86_64.rf.read64(Rbp), 0x40_2000);
+        // SYSCALL saves the next RIP, and RFLAGS with the reserved bit.
+        assert_eq!(x86_64.rf.read64(Rcx), 0x40_1045);
+        assert_eq!(x86_64.rf.read64(R11) & RFLAGS_RESERVED, RFLAGS_RESERVED);
+    }
 }
@@ -0,0 +1,680 @@
+//! Linux system calls for user-mode emulation.
+//! A statically linked program runs in its own address space without a kernel,
+//! and SYSCALL instructions are serviced on the host.
+use crate::isa::registers::{Reg64Id, SegmentRegister};
+use crate::register_file::RegisterFile;
+use num::FromPrimitive;
+use peripherals::interconnect::Interconnect;
+use peripherals::memory_access::MemoryAccess;
+use peripherals::paged_memory::PAGE_SIZE;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{FileExt, MetadataExt, OpenOptionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The stack grows down from here.
+pub const STACK_TOP: u64 = 0x7fff_ffff_f000;
+const STACK_SIZE: u64 = 0x80_0000;
+/// Anonymous mappings are placed upward from here.
+const MMAP_BASE: u64 = 0x7f00_0000_0000;
+// Each of mappings and the heap is limited like RLIMIT_AS, so that the guest cannot exhaust the host.
+const MEMORY_LIMIT: u64 = 0x4000_0000;
+// Like MAX_RW_COUNT of Linux, a read or write transfers at most this many bytes at once.
+const MAX_IO_COUNT: u64 = 0x10_0000;
+const IOV_MAX: u64 = 1024;
+/// Selectors of the user code and stack segments, which run the process at CPL 3.
+pub const USER_CODE_SELECTOR: u16 = 0x33;
+pub const USER_STACK_SELECTOR: u16 = 0x2b;
+
+enum_from_primitive! {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyscallNumber {
+    Read          = 0,
+    Write         = 1,
+    Close         = 3,
+    Fstat         = 5,
+    Mmap          = 9,
+    Munmap        = 11,
+    Brk           = 12,
+    Ioctl         = 16,
+    Writev        = 20,
+    Exit          = 60,
+    ArchPrctl     = 158,
+    SetTidAddress = 218,
+    ClockGettime  = 228,
+    ExitGroup     = 231,
+    Openat        = 257,
+    Getrandom     = 318,
+}
+}
+
+// Error numbers returned as negative values in RAX.
+const ENOENT: i64 = 2;
+const EIO: i64 = 5;
+const EBADF: i64 = 9;
+const ENOMEM: i64 = 12;
+const EACCES: i64 = 13;
+const EFAULT: i64 = 14;
+const EINVAL: i64 = 22;
+const ENOTTY: i64 = 25;
+const ENOSYS: i64 = 38;
+
+type SyscallResult = Result<u64, i64>;
+
+const AT_FDCWD: i32 = -100;
+const O_ACCMODE: u64 = 0x3;
+const O_WRONLY: u64 = 0x1;
+const O_CREAT: u64 = 0x40;
+const O_EXCL: u64 = 0x80;
+const O_TRUNC: u64 = 0x200;
+const O_APPEND: u64 = 0x400;
+const O_NOFOLLOW: i32 = 0o400_000;
+
+const MAP_FIXED: u64 = 0x10;
+const MAP_ANONYMOUS: u64 = 0x20;
+
+const ARCH_SET_GS: u64 = 0x1001;
+const ARCH_SET_FS: u64 = 0x1002;
+const ARCH_GET_FS: u64 = 0x1003;
+const ARCH_GET_GS: u64 = 0x1004;
+
+const CLOCK_REALTIME: u64 = 0;
+
+// Character device with rw--w---- permission.
+const S_IFCHR: u32 = 0o020_620;
+const STAT_SIZE: usize = 144;
+
+// Entry types of the auxiliary vector.
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_ENTRY: u64 = 9;
+const AT_UID: u64 = 11;
+const AT_EUID: u64 = 12;
+const AT_GID: u64 = 13;
+const AT_EGID: u64 = 14;
+const AT_SECURE: u64 = 23;
+const AT_RANDOM: u64 = 25;
+
+/// The loaded program which the process is told through the auxiliary vector.
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramImage {
+    pub entry: u64,
+    /// Address of the program headers in the loaded segments.
+    pub program_headers: u64,
+    pub program_header_size: u64,
+    pub program_header_num: u64,
+    /// The end of the loaded segments, where the heap starts.
+    pub brk: u64,
+}
+
+enum FileDescriptor {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+/// State of the emulated process which the kernel would hold.
+pub struct LinuxProcess {
+    root: PathBuf,
+    args: Vec<String>,
+    envs: Vec<String>,
+    files: Vec<Option<FileDescriptor>>,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    brk_start: u64,
+    brk: u64,
+    mmap_next: u64,
+    random: u64,
+    started: Instant,
+    exit_status: Option<i32>,
+}
+
+impl LinuxProcess {
+    /// Files are opened under `root` as if the process was chrooted there.
+    /// `args` includes the program name.
+    pub fn new(root: &Path, args: &[String], envs: &[String]) -> LinuxProcess {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0);
+        LinuxProcess {
+            root: root.to_path_buf(),
+            args: args.to_vec(),
+            envs: envs.to_vec(),
+            files: vec![
+                Some(FileDescriptor::Stdin),
+                Some(FileDescriptor::Stdout),
+                Some(FileDescriptor::Stderr),
+            ],
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            brk_start: 0,
+            brk: 0,
+            mmap_next: MMAP_BASE,
+            // Xorshift must not start from zero.
+            random: seed | 1,
+            started: Instant::now(),
+            exit_status: None,
+        }
+    }
+
+    /// Replaces the host stdout which the process writes to.
+    pub fn set_stdout(&mut self, stdout: Box<dyn Write>) {
+        self.stdout = stdout;
+    }
+
+    pub fn set_stderr(&mut self, stderr: Box<dyn Write>) {
+        self.stderr = stderr;
+    }
+
+    /// Status passed to exit_group, after the process exited.
+    pub fn exit_status(&self) -> Option<i32> {
+        self.exit_status
+    }
+
+    /// Maps the stack and pushes argc, argv, envp and the auxiliary vector.
+    /// Returns the initial RSP which points argc.
+    pub(crate) fn start(&mut self, mmio: &mut Interconnect, image: &ProgramImage) -> u64 {
+        self.brk_start = page_align(image.brk).expect("The program must be below the stack.");
+        self.brk = self.brk_start;
+        let memory = mmio
+            .user_space_mut()
+            .expect("User-mode emulation requires a user space.");
+        memory.map((STACK_TOP - STACK_SIZE) as usize, STACK_SIZE as usize);
+
+        let mut sp = STACK_TOP;
+        let mut push_bytes = |bytes: &[u8]| {
+            sp -= bytes.len() as u64;
+            memory
+                .fill(bytes, sp as usize)
+                .expect("Stack must be mapped.");
+            sp
+        };
+        let mut push_strings = |strings: &[String]| {
+            strings
+                .iter()
+                .map(|string| push_bytes(&[string.as_bytes(), &[0]].concat()))
+                .collect::<Vec<u64>>()
+        };
+        let argv = push_strings(&self.args);
+        let envp = push_strings(&self.envs);
+        let random = self.random_bytes(16);
+        sp -= random.len() as u64;
+        memory.fill(&random, sp as usize).unwrap();
+        let at_random = sp;
+
+        let auxv = [
+            (AT_PHDR, image.program_headers),
+            (AT_PHENT, image.program_header_size),
+            (AT_PHNUM, image.program_header_num),
+            (AT_PAGESZ, PAGE_SIZE as u64),
+            (AT_ENTRY, image.entry),
+            (AT_UID, 0),
+            (AT_EUID, 0),
+            (AT_GID, 0),
+            (AT_EGID, 0),
+            (AT_SECURE, 0),
+            (AT_RANDOM, at_random),
+            (AT_NULL, 0),
+        ];
+        let mut words = vec![argv.len() as u64];
+        words.extend(&argv);
+        words.push(0);
+        words.extend(&envp);
+        words.push(0);
+        for (key, value) in auxv.iter() {
+            words.push(*key);
+            words.push(*value);
+        }
+        // RSP is 16-byte aligned at argc.
+        let rsp = (sp - words.len() as u64 * 8) & !0xf;
+        for (i, word) in words.iter().enumerate() {
+            memory.write_u64(rsp as usize + i * 8, *word).unwrap();
+        }
+        rsp
+    }
+
+    /// Services the system call requested by RAX, and returns the result in RAX.
+    /// Arguments are passed in RDI, RSI, RDX, R10, R8 and R9.
+    pub(crate) fn syscall(&mut self, rf: &mut RegisterFile, mmio: &mut Interconnect) {
+        use self::SyscallNumber::*;
+        let number = rf.read64(Reg64Id::Rax);
+        let args = [
+            rf.read64(Reg64Id::Rdi),
+            rf.read64(Reg64Id::Rsi),
+            rf.read64(Reg64Id::Rdx),
+            rf.read64(Reg64Id::R10),
+            rf.read64(Reg64Id::R8),
+            rf.read64(Reg64Id::R9),
+        ];
+        let result = match SyscallNumber::from_u64(number) {
+            Some(Read) => self.read(mmio, args[0], args[1], args[2]),
+            Some(Write) => self.write(mmio, args[0], args[1], args[2]),
+            Some(Close) => self.close(args[0]),
+            Some(Fstat) => self.fstat(mmio, args[0], args[1]),
+            Some(Mmap) => self.mmap(mmio, &args),
+            Some(Munmap) => self.munmap(mmio, args[0], args[1]),
+            Some(Brk) => Ok(self.set_brk(mmio, args[0])),
+            Some(Ioctl) => Err(ENOTTY),
+            Some(Writev) => self.writev(mmio, args[0], args[1], args[2]),
+            Some(Exit) | Some(ExitGroup) => {
+                self.exit_status = Some(args[0] as i32);
+                Ok(0)
+            }
+            Some(ArchPrctl) => arch_prctl(rf, mmio, args[0], args[1]),
+            // There is only one thread.
+            Some(SetTidAddress) => Ok(1),
+            Some(ClockGettime) => self.clock_gettime(mmio, args[0], args[1]),
+            Some(Openat) => self.openat(mmio, args[0], args[1], args[2]),
+            Some(Getrandom) => self.getrandom(mmio, args[0], args[1]),
+            None => Err(ENOSYS),
+        };
+        let rax = match result {
+            Ok(value) => value,
+            Err(errno) => (-errno) as u64,
+        };
+        rf.write64(Reg64Id::Rax, rax);
+    }
+
+    fn file(&mut self, fd: u64) -> Result<&mut FileDescriptor, i64> {
+        self.files
+            .get_mut(fd as usize)
+            .and_then(|file| file.as_mut())
+            .ok_or(EBADF)
+    }
+
+    fn read(&mut self, mmio: &mut Interconnect, fd: u64, buf: u64, count: u64) -> SyscallResult {
+        let mut data = vec![0; count.min(MAX_IO_COUNT) as usize];
+        let len = match self.file(fd)? {
+            FileDescriptor::Stdin => io::stdin().read(&mut data),
+            FileDescriptor::File(file) => file.read(&mut data),
+            _ => return Err(EBADF),
+        }
+        .map_err(|err| errno(&err))?;
+        write_bytes(mmio, buf, &data[..len])?;
+        Ok(len as u64)
+    }
+
+    fn write(&mut self, mmio: &mut Interconnect, fd: u64, buf: u64, count: u64) -> SyscallResult {
+        let data = read_bytes(mmio, buf, count.min(MAX_IO_COUNT))?;
+        self.write_file(fd, &data)
+    }
+
+    fn writev(&mut self, mmio: &mut Interconnect, fd: u64, iov: u64, iovcnt: u64) -> SyscallResult {
+        if iovcnt > IOV_MAX {
+            return Err(EINVAL);
+        }
+        let mut data = Vec::new();
+        for i in 0..iovcnt {
+            let entry = iov.wrapping_add(i * 16);
+            let base = read_u64(mmio, entry)?;
+            let len = read_u64(mmio, entry.wrapping_add(8))?;
+            let len = len.min(MAX_IO_COUNT - data.len() as u64);
+            data.extend(read_bytes(mmio, base, len)?);
+        }
+        self.write_file(fd, &data)
+    }
+
+    fn write_file(&mut self, fd: u64, data: &[u8]) -> SyscallResult {
+        let result = match self.file(fd)? {
+            FileDescriptor::Stdout => self.stdout.write_all(data),
+            FileDescriptor::Stderr => self.stderr.write_all(data),
+            FileDescriptor::File(file) => file.write_all(data),
+            FileDescriptor::Stdin => return Err(EBADF),
+        };
+        result.map_err(|err| errno(&err))?;
+        Ok(data.len() as u64)
+    }
+
+    fn close(&mut self, fd: u64) -> SyscallResult {
+        self.file(fd)?;
+        self.files[fd as usize] = None;
+        Ok(0)
+    }
+
+    // Writes struct stat of x86_64.
+    fn fstat(&mut self, mmio: &mut Interconnect, fd: u64, statbuf: u64) -> SyscallResult {
+        let mut stat = [0u8; STAT_SIZE];
+        let mut put = |offset: usize, value: u64| {
+            stat[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        };
+        match self.file(fd)? {
+            FileDescriptor::File(file) => {
+                let metadata = file.metadata().map_err(|err| errno(&err))?;
+                put(0, metadata.dev());
+                put(8, metadata.ino());
+                put(16, metadata.nlink());
+                put(
+                    24,
+                    u64::from(metadata.mode()) | u64::from(metadata.uid()) << 32,
+                );
+                put(32, u64::from(metadata.gid()));
+                put(40, metadata.rdev());
+                put(48, metadata.size());
+                put(56, metadata.blksize());
+                put(64, metadata.blocks());
+                put(72, metadata.atime() as u64);
+                put(80, metadata.atime_nsec() as u64);
+                put(88, metadata.mtime() as u64);
+                put(96, metadata.mtime_nsec() as u64);
+                put(104, metadata.ctime() as u64);
+                put(112, metadata.ctime_nsec() as u64);
+            }
+            _ => {
+                put(16, 1);
+                put(24, u64::from(S_IFCHR));
+                put(56, 1024);
+            }
+        }
+        write_bytes(mmio, statbuf, &stat)?;
+        Ok(0)
+    }
+
+    fn mmap(&mut self, mmio: &mut Interconnect, args: &[u64; 6]) -> SyscallResult {
+        let (addr, len, flags, fd, offset) = (args[0], args[1], args[3], args[4], args[5]);
+        if len == 0 || addr % PAGE_SIZE as u64 != 0 || offset % PAGE_SIZE as u64 != 0 {
+            return Err(EINVAL);
+        }
+        let len = page_align(len)
+            .filter(|len| *len <= MEMORY_LIMIT)
+            .ok_or(ENOMEM)?;
+        // Only the contents of the file are read, and the rest of the mapping is zero.
+        let data = if flags & MAP_ANONYMOUS == 0 {
+            match self.file(fd)? {
+                FileDescriptor::File(file) => {
+                    let size = file.metadata().map_err(|err| errno(&err))?.len();
+                    let mut data = vec![0; size.saturating_sub(offset).min(len) as usize];
+                    file.read_at(&mut data, offset).map_err(|err| errno(&err))?;
+                    Some(data)
+                }
+                _ => return Err(EACCES),
+            }
+        } else {
+            None
+        };
+        let addr = if flags & MAP_FIXED != 0 {
+            addr.checked_add(len).ok_or(EINVAL)?;
+            addr
+        } else {
+            let addr = self.mmap_next;
+            self.mmap_next = addr
+                .checked_add(len)
+                .filter(|end| *end <= MMAP_BASE + MEMORY_LIMIT)
+                .ok_or(ENOMEM)?;
+            addr
+        };
+
+        let memory = mmio.user_space_mut().ok_or(EFAULT)?;
+        memory.unmap(addr as usize, len as usize);
+        memory.map(addr as usize, len as usize);
+        if let Some(data) = data {
+            memory.fill(&data, addr as usize).map_err(|_| EFAULT)?;
+        }
+        Ok(addr)
+    }
+
+    fn munmap(&mut self, mmio: &mut Interconnect, addr: u64, len: u64) -> SyscallResult {
+        if addr % PAGE_SIZE as u64 != 0 || len == 0 || len > MEMORY_LIMIT {
+            return Err(EINVAL);
+        }
+        let memory = mmio.user_space_mut().ok_or(EFAULT)?;
+        memory.unmap(addr as usize, len as usize);
+        Ok(0)
+    }
+
+    // Returns the current break, which is unchanged if `addr` is invalid.
+    fn set_brk(&mut self, mmio: &mut Interconnect, addr: u64) -> u64 {
+        if addr < self.brk_start || addr - self.brk_start > MEMORY_LIMIT {
+            return self.brk;
+        }
+        if let Some(memory) = mmio.user_space_mut() {
+            // The break is below the limit, so that alignment does not overflow.
+            let (current, next) = (page_align(self.brk).unwrap(), page_align(addr).unwrap());
+            if next > current {
+                memory.map(current as usize, (next - current) as usize);
+            } else {
+                memory.unmap(next as usize, (current - next) as usize);
+            }
+            self.brk = addr;
+        }
+        self.brk
+    }
+
+    fn clock_gettime(&mut self, mmio: &mut Interconnect, clock: u64, tp: u64) -> SyscallResult {
+        let time = if clock == CLOCK_REALTIME {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|_| EINVAL)?
+        } else {
+            self.started.elapsed()
+        };
+        write_u64(mmio, tp, time.as_secs())?;
+        write_u64(mmio, tp + 8, u64::from(time.subsec_nanos()))?;
+        Ok(0)
+    }
+
+    fn openat(
+        &mut self,
+        mmio: &mut Interconnect,
+        dirfd: u64,
+        path: u64,
+        flags: u64,
+    ) -> SyscallResult {
+        let path = read_c_string(mmio, path)?;
+        // Only the current directory is supported as a base of relative paths.
+        if !path.starts_with('/') && dirfd as i32 != AT_FDCWD {
+            return Err(EBADF);
+        }
+        let path = self.sandboxed_path(&path)?;
+        let file = OpenOptions::new()
+            .read(flags & O_ACCMODE != O_WRONLY)
+            .write(flags & O_ACCMODE != 0)
+            .append(flags & O_APPEND != 0)
+            .truncate(flags & O_TRUNC != 0)
+            .create(flags & O_CREAT != 0)
+            .create_new(flags & (O_CREAT | O_EXCL) == O_CREAT | O_EXCL)
+            .mode(0o644)
+            .custom_flags(O_NOFOLLOW)
+            .open(path)
+            .map_err(|err| errno(&err))?;
+
+        let file = Some(FileDescriptor::File(file));
+        let fd = match self.files.iter().position(|fd| fd.is_none()) {
+            Some(fd) => {
+                self.files[fd] = file;
+                fd
+            }
+            None => {
+                self.files.push(file);
+                self.files.len() - 1
+            }
+        };
+        Ok(fd as u64)
+    }
+
+    // Resolves `path` of the process inside the root directory.
+    // ".." never goes above the root, and symbolic links must not point outside.
+    // The resolved path has no symbolic links, so that it can be opened with O_NOFOLLOW.
+    fn sandboxed_path(&self, path: &str) -> Result<PathBuf, i64> {
+        let mut resolved = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                _ => (),
+            }
+        }
+        let root = self.root.canonicalize().map_err(|err| errno(&err))?;
+        let path = root.join(resolved);
+        // A file to be created does not exist yet, but its directory must.
+        // A dangling symbolic link would create its target, which may be outside.
+        let resolved = match path.canonicalize() {
+            Ok(path) => path,
+            Err(_) if path.symlink_metadata().is_ok() => return Err(EACCES),
+            Err(_) => path
+                .parent()
+                .ok_or(ENOENT)?
+                .canonicalize()
+                .map_err(|err| errno(&err))?
+                .join(path.file_name().ok_or(ENOENT)?),
+        };
+        if resolved.starts_with(&root) {
+            Ok(resolved)
+        } else {
+            Err(EACCES)
+        }
+    }
+
+    fn getrandom(&mut self, mmio: &mut Interconnect, buf: u64, len: u64) -> SyscallResult {
+        let len = len.min(MAX_IO_COUNT);
+        let data = self.random_bytes(len as usize);
+        write_bytes(mmio, buf, &data)?;
+        Ok(len)
+    }
+
+    // Xorshift64*. It is not cryptographically secure, but enough for the guest.
+    fn random_bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(len + 8);
+        while bytes.len() < len {
+            self.random ^= self.random >> 12;
+            self.random ^= self.random << 25;
+            self.random ^= self.random >> 27;
+            bytes.extend(
+                &self
+                    .random
+                    .wrapping_mul(0x2545_f491_4f6c_dd1d)
+                    .to_le_bytes(),
+            );
+        }
+        bytes.truncate(len);
+        bytes
+    }
+}
+
+fn arch_prctl(
+    rf: &mut RegisterFile,
+    mmio: &mut Interconnect,
+    code: u64,
+    addr: u64,
+) -> SyscallResult {
+    match code {
+        ARCH_SET_FS => rf.write_segment_base(SegmentRegister::Fs, addr),
+        ARCH_SET_GS => rf.write_segment_base(SegmentRegister::Gs, addr),
+        ARCH_GET_FS => write_u64(mmio, addr, rf.segment_base(SegmentRegister::Fs))?,
+        ARCH_GET_GS => write_u64(mmio, addr, rf.segment_base(SegmentRegister::Gs))?,
+        _ => return Err(EINVAL),
+    }
+    Ok(0)
+}
+
+// None if the aligned address overflows.
+fn page_align(addr: u64) -> Option<u64> {
+    let mask = PAGE_SIZE as u64 - 1;
+    addr.checked_add(mask).map(|addr| addr & !mask)
+}
+
+// The host and the guest share error numbers.
+fn errno(err: &io::Error) -> i64 {
+    err.raw_os_error().map_or(EIO, i64::from)
+}
+
+// Accesses to the process memory fail with EFAULT.
+fn read_bytes(mmio: &Interconnect, addr: u64, len: u64) -> Result<Vec<u8>, i64> {
+    (0..len)
+        .map(|i| {
+            mmio.read_u8(addr.wrapping_add(i) as usize)
+                .map_err(|_| EFAULT)
+        })
+        .collect()
+}
+
+fn write_bytes(mmio: &mut Interconnect, addr: u64, data: &[u8]) -> Result<(), i64> {
+    for (i, byte) in data.iter().enumerate() {
+        mmio.write_u8(addr.wrapping_add(i as u64) as usize, *byte)
+            .map_err(|_| EFAULT)?;
+    }
+    Ok(())
+}
+
+fn read_u64(mmio: &Interconnect, addr: u64) -> Result<u64, i64> {
+    mmio.read_u64(addr as usize).map_err(|_| EFAULT)
+}
+
+fn write_u64(mmio: &mut Interconnect, addr: u64, data: u64) -> Result<(), i64> {
+    write_bytes(mmio, addr, &data.to_le_bytes())
+}
+
+fn read_c_string(mmio: &Interconnect, addr: u64) -> Result<String, i64> {
+    let mut bytes = Vec::new();
+    loop {
+        match mmio.read_u8(addr.wrapping_add(bytes.len() as u64) as usize) {
+            Ok(0) => break,
+            Ok(byte) => bytes.push(byte),
+            Err(_) => return Err(EFAULT),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| ENOENT)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sandboxed_path() {
+        let root = std::env::temp_dir();
+        let process = LinuxProcess::new(&root, &[], &[]);
+        let root = root.canonicalize().unwrap();
+
+        assert_eq!(process.sandboxed_path("/a/../b").unwrap(), root.join("b"));
+        // ".." at the root stays at the root like chroot.
+        assert_eq!(
+            process.sandboxed_path("../../etc").unwrap(),
+            root.join("etc")
+        );
+    }
+
+    #[test]
+    fn guest_sizes_are_limited() {
+        let mut mmio = Interconnect::new_user_space();
+        let mut process = LinuxProcess::new(&std::env::temp_dir(), &[], &[]);
+        let anonymous = |len| [0, len, 0x3, MAP_ANONYMOUS, !0, 0];
+
+        assert_eq!(process.mmap(&mut mmio, &anonymous(u64::MAX)), Err(ENOMEM));
+        let addr = process.mmap(&mut mmio, &anonymous(MAX_IO_COUNT)).unwrap();
+        assert_eq!(addr, MMAP_BASE);
+        // A large request is shortened.
+        assert_eq!(
+            process.getrandom(&mut mmio, addr, u64::MAX),
+            Ok(MAX_IO_COUNT)
+        );
+        // The mapping cursor stops at the limit.
+        let rest = MEMORY_LIMIT - MAX_IO_COUNT;
+        assert!(process.mmap(&mut mmio, &anonymous(rest)).is_ok());
+        assert_eq!(process.mmap(&mut mmio, &anonymous(1)), Err(ENOMEM));
+        assert_eq!(process.munmap(&mut mmio, addr, u64::MAX), Err(EINVAL));
+        assert_eq!(process.set_brk(&mut mmio, u64::MAX), 0);
+    }
+
+    #[test]
+    fn dangling_symlink() {
+        let dir = std::env::temp_dir().join(format!("rustemu86-{}", std::process::id()));
+        let root = dir.join("root");
+        let outside = dir.join("outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        let process = LinuxProcess::new(&root, &[], &[]);
+
+        // The link would create a file outside the root.
+        assert_eq!(process.sandboxed_path("/link"), Err(EACCES));
+        assert!(!outside.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,32 @@
+//! Model-specific registers accessed by RDMSR and WRMSR.
+//! EFER is held by the MMU, FS and GS bases by the register file,
+//! and the time stamp counter and APIC registers by the interconnect.
+pub const IA32_TSC: u32 = 0x10;
+pub const IA32_EFER: u32 = 0xc000_0080;
+pub const IA32_STAR: u32 = 0xc000_0081;
+pub const IA32_LSTAR: u32 = 0xc000_0082;
+pub const IA32_CSTAR: u32 = 0xc000_0083;
+pub const IA32_FMASK: u32 = 0xc000_0084;
+pub const IA32_FS_BASE: u32 = 0xc000_0100;
+pub const IA32_GS_BASE: u32 = 0xc000_0101;
+pub const IA32_KERNEL_GS_BASE: u32 = 0xc000_0102;
+
+/// MSRs used by SYSCALL, SYSRET and SWAPGS.
+#[derive(Debug, Default)]
+pub struct SystemCallMsrs {
+    /// SYSRET CS and SS in bits 48..64, and SYSCALL CS and SS in bits 32..48.
+    pub star: u64,
+    /// RIP of SYSCALL from 64-bit mode.
+    pub lstar: u64,
+    /// RIP of SYSCALL from compatibility mode, which is never used.
+    pub cstar: u64,
+    /// RFLAGS bits cleared by SYSCALL.
+    pub fmask: u64,
+    /// Swapped with the GS base by SWAPGS.
+    pub kernel_gs_base: u64,
+}
+
+/// True if bits 48..64 are copies of bit 47.
+pub fn is_canonical(addr: u64) -> bool {
+    ((addr << 16) as i64 >> 16) as u64 == addr
+}
@@ -0,0 +1,597 @@
+//! Address translation of 4-level paging in long mode and PAE paging in protected mode.
+//! Linear addresses are translated through PML4, PDPT, PD and PT,
+//! and PDPT or PD entries may map 1GiB or 2MiB pages.
+//! PAE paging starts from the 4-entry PDPT pointed by CR3.
+use crate::exceptions::InternalException;
+use crate::isa::control_registers::{Cr0, Cr4, Efer};
+use crate::isa::opcode::OperandSize;
+use crate::msr::is_canonical;
+use crate::Result;
+use peripherals::interconnect::Interconnect;
+use peripherals::memory_access::MemoryAccess;
+use std::collections::HashMap;
+
+pub const PAGE_SIZE: u64 = 0x1000;
+// Bits 12..52 of CR3 and table entries hold a physical address.
+const PHYSICAL_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+// The PDPT of PAE paging is 32-byte aligned below 4GiB.
+const PAE_PDPT_MASK: u64 = 0xffff_ffe0;
+const ENTRIES_PER_TABLE: u64 = 512;
+const PML4_LEVEL: u32 = 3;
+const PDPT_LEVEL: u32 = 2;
+
+bitflags! {
+    /// Flags of paging-structure entries.
+    struct PageTableFlags: u64 {
+        const PRESENT = 1 << 0;
+        const WRITABLE = 1 << 1;
+        const USER_ACCESSIBLE = 1 << 2;
+        const WRITE_THROUGH = 1 << 3;
+        const CACHE_DISABLE = 1 << 4;
+        const ACCESSED = 1 << 5;
+        const DIRTY = 1 << 6;
+        /// Maps a 1GiB page in a PDPT entry or a 2MiB page in a PD entry.
+        const HUGE_PAGE = 1 << 7;
+        const GLOBAL = 1 << 8;
+        const NO_EXECUTE = 1 << 63;
+    }
+}
+
+bitflags! {
+    /// Error code of #PF.
+    pub struct PageFaultErrorCode: u64 {
+        /// Caused by a protection violation, otherwise by a non-present page.
+        const PROTECTION_VIOLATION = 1 << 0;
+        const CAUSED_BY_WRITE = 1 << 1;
+        const USER_MODE = 1 << 2;
+        /// A reserved bit is set in a paging-structure entry.
+        const MALFORMED_TABLE = 1 << 3;
+        const INSTRUCTION_FETCH = 1 << 4;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    Execute,
+}
+
+// A cached translation of a 4KiB page. Large pages are cached per 4KiB.
+// WRITABLE and USER_ACCESSIBLE are granted by all levels, and NO_EXECUTE by any level.
+#[derive(Debug, Clone, Copy)]
+struct TlbEntry {
+    frame: u64,
+    flags: PageTableFlags,
+}
+
+/// Memory management unit which holds the control registers, EFER and the TLB.
+pub struct Mmu {
+    cr0: Cr0,
+    cr2: u64,
+    cr3: u64,
+    cr4: Cr4,
+    cr8: u64,
+    // The NX bit is reserved unless EFER.NXE is set.
+    efer: Efer,
+    tlb: HashMap<u64, TlbEntry>,
+}
+
+impl Mmu {
+    /// The CPU starts in protected mode with paging disabled,
+    /// where linear addresses are physical addresses.
+    /// EFER reports long mode, as instructions are executed as 64-bit code.
+    pub fn new() -> Mmu {
+        Mmu {
+            cr0: Cr0::PROTECTION_ENABLE | Cr0::EXTENSION_TYPE,
+            cr2: 0,
+            cr3: 0,
+            cr4: Cr4::empty(),
+            cr8: 0,
+            efer: Efer::LONG_MODE_ENABLE | Efer::LONG_MODE_ACTIVE | Efer::NO_EXECUTE_ENABLE,
+            tlb: HashMap::new(),
+        }
+    }
+
+    /// The state after reset, which is real mode.
+    pub fn new_real_mode() -> Mmu {
+        Mmu {
+            cr0: Cr0::EXTENSION_TYPE | Cr0::NOT_WRITE_THROUGH | Cr0::CACHE_DISABLE,
+            efer: Efer::empty(),
+            ..Mmu::new()
+        }
+    }
+
+    pub fn is_protected_mode(&self) -> bool {
+        self.cr0.contains(Cr0::PROTECTION_ENABLE)
+    }
+
+    pub fn is_long_mode_active(&self) -> bool {
+        self.efer.contains(Efer::LONG_MODE_ACTIVE)
+    }
+
+    /// CR0, CR2, CR3, CR4 and CR8 are defined.
+    pub fn is_control_register(number: u8) -> bool {
+        match number {
+            0 | 2 | 3 | 4 | 8 => true,
+            _ => false,
+        }
+    }
+
+    pub fn read_control_register(&self, number: u8) -> u64 {
+        match number {
+            0 => self.cr0.bits(),
+            2 => self.cr2,
+            3 => self.cr3,
+            4 => self.cr4.bits(),
+            8 => self.cr8,
+            _ => panic!("CR{} is not defined.", number),
+        }
+    }
+
+    /// Reloading CR3 flushes the TLB except global pages.
+    /// Writing CR0 or CR4 flushes the whole TLB.
+    /// Enabling paging with EFER.LME activates long mode, and disabling paging deactivates it.
+    /// False if the value is invalid for the current mode.
+    pub fn write_control_register(&mut self, number: u8, value: u64) -> bool {
+        match number {
+            0 => {
+                let cr0 = Cr0::from_bits_truncate(value);
+                let paging = cr0.contains(Cr0::PAGING);
+                if paging && !cr0.contains(Cr0::PROTECTION_ENABLE) {
+                    return false;
+                }
+                if paging
+                    && !self.cr0.contains(Cr0::PAGING)
+                    && self.efer.contains(Efer::LONG_MODE_ENABLE)
+                {
+                    if !self.cr4.contains(Cr4::PHYSICAL_ADDRESS_EXTENSION) {
+                        return false;
+                    }
+                    self.efer |= Efer::LONG_MODE_ACTIVE;
+                }
+                if !paging && self.cr0.contains(Cr0::PAGING) {
+                    self.efer -= Efer::LONG_MODE_ACTIVE;
+                }
+                self.cr0 = cr0;
+                self.tlb.clear();
+            }
+            2 => self.cr2 = value,
+            3 => {
+                self.cr3 = value;
+                let keep_global = self.cr4.contains(Cr4::PAGE_GLOBAL);
+                self.tlb
+                    .retain(|_, entry| keep_global && entry.flags.contains(PageTableFlags::GLOBAL));
+            }
+            4 => {
+                let cr4 = Cr4::from_bits_truncate(value);
+                let paging_long_mode = self.cr0.contains(Cr0::PAGING) && self.is_long_mode_active();
+                if paging_long_mode && !cr4.contains(Cr4::PHYSICAL_ADDRESS_EXTENSION) {
+                    return false;
+                }
+                self.cr4 = cr4;
+                self.tlb.clear();
+            }
+            8 => self.cr8 = value & 0xf,
+            _ => panic!("CR{} is not defined.", number),
+        }
+        true
+    }
+
+    pub fn efer(&self) -> Efer {
+        self.efer
+    }
+
+    /// LMA is read-only. False if reserved bits are set,
+    /// or LME is changed while paging is enabled.
+    pub fn write_efer(&mut self, value: u64) -> bool {
+        let efer = match Efer::from_bits(value) {
+            Some(efer) => efer - Efer::LONG_MODE_ACTIVE,
+            None => return false,
+        };
+        let long_mode_changed =
+            (efer ^ self.efer).contains(Efer::LONG_MODE_ENABLE) && self.cr0.contains(Cr0::PAGING);
+        if long_mode_changed {
+            return false;
+        }
+        self.efer = efer | (self.efer & Efer::LONG_MODE_ACTIVE);
+        self.tlb.clear();
+        true
+    }
+
+    /// INVLPG flushes the page including `addr` even if it is global.
+    pub fn invalidate_page(&mut self, addr: u64) {
+        self.tlb.remove(&(addr & !(PAGE_SIZE - 1)));
+    }
+
+    /// Paging requires CR4.PAE in addition to CR0.PG, as 32-bit paging is not supported.
+    pub fn is_paging_enabled(&self) -> bool {
+        self.cr0.contains(Cr0::PAGING) && self.cr4.contains(Cr4::PHYSICAL_ADDRESS_EXTENSION)
+    }
+
+    /// Translates `len` bytes from `addr` into physical ranges, which are split at page boundaries.
+    /// Page walks fill the TLB and set accessed and dirty flags of the entries.
+    /// Non-canonical addresses in long mode raise #GP(0) before the walk.
+    pub fn translate(
+        &mut self,
+        mmio: &mut Interconnect,
+        addr: u64,
+        len: usize,
+        kind: AccessKind,
+        user: bool,
+    ) -> Result<Vec<(usize, usize)>> {
+        if !self.is_paging_enabled() {
+            return Ok(vec![(addr as usize, len)]);
+        }
+        self.check_canonical(addr, len)?;
+        split_at_pages(addr, len)
+            .into_iter()
+            .map(|(addr, len)| Ok((self.translate_page(mmio, addr, kind, user)? as usize, len)))
+            .collect()
+    }
+
+    /// Translates like `translate()`, but neither fills the TLB nor updates the tables.
+    pub fn probe(
+        &self,
+        mmio: &Interconnect,
+        addr: u64,
+        len: usize,
+        kind: AccessKind,
+        user: bool,
+    ) -> Result<Vec<(usize, usize)>> {
+        if !self.is_paging_enabled() {
+            return Ok(vec![(addr as usize, len)]);
+        }
+        self.check_canonical(addr, len)?;
+        split_at_pages(addr, len)
+            .into_iter()
+            .map(|(addr, len)| {
+                let entry = match self.tlb_hit(addr, kind, user) {
+                    Some(entry) => entry,
+                    None => self.walk(mmio, addr, kind, user)?.0,
+                };
+                Ok(((entry.frame | (addr % PAGE_SIZE)) as usize, len))
+            })
+            .collect()
+    }
+
+    // Bits 63..48 of a linear address in long mode must be copies of bit 47,
+    // otherwise the walk would alias the address onto a canonical one.
+    fn check_canonical(&self, addr: u64, len: usize) -> Result<()> {
+        let last = addr.wrapping_add(len.max(1) as u64 - 1);
+        if self.is_long_mode_active() && !(is_canonical(addr) && is_canonical(last)) {
+            return Err(InternalException::GeneralProtection { error_code: 0 });
+        }
+        Ok(())
+    }
+
+    fn translate_page(
+        &mut self,
+        mmio: &mut Interconnect,
+        addr: u64,
+        kind: AccessKind,
+        user: bool,
+    ) -> Result<u64> {
+        let entry = match self.tlb_hit(addr, kind, user) {
+            Some(entry) => entry,
+            None => {
+                let (entry, updates) = self.walk(mmio, addr, kind, user)?;
+                for (entry_addr, value) in updates {
+                    mmio.write_u64(entry_addr, value).unwrap();
+                }
+                self.tlb.insert(addr & !(PAGE_SIZE - 1), entry);
+                entry
+            }
+        };
+        Ok(entry.frame | (addr % PAGE_SIZE))
+    }
+
+    // The first write to a clean page misses, so that the walk sets the dirty flag.
+    fn tlb_hit(&self, addr: u64, kind: AccessKind, user: bool) -> Option<TlbEntry> {
+        self.tlb
+            .get(&(addr & !(PAGE_SIZE - 1)))
+            .filter(|entry| self.is_permitted(entry.flags, kind, user))
+            .filter(|entry| {
+                kind != AccessKind::Write || entry.flags.contains(PageTableFlags::DIRTY)
+            })
+            .cloned()
+    }
+
+    // Walks the paging structures from CR3. Returns the translation and
+    // the entries whose accessed or dirty flags must be set with their new values.
+    fn walk(
+        &self,
+        mmio: &Interconnect,
+        addr: u64,
+        kind: AccessKind,
+        user: bool,
+    ) -> Result<(TlbEntry, Vec<(usize, u64)>)> {
+        let long_mode = self.is_long_mode_active();
+        let (top, mut table) = if long_mode {
+            (PML4_LEVEL, self.cr3 & PHYSICAL_ADDRESS_MASK)
+        } else {
+            (PDPT_LEVEL, self.cr3 & PAE_PDPT_MASK)
+        };
+        let mut granted = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        let mut updates = Vec::new();
+        for level in (0..=top).rev() {
+            let shift = 12 + 9 * level;
+            let entry_addr = (table + (addr >> shift) % ENTRIES_PER_TABLE * 8) as usize;
+            let entry = mmio
+                .read_u64(entry_addr)
+                .expect("Paging structures must be in memory.");
+            let mut flags = PageTableFlags::from_bits_truncate(entry);
+            // PDPT entries of PAE paging have neither access rights nor the accessed flag.
+            if !long_mode && level == top {
+                flags |= PageTableFlags::WRITABLE
+                    | PageTableFlags::USER_ACCESSIBLE
+                    | PageTableFlags::ACCESSED;
+            }
+            if !flags.contains(PageTableFlags::PRESENT) {
+                return Err(page_fault(addr, kind, user, PageFaultErrorCode::empty()));
+            }
+            let reserved = (level == top && flags.contains(PageTableFlags::HUGE_PAGE))
+                || (!self.efer.contains(Efer::NO_EXECUTE_ENABLE)
+                    && flags.contains(PageTableFlags::NO_EXECUTE));
+            if reserved {
+                let error_code =
+                    PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::MALFORMED_TABLE;
+                return Err(page_fault(addr, kind, user, error_code));
+            }
+            granted &= flags | PageTableFlags::NO_EXECUTE;
+            granted |= flags & PageTableFlags::NO_EXECUTE;
+
+            let leaf = level == 0 || flags.contains(PageTableFlags::HUGE_PAGE);
+            if !leaf {
+                if !flags.contains(PageTableFlags::ACCESSED) {
+                    updates.push((entry_addr, entry | PageTableFlags::ACCESSED.bits()));
+                }
+                table = entry & PHYSICAL_ADDRESS_MASK;
+                continue;
+            }
+
+            if !self.is_permitted(granted, kind, user) {
+                let error_code = PageFaultErrorCode::PROTECTION_VIOLATION;
+                return Err(page_fault(addr, kind, user, error_code));
+            }
+            let mut new_entry = entry | PageTableFlags::ACCESSED.bits();
+            if kind == AccessKind::Write {
+                new_entry |= PageTableFlags::DIRTY.bits();
+            }
+            if new_entry != entry {
+                updates.push((entry_addr, new_entry));
+            }
+            let page_mask = (1 << shift) - 1;
+            let frame = (entry & PHYSICAL_ADDRESS_MASK & !page_mask)
+                | (addr & page_mask & !(PAGE_SIZE - 1));
+            let flags = PageTableFlags::from_bits_truncate(new_entry);
+            let tlb_entry = TlbEntry {
+                frame,
+                flags: granted | (flags & (PageTableFlags::DIRTY | PageTableFlags::GLOBAL)),
+            };
+            return Ok((tlb_entry, updates));
+        }
+        unreachable!()
+    }
+
+    // Supervisor writes to read-only pages are allowed unless CR0.WP is set.
+    fn is_permitted(&self, flags: PageTableFlags, kind: AccessKind, user: bool) -> bool {
+        let write_protect = user || self.cr0.contains(Cr0::WRITE_PROTECT);
+        match kind {
+            _ if user && !flags.contains(PageTableFlags::USER_ACCESSIBLE) => false,
+            AccessKind::Read => true,
+            AccessKind::Write => !write_protect || flags.contains(PageTableFlags::WRITABLE),
+            AccessKind::Execute => !flags.contains(PageTableFlags::NO_EXECUTE),
+        }
+    }
+}
+
+fn page_fault(
+    addr: u64,
+    kind: AccessKind,
+    user: bool,
+    error_code: PageFaultErrorCode,
+) -> InternalException {
+    let mut error_code = error_code;
+    error_code.set(
+        PageFaultErrorCode::CAUSED_BY_WRITE,
+        kind == AccessKind::Write,
+    );
+    error_code.set(PageFaultErrorCode::USER_MODE, user);
+    error_code.set(
+        PageFaultErrorCode::INSTRUCTION_FETCH,
+        kind == AccessKind::Execute,
+    );
+    InternalException::PageFault {
+        addr,
+        error_code: error_code.bits(),
+    }
+}
+
+/// Read-only view of the linear address space for the decode stage,
+/// which must not update the CPU state.
+pub struct LinearMemory<'a> {
+    mmu: &'a Mmu,
+    mmio: &'a Interconnect,
+    user: bool,
+}
+
+impl<'a> LinearMemory<'a> {
+    pub fn new(mmu: &'a Mmu, mmio: &'a Interconnect, user: bool) -> LinearMemory<'a> {
+        LinearMemory { mmu, mmio, user }
+    }
+
+    pub fn read(&self, addr: u64, size: OperandSize) -> Result<u64> {
+        let len = (size.bits() / 8) as usize;
+        let ranges = self
+            .mmu
+            .probe(self.mmio, addr, len, AccessKind::Read, self.user)?;
+        Ok(read_physical(self.mmio, &ranges, size))
+    }
+}
+
+/// Reads a value from translated physical ranges.
+/// An access within a page is done at once, so that a device sees its width.
+pub fn read_physical(mmio: &Interconnect, ranges: &[(usize, usize)], size: OperandSize) -> u64 {
+    if let [(addr, _)] = ranges {
+        return match size {
+            OperandSize::Byte => mmio.read_u8(*addr).unwrap().into(),
+            OperandSize::Word => mmio.read_u16(*addr).unwrap().into(),
+            OperandSize::DoubleWord => mmio.read_u32(*addr).unwrap().into(),
+            OperandSize::QuadWord => mmio.read_u64(*addr).unwrap(),
+        };
+    }
+    let bytes = ranges
+        .iter()
+        .flat_map(|(addr, len)| *addr..*addr + *len)
+        .map(|addr| mmio.read_u8(addr).unwrap());
+    bytes
+        .enumerate()
+        .fold(0, |value, (i, byte)| value | u64::from(byte) << (i * 8))
+}
+
+/// Writes a value to translated physical ranges.
+pub fn write_physical(
+    mmio: &mut Interconnect,
+    ranges: &[(usize, usize)],
+    size: OperandSize,
+    data: u64,
+) {
+    if let [(addr, _)] = ranges {
+        match size {
+            OperandSize::Byte => mmio.write_u8(*addr, data as u8).unwrap(),
+            OperandSize::Word => mmio.write_u16(*addr, data as u16).unwrap(),
+            OperandSize::DoubleWord => mmio.write_u32(*addr, data as u32).unwrap(),
+            OperandSize::QuadWord => mmio.write_u64(*addr, data).unwrap(),
+        }
+        return;
+    }
+    let addrs = ranges.iter().flat_map(|(addr, len)| *addr..*addr + *len);
+    for (i, addr) in addrs.enumerate() {
+        mmio.write_u8(addr, (data >> (i * 8)) as u8).unwrap();
+    }
+}
+
+/// Reads all bytes of translated physical ranges.
+pub fn read_physical_bytes(mmio: &Interconnect, ranges: &[(usize, usize)]) -> Vec<u8> {
+    ranges
+        .iter()
+        .flat_map(|(addr, len)| *addr..*addr + *len)
+        .map(|addr| mmio.read_u8(addr).unwrap())
+        .collect()
+}
+
+/// Writes bytes to translated physical ranges.
+pub fn write_physical_bytes(mmio: &mut Interconnect, ranges: &[(usize, usize)], data: &[u8]) {
+    let addrs = ranges.iter().flat_map(|(addr, len)| *addr..*addr + *len);
+    for (addr, byte) in addrs.zip(data) {
+        mmio.write_u8(addr, *byte).unwrap();
+    }
+}
+
+// Splits an access into the parts in each page.
+fn split_at_pages(addr: u64, len: usize) -> Vec<(u64, usize)> {
+    let mut ranges = Vec::new();
+    let (mut addr, mut len) = (addr, len as u64);
+    while len > 0 {
+        let part = len.min(PAGE_SIZE - addr % PAGE_SIZE);
+        ranges.push((addr, part as usize));
+        addr = addr.wrapping_add(part);
+        len -= part;
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use peripherals::memory_access::MemoryAccess;
+    use peripherals::uart16550::{self, Target};
+
+    struct FakeDisplay();
+    impl MemoryAccess for FakeDisplay {
+        fn read_u8(&self, _addr: usize) -> peripherals::memory_access::Result<u8> {
+            unimplemented!()
+        }
+
+        fn write_u8(&mut self, _addr: usize, _data: u8) -> peripherals::memory_access::Result<()> {
+            unimplemented!()
+        }
+    }
+
+    // PML4 at 0x1000, PDPT at 0x2000, PD at 0x3000 and PT at 0x4000.
+    // 0x0 is mapped to 0x5000 by 4KiB page, and 0x20_0000 is a 2MiB page at 0.
+    fn page_tables() -> (Mmu, Interconnect) {
+        let serial = uart16550::uart_factory(Target::Buffer);
+        let mut mmio = Interconnect::new(serial, Box::new(FakeDisplay()));
+        let present_writable = 0x3;
+        mmio.write_u64(0x1000, 0x2000 | present_writable).unwrap();
+        mmio.write_u64(0x2000, 0x3000 | present_writable).unwrap();
+        mmio.write_u64(0x3000, 0x4000 | present_writable).unwrap();
+        mmio.write_u64(0x3008, PageTableFlags::HUGE_PAGE.bits() | 0x1)
+            .unwrap();
+        mmio.write_u64(0x4000, 0x5000 | present_writable).unwrap();
+
+        let mut mmu = Mmu::new();
+        mmu.write_control_register(3, 0x1000);
+        mmu.write_control_register(4, Cr4::PHYSICAL_ADDRESS_EXTENSION.bits());
+        mmu.write_control_register(0, (mmu.cr0 | Cr0::PAGING | Cr0::WRITE_PROTECT).bits());
+        (mmu, mmio)
+    }
+
+    #[test]
+    fn translate_pages() {
+        let (mut mmu, mut mmio) = page_tables();
+        let read = mmu.translate(&mut mmio, 0x10, 8, AccessKind::Read, false);
+        assert_eq!(read.unwrap(), vec![(0x5010, 8)]);
+        let large = mmu.translate(&mut mmio, 0x20_0ffc, 8, AccessKind::Read, false);
+        assert_eq!(large.unwrap(), vec![(0xffc, 4), (0x1000, 4)]);
+
+        // Accessed flags are set on the walk, and the dirty flag on the first write.
+        assert_eq!(mmio.read_u64(0x4000).unwrap(), 0x5000 | 0x23);
+        assert!(mmu
+            .translate(&mut mmio, 0x0, 1, AccessKind::Write, false)
+            .is_ok());
+        assert_eq!(mmio.read_u64(0x4000).unwrap(), 0x5000 | 0x63);
+    }
+
+    #[test]
+    fn page_faults() {
+        let (mut mmu, mut mmio) = page_tables();
+        let not_present = mmu.translate(&mut mmio, 0x1000, 1, AccessKind::Read, false);
+        match not_present {
+            Err(InternalException::PageFault { addr, error_code }) => {
+                assert_eq!((addr, error_code), (0x1000, 0));
+            }
+            _ => panic!("#PF is expected."),
+        }
+        // The 2MiB page is read-only, and user pages are not mapped.
+        let read_only = mmu.translate(&mut mmio, 0x20_0000, 1, AccessKind::Write, false);
+        match read_only {
+            Err(InternalException::PageFault { error_code, .. }) => assert_eq!(error_code, 0x3),
+            _ => panic!("#PF is expected."),
+        }
+        let user = mmu.translate(&mut mmio, 0x0, 1, AccessKind::Execute, true);
+        match user {
+            Err(InternalException::PageFault { error_code, .. }) => assert_eq!(error_code, 0x15),
+            _ => panic!("#PF is expected."),
+        }
+    }
+
+    #[test]
+    fn flush_tlb() {
+        let (mut mmu, mut mmio) = page_tables();
+        assert!(mmu
+            .translate(&mut mmio, 0x0, 1, AccessKind::Read, false)
+            .is_ok());
+        mmio.write_u64(0x4000, 0).unwrap();
+        // The stale translation is used until the page is invalidated.
+        assert!(mmu
+            .translate(&mut mmio, 0x0, 1, AccessKind::Read, false)
+            .is_ok());
+        mmu.invalidate_page(0x0);
+        assert!(mmu
+            .translate(&mut mmio, 0x0, 1, AccessKind::Read, false)
+            .is_err());
+    }
+}
@@ -1,31 +1,139 @@
-use crate::isa::registers::Reg64Id;
+use crate::isa::opcode::OperandSize;
+use crate::isa::registers::{Reg64Id, SegmentRegister};
+use num::FromPrimitive;
 use std::fmt;
 
+const NUM_OF_REGISTERS: usize = 16;
+const NUM_OF_XMM_REGISTERS: usize = 16;
+const NUM_OF_SEGMENT_REGISTERS: usize = 6;
+// All SIMD floating-point exceptions are masked after reset.
+const MXCSR_RESET: u32 = 0x1f80;
+
 #[derive(Debug)]
 pub struct RegisterFile {
     ram: Vec<u64>,
+    // Selectors and bases of ES, CS, SS, DS, FS and GS.
+    selectors: [u16; NUM_OF_SEGMENT_REGISTERS],
+    segment_bases: [u64; NUM_OF_SEGMENT_REGISTERS],
+    xmm: [u128; NUM_OF_XMM_REGISTERS],
+    mxcsr: u32,
 }
 
 impl RegisterFile {
     pub fn new() -> RegisterFile {
-        RegisterFile { ram: vec![0; 8] }
+        RegisterFile {
+            ram: vec![0; NUM_OF_REGISTERS],
+            selectors: [0; NUM_OF_SEGMENT_REGISTERS],
+            segment_bases: [0; NUM_OF_SEGMENT_REGISTERS],
+            xmm: [0; NUM_OF_XMM_REGISTERS],
+            mxcsr: MXCSR_RESET,
+        }
     }
 
     pub fn write64(&mut self, dest: Reg64Id, value: u64) {
-        self.ram[dest as usize] = value;
+        self.write(dest, OperandSize::QuadWord, value);
     }
 
     pub fn read64(&self, src: Reg64Id) -> u64 {
-        self.ram[src as usize]
+        self.read(src, OperandSize::QuadWord)
+    }
+
+    /// Reads the lower `size` bits of the register, or a high byte register.
+    pub fn read(&self, src: Reg64Id, size: OperandSize) -> u64 {
+        match src.high_byte_of() {
+            Some(reg) => (self.ram[reg as usize] >> 8) & 0xff,
+            None => self.ram[src as usize] & size.mask(),
+        }
+    }
+
+    /// 32-bit writes clear the upper half of the register,
+    /// while 8-bit and 16-bit writes preserve the other bits.
+    pub fn write(&mut self, dest: Reg64Id, size: OperandSize, value: u64) {
+        if let Some(reg) = dest.high_byte_of() {
+            let entry = &mut self.ram[reg as usize];
+            *entry = (*entry & !0xff00) | ((value & 0xff) << 8);
+            return;
+        }
+        let entry = &mut self.ram[dest as usize];
+        *entry = match size {
+            OperandSize::Byte | OperandSize::Word => {
+                (*entry & !size.mask()) | (value & size.mask())
+            }
+            OperandSize::DoubleWord | OperandSize::QuadWord => value & size.mask(),
+        };
+    }
+
+    pub fn selector(&self, segment: SegmentRegister) -> u16 {
+        self.selectors[segment as usize]
+    }
+
+    /// Base address cached when the segment register was loaded.
+    /// Only FS and GS bases are used in 64-bit mode, where other segments are flat.
+    pub fn segment_base(&self, segment: SegmentRegister) -> u64 {
+        self.segment_bases[segment as usize]
+    }
+
+    /// Loads the selector and the base of its descriptor into the segment register.
+    pub fn load_segment(&mut self, segment: SegmentRegister, selector: u16, base: u64) {
+        self.selectors[segment as usize] = selector;
+        self.segment_bases[segment as usize] = base;
+    }
+
+    /// Changes the selector only, e.g., on interrupts in 64-bit mode.
+    pub fn write_selector(&mut self, segment: SegmentRegister, selector: u16) {
+        self.selectors[segment as usize] = selector;
+    }
+
+    pub fn read_xmm(&self, src: u8) -> u128 {
+        self.xmm[usize::from(src)]
+    }
+
+    pub fn write_xmm(&mut self, dest: u8, value: u128) {
+        self.xmm[usize::from(dest)] = value;
+    }
+
+    /// MXCSR holds the rounding control, exception masks and exception flags of SSE.
+    pub fn mxcsr(&self) -> u32 {
+        self.mxcsr
+    }
+
+    pub fn write_mxcsr(&mut self, value: u32) {
+        self.mxcsr = value;
+    }
+
+    pub fn write_segment_base(&mut self, segment: SegmentRegister, base: u64) {
+        self.segment_bases[segment as usize] = base;
     }
 }
 
 impl fmt::Display for RegisterFile {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-      f,
-      "  rax: 0x{:>08X}\n  rcx: 0x{:>08X}\n  rdx: 0x{:>08X}\n  rbx: 0x{:>08X}\n  rsp: 0x{:>08X}\n  rbp: 0x{:>08X}\n  rsi: 0x{:>08X}\n  rdi: 0x{:>08X}",
-      self.ram[0], self.ram[1], self.ram[2], self.ram[3], self.ram[4], self.ram[5], self.ram[6], self.ram[7]
-    )
+        for (i, value) in self.ram.iter().enumerate() {
+            let name = format!("{:?}", Reg64Id::from_usize(i).unwrap()).to_lowercase();
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "  {:>3}: 0x{:>08X}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::registers::Reg64Id::*;
+
+    #[test]
+    fn partial_register_write() {
+        let mut rf = RegisterFile::new();
+        rf.write64(Rax, 0xffff_ffff_ffff_ffff);
+        rf.write(Rax, OperandSize::Byte, 0x12);
+        rf.write(Ah, OperandSize::Byte, 0x34);
+        assert_eq!(rf.read64(Rax), 0xffff_ffff_ffff_3412);
+        rf.write(Rax, OperandSize::Word, 0x5678);
+        assert_eq!(rf.read(Ah, OperandSize::Byte), 0x56);
+        rf.write(Rax, OperandSize::DoubleWord, 0x9abc_def0);
+        assert_eq!(rf.read64(Rax), 0x9abc_def0);
     }
 }
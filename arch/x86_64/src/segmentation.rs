@@ -0,0 +1,111 @@
+//! Segmentation of real mode, protected mode and long mode.
+//! A segment register caches the base of the descriptor loaded from the GDT,
+//! and the code segment determines the default operand and address sizes.
+//! Segment limits are not checked.
+use crate::isa::opcode::OperandSize;
+use bit_field::BitField;
+
+/// Default operand and address sizes of the code segment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CodeSize {
+    /// Real mode, or a 16-bit code segment.
+    Bits16,
+    /// A 32-bit code segment of protected mode or compatibility mode.
+    Bits32,
+    /// A 64-bit code segment of long mode.
+    Bits64,
+}
+
+impl CodeSize {
+    /// The default operand size, which is 32-bit in 64-bit mode.
+    pub fn operand_size(self) -> OperandSize {
+        match self {
+            CodeSize::Bits16 => OperandSize::Word,
+            _ => OperandSize::DoubleWord,
+        }
+    }
+
+    /// The default address size. The stack pointer is also of this size,
+    /// as the B flag of SS is assumed to match the D flag of CS.
+    pub fn address_size(self) -> OperandSize {
+        match self {
+            CodeSize::Bits16 => OperandSize::Word,
+            CodeSize::Bits32 => OperandSize::DoubleWord,
+            CodeSize::Bits64 => OperandSize::QuadWord,
+        }
+    }
+}
+
+/// A code or data segment descriptor in the GDT.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SegmentDescriptor {
+    pub base: u64,
+    pub dpl: u8,
+    pub present: bool,
+    pub is_code: bool,
+    /// Readable for code segments, and writable for data segments.
+    pub is_accessible: bool,
+    /// L flag, which makes a 64-bit code segment in long mode.
+    pub long: bool,
+    /// D flag, which makes a 32-bit code segment.
+    pub default_32bit: bool,
+    is_system: bool,
+}
+
+impl SegmentDescriptor {
+    pub fn new(descriptor: u64) -> SegmentDescriptor {
+        SegmentDescriptor {
+            base: descriptor.get_bits(16..40) | descriptor.get_bits(56..64) << 24,
+            dpl: descriptor.get_bits(45..47) as u8,
+            present: descriptor.get_bit(47),
+            is_code: descriptor.get_bit(43),
+            is_accessible: descriptor.get_bit(41),
+            long: descriptor.get_bit(53),
+            default_32bit: descriptor.get_bit(54),
+            is_system: !descriptor.get_bit(44),
+        }
+    }
+
+    /// SS must be a writable data segment.
+    pub fn is_valid_stack(&self) -> bool {
+        !self.is_system && !self.is_code && self.is_accessible
+    }
+
+    /// DS, ES, FS and GS may be data segments or readable code segments.
+    pub fn is_valid_data(&self) -> bool {
+        !self.is_system && (!self.is_code || self.is_accessible)
+    }
+
+    pub fn is_valid_code(&self) -> bool {
+        !self.is_system && self.is_code
+    }
+
+    /// The L flag is only effective while long mode is active.
+    pub fn code_size(&self, long_mode_active: bool) -> CodeSize {
+        if long_mode_active && self.long {
+            CodeSize::Bits64
+        } else if self.default_32bit {
+            CodeSize::Bits32
+        } else {
+            CodeSize::Bits16
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn segment_descriptors() {
+        let code32 = SegmentDescriptor::new(0x00cf_9a00_0000_ffff);
+        assert!(code32.is_valid_code() && code32.present);
+        assert_eq!(code32.code_size(true), CodeSize::Bits32);
+        let code64 = SegmentDescriptor::new(0x00af_9a00_0000_ffff);
+        assert_eq!(code64.code_size(true), CodeSize::Bits64);
+        assert_eq!(code64.code_size(false), CodeSize::Bits16);
+        let data = SegmentDescriptor::new(0x12cf_9234_5678_ffff);
+        assert!(data.is_valid_stack() && data.is_valid_data() && !data.is_valid_code());
+        assert_eq!(data.base, 0x1234_5678);
+    }
+}
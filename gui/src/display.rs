@@ -169,7 +169,7 @@ fn create_text_grid() -> Grid {
     screen
 }
 
-pub fn start_with_gtk(start_emulation: fn(GtkVgaTextBuffer)) {
+pub fn start_with_gtk<F: Fn(GtkVgaTextBuffer) + 'static>(start_emulation: F) {
     match gtk::Application::new(
         "com.github.tomoyuki-nakabayashi.Rustemu86",
         gio::APPLICATION_HANDLES_OPEN,
@@ -10,22 +10,25 @@ const SIZE_ELF32_HEADER: usize = 52;
 const SIZE_ELF64_HEADER: usize = 64;
 
 const ELF_CLASS_32: u8 = 1;
+const ELF_CLASS_64: u8 = 2;
 const ELF_DATA_LSB: u8 = 1;
 const EIV_CURRENT: u8 = 1;
 const ELF_OS_ABI_NONE: u8 = 0;
 
 const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const EM_X86_64: u16 = 62;
 const EM_RISCV: u16 = 243;
 const EV_CURRENT: u32 = 1;
 
 const PT_LOAD: u32 = 1;
+const PT_INTERP: u32 = 3;
 // flags in program header indicate access permission for the segment.
 const PF_X: u32 = 0x1;
 const PF_W: u32 = 0x2;
 const PF_R: u32 = 0x4;
 
 /// ELF header parsing first 52/64 bytes for 32/64 bits binary.
-/// Currently, support only 32-bit binary.
 #[derive(Debug)]
 pub struct ElfHeader {
     identification: ElfIdentification,
@@ -50,6 +53,9 @@ impl ElfHeader {
         if binary.len() < SIZE_ELF32_HEADER {
             return Err(LoaderError::TooShortBinary {});
         }
+        if binary[4] == ELF_CLASS_64 {
+            return ElfHeader::try_new64(binary);
+        }
         // unwrap the result of read_u* because the binary has enough length to read
         // and the operations never fail.
         Ok(ElfHeader {
@@ -70,10 +76,72 @@ impl ElfHeader {
         })
     }
 
+    // ELF64 header has 64-bit entry point and offsets.
+    fn try_new64(binary: &[u8]) -> Result<ElfHeader> {
+        if binary.len() < SIZE_ELF64_HEADER {
+            return Err(LoaderError::TooShortBinary {});
+        }
+        Ok(ElfHeader {
+            identification: ElfIdentification::new(&binary[0..=15]),
+            elf_type: read_u16(&binary[16..=17]).unwrap(),
+            elf_machine: read_u16(&binary[18..=19]).unwrap(),
+            elf_version: read_u32(&binary[20..=23]).unwrap(),
+            elf_entry: read_u64(&binary[24..=31]).unwrap() as usize,
+            pheader_offset: read_u64(&binary[32..=39]).unwrap() as usize,
+            elf_section_header_offset: read_u64(&binary[40..=47]).unwrap() as usize,
+            elf_flag: read_u32(&binary[48..=51]).unwrap(),
+            elf_header_size: read_u16(&binary[52..=53]).unwrap(),
+            pheader_entry_size: read_u16(&binary[54..=55]).unwrap(),
+            pheader_num: read_u16(&binary[56..=57]).unwrap(),
+            elf_section_header_entry_size: read_u16(&binary[58..=59]).unwrap(),
+            elf_section_header_num: read_u16(&binary[60..=61]).unwrap(),
+            elf_section_header_table_index: read_u16(&binary[62..=63]).unwrap(),
+        })
+    }
+
     /// Check the elf magic.
     pub fn is_elf(&self) -> bool {
         self.identification.magic == HEADER_MAGIC
     }
+
+    /// Check the binary is ELF64.
+    pub fn is_elf64(&self) -> bool {
+        self.identification.class == ELF_CLASS_64
+    }
+
+    /// Check the binary is an executable for x86_64.
+    /// Position independent executables are also executable.
+    pub fn is_x86_64_executable(&self) -> bool {
+        self.is_elf64()
+            && self.elf_machine == EM_X86_64
+            && (self.elf_type == ET_EXEC || self.elf_type == ET_DYN)
+    }
+
+    pub fn is_position_independent(&self) -> bool {
+        self.elf_type == ET_DYN
+    }
+
+    pub fn entry_point(&self) -> usize {
+        self.elf_entry
+    }
+
+    pub fn pheader_offset(&self) -> usize {
+        self.pheader_offset
+    }
+
+    pub fn pheader_entry_size(&self) -> usize {
+        self.pheader_entry_size as usize
+    }
+
+    pub fn pheader_num(&self) -> usize {
+        self.pheader_num as usize
+    }
+}
+
+// Reads u64 from the head of given byte array.
+#[inline(always)]
+fn read_u64(binary: &[u8]) -> std::io::Result<u64> {
+    (&binary[0..=7]).read_u64::<LittleEndian>()
 }
 
 // Reads u32 from the head of given byte array.
@@ -122,13 +190,14 @@ impl ElfIdentification {
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct ProgramHeader {
     program_type: u32,
-    offset: usize, // 4-byte
-    vaddr: usize,  // 4-byte
-    pub paddr: usize,  // 4-byte
-    file_size: u32,
-    pub mem_size: u32,
+    // Address and size fields are 4-byte in ELF32, and 8-byte in ELF64.
+    offset: usize,
+    pub vaddr: usize,
+    pub paddr: usize,
+    pub file_size: usize,
+    pub mem_size: usize,
     flags: u32,
-    pub align: u32,
+    pub align: usize,
 }
 
 impl ProgramHeader {
@@ -141,10 +210,24 @@ impl ProgramHeader {
             offset: read_u32(&start[4..=7]).unwrap() as usize,
             vaddr: read_u32(&start[8..=11]).unwrap() as usize,
             paddr: read_u32(&start[12..=15]).unwrap() as usize,
-            file_size: read_u32(&start[16..=19]).unwrap(),
-            mem_size: read_u32(&start[20..=23]).unwrap(),
+            file_size: read_u32(&start[16..=19]).unwrap() as usize,
+            mem_size: read_u32(&start[20..=23]).unwrap() as usize,
             flags: read_u32(&start[24..=27]).unwrap(),
-            align: read_u32(&start[28..=31]).unwrap(),
+            align: read_u32(&start[28..=31]).unwrap() as usize,
+        }
+    }
+
+    // ELF64 program header has flags next to type, and 8-byte fields.
+    fn new64(start: &[u8]) -> ProgramHeader {
+        ProgramHeader {
+            program_type: read_u32(&start[0..=3]).unwrap(),
+            flags: read_u32(&start[4..=7]).unwrap(),
+            offset: read_u64(&start[8..=15]).unwrap() as usize,
+            vaddr: read_u64(&start[16..=23]).unwrap() as usize,
+            paddr: read_u64(&start[24..=31]).unwrap() as usize,
+            file_size: read_u64(&start[32..=39]).unwrap() as usize,
+            mem_size: read_u64(&start[40..=47]).unwrap() as usize,
+            align: read_u64(&start[48..=55]).unwrap() as usize,
         }
     }
 
@@ -156,24 +239,45 @@ impl ProgramHeader {
         let mut pheaders: Vec<ProgramHeader> = Vec::new();
         for i in 0..header.pheader_num {
             let offset = header.pheader_offset + (header.pheader_entry_size * i) as usize;
-            let header = ProgramHeader::new(&binary[offset..]);
-            pheaders.push(header);
+            let pheader = if header.is_elf64() {
+                ProgramHeader::new64(&binary[offset..])
+            } else {
+                ProgramHeader::new(&binary[offset..])
+            };
+            pheaders.push(pheader);
         }
 
         pheaders
     }
 
+    /// Check the segment is loaded into memory.
+    pub fn is_loadable(&self) -> bool {
+        self.program_type == PT_LOAD
+    }
+
+    /// Check the segment requests a program interpreter, i.e., dynamic linking.
+    pub fn is_interpreter(&self) -> bool {
+        self.program_type == PT_INTERP
+    }
+
     /// Returns the offsets indicating segment range in the elf binary.
     /// - return: (begin, end)
     pub fn segment_offset_range(&self) -> (usize, usize) {
         let begin = self.offset;
-        let end = begin + (self.mem_size as usize);
+        let end = begin + self.mem_size;
         (begin, end)
     }
 
+    /// Returns the offsets indicating the file image of the segment.
+    /// The rest of the segment up to `mem_size` is zero-filled.
+    /// - return: (begin, end)
+    pub fn file_offset_range(&self) -> (usize, usize) {
+        (self.offset, self.offset + self.file_size)
+    }
+
     pub fn paddr_range(&self) -> (usize, usize) {
         let begin = self.paddr;
-        let end = begin + (self.mem_size as usize);
+        let end = begin + self.mem_size;
         (begin, end)
     }
 }
@@ -279,6 +383,39 @@ mod test {
         assert_eq!(inst.unwrap(), 0x04c0_006f);
     }
 
+    // ELF64 header of the static x86_64 executable:
+    //   Class:                             ELF64
+    //   Type:                              EXEC (Executable file)
+    //   Machine:                           Advanced Micro Devices X86-64
+    //   Entry point address:               0x401000
+    //   Start of program headers:          64 (bytes into file)
+    //   Size of program headers:           56 (bytes)
+    //   Number of program headers:         3
+    //   LOAD           0x0000000000001000 0x0000000000401000 0x0000000000401000
+    //                  0x0000000000000055 0x0000000000000055  R E    0x1000
+    #[test]
+    fn elf64_header() {
+        let file = File::open("tests/data/elf/hello-x86_64").unwrap();
+        let mapped_file = unsafe { Mmap::map(&file).unwrap() };
+        let header = ElfHeader::try_new(&mapped_file).unwrap();
+
+        assert!(header.is_elf64());
+        assert!(header.is_x86_64_executable());
+        assert_eq!(header.elf_type, ET_EXEC);
+        assert_eq!(header.entry_point(), 0x40_1000);
+        assert_eq!(header.pheader_offset, 64);
+        assert_eq!(header.pheader_entry_size, 56);
+        assert_eq!(header.pheader_num, 3);
+
+        let pheaders = ProgramHeader::extract_pheaders(&mapped_file, &header);
+        let text = &pheaders[1];
+        assert!(text.is_loadable());
+        assert_eq!(text.offset, 0x1000);
+        assert_eq!(text.vaddr, 0x40_1000);
+        assert_eq!(text.file_size, 0x55);
+        assert_eq!(text.flags, PF_R | PF_X);
+    }
+
     #[test]
     fn program_headers() {
         let file = File::open("tests/data/elf/rv32ui-p-simple").unwrap();
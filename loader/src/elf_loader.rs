@@ -52,6 +52,60 @@ impl ElfLoader {
 
         memory_image
     }
+
+    /// Returns loadable segments to be placed at their virtual addresses.
+    /// Unlike memory_image(), the binary of each segment is only the file image.
+    /// The rest of the segment up to `size()` must be zero-filled.
+    pub fn load_segments(&self) -> Vec<MemoryLayout> {
+        self.pheaders
+            .iter()
+            .filter(|pheader| pheader.is_loadable())
+            .map(|pheader| {
+                let (begin, end) = pheader.file_offset_range();
+                MemoryLayout {
+                    binary: self.mapped_file[begin..end].to_vec(),
+                    pheader: pheader.clone(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn entry_point(&self) -> usize {
+        self.header.entry_point()
+    }
+
+    /// Check the binary is a statically linked x86_64 executable,
+    /// which does not request a program interpreter.
+    pub fn is_static_x86_64_executable(&self) -> bool {
+        self.header.is_x86_64_executable()
+            && !self.pheaders.iter().any(|pheader| pheader.is_interpreter())
+    }
+
+    /// Position independent executables can be loaded at any address.
+    pub fn is_position_independent(&self) -> bool {
+        self.header.is_position_independent()
+    }
+
+    /// Returns the virtual address of the program headers in the loaded segments,
+    /// the size of an entry and the number of entries.
+    pub fn program_headers(&self) -> Option<(usize, usize, usize)> {
+        let offset = self.header.pheader_offset();
+        self.pheaders
+            .iter()
+            .filter(|pheader| pheader.is_loadable())
+            .find(|pheader| {
+                let (begin, end) = pheader.file_offset_range();
+                begin <= offset && offset < end
+            })
+            .map(|pheader| {
+                let (begin, _) = pheader.file_offset_range();
+                (
+                    pheader.vaddr + offset - begin,
+                    self.header.pheader_entry_size(),
+                    self.header.pheader_num(),
+                )
+            })
+    }
 }
 
 pub struct MemoryLayout {
@@ -68,6 +122,10 @@ impl<'a> MemoryLayout {
         self.pheader.paddr
     }
 
+    pub fn virtual_addr(&self) -> usize {
+        self.pheader.vaddr
+    }
+
     pub fn size(&self) -> usize {
         self.pheader.mem_size as usize
     }
@@ -97,6 +155,23 @@ mod test {
         assert_eq!(first_inst, 0x04c0_006f);
     }
 
+    #[test]
+    fn load_elf64() {
+        let loader = ElfLoader::try_new("tests/data/elf/hello-x86_64").unwrap();
+        assert!(loader.is_static_x86_64_executable());
+        assert!(!loader.is_position_independent());
+        assert_eq!(loader.entry_point(), 0x40_1000);
+        assert_eq!(loader.program_headers(), Some((0x40_0040, 56, 3)));
+
+        let segments = loader.load_segments();
+        assert_eq!(segments.len(), 3);
+        let text = &segments[1];
+        assert_eq!(text.virtual_addr(), 0x40_1000);
+        assert_eq!(text.binary_as_ref().len(), text.size());
+        // mov rbx, rsp
+        assert_eq!(&text.binary_as_ref()[0..3], &[0x48, 0x89, 0xe3]);
+    }
+
     #[test]
     fn load_non_elf_binary() {
         let loader = ElfLoader::try_new("tests/data/non-elf-binary");
@@ -14,6 +14,18 @@ pub enum LoaderError {
 
     #[fail(display = "lack of enough length data!")]
     TooShortBinary,
+
+    #[fail(display = "multiboot header is not found!")]
+    NoMultibootHeader,
+
+    #[fail(display = "invalid multiboot header!")]
+    InvalidMultibootHeader,
+
+    #[fail(display = "multiboot feature {:#x} is not supported!", feature)]
+    UnsupportedMultibootFeature { feature: u32 },
+
+    #[fail(display = "boot image does not fit in the memory!")]
+    OutOfMemory,
 }
 
 impl From<std::io::Error> for LoaderError {
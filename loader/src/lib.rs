@@ -6,6 +6,7 @@ extern crate failure;
 mod elf;
 pub mod elf_loader;
 pub mod error;
+pub mod multiboot;
 
 use std::fs::File;
 use std::io::BufReader;
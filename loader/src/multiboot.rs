@@ -0,0 +1,835 @@
+//! Multiboot and Multiboot2 kernels.
+//! A kernel is loaded from its ELF segments, or from the address fields in its header,
+//! which is known as the a.out kludge.
+//! Modules and the boot information are placed in pages after the kernel.
+use crate::elf::{ElfHeader, ProgramHeader};
+use crate::error::{LoaderError, Result};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// EAX holds this when a Multiboot kernel starts.
+pub const MULTIBOOT_BOOTLOADER_MAGIC: u32 = 0x2bad_b002;
+/// EAX holds this when a Multiboot2 kernel starts.
+pub const MULTIBOOT2_BOOTLOADER_MAGIC: u32 = 0x36d7_6289;
+
+const MULTIBOOT_HEADER_MAGIC: u32 = 0x1bad_b002;
+const MULTIBOOT2_HEADER_MAGIC: u32 = 0xe852_50d6;
+// Headers must be completely contained within these bytes from the beginning.
+const MULTIBOOT_SEARCH: usize = 0x2000;
+const MULTIBOOT2_SEARCH: usize = 0x8000;
+
+// Flags in the Multiboot header. The lower 16 bits are required features.
+const HEADER_PAGE_ALIGN: u32 = 1 << 0;
+const HEADER_MEMORY_INFO: u32 = 1 << 1;
+const HEADER_VIDEO_MODE: u32 = 1 << 2;
+const HEADER_ADDRESS: u32 = 1 << 16;
+const HEADER_REQUIRED: u32 = 0xffff;
+
+// Flags in the Multiboot information, which tell the valid fields.
+const INFO_MEMORY: u32 = 1 << 0;
+const INFO_CMDLINE: u32 = 1 << 2;
+const INFO_MODS: u32 = 1 << 3;
+const INFO_MEMORY_MAP: u32 = 1 << 6;
+const INFO_BOOT_LOADER_NAME: u32 = 1 << 9;
+const INFO_FRAMEBUFFER: u32 = 1 << 12;
+const SIZE_MULTIBOOT_INFO: usize = 116;
+
+// Tags in the Multiboot2 header.
+const TAG_END: u16 = 0;
+const TAG_INFORMATION_REQUEST: u16 = 1;
+const TAG_ADDRESS: u16 = 2;
+const TAG_ENTRY_ADDRESS: u16 = 3;
+const TAG_CONSOLE_FLAGS: u16 = 4;
+const TAG_FRAMEBUFFER: u16 = 5;
+const TAG_MODULE_ALIGN: u16 = 6;
+const TAG_RELOCATABLE: u16 = 10;
+const TAG_OPTIONAL: u16 = 1;
+
+// Tags in the Multiboot2 information.
+const INFO_TAG_END: u32 = 0;
+const INFO_TAG_CMDLINE: u32 = 1;
+const INFO_TAG_BOOT_LOADER_NAME: u32 = 2;
+const INFO_TAG_MODULE: u32 = 3;
+const INFO_TAG_BASIC_MEMINFO: u32 = 4;
+const INFO_TAG_MEMORY_MAP: u32 = 6;
+const INFO_TAG_FRAMEBUFFER: u32 = 8;
+
+const MEMORY_AVAILABLE: u32 = 1;
+const MEMORY_RESERVED: u32 = 2;
+const EXTENDED_MEMORY: usize = 0x10_0000;
+const PAGE_SIZE: usize = 0x1000;
+const BOOT_LOADER_NAME: &str = "rustemu86";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Version {
+    Multiboot,
+    Multiboot2,
+}
+
+/// The memory and the files passed to a kernel.
+pub struct BootConfig {
+    /// Bytes of the memory from address 0.
+    pub lower_memory: usize,
+    /// Bytes of the memory from 1 MiB.
+    pub upper_memory: usize,
+    pub cmdline: String,
+    pub modules: Vec<Module>,
+    pub framebuffer: Option<Framebuffer>,
+}
+
+pub struct Module {
+    pub binary: Vec<u8>,
+    /// Conventionally starts with the path of the module.
+    pub cmdline: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Framebuffer {
+    pub addr: u64,
+    /// Bytes of a line.
+    pub pitch: u32,
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u8,
+    pub kind: FramebufferType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FramebufferType {
+    /// Direct color with the field position and the mask size of red, green and blue.
+    Rgb([(u8, u8); 3]),
+    /// EGA text, whose width and height are in characters.
+    EgaText,
+}
+
+/// A region placed at `start_addr()`.
+/// The rest of the region up to `size()` must be zero-filled.
+#[derive(Debug)]
+pub struct Segment {
+    addr: usize,
+    binary: Vec<u8>,
+    size: usize,
+}
+
+impl<'a> Segment {
+    pub fn binary_as_ref(&'a self) -> &'a Vec<u8> {
+        &self.binary
+    }
+
+    pub fn start_addr(&self) -> usize {
+        self.addr
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn end_addr(&self) -> usize {
+        self.addr + self.size
+    }
+}
+
+/// A kernel image which has a Multiboot or Multiboot2 header.
+#[derive(Debug)]
+pub struct MultibootKernel {
+    version: Version,
+    entry: usize,
+    segments: Vec<Segment>,
+    requires_framebuffer: bool,
+}
+
+// Fields of the header used to load the kernel.
+#[derive(Default)]
+struct Header {
+    offset: usize,
+    address: Option<AddressFields>,
+    entry: Option<usize>,
+    requires_framebuffer: bool,
+}
+
+// Load addresses of the a.out kludge.
+struct AddressFields {
+    header_addr: u32,
+    load_addr: u32,
+    load_end_addr: u32,
+    bss_end_addr: u32,
+}
+
+impl MultibootKernel {
+    /// Multiboot2 takes priority if both headers exist.
+    /// This method returns error when
+    /// - no valid header is found
+    /// - the header requires unsupported features
+    /// - segments are out of the image
+    pub fn try_new(image: &[u8]) -> Result<MultibootKernel> {
+        let (version, header) = match find_multiboot2_header(image)? {
+            Some(header) => (Version::Multiboot2, header),
+            None => match find_multiboot_header(image)? {
+                Some(header) => (Version::Multiboot, header),
+                None => return Err(LoaderError::NoMultibootHeader),
+            },
+        };
+        let (segments, entry) = match (&header.address, header.entry) {
+            (Some(address), Some(entry)) => {
+                (load_aout_kludge(image, header.offset, address)?, entry)
+            }
+            (Some(_), None) => return Err(LoaderError::InvalidMultibootHeader),
+            (None, entry) => {
+                let (segments, elf_entry) = load_elf(image)?;
+                (segments, entry.unwrap_or(elf_entry))
+            }
+        };
+
+        Ok(MultibootKernel {
+            version,
+            entry,
+            segments,
+            requires_framebuffer: header.requires_framebuffer,
+        })
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn entry_point(&self) -> usize {
+        self.entry
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Places modules and the boot information after the kernel.
+    /// All of them must be in the memory described by `config`.
+    pub fn boot(self, config: &BootConfig) -> Result<BootImage> {
+        if self.requires_framebuffer && config.framebuffer.is_none() {
+            return Err(LoaderError::UnsupportedMultibootFeature {
+                feature: u32::from(TAG_FRAMEBUFFER),
+            });
+        }
+        let mut segments = self.segments;
+        let kernel_end = segments.iter().map(Segment::end_addr).max().unwrap_or(0);
+        let mut next_page = align_up(kernel_end.max(EXTENDED_MEMORY), PAGE_SIZE);
+        let mut modules = Vec::new();
+        for module in &config.modules {
+            modules.push((next_page, next_page + module.binary.len()));
+            segments.push(Segment {
+                addr: next_page,
+                binary: module.binary.clone(),
+                size: module.binary.len(),
+            });
+            next_page = align_up(next_page + module.binary.len(), PAGE_SIZE);
+        }
+        let info = match self.version {
+            Version::Multiboot => multiboot_info(next_page, config, &modules),
+            Version::Multiboot2 => multiboot2_info(next_page, config, &modules),
+        };
+        segments.push(Segment {
+            addr: next_page,
+            size: info.len(),
+            binary: info,
+        });
+
+        let in_memory = |segment: &Segment| {
+            segment.end_addr() <= config.lower_memory
+                || segment.start_addr() >= EXTENDED_MEMORY
+                    && segment.end_addr() <= EXTENDED_MEMORY + config.upper_memory
+        };
+        if !segments.iter().all(in_memory) {
+            return Err(LoaderError::OutOfMemory);
+        }
+        let magic = match self.version {
+            Version::Multiboot => MULTIBOOT_BOOTLOADER_MAGIC,
+            Version::Multiboot2 => MULTIBOOT2_BOOTLOADER_MAGIC,
+        };
+
+        Ok(BootImage {
+            magic,
+            entry: self.entry,
+            info_addr: next_page,
+            segments,
+        })
+    }
+}
+
+/// A kernel ready to start in 32-bit protected mode
+/// with EAX = `magic()` and EBX = `info_addr()`.
+#[derive(Debug)]
+pub struct BootImage {
+    magic: u32,
+    entry: usize,
+    info_addr: usize,
+    segments: Vec<Segment>,
+}
+
+impl BootImage {
+    pub fn magic(&self) -> u32 {
+        self.magic
+    }
+
+    pub fn entry_point(&self) -> usize {
+        self.entry
+    }
+
+    pub fn info_addr(&self) -> usize {
+        self.info_addr
+    }
+
+    /// Segments of the kernel, modules and the boot information in this order.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+}
+
+// The header is 4-byte aligned, and the sum of magic, flags and checksum is zero.
+fn find_multiboot_header(image: &[u8]) -> Result<Option<Header>> {
+    let offset = (0..image.len().min(MULTIBOOT_SEARCH).saturating_sub(11))
+        .step_by(4)
+        .find(|&offset| {
+            let fields = &image[offset..offset + 12];
+            let magic = LittleEndian::read_u32(&fields[0..4]);
+            let sum = (0..3).fold(0u32, |sum, i| {
+                sum.wrapping_add(LittleEndian::read_u32(&fields[i * 4..]))
+            });
+            magic == MULTIBOOT_HEADER_MAGIC && sum == 0
+        });
+    let offset = match offset {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+
+    let flags = LittleEndian::read_u32(&image[offset + 4..]);
+    let supported = HEADER_PAGE_ALIGN | HEADER_MEMORY_INFO | HEADER_VIDEO_MODE;
+    let unsupported = flags & HEADER_REQUIRED & !supported;
+    if unsupported != 0 {
+        return Err(LoaderError::UnsupportedMultibootFeature {
+            feature: unsupported,
+        });
+    }
+    let mut header = Header {
+        offset,
+        ..Header::default()
+    };
+    if flags & HEADER_ADDRESS != 0 {
+        let fields = header_fields(image, offset + 12, 5)?;
+        header.address = Some(AddressFields {
+            header_addr: fields[0],
+            load_addr: fields[1],
+            load_end_addr: fields[2],
+            bss_end_addr: fields[3],
+        });
+        header.entry = Some(fields[4] as usize);
+    }
+    Ok(Some(header))
+}
+
+// The header is 8-byte aligned, and followed by 8-byte aligned tags.
+fn find_multiboot2_header(image: &[u8]) -> Result<Option<Header>> {
+    let offset = (0..image.len().min(MULTIBOOT2_SEARCH).saturating_sub(15))
+        .step_by(8)
+        .find(|&offset| {
+            let fields = &image[offset..offset + 16];
+            let magic = LittleEndian::read_u32(&fields[0..4]);
+            let sum = (0..4).fold(0u32, |sum, i| {
+                sum.wrapping_add(LittleEndian::read_u32(&fields[i * 4..]))
+            });
+            magic == MULTIBOOT2_HEADER_MAGIC && sum == 0
+        });
+    let offset = match offset {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+
+    let fields = header_fields(image, offset + 4, 2)?;
+    let (architecture, header_length) = (fields[0], fields[1] as usize);
+    // Only i386 protected mode is supported.
+    if architecture != 0 {
+        return Err(LoaderError::UnsupportedMultibootFeature {
+            feature: architecture,
+        });
+    }
+    let header_end = offset + header_length;
+    if header_end > image.len() {
+        return Err(LoaderError::TooShortBinary);
+    }
+
+    let mut header = Header {
+        offset,
+        ..Header::default()
+    };
+    let mut tag = offset + 16;
+    while tag + 8 <= header_end {
+        let tag_type = LittleEndian::read_u16(&image[tag..]);
+        let optional = LittleEndian::read_u16(&image[tag + 2..]) & TAG_OPTIONAL != 0;
+        let size = LittleEndian::read_u32(&image[tag + 4..]) as usize;
+        if size < 8 || tag + size > header_end {
+            return Err(LoaderError::InvalidMultibootHeader);
+        }
+        match tag_type {
+            TAG_END => break,
+            TAG_INFORMATION_REQUEST if !optional => {
+                let requests = header_fields(image, tag + 8, (size - 8) / 4)?;
+                let supported = [
+                    INFO_TAG_CMDLINE,
+                    INFO_TAG_BOOT_LOADER_NAME,
+                    INFO_TAG_MODULE,
+                    INFO_TAG_BASIC_MEMINFO,
+                    INFO_TAG_MEMORY_MAP,
+                    INFO_TAG_FRAMEBUFFER,
+                ];
+                if let Some(&request) = requests.iter().find(|r| !supported.contains(r)) {
+                    return Err(LoaderError::UnsupportedMultibootFeature { feature: request });
+                }
+            }
+            TAG_ADDRESS => {
+                let fields = header_fields(image, tag + 8, 4)?;
+                header.address = Some(AddressFields {
+                    header_addr: fields[0],
+                    load_addr: fields[1],
+                    load_end_addr: fields[2],
+                    bss_end_addr: fields[3],
+                });
+            }
+            TAG_ENTRY_ADDRESS => header.entry = Some(header_fields(image, tag + 8, 1)?[0] as usize),
+            TAG_FRAMEBUFFER => header.requires_framebuffer = !optional,
+            // Modules are always page aligned, and the kernel is never relocated.
+            TAG_INFORMATION_REQUEST | TAG_CONSOLE_FLAGS | TAG_MODULE_ALIGN | TAG_RELOCATABLE => (),
+            _ if optional => (),
+            _ => {
+                return Err(LoaderError::UnsupportedMultibootFeature {
+                    feature: u32::from(tag_type),
+                })
+            }
+        }
+        tag += align_up(size, 8);
+    }
+    Ok(Some(header))
+}
+
+// Reads `num` of 32-bit fields from `offset`.
+fn header_fields(image: &[u8], offset: usize, num: usize) -> Result<Vec<u32>> {
+    if offset + num * 4 > image.len() {
+        return Err(LoaderError::TooShortBinary);
+    }
+    Ok((0..num)
+        .map(|i| LittleEndian::read_u32(&image[offset + i * 4..]))
+        .collect())
+}
+
+// The image is loaded from the offset where `load_addr` is, so that the header is at `header_addr`.
+// Zero `load_end_addr` means the end of the image, and zero `bss_end_addr` means no bss.
+fn load_aout_kludge(
+    image: &[u8],
+    header_offset: usize,
+    address: &AddressFields,
+) -> Result<Vec<Segment>> {
+    let header_addr = address.header_addr as usize;
+    let load_addr = address.load_addr as usize;
+    // Multiboot2 loads the image from its beginning if `load_addr` is -1.
+    let (load_addr, begin) = if address.load_addr == 0xffff_ffff && header_addr >= header_offset {
+        (header_addr - header_offset, 0)
+    } else if header_addr >= load_addr && header_offset >= header_addr - load_addr {
+        (load_addr, header_offset - (header_addr - load_addr))
+    } else {
+        return Err(LoaderError::InvalidMultibootHeader);
+    };
+    let end = match address.load_end_addr as usize {
+        0 => image.len(),
+        load_end_addr if load_end_addr >= load_addr => begin + load_end_addr - load_addr,
+        _ => return Err(LoaderError::InvalidMultibootHeader),
+    };
+    if end > image.len() {
+        return Err(LoaderError::TooShortBinary);
+    }
+    let size = (address.bss_end_addr as usize)
+        .saturating_sub(load_addr)
+        .max(end - begin);
+
+    let segment = Segment {
+        addr: load_addr,
+        binary: image[begin..end].to_vec(),
+        size,
+    };
+    Ok(vec![segment])
+}
+
+// Loadable segments are placed at their physical addresses.
+fn load_elf(image: &[u8]) -> Result<(Vec<Segment>, usize)> {
+    let header = ElfHeader::try_new(image)?;
+    if !header.is_elf() {
+        return Err(LoaderError::InvalidElfFormat);
+    }
+    let mut segments = Vec::new();
+    for pheader in ProgramHeader::extract_pheaders(image, &header) {
+        if !pheader.is_loadable() {
+            continue;
+        }
+        let (begin, end) = pheader.file_offset_range();
+        if end > image.len() {
+            return Err(LoaderError::TooShortBinary);
+        }
+        segments.push(Segment {
+            addr: pheader.paddr,
+            binary: image[begin..end].to_vec(),
+            size: pheader.mem_size,
+        });
+    }
+    Ok((segments, header.entry_point()))
+}
+
+// The conventional memory above `lower_memory` is reserved for the BIOS and devices.
+fn memory_map(config: &BootConfig) -> Vec<(u64, u64, u32)> {
+    let lower_memory = config.lower_memory as u64;
+    let extended = EXTENDED_MEMORY as u64;
+    vec![
+        (0, lower_memory, MEMORY_AVAILABLE),
+        (lower_memory, extended - lower_memory, MEMORY_RESERVED),
+        (extended, config.upper_memory as u64, MEMORY_AVAILABLE),
+    ]
+    .into_iter()
+    .filter(|&(_, length, _)| length != 0)
+    .collect()
+}
+
+// Builds the Multiboot information structure followed by strings and tables it points.
+fn multiboot_info(addr: usize, config: &BootConfig, modules: &[(usize, usize)]) -> Vec<u8> {
+    let mut info = InfoBuilder::new(addr);
+    info.push_zeros(SIZE_MULTIBOOT_INFO);
+    let mut flags =
+        INFO_MEMORY | INFO_CMDLINE | INFO_MODS | INFO_MEMORY_MAP | INFO_BOOT_LOADER_NAME;
+    info.write_u32_at(4, (config.lower_memory / 1024) as u32);
+    info.write_u32_at(8, (config.upper_memory / 1024) as u32);
+    let cmdline = info.push_string(&config.cmdline);
+    info.write_u32_at(16, cmdline);
+
+    let strings: Vec<u32> = config
+        .modules
+        .iter()
+        .map(|module| info.push_string(&module.cmdline))
+        .collect();
+    info.align(4);
+    info.write_u32_at(20, modules.len() as u32);
+    info.write_u32_at(24, info.current_addr());
+    for (&(start, end), string) in modules.iter().zip(strings) {
+        info.push_u32(start as u32);
+        info.push_u32(end as u32);
+        info.push_u32(string);
+        info.push_u32(0);
+    }
+
+    // Each entry of the memory map begins with its size except the size field itself.
+    let map = info.current_addr();
+    for (base, length, memory_type) in memory_map(config) {
+        info.push_u32(20);
+        info.push_u64(base);
+        info.push_u64(length);
+        info.push_u32(memory_type);
+    }
+    info.write_u32_at(44, info.current_addr() - map);
+    info.write_u32_at(48, map);
+    let name = info.push_string(BOOT_LOADER_NAME);
+    info.write_u32_at(64, name);
+
+    if let Some(framebuffer) = config.framebuffer {
+        flags |= INFO_FRAMEBUFFER;
+        let mut fields = InfoBuilder::new(0);
+        fields.push_framebuffer(&framebuffer, 0);
+        info.write_bytes_at(88, &fields.bytes);
+    }
+    info.write_u32_at(0, flags);
+    info.bytes
+}
+
+// Builds the Multiboot2 information, which is a list of 8-byte aligned tags.
+fn multiboot2_info(addr: usize, config: &BootConfig, modules: &[(usize, usize)]) -> Vec<u8> {
+    let mut info = InfoBuilder::new(addr);
+    info.push_zeros(8);
+    info.push_tag(INFO_TAG_CMDLINE, |tag| {
+        tag.push_string(&config.cmdline);
+    });
+    info.push_tag(INFO_TAG_BOOT_LOADER_NAME, |tag| {
+        tag.push_string(BOOT_LOADER_NAME);
+    });
+    for (&(start, end), module) in modules.iter().zip(&config.modules) {
+        info.push_tag(INFO_TAG_MODULE, |tag| {
+            tag.push_u32(start as u32);
+            tag.push_u32(end as u32);
+            tag.push_string(&module.cmdline);
+        });
+    }
+    info.push_tag(INFO_TAG_BASIC_MEMINFO, |tag| {
+        tag.push_u32((config.lower_memory / 1024) as u32);
+        tag.push_u32((config.upper_memory / 1024) as u32);
+    });
+    info.push_tag(INFO_TAG_MEMORY_MAP, |tag| {
+        // The size and the version of an entry.
+        tag.push_u32(24);
+        tag.push_u32(0);
+        for (base, length, memory_type) in memory_map(config) {
+            tag.push_u64(base);
+            tag.push_u64(length);
+            tag.push_u32(memory_type);
+            tag.push_u32(0);
+        }
+    });
+    if let Some(framebuffer) = config.framebuffer {
+        info.push_tag(INFO_TAG_FRAMEBUFFER, |tag| {
+            tag.push_framebuffer(&framebuffer, 2)
+        });
+    }
+    info.push_tag(INFO_TAG_END, |_| ());
+    let total_size = info.bytes.len() as u32;
+    info.write_u32_at(0, total_size);
+    info.bytes
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+// Boot information under construction, which will be placed at `addr`.
+struct InfoBuilder {
+    addr: usize,
+    bytes: Vec<u8>,
+}
+
+impl InfoBuilder {
+    fn new(addr: usize) -> InfoBuilder {
+        InfoBuilder {
+            addr,
+            bytes: Vec::new(),
+        }
+    }
+
+    fn current_addr(&self) -> u32 {
+        (self.addr + self.bytes.len()) as u32
+    }
+
+    fn align(&mut self, align: usize) {
+        let len = align_up(self.bytes.len(), align);
+        self.bytes.resize(len, 0);
+    }
+
+    fn push_zeros(&mut self, len: usize) {
+        self.bytes.resize(self.bytes.len() + len, 0);
+    }
+
+    fn push_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn push_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // Returns the address of the null-terminated string.
+    fn push_string(&mut self, string: &str) -> u32 {
+        let addr = self.current_addr();
+        self.bytes.extend_from_slice(string.as_bytes());
+        self.bytes.push(0);
+        addr
+    }
+
+    // The tag is type, size and contents pushed by `contents`, which is padded to 8 bytes.
+    fn push_tag<F: FnOnce(&mut InfoBuilder)>(&mut self, tag_type: u32, contents: F) {
+        let begin = self.bytes.len();
+        self.push_u32(tag_type);
+        self.push_u32(0);
+        contents(self);
+        let size = (self.bytes.len() - begin) as u32;
+        self.write_u32_at(begin + 4, size);
+        self.align(8);
+    }
+
+    // Multiboot2 has `reserved` bytes between the type and the color information.
+    fn push_framebuffer(&mut self, framebuffer: &Framebuffer, reserved: usize) {
+        self.push_u64(framebuffer.addr);
+        self.push_u32(framebuffer.pitch);
+        self.push_u32(framebuffer.width);
+        self.push_u32(framebuffer.height);
+        self.push_u8(framebuffer.bpp);
+        match framebuffer.kind {
+            FramebufferType::Rgb(fields) => {
+                self.push_u8(1);
+                self.push_zeros(reserved);
+                for (position, size) in fields.iter() {
+                    self.push_u8(*position);
+                    self.push_u8(*size);
+                }
+            }
+            FramebufferType::EgaText => {
+                self.push_u8(2);
+                self.push_zeros(reserved);
+            }
+        }
+    }
+
+    fn write_u32_at(&mut self, offset: usize, value: u32) {
+        LittleEndian::write_u32(&mut self.bytes[offset..offset + 4], value);
+    }
+
+    fn write_bytes_at(&mut self, offset: usize, bytes: &[u8]) {
+        self.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> BootConfig {
+        BootConfig {
+            lower_memory: 0x1_0000,
+            upper_memory: 0x10_0000,
+            cmdline: "kernel quiet".to_string(),
+            modules: vec![Module {
+                binary: vec![0xaa; 0x1800],
+                cmdline: "initrd".to_string(),
+            }],
+            framebuffer: None,
+        }
+    }
+
+    fn push_u32s(image: &mut Vec<u8>, values: &[u32]) {
+        values
+            .iter()
+            .for_each(|value| image.extend_from_slice(&value.to_le_bytes()));
+    }
+
+    fn read_u32(binary: &[u8], offset: usize) -> u32 {
+        LittleEndian::read_u32(&binary[offset..])
+    }
+
+    fn read_string(binary: &[u8], offset: usize) -> &str {
+        let len = binary[offset..].iter().position(|&b| b == 0).unwrap();
+        std::str::from_utf8(&binary[offset..offset + len]).unwrap()
+    }
+
+    // Reads the string pointed by the field at `offset` of the information at 0x10_4000.
+    fn pointed_string(info: &[u8], offset: usize) -> &str {
+        read_string(info, read_u32(info, offset) as usize - 0x10_4000)
+    }
+
+    // Loads 0x100 bytes from 0x10_0000 with bss up to 0x10_2000.
+    fn aout_kludge_image() -> Vec<u8> {
+        let mut image = vec![0x90; 0x40];
+        let flags = HEADER_PAGE_ALIGN | HEADER_MEMORY_INFO | HEADER_ADDRESS;
+        let checksum = 0u32.wrapping_sub(MULTIBOOT_HEADER_MAGIC + flags);
+        push_u32s(&mut image, &[MULTIBOOT_HEADER_MAGIC, flags, checksum]);
+        push_u32s(&mut image, &[0x10_0040, 0x10_0000, 0, 0x10_2000, 0x10_0060]);
+        image.resize(0x100, 0xf4);
+        image
+    }
+
+    #[test]
+    fn multiboot_aout_kludge() {
+        let kernel = MultibootKernel::try_new(&aout_kludge_image()).unwrap();
+        assert_eq!(kernel.version(), Version::Multiboot);
+        assert_eq!(kernel.entry_point(), 0x10_0060);
+        let text = &kernel.segments()[0];
+        assert_eq!((text.start_addr(), text.size()), (0x10_0000, 0x2000));
+        assert_eq!(text.binary_as_ref().len(), 0x100);
+
+        let boot = kernel.boot(&config()).unwrap();
+        assert_eq!(boot.magic(), MULTIBOOT_BOOTLOADER_MAGIC);
+        assert_eq!(boot.info_addr(), 0x10_4000);
+        let module = &boot.segments()[1];
+        assert_eq!((module.start_addr(), module.size()), (0x10_2000, 0x1800));
+
+        let info = boot.segments()[2].binary_as_ref();
+        assert_eq!(read_u32(info, 0), 0x24d);
+        assert_eq!((read_u32(info, 4), read_u32(info, 8)), (64, 1024));
+        assert_eq!(pointed_string(info, 16), "kernel quiet");
+        let mods = read_u32(info, 24) as usize - 0x10_4000;
+        assert_eq!(read_u32(info, 20), 1);
+        assert_eq!(read_u32(info, mods), 0x10_2000);
+        assert_eq!(read_u32(info, mods + 4), 0x10_3800);
+        assert_eq!(pointed_string(info, mods + 8), "initrd");
+        // Available, reserved and available regions.
+        let map = read_u32(info, 48) as usize - 0x10_4000;
+        assert_eq!(read_u32(info, 44), 72);
+        assert_eq!(read_u32(info, map + 24 + 20), MEMORY_RESERVED);
+        assert_eq!(LittleEndian::read_u64(&info[map + 48 + 4..]), 0x10_0000);
+        assert_eq!(pointed_string(info, 64), "rustemu86");
+    }
+
+    #[test]
+    fn multiboot2_tags() {
+        let mut image = vec![0; 0x1000];
+        let length = 16 + 24 + 16 + 24 + 8;
+        let checksum = 0u32.wrapping_sub(MULTIBOOT2_HEADER_MAGIC + length);
+        push_u32s(&mut image, &[MULTIBOOT2_HEADER_MAGIC, 0, length, checksum]);
+        push_u32s(&mut image, &[2, 24, 0x10_1000, 0x10_0000, 0, 0]);
+        push_u32s(&mut image, &[3, 16, 0x10_0000, 0]);
+        // Required framebuffer tag of 80x25 text.
+        push_u32s(&mut image, &[5, 20, 80, 25, 0, 0]);
+        push_u32s(&mut image, &[0, 8]);
+        image.resize(0x1100, 0xf4);
+
+        let kernel = MultibootKernel::try_new(&image).unwrap();
+        assert_eq!(kernel.version(), Version::Multiboot2);
+        assert_eq!(kernel.segments()[0].binary_as_ref().len(), 0x1100);
+        assert!(kernel.boot(&config()).is_err());
+
+        let kernel = MultibootKernel::try_new(&image).unwrap();
+        let framebuffer = Framebuffer {
+            addr: 0xb8000,
+            pitch: 160,
+            width: 80,
+            height: 25,
+            bpp: 16,
+            kind: FramebufferType::EgaText,
+        };
+        let config = BootConfig {
+            framebuffer: Some(framebuffer),
+            ..config()
+        };
+        let boot = kernel.boot(&config).unwrap();
+        assert_eq!(boot.magic(), MULTIBOOT2_BOOTLOADER_MAGIC);
+        assert_eq!(boot.entry_point(), 0x10_0000);
+        assert_eq!(boot.info_addr(), 0x10_4000);
+
+        let info = boot.segments()[2].binary_as_ref();
+        assert_eq!(read_u32(info, 0) as usize, info.len());
+        let mut tags = Vec::new();
+        let mut offset = 8;
+        while offset < info.len() {
+            tags.push(read_u32(info, offset));
+            offset += align_up(read_u32(info, offset + 4) as usize, 8);
+        }
+        assert_eq!(tags, vec![1, 2, 3, 4, 6, 8, 0]);
+        assert_eq!(read_string(info, 16), "kernel quiet");
+        let framebuffer = info.len() - 40;
+        assert_eq!(LittleEndian::read_u64(&info[framebuffer + 8..]), 0xb8000);
+    }
+
+    #[test]
+    fn invalid_multiboot_header() {
+        let result = MultibootKernel::try_new(&[0x90; 0x100]);
+        assert!(result.is_err());
+
+        let mut image = aout_kludge_image();
+        image[0x48] ^= 1;
+        assert!(MultibootKernel::try_new(&image).is_err());
+
+        // Flag 3 is required but unknown.
+        let mut image = aout_kludge_image();
+        image[0x44] |= 8;
+        image[0x48] = image[0x48].wrapping_sub(8);
+        match MultibootKernel::try_new(&image) {
+            Err(LoaderError::UnsupportedMultibootFeature { feature }) => assert_eq!(feature, 8),
+            result => panic!("{:?}", result),
+        }
+
+        let config = BootConfig {
+            upper_memory: 0x4000,
+            ..config()
+        };
+        let kernel = MultibootKernel::try_new(&aout_kludge_image()).unwrap();
+        assert!(kernel.boot(&config).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+# Static Linux executable for user-mode emulation tests.
+# Prints a greeting and argv[1], then exits with argc.
+#   as hello-x86_64.s -o hello.o && ld -static -s --build-id=none -o hello-x86_64 hello.o
+    .intel_syntax noprefix
+    .globl _start
+
+    .section .rodata
+msg:
+    .ascii "Hello, Linux!\n"
+    .set msg_len, . - msg
+
+    .text
+_start:
+    mov rbx, rsp
+    lea rsi, [rip + msg]
+    mov edx, msg_len
+    mov edi, 1
+    mov eax, 1              # write
+    syscall
+
+    mov rcx, [rbx]          # argc
+    cmp rcx, 2
+    jb exit
+    mov rdi, [rbx + 16]     # argv[1]
+    mov rsi, rdi
+    xor eax, eax
+    mov rcx, -1
+    repne scasb
+    not rcx
+    dec rcx
+    mov rdx, rcx
+    mov edi, 1
+    mov eax, 1              # write
+    syscall
+
+exit:
+    mov rdi, [rbx]
+    mov eax, 231            # exit_group
+    syscall
@@ -1,67 +1,347 @@
 //! Memory mapped system bus.
 //! Currently memory map assumes AT&T compatible machine.
-use crate::error::MemoryAccessError;
+//! For user-mode emulation, the bus is a flat address space of a process instead.
+//! The port I/O bus is also reached from here, because CPUs own only the interconnect.
+//! Interrupts are delivered through the local APIC, and from the PIC while it passes them.
+use crate::io_apic::{IoApic, IO_APIC};
+use crate::io_bus::{IoBus, IoDevice};
+use crate::local_apic::{LocalApic, LOCAL_APIC};
 use crate::memory::Memory;
 use crate::memory_access::{MemoryAccess, Result};
+use crate::mmio::Mmio;
+use crate::paged_memory::PagedMemory;
+use crate::pic8259::Pic;
+use crate::pit8254::{Pit8254, PIT};
+use crate::uart16550::COM1;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 // From x86_64 specification.
 const MAX_INSTRUCTION_LENGTH: usize = 15;
-// This is temporary.
-const MEMORY_SIZE: usize = 0x10000;
+/// Size of the conventional memory from address 0. This is temporary.
+pub const MEMORY_SIZE: usize = 0x10000;
+/// Extended memory starts at 1 MiB, where Multiboot kernels are loaded.
+pub const EXTENDED_MEMORY: usize = 0x10_0000;
+// Legacy test programs write the serial here instead of COM1.
+const SERIAL_ALIAS: usize = 0x1000_0000;
 
 pub struct Interconnect {
-    memory: Memory,
-    serial: Box<dyn MemoryAccess>,
-    display: Box<dyn MemoryAccess>,
+    address_map: AddressMap,
+    io_bus: IoBus,
+    pic: Pic,
+    pit: Rc<RefCell<Pit8254>>,
+    local_apic: Rc<RefCell<LocalApic>>,
+    io_apic: Rc<RefCell<IoApic>>,
+    // Clocks since reset. The time stamp counter advances with it.
+    clock: u64,
+    tsc_offset: u64,
+}
+
+enum AddressMap {
+    Machine {
+        memory: Memory,
+        // Empty unless added by `add_extended_memory()`.
+        extended: Memory,
+        display: Box<dyn MemoryAccess>,
+        // Devices above the memory, such as APICs.
+        devices: Mmio,
+    },
+    // No device is mapped in the address space of a user process.
+    UserSpace(PagedMemory),
 }
 
 impl Interconnect {
-    pub fn new(serial: Box<dyn MemoryAccess>, display: Box<dyn MemoryAccess>) -> Interconnect {
+    /// The serial is mapped on COM1, and its data register also at 0x1000_0000.
+    /// The PICs and the PIT are mapped on their legacy ports.
+    /// The local APIC and the IOAPIC are mapped at their default addresses.
+    pub fn new(serial: Box<dyn IoDevice>, display: Box<dyn MemoryAccess>) -> Interconnect {
+        let mut io_bus = IoBus::empty();
+        io_bus.add(COM1, serial);
+        let pic = Pic::new();
+        pic.attach(&mut io_bus);
+        let pit = Rc::new(RefCell::new(Pit8254::new()));
+        io_bus.add(PIT, Box::new(pit.clone()));
+        let local_apic = Rc::new(RefCell::new(LocalApic::new()));
+        let io_apic = Rc::new(RefCell::new(IoApic::new()));
+        let mut devices = Mmio::empty();
+        devices
+            .add(LOCAL_APIC, Box::new(local_apic.clone()))
+            .unwrap();
+        devices.add(IO_APIC, Box::new(io_apic.clone())).unwrap();
         Interconnect {
-            memory: Memory::new(MEMORY_SIZE),
-            serial,
-            display,
+            address_map: AddressMap::Machine {
+                memory: Memory::new(MEMORY_SIZE),
+                extended: Memory::new(0),
+                display,
+                devices,
+            },
+            io_bus,
+            pic,
+            pit,
+            local_apic,
+            io_apic,
+            clock: 0,
+            tsc_offset: 0,
+        }
+    }
+
+    /// Creates an empty address space of a user process.
+    /// Pages must be mapped by `user_space_mut()` before access.
+    pub fn new_user_space() -> Interconnect {
+        Interconnect {
+            address_map: AddressMap::UserSpace(PagedMemory::new()),
+            io_bus: IoBus::empty(),
+            pic: Pic::new(),
+            pit: Rc::new(RefCell::new(Pit8254::new())),
+            local_apic: Rc::new(RefCell::new(LocalApic::new())),
+            io_apic: Rc::new(RefCell::new(IoApic::new())),
+            clock: 0,
+            tsc_offset: 0,
+        }
+    }
+
+    /// Clocks elapsed by `tick()`.
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    pub fn time_stamp_counter(&self) -> u64 {
+        self.clock.wrapping_add(self.tsc_offset)
+    }
+
+    /// Writing IA32_TSC changes the counter, but not the clock of devices.
+    pub fn set_time_stamp_counter(&mut self, value: u64) {
+        self.tsc_offset = value.wrapping_sub(self.clock);
+    }
+
+    /// Advances the PIT and the timer of the local APIC by `ticks` clocks.
+    /// Channel 0 of the PIT raises IRQ0.
+    pub fn tick(&mut self, ticks: u64) {
+        self.clock += ticks;
+        if self.pit.borrow_mut().tick(ticks) {
+            self.raise_irq(0);
+        }
+        let tsc = self.time_stamp_counter();
+        self.local_apic.borrow_mut().tick(ticks, tsc);
+    }
+
+    /// Raises the ISA IRQ on the PIC and the pin of the same number on the IOAPIC.
+    pub fn raise_irq(&mut self, irq: u8) {
+        self.pic.raise_irq(irq);
+        let message = self.io_apic.borrow_mut().raise_irq(irq);
+        if let Some(message) = message {
+            self.local_apic.borrow_mut().receive(message);
+        }
+    }
+
+    /// True if the local APIC or the PIC asserts INTR to the CPU.
+    pub fn has_interrupt(&self) -> bool {
+        let local_apic = self.local_apic.borrow();
+        local_apic.has_interrupt() || local_apic.accepts_ext_int() && self.pic.has_interrupt()
+    }
+
+    /// Acknowledges the interrupt of the highest priority and returns its vector.
+    /// Interrupts of the local APIC take priority over those from the PIC.
+    pub fn acknowledge_interrupt(&mut self) -> Option<u8> {
+        let mut local_apic = self.local_apic.borrow_mut();
+        match local_apic.acknowledge() {
+            Some(vector) => Some(vector),
+            None if local_apic.accepts_ext_int() => self.pic.acknowledge(),
+            None => None,
+        }
+    }
+
+    /// Advances time until an interrupt is requested, as a halted CPU does nothing else.
+    /// False if no interrupt will be requested.
+    pub fn wait_for_interrupt(&mut self) -> bool {
+        if !self.has_interrupt() {
+            let tsc = self.time_stamp_counter();
+            let timer = self.local_apic.borrow().ticks_to_timer(tsc);
+            let pit = if self.is_irq0_routed() {
+                self.pit.borrow().ticks_to_irq0()
+            } else {
+                None
+            };
+            let ticks = match (pit, timer) {
+                (Some(pit), Some(timer)) => Some(pit.min(timer)),
+                (pit, timer) => pit.or(timer),
+            };
+            if let Some(ticks) = ticks {
+                self.tick(ticks);
+            }
+        }
+        self.has_interrupt()
+    }
+
+    /// Reads an MSR of the local APIC. None if it does not exist.
+    pub fn read_msr(&self, msr: u32) -> Option<u64> {
+        self.local_apic.borrow().read_msr(msr)
+    }
+
+    /// False if the MSR does not exist or the value is invalid.
+    pub fn write_msr(&mut self, msr: u32, value: u64) -> bool {
+        let result = self.local_apic.borrow_mut().write_msr(msr, value);
+        self.broadcast_eoi();
+        result
+    }
+
+    // True if IRQ0 reaches the CPU through either the PIC or the IOAPIC.
+    fn is_irq0_routed(&self) -> bool {
+        let local_apic = self.local_apic.borrow();
+        local_apic.accepts_ext_int() && !self.pic.is_masked(0)
+            || local_apic.is_enabled() && !self.io_apic.borrow().is_masked(0)
+    }
+
+    // EOIs of level triggered interrupts let the IOAPIC send them again.
+    fn broadcast_eoi(&mut self) {
+        let vectors = self.local_apic.borrow_mut().take_level_eois();
+        let mut io_apic = self.io_apic.borrow_mut();
+        vectors
+            .into_iter()
+            .for_each(|vector| io_apic.end_of_interrupt(vector));
+    }
+
+    pub fn io_bus(&self) -> &IoBus {
+        &self.io_bus
+    }
+
+    /// Devices are added to the port I/O bus through this.
+    pub fn io_bus_mut(&mut self) -> &mut IoBus {
+        &mut self.io_bus
+    }
+
+    /// The address space of a user process, if this is created by `new_user_space()`.
+    pub fn user_space(&self) -> Option<&PagedMemory> {
+        match &self.address_map {
+            AddressMap::UserSpace(memory) => Some(memory),
+            _ => None,
+        }
+    }
+
+    pub fn user_space_mut(&mut self) -> Option<&mut PagedMemory> {
+        match &mut self.address_map {
+            AddressMap::UserSpace(memory) => Some(memory),
+            _ => None,
+        }
+    }
+
+    /// Maps `size` bytes of RAM from `EXTENDED_MEMORY`.
+    /// Does nothing for the address space of a user process.
+    pub fn add_extended_memory(&mut self, size: usize) {
+        if let AddressMap::Machine { extended, .. } = &mut self.address_map {
+            *extended = Memory::new(size);
         }
     }
 
     pub fn init_memory(&mut self, program: &[u8], start: usize) {
-        self.memory.fill_ram(&program, start);
+        match &mut self.address_map {
+            AddressMap::Machine { extended, .. } if is_extended(extended, start) => {
+                extended.fill_ram(&program, start - EXTENDED_MEMORY)
+            }
+            AddressMap::Machine { memory, .. } => memory.fill_ram(&program, start),
+            AddressMap::UserSpace(memory) => {
+                memory.map(start, program.len());
+                memory.fill(&program, start).unwrap();
+            }
+        }
     }
 
+    // An instruction at the end of the memory may be shorter than the maximum length.
     pub fn fetch_inst_candidate(&self, rip: u64) -> Vec<u8> {
         (0..MAX_INSTRUCTION_LENGTH)
             .map(|x| self.read_u8(rip as usize + x))
+            .take_while(|byte| byte.is_ok())
             .collect::<Result<Vec<u8>>>()
             .unwrap()
     }
 }
 
+fn is_extended(extended: &Memory, addr: usize) -> bool {
+    addr.wrapping_sub(EXTENDED_MEMORY) < extended.size()
+}
+
 impl MemoryAccess for Interconnect {
     fn read_u8(&self, addr: usize) -> Result<u8> {
-        match addr {
-            0x0...MEMORY_SIZE => self.memory.read_u8(addr as usize),
-            0x1000_0000 => self.serial.read_u8(0),
-            _ => Err(MemoryAccessError::DeviceNotMapped { addr }),
+        match &self.address_map {
+            AddressMap::Machine {
+                memory,
+                extended,
+                devices,
+                ..
+            } => match addr {
+                0x0...MEMORY_SIZE => memory.read_u8(addr as usize),
+                _ if is_extended(extended, addr) => extended.read_u8(addr - EXTENDED_MEMORY),
+                SERIAL_ALIAS => Ok(self.io_bus.read_u8(COM1.0)),
+                _ => devices.read_u8(addr),
+            },
+            AddressMap::UserSpace(memory) => memory.read_u8(addr),
         }
     }
 
     fn write_u8(&mut self, addr: usize, data: u8) -> Result<()> {
-        match addr {
-            0x0...MEMORY_SIZE => self.memory.write_u8(addr as usize, data),
-            0x000B_8000...0x000B_8FA0 => self.display.write_u8((addr & 0xfff) as usize, data),
-            0x1000_0000 => self.serial.write_u8(0, data),
-            _ => Err(MemoryAccessError::DeviceNotMapped { addr }),
+        match &mut self.address_map {
+            AddressMap::Machine {
+                memory,
+                extended,
+                display,
+                devices,
+            } => match addr {
+                0x0...MEMORY_SIZE => memory.write_u8(addr as usize, data),
+                0x000B_8000...0x000B_8FA0 => display.write_u8((addr & 0xfff) as usize, data),
+                _ if is_extended(extended, addr) => extended.write_u8(addr - EXTENDED_MEMORY, data),
+                SERIAL_ALIAS => {
+                    self.io_bus.write_u8(COM1.0, data);
+                    Ok(())
+                }
+                _ => devices.write_u8(addr, data),
+            },
+            AddressMap::UserSpace(memory) => memory.write_u8(addr, data),
         }
     }
 
-    fn write_u64(&mut self, addr: usize, data: u64) -> Result<()> {
-        match addr {
-            0x0...MEMORY_SIZE => self.memory.write_u64(addr as usize, data),
-            0x000B_8000...0x000B_8FA0 => {
-                self.display.write_u16((addr & 0xfff) as usize, data as u16)
+    // Registers of APICs are accessed only as 32-bit wide.
+    fn read_u32(&self, addr: usize) -> Result<u32> {
+        match &self.address_map {
+            AddressMap::Machine { devices, .. } if devices.contains(addr) => devices.read_u32(addr),
+            AddressMap::UserSpace(memory) => memory.read_u32(addr),
+            _ => (0..4).try_fold(0, |value, i| {
+                Ok(value | u32::from(self.read_u8(addr + i)?) << (i * 8))
+            }),
+        }
+    }
+
+    fn write_u32(&mut self, addr: usize, data: u32) -> Result<()> {
+        match &mut self.address_map {
+            AddressMap::Machine { devices, .. } if devices.contains(addr) => {
+                devices.write_u32(addr, data)?;
+                self.broadcast_eoi();
+                Ok(())
             }
-            0x1000_0000 => self.serial.write_u8(0, data as u8),
-            _ => Err(MemoryAccessError::DeviceNotMapped { addr }),
+            AddressMap::UserSpace(memory) => memory.write_u32(addr, data),
+            _ => (0..4).try_for_each(|i| self.write_u8(addr + i, (data >> (i * 8)) as u8)),
+        }
+    }
+
+    fn write_u64(&mut self, addr: usize, data: u64) -> Result<()> {
+        match &mut self.address_map {
+            AddressMap::Machine {
+                memory,
+                extended,
+                display,
+                devices,
+            } => match addr {
+                0x0...MEMORY_SIZE => memory.write_u64(addr as usize, data),
+                0x000B_8000...0x000B_8FA0 => display.write_u16((addr & 0xfff) as usize, data as u16),
+                _ if is_extended(extended, addr) => {
+                    extended.write_u64(addr - EXTENDED_MEMORY, data)
+                }
+                SERIAL_ALIAS => {
+                    self.io_bus.write_u8(COM1.0, data as u8);
+                    Ok(())
+                }
+                _ => devices.write_u64(addr, data),
+            },
+            AddressMap::UserSpace(memory) => memory.write_u64(addr, data),
         }
     }
 }
@@ -69,6 +349,7 @@ impl MemoryAccess for Interconnect {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::error::MemoryAccessError;
     use crate::uart16550::{self, Target};
 
     struct TestMemory(Vec<u8>);
@@ -111,6 +392,77 @@ mod test {
         assert_eq!(interconnect.read_u8(0x10000000).unwrap(), b'o');
     }
 
+    #[test]
+    fn com1_port() {
+        let buffer = vec![0x00; 8];
+        let display: Box<dyn MemoryAccess> = Box::new(TestMemory(buffer));
+        let serial = uart16550::uart_factory(Target::Buffer);
+        let mut interconnect = Interconnect::new(serial, display);
+
+        // The legacy address is the data register of COM1.
+        assert!(interconnect.write_u8(0x10000000, b'h').is_ok());
+        assert_eq!(interconnect.io_bus().read_u8(0x3fd) & 0x01, 0x01);
+        assert_eq!(interconnect.io_bus().read_u8(0x3f8), b'h');
+        interconnect.io_bus_mut().write_u8(0x3f8, b'i');
+        assert_eq!(interconnect.read_u8(0x10000000).unwrap(), b'i');
+        assert_eq!(interconnect.io_bus().read_u8(0x2f8), 0xff);
+    }
+
+    #[test]
+    fn timer_interrupt() {
+        let display: Box<dyn MemoryAccess> = Box::new(TestMemory(vec![0x00; 8]));
+        let serial = uart16550::uart_factory(Target::Buffer);
+        let mut interconnect = Interconnect::new(serial, display);
+        assert!(!interconnect.wait_for_interrupt());
+
+        let io_bus = interconnect.io_bus_mut();
+        for (port, data) in &[(0x20, 0x11), (0x21, 0x20), (0x21, 0x04), (0x21, 0x01)] {
+            io_bus.write_u8(*port, *data);
+        }
+        io_bus.write_u8(0x43, 0x34);
+        io_bus.write_u8(0x40, 0x00);
+        io_bus.write_u8(0x40, 0x10);
+        interconnect.tick(0x0fff);
+        assert!(!interconnect.has_interrupt());
+        interconnect.tick(1);
+        assert_eq!(interconnect.acknowledge_interrupt(), Some(0x20));
+        interconnect.io_bus_mut().write_u8(0x20, 0x20);
+        assert!(interconnect.wait_for_interrupt());
+        assert_eq!(interconnect.acknowledge_interrupt(), Some(0x20));
+    }
+
+    #[test]
+    fn apic_interrupts() {
+        let display: Box<dyn MemoryAccess> = Box::new(TestMemory(vec![0x00; 8]));
+        let serial = uart16550::uart_factory(Target::Buffer);
+        let mut interconnect = Interconnect::new(serial, display);
+        // Enable the local APIC, which masks LINT0 and so the PIC.
+        interconnect.write_u32(0xfee0_00f0, 0x1ff).unwrap();
+        assert_eq!(interconnect.read_u32(0xfee0_00f0).unwrap(), 0x1ff);
+        interconnect.raise_irq(4);
+        assert!(!interconnect.has_interrupt());
+
+        // Level triggered IRQ4 to vector 0x34 through the IOAPIC.
+        interconnect.write_u32(0xfec0_0000, 0x18).unwrap();
+        interconnect.write_u32(0xfec0_0010, 0x8034).unwrap();
+        interconnect.raise_irq(4);
+        assert_eq!(interconnect.acknowledge_interrupt(), Some(0x34));
+        interconnect.raise_irq(4);
+        interconnect.write_u32(0xfee0_00b0, 0).unwrap();
+        interconnect.raise_irq(4);
+        assert_eq!(interconnect.acknowledge_interrupt(), Some(0x34));
+        interconnect.write_u32(0xfee0_00b0, 0).unwrap();
+
+        // One-shot timer of vector 0x40 divided by 1.
+        interconnect.write_u32(0xfee0_03e0, 0xb).unwrap();
+        interconnect.write_u32(0xfee0_0320, 0x40).unwrap();
+        interconnect.write_u32(0xfee0_0380, 0x100).unwrap();
+        assert!(interconnect.wait_for_interrupt());
+        assert_eq!(interconnect.clock(), 0x100);
+        assert_eq!(interconnect.acknowledge_interrupt(), Some(0x40));
+        assert!(!interconnect.wait_for_interrupt());
+    }
+
     #[test]
     fn test_init_memory() {
         let buffer = vec![0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
@@ -125,4 +477,32 @@ mod test {
         assert_eq!(interconnect.read_u8(0x1).unwrap(), 0xff);
         assert_eq!(interconnect.read_u8(0x2).unwrap(), 0xc0);
     }
+
+    #[test]
+    fn extended_memory() {
+        let display: Box<dyn MemoryAccess> = Box::new(TestMemory(vec![0x00; 8]));
+        let serial = uart16550::uart_factory(Target::Buffer);
+        let mut interconnect = Interconnect::new(serial, display);
+        assert!(interconnect.read_u8(EXTENDED_MEMORY).is_err());
+
+        interconnect.add_extended_memory(0x1000);
+        interconnect.init_memory(&[0x0f, 0x0b], 0x10_0ffe);
+        assert_eq!(interconnect.fetch_inst_candidate(0x10_0ffe).len(), 2);
+        assert!(interconnect.write_u64(EXTENDED_MEMORY, 0x1234).is_ok());
+        assert_eq!(interconnect.read_u32(EXTENDED_MEMORY).unwrap(), 0x1234);
+        assert!(interconnect.write_u8(0x10_1000, 0).is_err());
+    }
+
+    #[test]
+    fn user_space() {
+        let mut interconnect = Interconnect::new_user_space();
+        assert!(interconnect.read_u8(0x40_0000).is_err());
+
+        interconnect.init_memory(&[0x0f, 0x05], 0x40_0ffe);
+        assert_eq!(interconnect.fetch_inst_candidate(0x40_0ffe), vec![0x0f, 0x05]);
+        // No serial port in a user process.
+        interconnect.user_space_mut().unwrap().map(0x1000_0000, 1);
+        assert!(interconnect.write_u64(0x1000_0000, 0x41).is_ok());
+        assert_eq!(interconnect.read_u64(0x1000_0000).unwrap(), 0x41);
+    }
 }
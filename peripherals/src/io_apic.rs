@@ -0,0 +1,206 @@
+//! IOAPIC of x86_64 which routes device interrupts to local APICs.
+//! Registers are accessed indirectly through IOREGSEL and IOWIN.
+//! ISA IRQs are connected to the pins of the same number.
+use crate::local_apic::InterruptMessage;
+use crate::memory_access::{MemoryAccess, Result};
+use crate::mmio::MemoryRange;
+
+pub const IO_APIC: MemoryRange = (0xfec0_0000, 0x20);
+pub const IO_APIC_PINS: usize = 24;
+
+// Offsets of memory mapped registers.
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+
+// Indirect registers.
+const ID: u8 = 0x00;
+const VERSION: u8 = 0x01;
+const ARBITRATION: u8 = 0x02;
+const REDIRECTION_TABLE: u8 = 0x10;
+
+// Version 0x11 with 24 redirection entries.
+const VERSION_VALUE: u32 = 0x0011 | ((IO_APIC_PINS as u32 - 1) << 16);
+
+const MASKED: u64 = 1 << 16;
+const LEVEL_TRIGGERED: u64 = 1 << 15;
+const REMOTE_IRR: u64 = 1 << 14;
+const LOGICAL: u64 = 1 << 11;
+// Delivery status and remote IRR are read-only.
+const READ_ONLY: u64 = 1 << 12 | REMOTE_IRR;
+
+pub struct IoApic {
+    id: u32,
+    select: u8,
+    redirection: [u64; IO_APIC_PINS],
+}
+
+impl IoApic {
+    /// All pins are masked after reset.
+    pub fn new() -> IoApic {
+        IoApic {
+            id: 0,
+            select: 0,
+            redirection: [MASKED; IO_APIC_PINS],
+        }
+    }
+
+    pub fn is_masked(&self, pin: u8) -> bool {
+        match self.redirection.get(usize::from(pin)) {
+            Some(entry) => entry & MASKED != 0,
+            None => true,
+        }
+    }
+
+    /// Asserts the pin and returns the message to local APICs, unless it is masked
+    /// or a level triggered interrupt from the pin is still in service.
+    pub fn raise_irq(&mut self, pin: u8) -> Option<InterruptMessage> {
+        let entry = self.redirection.get_mut(usize::from(pin))?;
+        if *entry & MASKED != 0 {
+            return None;
+        }
+        let level_triggered = *entry & LEVEL_TRIGGERED != 0;
+        if level_triggered {
+            if *entry & REMOTE_IRR != 0 {
+                return None;
+            }
+            *entry |= REMOTE_IRR;
+        }
+        Some(InterruptMessage {
+            vector: *entry as u8,
+            delivery_mode: ((*entry >> 8) & 0x7) as u8,
+            level_triggered,
+            logical: *entry & LOGICAL != 0,
+            destination: (*entry >> 56) as u32,
+        })
+    }
+
+    /// Clears remote IRR of level triggered entries of the vector, on EOI broadcast by local APICs.
+    pub fn end_of_interrupt(&mut self, vector: u8) {
+        self.redirection
+            .iter_mut()
+            .filter(|entry| **entry & LEVEL_TRIGGERED != 0 && **entry as u8 == vector)
+            .for_each(|entry| *entry &= !REMOTE_IRR);
+    }
+
+    fn read_register(&self) -> u32 {
+        match self.select {
+            ID | ARBITRATION => self.id << 24,
+            VERSION => VERSION_VALUE,
+            index => match self.entry_index(index) {
+                Some((pin, false)) => self.redirection[pin] as u32,
+                Some((pin, true)) => (self.redirection[pin] >> 32) as u32,
+                None => 0,
+            },
+        }
+    }
+
+    fn write_register(&mut self, data: u32) {
+        match self.select {
+            ID => self.id = (data >> 24) & 0xf,
+            index => {
+                if let Some((pin, high)) = self.entry_index(index) {
+                    let entry = &mut self.redirection[pin];
+                    let value = if high {
+                        *entry & 0xffff_ffff | u64::from(data) << 32
+                    } else {
+                        *entry & !0xffff_ffff | u64::from(data)
+                    };
+                    *entry = *entry & READ_ONLY | value & !READ_ONLY;
+                }
+            }
+        }
+    }
+
+    // The pin and whether the upper half of the entry is selected.
+    fn entry_index(&self, index: u8) -> Option<(usize, bool)> {
+        let offset = usize::from(index.checked_sub(REDIRECTION_TABLE)?);
+        if offset < IO_APIC_PINS * 2 {
+            Some((offset / 2, offset % 2 == 1))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for IoApic {
+    fn default() -> IoApic {
+        IoApic::new()
+    }
+}
+
+/// Registers must be accessed by 32-bit accesses, except that IOREGSEL is a byte.
+impl MemoryAccess for IoApic {
+    fn read_u8(&self, addr: usize) -> Result<u8> {
+        match addr {
+            IOREGSEL => Ok(self.select),
+            _ => Ok(0),
+        }
+    }
+
+    fn read_u32(&self, addr: usize) -> Result<u32> {
+        match addr {
+            IOREGSEL => Ok(u32::from(self.select)),
+            IOWIN => Ok(self.read_register()),
+            _ => Ok(0),
+        }
+    }
+
+    fn write_u8(&mut self, addr: usize, data: u8) -> Result<()> {
+        if addr == IOREGSEL {
+            self.select = data;
+        }
+        Ok(())
+    }
+
+    fn write_u32(&mut self, addr: usize, data: u32) -> Result<()> {
+        match addr {
+            IOREGSEL => self.select = data as u8,
+            IOWIN => self.write_register(data),
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_entry(io_apic: &mut IoApic, pin: u8, entry: u64) {
+        io_apic
+            .write_u32(IOREGSEL, u32::from(0x10 + pin * 2))
+            .unwrap();
+        io_apic.write_u32(IOWIN, entry as u32).unwrap();
+        io_apic
+            .write_u32(IOREGSEL, u32::from(0x11 + pin * 2))
+            .unwrap();
+        io_apic.write_u32(IOWIN, (entry >> 32) as u32).unwrap();
+    }
+
+    #[test]
+    fn redirection() {
+        let mut io_apic = IoApic::new();
+        io_apic.write_u8(IOREGSEL, 0x01).unwrap();
+        assert_eq!(io_apic.read_u32(IOWIN).unwrap(), 0x0017_0011);
+        assert_eq!(io_apic.raise_irq(1), None);
+
+        // Edge triggered IRQ1 to vector 0x31 of the APIC 0.
+        write_entry(&mut io_apic, 1, 0x0000_0031);
+        assert!(!io_apic.is_masked(1));
+        let message = io_apic.raise_irq(1).unwrap();
+        assert_eq!(message.vector, 0x31);
+        assert!(!message.level_triggered);
+        assert!(io_apic.raise_irq(1).is_some());
+
+        // Level triggered IRQ9 to logical destination 1 waits for EOI.
+        write_entry(&mut io_apic, 9, 0x0100_0000_0000_a839);
+        let message = io_apic.raise_irq(9).unwrap();
+        assert!(message.level_triggered && message.logical);
+        assert_eq!(message.destination, 1);
+        assert_eq!(io_apic.raise_irq(9), None);
+        io_apic.write_u32(IOREGSEL, 0x22).unwrap();
+        assert_eq!(io_apic.read_u32(IOWIN).unwrap(), 0x0000_e839);
+        io_apic.end_of_interrupt(0x39);
+        assert!(io_apic.raise_irq(9).is_some());
+    }
+}
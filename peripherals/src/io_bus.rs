@@ -0,0 +1,175 @@
+//! Port I/O address space of x86, which is separate from the memory space.
+//! Devices are mapped on 16-bit port ranges and accessed by IN/OUT instructions.
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// First port and the number of ports which a device occupies.
+pub type PortRange = (u16, u16);
+
+/// Provides port access interface for different byte size.
+///
+/// An implementor must implement both read_u8()/write_u8().
+/// Wider accesses default to consecutive byte accesses in little endian.
+/// The port is an offset from the first port of the device.
+///
+/// Port accesses never fail. Reading a port which holds no data returns all ones,
+/// and writing a read-only port is ignored, as on real buses.
+pub trait IoDevice {
+    /// Reads an unsigned 8 bit integer from the port.
+    fn read_u8(&self, port: u16) -> u8;
+
+    fn read_u16(&self, port: u16) -> u16 {
+        u16::from(self.read_u8(port)) | u16::from(self.read_u8(port.wrapping_add(1))) << 8
+    }
+
+    fn read_u32(&self, port: u16) -> u32 {
+        u32::from(self.read_u16(port)) | u32::from(self.read_u16(port.wrapping_add(2))) << 16
+    }
+
+    /// Writes an unsigned 8 bit integer to the port.
+    fn write_u8(&mut self, port: u16, data: u8);
+
+    fn write_u16(&mut self, port: u16, data: u16) {
+        self.write_u8(port, data as u8);
+        self.write_u8(port.wrapping_add(1), (data >> 8) as u8);
+    }
+
+    fn write_u32(&mut self, port: u16, data: u32) {
+        self.write_u16(port, data as u16);
+        self.write_u16(port.wrapping_add(2), (data >> 16) as u16);
+    }
+}
+
+/// A device shared with its owner, which raises interrupts or advances time of the device.
+impl<T: IoDevice> IoDevice for Rc<RefCell<T>> {
+    fn read_u8(&self, port: u16) -> u8 {
+        self.borrow().read_u8(port)
+    }
+
+    fn read_u16(&self, port: u16) -> u16 {
+        self.borrow().read_u16(port)
+    }
+
+    fn read_u32(&self, port: u16) -> u32 {
+        self.borrow().read_u32(port)
+    }
+
+    fn write_u8(&mut self, port: u16, data: u8) {
+        self.borrow_mut().write_u8(port, data)
+    }
+
+    fn write_u16(&mut self, port: u16, data: u16) {
+        self.borrow_mut().write_u16(port, data)
+    }
+
+    fn write_u32(&mut self, port: u16, data: u32) {
+        self.borrow_mut().write_u32(port, data)
+    }
+}
+
+/// Port I/O bus. A wide access is passed to the device of its first port as it is.
+pub struct IoBus {
+    devices: Vec<(PortRange, Box<dyn IoDevice>)>,
+}
+
+impl IoBus {
+    /// Create the bus without devices.
+    pub fn empty() -> IoBus {
+        IoBus {
+            devices: Vec::new(),
+        }
+    }
+
+    /// Add a device occupying the port range.
+    pub fn add(&mut self, range: PortRange, device: Box<dyn IoDevice>) {
+        self.devices.push((range, device));
+    }
+
+    // The device and the offset in it.
+    fn device(&self, port: u16) -> Option<(&dyn IoDevice, u16)> {
+        self.devices
+            .iter()
+            .find(|((base, length), _)| *base <= port && port - *base < *length)
+            .map(|((base, _), device)| (device.as_ref(), port - *base))
+    }
+
+    fn device_mut(&mut self, port: u16) -> Option<(&mut Box<dyn IoDevice>, u16)> {
+        self.devices
+            .iter_mut()
+            .find(|((base, length), _)| *base <= port && port - *base < *length)
+            .map(|((base, _), device)| (device, port - *base))
+    }
+}
+
+impl IoDevice for IoBus {
+    fn read_u8(&self, port: u16) -> u8 {
+        self.device(port)
+            .map_or(0xff, |(device, offset)| device.read_u8(offset))
+    }
+
+    fn read_u16(&self, port: u16) -> u16 {
+        self.device(port)
+            .map_or(0xffff, |(device, offset)| device.read_u16(offset))
+    }
+
+    fn read_u32(&self, port: u16) -> u32 {
+        self.device(port)
+            .map_or(0xffff_ffff, |(device, offset)| device.read_u32(offset))
+    }
+
+    fn write_u8(&mut self, port: u16, data: u8) {
+        if let Some((device, offset)) = self.device_mut(port) {
+            device.write_u8(offset, data);
+        }
+    }
+
+    fn write_u16(&mut self, port: u16, data: u16) {
+        if let Some((device, offset)) = self.device_mut(port) {
+            device.write_u16(offset, data);
+        }
+    }
+
+    fn write_u32(&mut self, port: u16, data: u32) {
+        if let Some((device, offset)) = self.device_mut(port) {
+            device.write_u32(offset, data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestPorts([u8; 4]);
+    impl IoDevice for TestPorts {
+        fn read_u8(&self, port: u16) -> u8 {
+            self.0[port as usize]
+        }
+
+        fn write_u8(&mut self, port: u16, data: u8) {
+            self.0[port as usize] = data;
+        }
+    }
+
+    #[test]
+    fn port_access() {
+        let mut bus = IoBus::empty();
+        bus.add((0x80, 4), Box::new(TestPorts([0; 4])));
+
+        bus.write_u8(0x80, 0x01);
+        bus.write_u16(0x82, 0x0302);
+        assert_eq!(bus.read_u32(0x80), 0x0302_0001);
+        assert_eq!(bus.read_u8(0x83), 0x03);
+    }
+
+    #[test]
+    fn unmapped_ports() {
+        let mut bus = IoBus::empty();
+        bus.add((0x80, 4), Box::new(TestPorts([0; 4])));
+
+        bus.write_u8(0x84, 0x01);
+        assert_eq!(bus.read_u8(0x84), 0xff);
+        assert_eq!(bus.read_u16(0x7f), 0xffff);
+        assert_eq!(bus.read_u32(0), 0xffff_ffff);
+    }
+}
@@ -3,8 +3,14 @@ extern crate failure;
 
 pub mod error;
 pub mod interconnect;
+pub mod io_apic;
+pub mod io_bus;
+pub mod local_apic;
 pub mod memory;
 pub mod memory_access;
 pub mod mmio;
+pub mod paged_memory;
+pub mod pic8259;
+pub mod pit8254;
 pub mod uart16550;
 pub mod sifive_uart;
@@ -0,0 +1,637 @@
+//! Local APIC of x86_64 in xAPIC and x2APIC modes.
+//! xAPIC registers are mapped at 0xfee0_0000, and x2APIC ones are MSRs from 0x800.
+//! As there is only one processor, IPIs can be sent only to itself,
+//! and only fixed and lowest priority interrupts are delivered.
+use crate::memory_access::{MemoryAccess, Result};
+use crate::mmio::MemoryRange;
+
+pub const LOCAL_APIC: MemoryRange = (0xfee0_0000, 0x1000);
+pub const IA32_APIC_BASE: u32 = 0x1b;
+pub const IA32_TSC_DEADLINE: u32 = 0x6e0;
+const X2APIC_MSR_BASE: u32 = 0x800;
+const X2APIC_MSR_LAST: u32 = 0x8ff;
+
+const APIC_BASE_BSP: u64 = 1 << 8;
+const APIC_BASE_EXTD: u64 = 1 << 10;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+// Register offsets in xAPIC mode. The MSR of x2APIC is 0x800 + offset / 16.
+const ID: u32 = 0x020;
+const VERSION: u32 = 0x030;
+const TPR: u32 = 0x080;
+const PPR: u32 = 0x0a0;
+const EOI: u32 = 0x0b0;
+const LDR: u32 = 0x0d0;
+const DFR: u32 = 0x0e0;
+const SVR: u32 = 0x0f0;
+const ISR: u32 = 0x100;
+const TMR: u32 = 0x180;
+const IRR: u32 = 0x200;
+const ESR: u32 = 0x280;
+const LVT_CMCI: u32 = 0x2f0;
+const ICR_LOW: u32 = 0x300;
+const ICR_HIGH: u32 = 0x310;
+const LVT_TIMER: u32 = 0x320;
+const LVT_LINT0: u32 = 0x350;
+const LVT_ERROR: u32 = 0x370;
+const TIMER_INITIAL_COUNT: u32 = 0x380;
+const TIMER_CURRENT_COUNT: u32 = 0x390;
+const TIMER_DIVIDE: u32 = 0x3e0;
+// Only in x2APIC mode.
+const SELF_IPI: u32 = 0x3f0;
+
+// Version 0x14 with 7 LVT entries.
+const VERSION_VALUE: u32 = 0x0006_0014;
+const SVR_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_WRITABLE: u32 = 0x0007_afff;
+const DELIVERY_MODE_FIXED: u32 = 0;
+const DELIVERY_MODE_LOWEST_PRIORITY: u32 = 1;
+const DELIVERY_MODE_EXT_INT: u32 = 7;
+const ESR_RECEIVE_ILLEGAL_VECTOR: u32 = 1 << 6;
+const ESR_SEND_ILLEGAL_VECTOR: u32 = 1 << 5;
+// Destination shorthands of ICR.
+const NO_SHORTHAND: u64 = 0;
+const SELF: u64 = 1;
+const ALL_INCLUDING_SELF: u64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimerMode {
+    OneShot,
+    Periodic,
+    TscDeadline,
+}
+
+/// An interrupt sent to local APICs by an IOAPIC or an IPI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterruptMessage {
+    pub vector: u8,
+    pub delivery_mode: u8,
+    pub level_triggered: bool,
+    pub logical: bool,
+    pub destination: u32,
+}
+
+pub struct LocalApic {
+    apic_base: u64,
+    id: u32,
+    tpr: u32,
+    ldr: u32,
+    dfr: u32,
+    svr: u32,
+    // 256-bit vector sets in the layout of the registers.
+    isr: [u32; 8],
+    tmr: [u32; 8],
+    irr: [u32; 8],
+    esr: u32,
+    icr: u64,
+    // CMCI, timer, thermal, performance counter, LINT0, LINT1 and error.
+    lvt: [u32; 7],
+    initial_count: u32,
+    current_count: u32,
+    divide: u32,
+    // Clocks which are not enough for the divided timer to count.
+    prescaler: u64,
+    tsc_deadline: u64,
+    // Vectors of level triggered interrupts ended by EOI, to be broadcast to IOAPICs.
+    level_eois: Vec<u8>,
+}
+
+impl LocalApic {
+    /// The state after reset, where the APIC is enabled but not by software.
+    pub fn new() -> LocalApic {
+        LocalApic {
+            apic_base: LOCAL_APIC.0 as u64 | APIC_BASE_BSP | APIC_BASE_ENABLE,
+            id: 0,
+            tpr: 0,
+            ldr: 0,
+            dfr: 0xffff_ffff,
+            svr: 0xff,
+            isr: [0; 8],
+            tmr: [0; 8],
+            irr: [0; 8],
+            esr: 0,
+            icr: 0,
+            lvt: [LVT_MASKED; 7],
+            initial_count: 0,
+            current_count: 0,
+            divide: 0,
+            prescaler: 0,
+            tsc_deadline: 0,
+            level_eois: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.apic_base & APIC_BASE_ENABLE != 0 && self.svr & SVR_ENABLE != 0
+    }
+
+    pub fn is_x2apic(&self) -> bool {
+        self.apic_base & APIC_BASE_EXTD != 0
+    }
+
+    /// True if interrupts from the 8259 PIC reach the processor.
+    /// They pass through LINT0 programmed as ExtINT, or directly while the APIC is disabled.
+    pub fn accepts_ext_int(&self) -> bool {
+        let lint0 = self.lvt[lvt_index(LVT_LINT0).unwrap()];
+        !self.is_enabled()
+            || lint0 & LVT_MASKED == 0 && delivery_mode(lint0) == DELIVERY_MODE_EXT_INT
+    }
+
+    /// Accepts a message if it is destined to this APIC.
+    pub fn receive(&mut self, message: InterruptMessage) {
+        if !self.is_destination(message.destination, message.logical) {
+            return;
+        }
+        match u32::from(message.delivery_mode) {
+            DELIVERY_MODE_FIXED | DELIVERY_MODE_LOWEST_PRIORITY => {
+                self.request(message.vector, message.level_triggered)
+            }
+            _ => (),
+        }
+    }
+
+    /// True if an interrupt of higher priority than the processor priority is requested.
+    pub fn has_interrupt(&self) -> bool {
+        self.deliverable().is_some()
+    }
+
+    /// Moves the interrupt of the highest priority from IRR to ISR and returns its vector.
+    pub fn acknowledge(&mut self) -> Option<u8> {
+        let vector = self.deliverable()?;
+        clear_vector(&mut self.irr, vector);
+        set_vector(&mut self.isr, vector);
+        Some(vector)
+    }
+
+    /// Vectors of level triggered interrupts ended since the last call.
+    pub fn take_level_eois(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.level_eois)
+    }
+
+    pub fn timer_mode(&self) -> TimerMode {
+        match (self.lvt_timer() >> 17) & 0x3 {
+            1 => TimerMode::Periodic,
+            2 => TimerMode::TscDeadline,
+            _ => TimerMode::OneShot,
+        }
+    }
+
+    /// Advances the bus clock of the timer. `tsc` is the time stamp counter after that.
+    pub fn tick(&mut self, ticks: u64, tsc: u64) {
+        if self.timer_mode() == TimerMode::TscDeadline {
+            if self.tsc_deadline != 0 && tsc >= self.tsc_deadline {
+                self.tsc_deadline = 0;
+                self.fire_timer();
+            }
+            return;
+        }
+        if self.current_count == 0 {
+            return;
+        }
+        let clocks = self.prescaler + ticks;
+        let counts = clocks / self.divisor();
+        self.prescaler = clocks % self.divisor();
+        let current = u64::from(self.current_count);
+        if counts < current {
+            self.current_count -= counts as u32;
+            return;
+        }
+        self.fire_timer();
+        self.current_count = match self.timer_mode() {
+            TimerMode::Periodic if self.initial_count != 0 => {
+                let initial = u64::from(self.initial_count);
+                (initial - (counts - current) % initial) as u32
+            }
+            _ => 0,
+        };
+    }
+
+    /// Clocks until the timer expires next, unless it is masked or stopped.
+    pub fn ticks_to_timer(&self, tsc: u64) -> Option<u64> {
+        if self.lvt_timer() & LVT_MASKED != 0 {
+            return None;
+        }
+        match self.timer_mode() {
+            TimerMode::TscDeadline if self.tsc_deadline != 0 => {
+                Some(self.tsc_deadline.saturating_sub(tsc))
+            }
+            TimerMode::TscDeadline => None,
+            _ if self.current_count == 0 => None,
+            _ => Some(u64::from(self.current_count) * self.divisor() - self.prescaler),
+        }
+    }
+
+    /// Reads IA32_APIC_BASE, IA32_TSC_DEADLINE or an x2APIC register.
+    /// None if the MSR does not exist or cannot be read.
+    pub fn read_msr(&self, msr: u32) -> Option<u64> {
+        match msr {
+            IA32_APIC_BASE => Some(self.apic_base),
+            IA32_TSC_DEADLINE => Some(self.tsc_deadline),
+            X2APIC_MSR_BASE..=X2APIC_MSR_LAST if self.is_x2apic() => match x2apic_offset(msr) {
+                ICR_LOW => Some(self.icr),
+                EOI | SELF_IPI => None,
+                offset => self.read_register(offset).map(u64::from),
+            },
+            _ => None,
+        }
+    }
+
+    /// False if the MSR does not exist or the value is invalid.
+    pub fn write_msr(&mut self, msr: u32, value: u64) -> bool {
+        match msr {
+            IA32_APIC_BASE => self.write_apic_base(value),
+            IA32_TSC_DEADLINE => {
+                if self.timer_mode() == TimerMode::TscDeadline {
+                    self.tsc_deadline = value;
+                }
+                true
+            }
+            X2APIC_MSR_BASE..=X2APIC_MSR_LAST if self.is_x2apic() => {
+                match x2apic_offset(msr) {
+                    ICR_LOW => {
+                        self.icr = value;
+                        self.send_ipi();
+                        true
+                    }
+                    SELF_IPI => {
+                        self.request(value as u8, false);
+                        true
+                    }
+                    // Read-only registers and ones only in xAPIC mode.
+                    ID | VERSION | PPR | LDR | DFR | ICR_HIGH | TIMER_CURRENT_COUNT => false,
+                    ISR..=0x270 => false,
+                    offset => {
+                        self.write_register(offset, value as u32);
+                        true
+                    }
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // The relocation of the APIC and the transition from x2APIC to xAPIC are not supported.
+    fn write_apic_base(&mut self, value: u64) -> bool {
+        let enable = value & APIC_BASE_ENABLE != 0;
+        let extd = value & APIC_BASE_EXTD != 0;
+        if extd && !enable || self.is_x2apic() && enable && !extd {
+            return false;
+        }
+        if !enable {
+            *self = LocalApic {
+                apic_base: self.apic_base & !(APIC_BASE_ENABLE | APIC_BASE_EXTD),
+                ..LocalApic::new()
+            };
+            return true;
+        }
+        self.apic_base =
+            LOCAL_APIC.0 as u64 | value & (APIC_BASE_ENABLE | APIC_BASE_EXTD) | APIC_BASE_BSP;
+        true
+    }
+
+    fn read_register(&self, offset: u32) -> Option<u32> {
+        let value = match offset {
+            ID if self.is_x2apic() => self.id,
+            ID => self.id << 24,
+            VERSION => VERSION_VALUE,
+            TPR => self.tpr,
+            PPR => self.processor_priority(),
+            // The logical ID of x2APIC is derived from the APIC ID.
+            LDR if self.is_x2apic() => (self.id >> 4) << 16 | 1 << (self.id & 0xf),
+            LDR => self.ldr,
+            DFR if !self.is_x2apic() => self.dfr,
+            SVR => self.svr,
+            ISR..=0x170 => self.isr[((offset - ISR) >> 4) as usize],
+            TMR..=0x1f0 => self.tmr[((offset - TMR) >> 4) as usize],
+            IRR..=0x270 => self.irr[((offset - IRR) >> 4) as usize],
+            ESR => self.esr,
+            ICR_LOW => self.icr as u32,
+            ICR_HIGH => (self.icr >> 32) as u32,
+            LVT_CMCI | LVT_TIMER..=LVT_ERROR => self.lvt[lvt_index(offset)?],
+            TIMER_INITIAL_COUNT => self.initial_count,
+            TIMER_CURRENT_COUNT => self.current_count,
+            TIMER_DIVIDE => self.divide,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn write_register(&mut self, offset: u32, value: u32) {
+        match offset {
+            ID => self.id = value >> 24,
+            TPR => self.tpr = value & 0xff,
+            EOI => self.end_of_interrupt(),
+            LDR => self.ldr = value & 0xff00_0000,
+            DFR => self.dfr = value | 0x0fff_ffff,
+            SVR => {
+                self.svr = value & 0x1ff;
+                if value & SVR_ENABLE == 0 {
+                    self.lvt.iter_mut().for_each(|lvt| *lvt |= LVT_MASKED);
+                }
+            }
+            // Writing ESR updates it with errors, which are not kept separately here.
+            ESR => self.esr = 0,
+            ICR_LOW => {
+                self.icr = self.icr & !0xffff_ffff | u64::from(value);
+                self.send_ipi();
+            }
+            ICR_HIGH => self.icr = self.icr & 0xffff_ffff | u64::from(value) << 32,
+            LVT_CMCI | LVT_TIMER..=LVT_ERROR => self.write_lvt(offset, value),
+            TIMER_INITIAL_COUNT => {
+                self.initial_count = value;
+                if self.timer_mode() != TimerMode::TscDeadline {
+                    self.current_count = value;
+                    self.prescaler = 0;
+                }
+            }
+            TIMER_DIVIDE => self.divide = value & 0xb,
+            _ => (),
+        }
+    }
+
+    fn write_lvt(&mut self, offset: u32, value: u32) {
+        let index = match lvt_index(offset) {
+            Some(index) => index,
+            None => return,
+        };
+        let mut value = value & LVT_WRITABLE;
+        if self.svr & SVR_ENABLE == 0 {
+            value |= LVT_MASKED;
+        }
+        if offset == LVT_TIMER {
+            let old_mode = self.timer_mode();
+            self.lvt[index] = value;
+            // Changing the mode disarms the timer.
+            if old_mode != self.timer_mode() {
+                self.current_count = 0;
+                self.tsc_deadline = 0;
+            }
+        } else {
+            self.lvt[index] = value;
+        }
+    }
+
+    fn lvt_timer(&self) -> u32 {
+        self.lvt[lvt_index(LVT_TIMER).unwrap()]
+    }
+
+    fn fire_timer(&mut self) {
+        let lvt = self.lvt_timer();
+        if lvt & LVT_MASKED == 0 {
+            self.request(lvt as u8, false);
+        }
+    }
+
+    // Divide configuration is bits 0, 1 and 3. 0b111 divides by 1.
+    fn divisor(&self) -> u64 {
+        let value = self.divide & 0x3 | (self.divide & 0x8) >> 1;
+        if value == 0x7 {
+            1
+        } else {
+            2 << value
+        }
+    }
+
+    fn send_ipi(&mut self) {
+        let shorthand = (self.icr >> 18) & 0x3;
+        let destination = if self.is_x2apic() {
+            (self.icr >> 32) as u32
+        } else {
+            (self.icr >> 56) as u32
+        };
+        let to_self = match shorthand {
+            NO_SHORTHAND => self.is_destination(destination, self.icr & (1 << 11) != 0),
+            SELF | ALL_INCLUDING_SELF => true,
+            _ => false,
+        };
+        let vector = self.icr as u8;
+        match delivery_mode(self.icr as u32) {
+            DELIVERY_MODE_FIXED | DELIVERY_MODE_LOWEST_PRIORITY if vector < 16 => {
+                self.esr |= ESR_SEND_ILLEGAL_VECTOR
+            }
+            DELIVERY_MODE_FIXED | DELIVERY_MODE_LOWEST_PRIORITY if to_self => {
+                self.request(vector, self.icr & (1 << 15) != 0)
+            }
+            // INIT and SIPI have no other processor to start.
+            _ => (),
+        }
+    }
+
+    fn is_destination(&self, destination: u32, logical: bool) -> bool {
+        let broadcast = if self.is_x2apic() { 0xffff_ffff } else { 0xff };
+        if destination == broadcast {
+            return true;
+        }
+        match (logical, self.is_x2apic()) {
+            (false, _) => destination == self.id,
+            // Cluster ID and the bitmap of logical IDs in the cluster.
+            (true, true) => {
+                let ldr = self.read_register(LDR).unwrap();
+                destination >> 16 == ldr >> 16 && destination & ldr & 0xffff != 0
+            }
+            // Only the flat model is supported.
+            (true, false) => destination & (self.ldr >> 24) != 0,
+        }
+    }
+
+    fn request(&mut self, vector: u8, level_triggered: bool) {
+        if vector < 16 {
+            self.esr |= ESR_RECEIVE_ILLEGAL_VECTOR;
+            return;
+        }
+        if !self.is_enabled() {
+            return;
+        }
+        set_vector(&mut self.irr, vector);
+        if level_triggered {
+            set_vector(&mut self.tmr, vector);
+        } else {
+            clear_vector(&mut self.tmr, vector);
+        }
+    }
+
+    fn end_of_interrupt(&mut self) {
+        if let Some(vector) = highest_vector(&self.isr) {
+            clear_vector(&mut self.isr, vector);
+            if has_vector(&self.tmr, vector) {
+                self.level_eois.push(vector);
+            }
+        }
+    }
+
+    fn processor_priority(&self) -> u32 {
+        let isrv = highest_vector(&self.isr).map_or(0, u32::from);
+        if self.tpr & 0xf0 >= isrv & 0xf0 {
+            self.tpr & 0xff
+        } else {
+            isrv & 0xf0
+        }
+    }
+
+    fn deliverable(&self) -> Option<u8> {
+        let vector = highest_vector(&self.irr)?;
+        if u32::from(vector) & 0xf0 > self.processor_priority() & 0xf0 {
+            Some(vector)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for LocalApic {
+    fn default() -> LocalApic {
+        LocalApic::new()
+    }
+}
+
+/// Registers are 32-bit wide and aligned to 16 bytes.
+/// They are not accessible from memory in x2APIC mode.
+impl MemoryAccess for LocalApic {
+    fn read_u8(&self, addr: usize) -> Result<u8> {
+        let value = self.read_u32(addr & !0x3)?;
+        Ok((value >> ((addr & 0x3) * 8)) as u8)
+    }
+
+    fn read_u32(&self, addr: usize) -> Result<u32> {
+        if self.is_x2apic() || addr & 0xf != 0 {
+            return Ok(0);
+        }
+        Ok(self.read_register(addr as u32).unwrap_or(0))
+    }
+
+    /// Registers must be written by 32-bit accesses.
+    fn write_u8(&mut self, _addr: usize, _data: u8) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_u32(&mut self, addr: usize, data: u32) -> Result<()> {
+        if !self.is_x2apic() && addr & 0xf == 0 {
+            self.write_register(addr as u32, data);
+        }
+        Ok(())
+    }
+}
+
+fn x2apic_offset(msr: u32) -> u32 {
+    (msr - X2APIC_MSR_BASE) << 4
+}
+
+fn lvt_index(offset: u32) -> Option<usize> {
+    match offset {
+        LVT_CMCI => Some(0),
+        LVT_TIMER..=LVT_ERROR if offset & 0xf == 0 => {
+            Some(((offset - LVT_TIMER) >> 4) as usize + 1)
+        }
+        _ => None,
+    }
+}
+
+fn delivery_mode(value: u32) -> u32 {
+    (value >> 8) & 0x7
+}
+
+fn set_vector(set: &mut [u32; 8], vector: u8) {
+    set[usize::from(vector >> 5)] |= 1 << (vector & 0x1f);
+}
+
+fn clear_vector(set: &mut [u32; 8], vector: u8) {
+    set[usize::from(vector >> 5)] &= !(1 << (vector & 0x1f));
+}
+
+fn has_vector(set: &[u32; 8], vector: u8) -> bool {
+    set[usize::from(vector >> 5)] & 1 << (vector & 0x1f) != 0
+}
+
+fn highest_vector(set: &[u32; 8]) -> Option<u8> {
+    set.iter()
+        .enumerate()
+        .rev()
+        .find(|(_, bits)| **bits != 0)
+        .map(|(i, bits)| (i * 32 + 31 - bits.leading_zeros() as usize) as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn enabled_apic() -> LocalApic {
+        let mut apic = LocalApic::new();
+        apic.write_u32(SVR as usize, 0x1ff).unwrap();
+        apic
+    }
+
+    #[test]
+    fn priority_and_eoi() {
+        let mut apic = enabled_apic();
+        assert_eq!(apic.read_u32(VERSION as usize).unwrap(), VERSION_VALUE);
+        apic.request(0x31, false);
+        apic.request(0x52, true);
+        apic.write_u32(TPR as usize, 0x50).unwrap();
+        assert!(!apic.has_interrupt());
+        apic.write_u32(TPR as usize, 0x40).unwrap();
+        assert_eq!(apic.acknowledge(), Some(0x52));
+        // 0x31 is blocked by 0x52 in service.
+        assert_eq!(apic.read_u32(PPR as usize).unwrap(), 0x50);
+        assert!(!apic.has_interrupt());
+        apic.write_u32(EOI as usize, 0).unwrap();
+        assert_eq!(apic.take_level_eois(), vec![0x52]);
+        apic.write_u32(TPR as usize, 0).unwrap();
+        assert_eq!(apic.read_u32(0x200 + 0x10).unwrap(), 1 << 0x11);
+        assert_eq!(apic.acknowledge(), Some(0x31));
+        assert_eq!(apic.read_u32(0x100 + 0x10).unwrap(), 1 << 0x11);
+    }
+
+    #[test]
+    fn timer_modes() {
+        let mut apic = enabled_apic();
+        // Periodic timer of vector 0x40, divided by 2.
+        apic.write_u32(TIMER_DIVIDE as usize, 0x0).unwrap();
+        apic.write_u32(LVT_TIMER as usize, 0x2_0040).unwrap();
+        apic.write_u32(TIMER_INITIAL_COUNT as usize, 100).unwrap();
+        assert_eq!(apic.ticks_to_timer(0), Some(200));
+        apic.tick(199, 199);
+        assert!(!apic.has_interrupt());
+        apic.tick(1, 200);
+        assert_eq!(apic.acknowledge(), Some(0x40));
+        assert_eq!(apic.read_u32(TIMER_CURRENT_COUNT as usize).unwrap(), 100);
+        apic.write_u32(EOI as usize, 0).unwrap();
+
+        // TSC-deadline mode.
+        apic.write_u32(LVT_TIMER as usize, 0x4_0041).unwrap();
+        assert!(apic.write_msr(IA32_TSC_DEADLINE, 1000));
+        assert_eq!(apic.ticks_to_timer(600), Some(400));
+        apic.tick(400, 1000);
+        assert_eq!(apic.acknowledge(), Some(0x41));
+        assert_eq!(apic.read_msr(IA32_TSC_DEADLINE), Some(0));
+    }
+
+    #[test]
+    fn x2apic_and_ipi() {
+        let mut apic = enabled_apic();
+        // Fixed IPI to itself by the physical ID.
+        apic.write_u32(ICR_HIGH as usize, 0).unwrap();
+        apic.write_u32(ICR_LOW as usize, 0x0000_0050).unwrap();
+        assert_eq!(apic.acknowledge(), Some(0x50));
+        apic.write_u32(ICR_LOW as usize, 0x0000_0005).unwrap();
+        assert_eq!(
+            apic.read_u32(ESR as usize).unwrap(),
+            ESR_SEND_ILLEGAL_VECTOR
+        );
+
+        // x2APIC can be entered only from xAPIC.
+        assert!(!apic.write_msr(IA32_APIC_BASE, 0xfee0_0400));
+        assert!(apic.write_msr(IA32_APIC_BASE, 0xfee0_0c00));
+        assert!(apic.is_x2apic());
+        assert_eq!(apic.read_u32(SVR as usize).unwrap(), 0);
+        assert_eq!(apic.read_msr(0x80f), Some(0x1ff));
+        assert_eq!(apic.read_msr(0x80d), Some(0x1));
+        assert_eq!(apic.read_msr(0x80b), None);
+        assert!(!apic.write_msr(0x802, 1));
+        assert!(apic.write_msr(0x83f, 0x60));
+        assert!(apic.write_msr(0x830, 0x0004_0061));
+        assert_eq!(apic.read_u32(0x100 + 0x20).unwrap(), 0);
+        assert_eq!(apic.read_msr(0x800 + 0x23), Some(0x3));
+        assert!(!apic.write_msr(IA32_APIC_BASE, 0xfee0_0800));
+    }
+}
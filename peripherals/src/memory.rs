@@ -24,6 +24,10 @@ impl Memory {
         }
     }
 
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
     pub fn fill_ram(&mut self, data: &[u8], start: usize) {
         for (pos, b) in data.iter().enumerate() {
             self.ram[start + pos] = *b;
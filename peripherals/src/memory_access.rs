@@ -1,6 +1,8 @@
 use crate::error::MemoryAccessError;
 use byteorder::{LittleEndian, ReadBytesExt};
+use std::cell::RefCell;
 use std::mem;
+use std::rc::Rc;
 use std::result;
 
 pub type Result<T> = result::Result<T, MemoryAccessError>;
@@ -108,6 +110,41 @@ pub trait MemoryAccess {
     }
 }
 
+/// A device shared with its owner, which signals interrupts or advances time of the device.
+impl<T: MemoryAccess> MemoryAccess for Rc<RefCell<T>> {
+    fn read_u8(&self, addr: usize) -> Result<u8> {
+        self.borrow().read_u8(addr)
+    }
+
+    fn read_u16(&self, addr: usize) -> Result<u16> {
+        self.borrow().read_u16(addr)
+    }
+
+    fn read_u32(&self, addr: usize) -> Result<u32> {
+        self.borrow().read_u32(addr)
+    }
+
+    fn read_u64(&self, addr: usize) -> Result<u64> {
+        self.borrow().read_u64(addr)
+    }
+
+    fn write_u8(&mut self, addr: usize, data: u8) -> Result<()> {
+        self.borrow_mut().write_u8(addr, data)
+    }
+
+    fn write_u16(&mut self, addr: usize, data: u16) -> Result<()> {
+        self.borrow_mut().write_u16(addr, data)
+    }
+
+    fn write_u32(&mut self, addr: usize, data: u32) -> Result<()> {
+        self.borrow_mut().write_u32(addr, data)
+    }
+
+    fn write_u64(&mut self, addr: usize, data: u64) -> Result<()> {
+        self.borrow_mut().write_u64(addr, data)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
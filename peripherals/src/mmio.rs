@@ -40,27 +40,85 @@ impl Mmio {
         self.memory_map.insert(range, device);
         Ok(())
     }
+
+    /// True if a device is mapped at the address.
+    pub fn contains(&self, addr: usize) -> bool {
+        self.device(addr).is_some()
+    }
+
+    // The device and the offset in it.
+    fn device(&self, addr: usize) -> Option<(&dyn MemoryAccess, usize)> {
+        self.memory_map
+            .iter()
+            .find(|((base, length), _)| *base <= addr && addr < *base + *length)
+            .map(|((base, _), device)| (device.as_ref(), addr - *base))
+    }
+
+    fn device_mut(&mut self, addr: usize) -> Option<(&mut Box<dyn MemoryAccess>, usize)> {
+        self.memory_map
+            .iter_mut()
+            .find(|((base, length), _)| *base <= addr && addr < *base + *length)
+            .map(|((base, _), device)| (device, addr - *base))
+    }
 }
 
+/// A wide access is passed to the device as it is, as registers of devices
+/// may behave differently from a sequence of byte accesses.
 impl MemoryAccess for Mmio {
     fn read_u8(&self, addr: usize) -> memory_access::Result<u8> {
-        for (range, device) in &self.memory_map {
-            let (base, length) = range;
-            if *base <= addr && addr < *base + *length {
-                return device.read_u8(addr - *base);
-            }
-        }
-        Err(MemoryAccessError::DeviceNotMapped { addr })
+        let (device, offset) = self
+            .device(addr)
+            .ok_or(MemoryAccessError::DeviceNotMapped { addr })?;
+        device.read_u8(offset)
+    }
+
+    fn read_u16(&self, addr: usize) -> memory_access::Result<u16> {
+        let (device, offset) = self
+            .device(addr)
+            .ok_or(MemoryAccessError::DeviceNotMapped { addr })?;
+        device.read_u16(offset)
+    }
+
+    fn read_u32(&self, addr: usize) -> memory_access::Result<u32> {
+        let (device, offset) = self
+            .device(addr)
+            .ok_or(MemoryAccessError::DeviceNotMapped { addr })?;
+        device.read_u32(offset)
+    }
+
+    fn read_u64(&self, addr: usize) -> memory_access::Result<u64> {
+        let (device, offset) = self
+            .device(addr)
+            .ok_or(MemoryAccessError::DeviceNotMapped { addr })?;
+        device.read_u64(offset)
     }
 
     fn write_u8(&mut self, addr: usize, data: u8) -> memory_access::Result<()> {
-        for (range, device) in &mut self.memory_map {
-            let (base, length) = range;
-            if *base <= addr && addr < *base + *length {
-                return device.write_u8(addr - base, data);
-            }
-        }
-        Err(MemoryAccessError::DeviceNotMapped { addr })
+        let (device, offset) = self
+            .device_mut(addr)
+            .ok_or(MemoryAccessError::DeviceNotMapped { addr })?;
+        device.write_u8(offset, data)
+    }
+
+    fn write_u16(&mut self, addr: usize, data: u16) -> memory_access::Result<()> {
+        let (device, offset) = self
+            .device_mut(addr)
+            .ok_or(MemoryAccessError::DeviceNotMapped { addr })?;
+        device.write_u16(offset, data)
+    }
+
+    fn write_u32(&mut self, addr: usize, data: u32) -> memory_access::Result<()> {
+        let (device, offset) = self
+            .device_mut(addr)
+            .ok_or(MemoryAccessError::DeviceNotMapped { addr })?;
+        device.write_u32(offset, data)
+    }
+
+    fn write_u64(&mut self, addr: usize, data: u64) -> memory_access::Result<()> {
+        let (device, offset) = self
+            .device_mut(addr)
+            .ok_or(MemoryAccessError::DeviceNotMapped { addr })?;
+        device.write_u64(offset, data)
     }
 }
 
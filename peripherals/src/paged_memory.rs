@@ -0,0 +1,108 @@
+//! Sparse memory which consists of mapped pages.
+//! It is suitable for the address space of a user process scattered in 64-bit space.
+use crate::error::MemoryAccessError;
+use crate::memory_access::{MemoryAccess, Result};
+use std::collections::HashMap;
+use std::ops::Range;
+
+pub const PAGE_SIZE: usize = 0x1000;
+
+/// Memory of mapped 4KiB pages. A page is allocated when it is written for the first time,
+/// and unallocated pages are read as zero.
+#[derive(Default)]
+pub struct PagedMemory {
+    pages: HashMap<usize, Option<Box<[u8]>>>,
+}
+
+impl PagedMemory {
+    pub fn new() -> PagedMemory {
+        PagedMemory::default()
+    }
+
+    /// Maps zero-filled pages which cover `len` bytes from `addr`.
+    /// Pages already mapped keep their contents.
+    pub fn map(&mut self, addr: usize, len: usize) {
+        for page in page_range(addr, len) {
+            self.pages.entry(page).or_insert(None);
+        }
+    }
+
+    /// Unmaps pages which cover `len` bytes from `addr`.
+    pub fn unmap(&mut self, addr: usize, len: usize) {
+        for page in page_range(addr, len) {
+            self.pages.remove(&page);
+        }
+    }
+
+    /// Check all pages which cover `len` bytes from `addr` are mapped.
+    pub fn is_mapped(&self, addr: usize, len: usize) -> bool {
+        page_range(addr, len).all(|page| self.pages.contains_key(&page))
+    }
+
+    /// Copies `data` to the mapped pages from `start`.
+    pub fn fill(&mut self, data: &[u8], start: usize) -> Result<()> {
+        for (pos, b) in data.iter().enumerate() {
+            self.write_u8(start + pos, *b)?;
+        }
+        Ok(())
+    }
+}
+
+// Page numbers which cover `len` bytes from `addr`.
+fn page_range(addr: usize, len: usize) -> Range<usize> {
+    let end = addr.saturating_add(len).saturating_add(PAGE_SIZE - 1);
+    (addr / PAGE_SIZE)..(end / PAGE_SIZE)
+}
+
+impl MemoryAccess for PagedMemory {
+    fn read_u8(&self, addr: usize) -> Result<u8> {
+        match self.pages.get(&(addr / PAGE_SIZE)) {
+            Some(Some(page)) => Ok(page[addr % PAGE_SIZE]),
+            Some(None) => Ok(0),
+            None => Err(MemoryAccessError::DeviceNotMapped { addr }),
+        }
+    }
+
+    fn write_u8(&mut self, addr: usize, data: u8) -> Result<()> {
+        match self.pages.get_mut(&(addr / PAGE_SIZE)) {
+            Some(page) => {
+                let page = page.get_or_insert_with(|| vec![0; PAGE_SIZE].into_boxed_slice());
+                page[addr % PAGE_SIZE] = data;
+                Ok(())
+            }
+            None => Err(MemoryAccessError::DeviceNotMapped { addr }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn map_and_access() {
+        let mut memory = PagedMemory::new();
+        memory.map(0x7fff_ffff_e800, 0x1000);
+
+        assert!(memory.is_mapped(0x7fff_ffff_e000, 0x2000));
+        assert_eq!(memory.read_u64(0x7fff_ffff_e000).unwrap(), 0);
+        // Access across the page boundary.
+        assert!(memory.write_u64(0x7fff_ffff_effc, 0x1122_3344_5566_7788).is_ok());
+        assert_eq!(memory.read_u64(0x7fff_ffff_effc).unwrap(), 0x1122_3344_5566_7788);
+    }
+
+    #[test]
+    fn unmapped_access() {
+        let mut memory = PagedMemory::new();
+        memory.map(0x40_0000, 0x2000);
+        memory.unmap(0x40_1000, 1);
+
+        assert!(!memory.is_mapped(0x40_0000, 0x2000));
+        assert!(memory.fill(&[1, 2, 3], 0x40_0000).is_ok());
+        assert_eq!(
+            memory.read_u8(0x40_1000),
+            Err(MemoryAccessError::DeviceNotMapped { addr: 0x40_1000 })
+        );
+        assert!(memory.write_u8(0x10, 0).is_err());
+    }
+}
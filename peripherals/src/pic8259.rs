@@ -0,0 +1,291 @@
+//! Two cascaded 8259A programmable interrupt controllers of PC/AT.
+//! IRQ0-7 go to the master, and IRQ8-15 to the slave connected on IRQ2 of the master.
+//! Priorities are fixed, IRQ0 is the highest, and all inputs are edge triggered.
+use crate::io_bus::{IoBus, IoDevice, PortRange};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub const MASTER_PIC: PortRange = (0x20, 2);
+pub const SLAVE_PIC: PortRange = (0xa0, 2);
+
+// IRQ of the master which the slave is connected to.
+const CASCADE_IRQ: u8 = 2;
+
+// Register offsets.
+const COMMAND: u16 = 0;
+const DATA: u16 = 1;
+
+const ICW1: u8 = 1 << 4;
+const ICW1_NEEDS_ICW4: u8 = 1 << 0;
+const ICW1_SINGLE: u8 = 1 << 1;
+const ICW4_AUTO_EOI: u8 = 1 << 1;
+const OCW3: u8 = 1 << 3;
+const OCW3_READ_REGISTER: u8 = 1 << 1;
+const OCW3_READ_ISR: u8 = 1 << 0;
+// Commands in the upper 3 bits of OCW2.
+const NON_SPECIFIC_EOI: u8 = 0b001;
+const SPECIFIC_EOI: u8 = 0b011;
+const ROTATE_ON_NON_SPECIFIC_EOI: u8 = 0b101;
+const ROTATE_ON_SPECIFIC_EOI: u8 = 0b111;
+
+// The initialization command word expected next on the data port.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Initialization {
+    Done,
+    Icw2,
+    Icw3,
+    Icw4,
+}
+
+struct Pic8259 {
+    irr: u8,
+    isr: u8,
+    imr: u8,
+    vector_base: u8,
+    initialization: Initialization,
+    single: bool,
+    needs_icw4: bool,
+    auto_eoi: bool,
+    read_isr: bool,
+}
+
+impl Pic8259 {
+    fn new() -> Pic8259 {
+        Pic8259 {
+            irr: 0,
+            isr: 0,
+            imr: 0,
+            vector_base: 0,
+            initialization: Initialization::Done,
+            single: false,
+            needs_icw4: false,
+            auto_eoi: false,
+            read_isr: false,
+        }
+    }
+
+    // The highest priority request which is neither masked nor blocked by one in service.
+    // `irr` includes the request from the slave.
+    fn pending(&self, irr: u8) -> Option<u8> {
+        let requests = irr & !self.imr;
+        if requests == 0 {
+            return None;
+        }
+        let irq = requests.trailing_zeros();
+        if irq < self.isr.trailing_zeros() {
+            Some(irq as u8)
+        } else {
+            None
+        }
+    }
+
+    fn acknowledge(&mut self, irq: u8) -> u8 {
+        self.irr &= !(1 << irq);
+        if !self.auto_eoi {
+            self.isr |= 1 << irq;
+        }
+        self.vector_base + irq
+    }
+
+    fn end_of_interrupt(&mut self, command: u8) {
+        match command >> 5 {
+            NON_SPECIFIC_EOI | ROTATE_ON_NON_SPECIFIC_EOI => {
+                // Clears the highest priority one in service.
+                self.isr &= self.isr.wrapping_sub(1);
+            }
+            SPECIFIC_EOI | ROTATE_ON_SPECIFIC_EOI => self.isr &= !(1 << (command & 0x7)),
+            // Rotation and special mask modes are not supported.
+            _ => (),
+        }
+    }
+
+    fn write_command(&mut self, data: u8) {
+        if data & ICW1 != 0 {
+            *self = Pic8259 {
+                initialization: Initialization::Icw2,
+                single: data & ICW1_SINGLE != 0,
+                needs_icw4: data & ICW1_NEEDS_ICW4 != 0,
+                ..Pic8259::new()
+            };
+        } else if data & OCW3 != 0 {
+            if data & OCW3_READ_REGISTER != 0 {
+                self.read_isr = data & OCW3_READ_ISR != 0;
+            }
+        } else {
+            self.end_of_interrupt(data);
+        }
+    }
+
+    fn write_data(&mut self, data: u8) {
+        self.initialization = match self.initialization {
+            Initialization::Icw2 => {
+                self.vector_base = data & 0xf8;
+                match (self.single, self.needs_icw4) {
+                    (false, _) => Initialization::Icw3,
+                    (true, true) => Initialization::Icw4,
+                    (true, false) => Initialization::Done,
+                }
+            }
+            // The slave is always connected on IRQ2.
+            Initialization::Icw3 if self.needs_icw4 => Initialization::Icw4,
+            Initialization::Icw3 => Initialization::Done,
+            Initialization::Icw4 => {
+                self.auto_eoi = data & ICW4_AUTO_EOI != 0;
+                Initialization::Done
+            }
+            Initialization::Done => {
+                self.imr = data;
+                Initialization::Done
+            }
+        };
+    }
+}
+
+impl IoDevice for Pic8259 {
+    fn read_u8(&self, port: u16) -> u8 {
+        match port {
+            COMMAND if self.read_isr => self.isr,
+            COMMAND => self.irr,
+            _ => self.imr,
+        }
+    }
+
+    fn write_u8(&mut self, port: u16, data: u8) {
+        match port {
+            COMMAND => self.write_command(data),
+            DATA => self.write_data(data),
+            _ => (),
+        }
+    }
+}
+
+/// The master and the slave. Each of them is shared with the port I/O bus.
+pub struct Pic {
+    master: Rc<RefCell<Pic8259>>,
+    slave: Rc<RefCell<Pic8259>>,
+}
+
+impl Pic {
+    pub fn new() -> Pic {
+        Pic {
+            master: Rc::new(RefCell::new(Pic8259::new())),
+            slave: Rc::new(RefCell::new(Pic8259::new())),
+        }
+    }
+
+    /// Maps the ports of both controllers on the bus.
+    pub fn attach(&self, io_bus: &mut IoBus) {
+        io_bus.add(MASTER_PIC, Box::new(self.master.clone()));
+        io_bus.add(SLAVE_PIC, Box::new(self.slave.clone()));
+    }
+
+    /// Raises the edge of the IRQ line from 0 to 15.
+    pub fn raise_irq(&mut self, irq: u8) {
+        if irq < 8 {
+            self.master.borrow_mut().irr |= 1 << irq;
+        } else {
+            self.slave.borrow_mut().irr |= 1 << (irq - 8);
+        }
+    }
+
+    /// True if the IRQ is masked on the controller or, for the slave, on the cascade.
+    pub fn is_masked(&self, irq: u8) -> bool {
+        let master = self.master.borrow().imr;
+        if irq < 8 {
+            master & (1 << irq) != 0
+        } else {
+            master & (1 << CASCADE_IRQ) != 0 || self.slave.borrow().imr & (1 << (irq - 8)) != 0
+        }
+    }
+
+    /// True if the INTR line to the CPU is asserted.
+    pub fn has_interrupt(&self) -> bool {
+        let master = self.master.borrow();
+        master.pending(master.irr | self.cascade()).is_some()
+    }
+
+    /// Responds to the interrupt acknowledge cycle of the CPU and returns the vector.
+    pub fn acknowledge(&mut self) -> Option<u8> {
+        let cascade = self.cascade();
+        let mut master = self.master.borrow_mut();
+        let irq = master.pending(master.irr | cascade)?;
+        let vector = master.acknowledge(irq);
+        let mut slave = self.slave.borrow_mut();
+        match slave.pending(slave.irr) {
+            Some(slave_irq) if irq == CASCADE_IRQ => Some(slave.acknowledge(slave_irq)),
+            _ => Some(vector),
+        }
+    }
+
+    // The output of the slave seen as a request on the master.
+    fn cascade(&self) -> u8 {
+        let slave = self.slave.borrow();
+        if slave.pending(slave.irr).is_some() {
+            1 << CASCADE_IRQ
+        } else {
+            0
+        }
+    }
+}
+
+impl Default for Pic {
+    fn default() -> Pic {
+        Pic::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Remaps IRQ0-15 to 0x20-0x2f as operating systems do.
+    fn initialized_pic(auto_eoi: bool) -> (Pic, IoBus) {
+        let pic = Pic::new();
+        let mut io_bus = IoBus::empty();
+        pic.attach(&mut io_bus);
+        let icw4 = if auto_eoi { 0x03 } else { 0x01 };
+        for (port, icw2, icw3) in &[(0x20, 0x20, 0x04), (0xa0, 0x28, 0x02)] {
+            io_bus.write_u8(*port, 0x11);
+            io_bus.write_u8(*port + 1, *icw2);
+            io_bus.write_u8(*port + 1, *icw3);
+            io_bus.write_u8(*port + 1, icw4);
+        }
+        (pic, io_bus)
+    }
+
+    #[test]
+    fn priority_and_eoi() {
+        let (mut pic, mut io_bus) = initialized_pic(false);
+        pic.raise_irq(4);
+        pic.raise_irq(1);
+        assert_eq!(io_bus.read_u8(0x20), 0x12);
+        assert_eq!(pic.acknowledge(), Some(0x21));
+        // IRQ4 waits until IRQ1 ends.
+        assert!(!pic.has_interrupt());
+        io_bus.write_u8(0x20, 0x0b);
+        assert_eq!(io_bus.read_u8(0x20), 0x02);
+        io_bus.write_u8(0x20, 0x20);
+        assert_eq!(pic.acknowledge(), Some(0x24));
+        io_bus.write_u8(0x20, 0x64);
+        assert_eq!(io_bus.read_u8(0x20), 0x00);
+
+        // Masked requests are kept until unmasked.
+        io_bus.write_u8(0x21, 0x01);
+        pic.raise_irq(0);
+        assert!(!pic.has_interrupt());
+        io_bus.write_u8(0x21, 0x00);
+        assert_eq!(pic.acknowledge(), Some(0x20));
+    }
+
+    #[test]
+    fn cascade_and_auto_eoi() {
+        let (mut pic, io_bus) = initialized_pic(true);
+        pic.raise_irq(12);
+        assert!(pic.has_interrupt());
+        assert_eq!(pic.acknowledge(), Some(0x2c));
+        assert_eq!(pic.acknowledge(), None);
+        assert_eq!(io_bus.read_u8(0xa0), 0x00);
+        pic.raise_irq(12);
+        assert_eq!(pic.acknowledge(), Some(0x2c));
+    }
+}
@@ -0,0 +1,299 @@
+//! 8254 programmable interval timer of PC/AT.
+//! The output of channel 0 is connected to IRQ0, and channel 2 to the speaker.
+//! Time advances only by `tick()`, which callers relate to their own clock.
+//!
+//! Gates are tied high as port 0x61 is not emulated. So modes 1 and 5, which start
+//! on a rising edge of the gate, never count. BCD counting is not supported either.
+use crate::io_bus::{IoDevice, PortRange};
+use std::cell::RefCell;
+
+pub const PIT: PortRange = (0x40, 4);
+/// Frequency of the input clock in Hz.
+pub const PIT_FREQUENCY: u64 = 1_193_182;
+
+const CONTROL: u16 = 3;
+const READ_BACK: u8 = 3;
+const READ_BACK_COUNT: u8 = 1 << 5;
+const READ_BACK_STATUS: u8 = 1 << 4;
+const LATCH: u8 = 0;
+const LSB_ONLY: u8 = 1;
+const MSB_ONLY: u8 = 2;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counter {
+    mode: u8,
+    access: u8,
+    bcd: bool,
+    reload: u16,
+    // Clocks until the terminal count, from 1 to 0x10000, while running.
+    count: u32,
+    running: bool,
+    output: bool,
+    // Modes 0 and 4 raise the output only once after a count is written.
+    fired: bool,
+    null_count: bool,
+    write_msb: bool,
+    read_msb: bool,
+    latch: Option<u16>,
+    status: Option<u8>,
+}
+
+impl Counter {
+    fn set_mode(&mut self, access: u8, mode: u8, bcd: bool) {
+        *self = Counter {
+            // Modes 6 and 7 are aliases of 2 and 3.
+            mode: if mode >= 6 { mode - 4 } else { mode },
+            access,
+            bcd,
+            output: mode != 0,
+            null_count: true,
+            ..Counter::default()
+        };
+    }
+
+    // A count of 0 stands for 0x10000.
+    fn period(&self) -> u32 {
+        if self.reload == 0 {
+            0x1_0000
+        } else {
+            u32::from(self.reload)
+        }
+    }
+
+    fn current(&self) -> u16 {
+        self.count as u16
+    }
+
+    fn status(&self) -> u8 {
+        (self.output as u8) << 7
+            | (self.null_count as u8) << 6
+            | self.access << 4
+            | self.mode << 1
+            | self.bcd as u8
+    }
+
+    fn write(&mut self, data: u8) {
+        let data = u16::from(data);
+        match self.access {
+            LSB_ONLY => self.reload = data,
+            MSB_ONLY => self.reload = data << 8,
+            _ if !self.write_msb => {
+                self.reload = self.reload & 0xff00 | data;
+                self.write_msb = true;
+                return;
+            }
+            _ => {
+                self.reload = self.reload & 0x00ff | data << 8;
+                self.write_msb = false;
+            }
+        }
+        self.load();
+    }
+
+    fn load(&mut self) {
+        self.null_count = false;
+        match self.mode {
+            // A new count takes effect at the end of the current period.
+            2 | 3 if self.running => (),
+            1 | 5 => (),
+            _ => {
+                self.count = self.period();
+                self.running = true;
+                self.fired = false;
+                self.output = self.mode != 0;
+            }
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        if let Some(status) = self.status.take() {
+            return status;
+        }
+        let value = self.latch.unwrap_or_else(|| self.current());
+        let (byte, done) = match self.access {
+            LSB_ONLY => (value as u8, true),
+            MSB_ONLY => ((value >> 8) as u8, true),
+            _ if !self.read_msb => (value as u8, false),
+            _ => ((value >> 8) as u8, true),
+        };
+        self.read_msb = !done;
+        if done {
+            self.latch = None;
+        }
+        byte
+    }
+
+    // Advances the count and returns the number of rising edges of the output.
+    fn advance(&mut self, ticks: u64) -> u64 {
+        if !self.running {
+            return 0;
+        }
+        let count = u64::from(self.count);
+        match self.mode {
+            0 | 4 => {
+                if ticks < count {
+                    self.count -= ticks as u32;
+                    return 0;
+                }
+                // The counter wraps around and keeps counting after the terminal count.
+                self.count = (0x1_0000 - (ticks - count) % 0x1_0000) as u32;
+                if self.fired {
+                    return 0;
+                }
+                self.fired = true;
+                self.output = true;
+                1
+            }
+            2 | 3 => {
+                let edges = if ticks < count {
+                    self.count -= ticks as u32;
+                    0
+                } else {
+                    let period = u64::from(self.period());
+                    let rest = ticks - count;
+                    self.count = (period - rest % period) as u32;
+                    1 + rest / period
+                };
+                // Mode 2 is low for the last clock, and mode 3 for the latter half.
+                self.output = if self.mode == 2 {
+                    self.count != 1
+                } else {
+                    self.count > self.period() / 2
+                };
+                edges
+            }
+            _ => 0,
+        }
+    }
+
+    fn ticks_to_edge(&self) -> Option<u64> {
+        match self.mode {
+            _ if !self.running => None,
+            0 | 4 if self.fired => None,
+            0 | 2 | 3 | 4 => Some(u64::from(self.count)),
+            _ => None,
+        }
+    }
+}
+
+pub struct Pit8254 {
+    counters: RefCell<[Counter; 3]>,
+}
+
+impl Pit8254 {
+    pub fn new() -> Pit8254 {
+        Pit8254 {
+            counters: RefCell::new([Counter::default(); 3]),
+        }
+    }
+
+    /// Advances the input clock. True if the output of channel 0 rose.
+    pub fn tick(&mut self, ticks: u64) -> bool {
+        let counters = self.counters.get_mut();
+        let edges = counters[0].advance(ticks);
+        counters[1].advance(ticks);
+        counters[2].advance(ticks);
+        edges > 0
+    }
+
+    /// Clocks until the output of channel 0 rises next, if it will.
+    pub fn ticks_to_irq0(&self) -> Option<u64> {
+        self.counters.borrow()[0].ticks_to_edge()
+    }
+
+    fn write_control(&mut self, data: u8) {
+        let counters = self.counters.get_mut();
+        let channel = data >> 6;
+        if channel == READ_BACK {
+            for counter in (0..3).filter(|i| data & (2 << i) != 0) {
+                let counter = &mut counters[counter];
+                if data & READ_BACK_COUNT == 0 && counter.latch.is_none() {
+                    counter.latch = Some(counter.current());
+                }
+                if data & READ_BACK_STATUS == 0 && counter.status.is_none() {
+                    counter.status = Some(counter.status());
+                }
+            }
+            return;
+        }
+        let counter = &mut counters[channel as usize];
+        let access = (data >> 4) & 0x3;
+        if access == LATCH {
+            if counter.latch.is_none() {
+                counter.latch = Some(counter.current());
+            }
+        } else {
+            counter.set_mode(access, (data >> 1) & 0x7, data & 1 != 0);
+        }
+    }
+}
+
+impl Default for Pit8254 {
+    fn default() -> Pit8254 {
+        Pit8254::new()
+    }
+}
+
+impl IoDevice for Pit8254 {
+    fn read_u8(&self, port: u16) -> u8 {
+        match port {
+            0..=2 => self.counters.borrow_mut()[port as usize].read(),
+            // The control word register is write-only.
+            _ => 0xff,
+        }
+    }
+
+    fn write_u8(&mut self, port: u16, data: u8) {
+        match port {
+            0..=2 => self.counters.get_mut()[port as usize].write(data),
+            CONTROL => self.write_control(data),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rate_generator() {
+        let mut pit = Pit8254::new();
+        // Channel 0, LSB then MSB, mode 2, 1000 clocks.
+        pit.write_u8(CONTROL, 0x34);
+        pit.write_u8(0, 0xe8);
+        pit.write_u8(0, 0x03);
+        assert_eq!(pit.ticks_to_irq0(), Some(1000));
+        assert!(!pit.tick(999));
+        assert!(pit.tick(1));
+        assert!(pit.tick(2500));
+        assert_eq!(pit.ticks_to_irq0(), Some(500));
+
+        // Latch the count, then read it as LSB and MSB.
+        pit.write_u8(CONTROL, 0x00);
+        pit.tick(100);
+        assert_eq!(pit.read_u8(0), 0xf4);
+        assert_eq!(pit.read_u8(0), 0x01);
+        assert_eq!(pit.read_u8(0), 0x90);
+    }
+
+    #[test]
+    fn one_shot_and_read_back() {
+        let mut pit = Pit8254::new();
+        // Channel 2, LSB only, mode 0.
+        pit.write_u8(CONTROL, 0x90);
+        pit.write_u8(2, 0x10);
+        pit.write_u8(CONTROL, 0xe8);
+        assert_eq!(pit.read_u8(2), 0x10);
+        pit.tick(0x10);
+        pit.write_u8(CONTROL, 0xe8);
+        assert_eq!(pit.read_u8(2), 0x90);
+
+        // Channel 0, MSB only, mode 4 fires once.
+        pit.write_u8(CONTROL, 0x28);
+        pit.write_u8(0, 0x01);
+        assert!(pit.tick(0x100));
+        assert_eq!(pit.ticks_to_irq0(), None);
+        assert!(!pit.tick(0x1_0000));
+    }
+}
@@ -1,104 +1,157 @@
-use crate::error::MemoryAccessError;
-use crate::memory_access::{MemoryAccess, Result};
+//! UART 16550 mapped on the I/O ports of COM1.
+//! Transmitted data goes to the target immediately, so the transmitter is always empty.
+use crate::io_bus::{IoDevice, PortRange};
 use std::cell::RefCell;
 use std::collections::VecDeque;
-use std::fmt::{self, Write};
 use std::fs;
+use std::io::Write;
+
+/// Ports of COM1.
+pub const COM1: PortRange = (0x3f8, 8);
+
+// Register offsets.
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+// Interrupt identification on read, FIFO control on write.
+const INTERRUPT_ID: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const MODEM_STATUS: u16 = 6;
+const SCRATCH: u16 = 7;
+
+// The divisor latch is accessed at offset 0 and 1 while this bit of LCR is set.
+const DIVISOR_LATCH_ACCESS: u8 = 1 << 7;
+const MODEM_CONTROL_LOOPBACK: u8 = 1 << 4;
+const FIFO_ENABLE: u8 = 1 << 0;
+const FIFO_CLEAR_RECEIVER: u8 = 1 << 1;
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_TRANSMITTER_EMPTY: u8 = 0b0110_0000;
+// Interrupts are not raised yet.
+const NO_INTERRUPT_PENDING: u8 = 1 << 0;
+const FIFO_ENABLED: u8 = 0b1100_0000;
+// CTS, DSR and DCD are always asserted.
+const MODEM_STATUS_CONNECTED: u8 = 0b1011_0000;
 
-/// UART 16550 but it's not compatible yet.
-pub struct Uart16550 {
-    tx_writer: Box<Write>,
-}
-
-impl MemoryAccess for Uart16550 {
-    /// Not implemented yet.
-    fn read_u8(&self, _addr: usize) -> Result<u8> {
-        Err(MemoryAccessError::NoPermission)
-    }
-
-    /// TODO: Allow write only to tx buffer.
-    fn write_u8(&mut self, _addr: usize, data: u8) -> Result<()> {
-        write!(self.tx_writer, "{}", data as char).expect("Printing to serial failed");
-        Ok(())
-    }
-}
-
-/// Loopback write data to read buffer. This is mainly for integration test.
-struct UartLoopback {
-    buffer: RefCell<VecDeque<u8>>,
+#[derive(Debug, Clone)]
+pub enum Target {
+    Stdout,
+    /// Transmitted data is received by itself. This is mainly for integration test.
+    Buffer,
+    File(String),
 }
 
-impl MemoryAccess for UartLoopback {
-    // Read written data from FIFO buffer.
-    fn read_u8(&self, _addr: usize) -> Result<u8> {
-        Ok(self.buffer.borrow_mut().pop_front().unwrap())
-    }
-
-    // Write to FIFO buffer.
-    fn write_u8(&mut self, _addr: usize, data: u8) -> Result<()> {
-        self.buffer.get_mut().push_back(data);
-        Ok(())
-    }
+enum Line {
+    Stdout,
+    Loopback,
+    File(fs::File),
 }
 
-/// UART which writes the tx data to a file.
-struct UartFile {
-    file: fs::File,
+#[derive(Default)]
+struct Registers {
+    receiver: VecDeque<u8>,
+    interrupt_enable: u8,
+    fifo_control: u8,
+    line_control: u8,
+    modem_control: u8,
+    scratch: u8,
+    divisor: u16,
 }
 
-impl UartFile {
-    fn new(path: &str) -> UartFile {
-        let file = fs::File::create(&path).expect("Fail to create file.");
-        UartFile { file }
-    }
+pub struct Uart16550 {
+    line: Line,
+    registers: RefCell<Registers>,
 }
 
-impl MemoryAccess for UartFile {
-    /// Not implemented yet.
-    fn read_u8(&self, _addr: usize) -> Result<u8> {
-        Err(MemoryAccessError::NoPermission)
+impl Uart16550 {
+    pub fn new(target: Target) -> Uart16550 {
+        let line = match target {
+            Target::Stdout => Line::Stdout,
+            Target::Buffer => Line::Loopback,
+            Target::File(path) => {
+                Line::File(fs::File::create(&path).expect("Fail to create file."))
+            }
+        };
+        Uart16550 {
+            line,
+            registers: RefCell::new(Registers::default()),
+        }
     }
 
-    /// TODO: Allow write only to tx buffer.
-    fn write_u8(&mut self, _addr: usize, data: u8) -> Result<()> {
-        use std::io::Write;
-        write!(self.file, "{}", data as char).map_err(|_| MemoryAccessError::NoPermission)?;
-        Ok(())
+    fn transmit(&mut self, data: u8) {
+        let registers = self.registers.get_mut();
+        if registers.modem_control & MODEM_CONTROL_LOOPBACK != 0 {
+            registers.receiver.push_back(data);
+            return;
+        }
+        match &mut self.line {
+            Line::Stdout => print!("{}", data as char),
+            Line::Loopback => registers.receiver.push_back(data),
+            Line::File(file) => {
+                write!(file, "{}", data as char).expect("Printing to serial failed")
+            }
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Target {
-    Stdout,
-    Buffer,
-    File(String),
-}
-
-pub fn uart_factory(target: Target) -> Box<dyn MemoryAccess> {
-    use self::Target::*;
-    match target {
-        Stdout => Box::new(Uart16550 {
-            tx_writer: Box::new(StdoutWriter::new()),
-        }),
-        Buffer => Box::new(UartLoopback {
-            buffer: RefCell::new(VecDeque::new()),
-        }),
-        File(path) => Box::new(UartFile::new(&path)),
+impl IoDevice for Uart16550 {
+    fn read_u8(&self, port: u16) -> u8 {
+        let mut registers = self.registers.borrow_mut();
+        let dlab = registers.line_control & DIVISOR_LATCH_ACCESS != 0;
+        match port {
+            DATA if dlab => registers.divisor as u8,
+            DATA => registers.receiver.pop_front().unwrap_or(0),
+            INTERRUPT_ENABLE if dlab => (registers.divisor >> 8) as u8,
+            INTERRUPT_ENABLE => registers.interrupt_enable,
+            INTERRUPT_ID if registers.fifo_control & FIFO_ENABLE != 0 => {
+                NO_INTERRUPT_PENDING | FIFO_ENABLED
+            }
+            INTERRUPT_ID => NO_INTERRUPT_PENDING,
+            LINE_CONTROL => registers.line_control,
+            MODEM_CONTROL => registers.modem_control,
+            LINE_STATUS if registers.receiver.is_empty() => LINE_STATUS_TRANSMITTER_EMPTY,
+            LINE_STATUS => LINE_STATUS_TRANSMITTER_EMPTY | LINE_STATUS_DATA_READY,
+            // In loopback mode, DTR, RTS, OUT1 and OUT2 are seen as DSR, CTS, RI and DCD.
+            MODEM_STATUS if registers.modem_control & MODEM_CONTROL_LOOPBACK != 0 => {
+                let control = registers.modem_control;
+                (control & 0b0001) << 5
+                    | (control & 0b0010) << 3
+                    | (control & 0b0100) << 4
+                    | (control & 0b1000) << 4
+            }
+            MODEM_STATUS => MODEM_STATUS_CONNECTED,
+            SCRATCH => registers.scratch,
+            _ => 0xff,
+        }
     }
-}
 
-struct StdoutWriter;
-impl StdoutWriter {
-    fn new() -> StdoutWriter {
-        StdoutWriter {}
+    fn write_u8(&mut self, port: u16, data: u8) {
+        let registers = self.registers.get_mut();
+        let dlab = registers.line_control & DIVISOR_LATCH_ACCESS != 0;
+        match port {
+            DATA if dlab => registers.divisor = registers.divisor & 0xff00 | u16::from(data),
+            DATA => self.transmit(data),
+            INTERRUPT_ENABLE if dlab => {
+                registers.divisor = registers.divisor & 0x00ff | u16::from(data) << 8
+            }
+            INTERRUPT_ENABLE => registers.interrupt_enable = data & 0x0f,
+            INTERRUPT_ID => {
+                if data & FIFO_CLEAR_RECEIVER != 0 {
+                    registers.receiver.clear();
+                }
+                registers.fifo_control = data;
+            }
+            LINE_CONTROL => registers.line_control = data,
+            MODEM_CONTROL => registers.modem_control = data & 0x1f,
+            SCRATCH => registers.scratch = data,
+            // Line status and modem status are read-only.
+            _ => (),
+        }
     }
 }
 
-impl Write for StdoutWriter {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        print!("{}", s);
-        Ok(())
-    }
+pub fn uart_factory(target: Target) -> Box<dyn IoDevice> {
+    Box::new(Uart16550::new(target))
 }
 
 #[cfg(test)]
@@ -108,16 +161,47 @@ mod test {
     #[test]
     fn stdout_write() {
         let mut uart = uart_factory(Target::Stdout);
-        assert!(uart.write_u8(0, b'a').is_ok());
+        uart.write_u8(DATA, b'a');
+        assert_eq!(uart.read_u8(LINE_STATUS), LINE_STATUS_TRANSMITTER_EMPTY);
     }
 
     #[test]
     fn buffer_write() {
         let mut loopback_uart = uart_factory(Target::Buffer);
-        assert!(loopback_uart.write_u8(0, b'o').is_ok());
-        assert!(loopback_uart.write_u8(0, b'k').is_ok());
+        loopback_uart.write_u8(DATA, b'o');
+        loopback_uart.write_u8(DATA, b'k');
+
+        assert_eq!(
+            loopback_uart.read_u8(LINE_STATUS) & LINE_STATUS_DATA_READY,
+            1
+        );
+        assert_eq!(loopback_uart.read_u8(DATA), b'o');
+        assert_eq!(loopback_uart.read_u8(DATA), b'k');
+        assert_eq!(
+            loopback_uart.read_u8(LINE_STATUS) & LINE_STATUS_DATA_READY,
+            0
+        );
+    }
 
-        assert_eq!(loopback_uart.read_u8(0).unwrap(), b'o');
-        assert_eq!(loopback_uart.read_u8(0).unwrap(), b'k');
+    #[test]
+    fn divisor_and_loopback() {
+        let mut uart = uart_factory(Target::Stdout);
+        // 115200 / 3 = 38400 baud, 8N1.
+        uart.write_u8(LINE_CONTROL, 0x80);
+        uart.write_u8(DATA, 0x03);
+        uart.write_u8(INTERRUPT_ENABLE, 0x00);
+        uart.write_u8(LINE_CONTROL, 0x03);
+        assert_eq!(uart.read_u8(INTERRUPT_ENABLE), 0x00);
+        uart.write_u8(LINE_CONTROL, 0x83);
+        assert_eq!(uart.read_u16(DATA), 0x0003);
+        uart.write_u8(LINE_CONTROL, 0x03);
+
+        // Probe in loopback mode, as Linux does.
+        uart.write_u8(MODEM_CONTROL, 0x1e);
+        uart.write_u8(DATA, 0xae);
+        assert_eq!(uart.read_u8(DATA), 0xae);
+        assert_eq!(uart.read_u8(MODEM_STATUS), 0xd0);
+        uart.write_u8(SCRATCH, 0x5a);
+        assert_eq!(uart.read_u8(SCRATCH), 0x5a);
     }
 }
@@ -11,11 +11,35 @@ use cpu::model::cpu_factory;
 use cpu::model::CpuModel;
 use crate::options::EmulationMode;
 use debug::DebugMode;
+use loader::elf_loader::ElfLoader;
+use loader::multiboot::{BootConfig, Framebuffer, FramebufferType, Module, MultibootKernel};
+use std::fs;
+use std::path::Path;
+use x86::X86;
+use x86_64::linux::{LinuxProcess, ProgramImage};
 use x86_64::{self, X86_64};
 
-use peripherals::interconnect::Interconnect;
+use peripherals::interconnect::{Interconnect, MEMORY_SIZE};
+use peripherals::io_bus::IoDevice;
 use peripherals::memory_access::MemoryAccess;
 
+// Linux loads position independent executables around here.
+const PIE_LOAD_BIAS: usize = 0x5555_5555_4000;
+// BIOS loads the boot sector here.
+const BOOT_SECTOR_ADDR: usize = 0x7c00;
+const BOOT_SECTOR_SIZE: usize = 512;
+// Memory from 1 MiB for Multiboot kernels.
+const MULTIBOOT_UPPER_MEMORY: usize = 0x100_0000;
+// The display is the VGA text buffer of 80x25 characters.
+const VGA_TEXT: Framebuffer = Framebuffer {
+    addr: 0xb_8000,
+    pitch: 160,
+    width: 80,
+    height: 25,
+    bpp: 16,
+    kind: FramebufferType::EgaText,
+};
+
 pub struct CpuError {}
 
 /* Pseudo code for switching target isa.
@@ -29,19 +53,106 @@ fn run(target: TargetArch, interconnect: Interconnect, mode: EmulationMode) {
 pub fn start_emulation(
     program: Vec<u8>,
     mode_option: EmulationMode,
-    serial: Box<dyn MemoryAccess>,
+    serial: Box<dyn IoDevice>,
     display: Box<dyn MemoryAccess>,
 ) -> Result<(), CpuError> {
     let mut interconnect = Interconnect::new(serial, display);
     // Need to initialize according to elf.
     interconnect.init_memory(&program, 0);
-    let debug: DebugMode = match mode_option {
-        EmulationMode::Normal | EmulationMode::Test(_) => DebugMode::Disabled,
-        EmulationMode::PerCycleDump => DebugMode::PerCycleDump,
-        EmulationMode::InteractiveMode => DebugMode::Interactive,
-    };
+    let debug = debug_mode(&mode_option);
+
+    let mut cpu = cpu_factory::<X86_64>(interconnect, debug);
+    let result = cpu.run();
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            println!("Emulation stopped at error: {:?}", err);
+            Err(CpuError {})
+        }
+    }
+}
+
+/// Runs a boot sector from 0x7c00 in real mode, as BIOS does after POST.
+/// The boot sector may switch the CPU to protected mode and long mode by itself.
+pub fn start_boot_sector_emulation(
+    boot_sector: &[u8],
+    mode_option: EmulationMode,
+    serial: Box<dyn IoDevice>,
+    display: Box<dyn MemoryAccess>,
+) -> Result<(), CpuError> {
+    if boot_sector.len() != BOOT_SECTOR_SIZE || boot_sector[510..] != [0x55, 0xaa] {
+        println!("The binary is not a bootable sector.");
+        return Err(CpuError {});
+    }
 
+    let mut interconnect = Interconnect::new(serial, display);
+    interconnect.init_memory(boot_sector, BOOT_SECTOR_ADDR);
+    let debug = debug_mode(&mode_option);
     let mut cpu = cpu_factory::<X86_64>(interconnect, debug);
+    cpu.boot_bios();
+    let result = cpu.run();
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            println!("Emulation stopped at error: {:?}", err);
+            Err(CpuError {})
+        }
+    }
+}
+
+/// Boots a Multiboot or Multiboot2 kernel in 32-bit protected mode, as `qemu -kernel` does.
+/// Each module is a path to the file followed by its arguments,
+/// and the whole string is passed to the kernel.
+pub fn start_multiboot_emulation(
+    kernel: &[u8],
+    cmdline: &str,
+    modules: &[String],
+    mode_option: EmulationMode,
+    serial: Box<dyn IoDevice>,
+    display: Box<dyn MemoryAccess>,
+) -> Result<(), CpuError> {
+    let mut config = BootConfig {
+        lower_memory: MEMORY_SIZE,
+        upper_memory: MULTIBOOT_UPPER_MEMORY,
+        cmdline: cmdline.to_string(),
+        modules: Vec::new(),
+        framebuffer: Some(VGA_TEXT),
+    };
+    for module in modules {
+        let path = module.split_whitespace().next().unwrap_or("");
+        match fs::read(path) {
+            Ok(binary) => config.modules.push(Module {
+                binary,
+                cmdline: module.clone(),
+            }),
+            Err(err) => {
+                println!("Failed to load module {}: {}", path, err);
+                return Err(CpuError {});
+            }
+        }
+    }
+    let boot = match MultibootKernel::try_new(kernel).and_then(|kernel| kernel.boot(&config)) {
+        Ok(boot) => boot,
+        Err(err) => {
+            println!("Failed to boot the kernel: {}", err);
+            return Err(CpuError {});
+        }
+    };
+
+    let mut interconnect = Interconnect::new(serial, display);
+    interconnect.add_extended_memory(MULTIBOOT_UPPER_MEMORY);
+    for segment in boot.segments() {
+        interconnect.init_memory(segment.binary_as_ref(), segment.start_addr());
+    }
+    let debug = debug_mode(&mode_option);
+    let mut cpu = cpu_factory::<X86>(interconnect, debug);
+    cpu.boot_multiboot(
+        boot.entry_point() as u64,
+        boot.magic(),
+        boot.info_addr() as u64,
+    );
     let result = cpu.run();
 
     match result {
@@ -53,6 +164,75 @@ pub fn start_emulation(
     }
 }
 
+/// Runs a statically linked x86_64 Linux binary as a user process.
+/// `args[0]` is the path to the binary, and files are opened under `sandbox_root`.
+/// The environment of the host is not inherited, and the process has only `envs` of `KEY=VALUE`.
+/// Returns the exit status of the process.
+pub fn start_user_emulation(
+    args: &[String],
+    envs: &[String],
+    sandbox_root: &str,
+    mode_option: EmulationMode,
+) -> Result<i32, CpuError> {
+    let loader = match ElfLoader::try_new(&args[0]) {
+        Ok(loader) => loader,
+        Err(err) => {
+            println!("Failed to load {}: {}", args[0], err);
+            return Err(CpuError {});
+        }
+    };
+    if !loader.is_static_x86_64_executable() {
+        println!("{} is not a static x86_64 executable.", args[0]);
+        return Err(CpuError {});
+    }
+    let bias = if loader.is_position_independent() {
+        PIE_LOAD_BIAS
+    } else {
+        0
+    };
+
+    let mut interconnect = Interconnect::new_user_space();
+    let memory = interconnect.user_space_mut().unwrap();
+    let mut brk = 0;
+    for segment in loader.load_segments() {
+        let addr = segment.virtual_addr() + bias;
+        memory.map(addr, segment.size());
+        memory.fill(segment.binary_as_ref(), addr).unwrap();
+        brk = brk.max(addr + segment.size());
+    }
+    let (phdr, phent, phnum) = loader
+        .program_headers()
+        .map_or((0, 0, 0), |(phdr, phent, phnum)| (phdr + bias, phent, phnum));
+    let image = ProgramImage {
+        entry: (loader.entry_point() + bias) as u64,
+        program_headers: phdr as u64,
+        program_header_size: phent as u64,
+        program_header_num: phnum as u64,
+        brk: brk as u64,
+    };
+
+    let process = LinuxProcess::new(Path::new(sandbox_root), args, envs);
+    let debug = debug_mode(&mode_option);
+    let mut cpu = X86_64::new_user_process(interconnect, debug, process, &image);
+    let result = cpu.run();
+
+    match result {
+        Ok(_) => Ok(cpu.exit_status().unwrap_or(0)),
+        Err(err) => {
+            println!("Emulation stopped at error: {:?}", err);
+            Err(CpuError {})
+        }
+    }
+}
+
+fn debug_mode(mode_option: &EmulationMode) -> DebugMode {
+    match mode_option {
+        EmulationMode::Normal | EmulationMode::Test(_) => DebugMode::Disabled,
+        EmulationMode::PerCycleDump => DebugMode::PerCycleDump,
+        EmulationMode::InteractiveMode => DebugMode::Interactive,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
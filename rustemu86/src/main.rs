@@ -3,20 +3,44 @@ use peripherals::memory_access::MemoryAccess;
 use peripherals::uart16550::{uart_factory, Target};
 use loader::{load, map_to_memory};
 use rustemu86::{
-    options::parse_args,
-    start_emulation,
+    options::{parse_args, RustemuOptions},
+    start_boot_sector_emulation, start_emulation, start_multiboot_emulation, start_user_emulation,
 };
+use std::process;
 
-fn start_rustemu86(screen: GtkVgaTextBuffer) {
-    let options = parse_args();
+fn start_rustemu86(screen: GtkVgaTextBuffer, options: &RustemuOptions) {
     let mut reader = load(&options.file_path).unwrap();
     let program = map_to_memory(&mut reader).unwrap();
     let display: Box<dyn MemoryAccess> = Box::new(screen);
     let serial = uart_factory(Target::Stdout);
 
-    let _result = start_emulation(program, options.emulation_mode, serial, display);
+    let _result = if options.multiboot {
+        start_multiboot_emulation(
+            &program,
+            &options.cmdline,
+            &options.modules,
+            options.emulation_mode.clone(),
+            serial,
+            display,
+        )
+    } else if options.boot_sector {
+        start_boot_sector_emulation(&program, options.emulation_mode.clone(), serial, display)
+    } else {
+        start_emulation(program, options.emulation_mode.clone(), serial, display)
+    };
 }
 
 fn main() {
-    start_with_gtk(start_rustemu86);
+    // A user process does not have a display.
+    let options = parse_args();
+    if options.user_mode {
+        let result = start_user_emulation(
+            &options.args,
+            &options.envs,
+            &options.sandbox_root,
+            options.emulation_mode,
+        );
+        process::exit(result.unwrap_or(1));
+    }
+    start_with_gtk(move |screen| start_rustemu86(screen, &options));
 }
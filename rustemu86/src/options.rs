@@ -1,4 +1,4 @@
-use getopts::Options;
+use getopts::{Options, ParsingStyle};
 use std::{env, process};
 
 #[derive(Debug, Clone)]
@@ -13,19 +13,45 @@ pub enum EmulationMode {
 pub struct RustemuOptions {
     pub file_path: String,
     pub emulation_mode: EmulationMode,
+    /// Runs the binary as a Linux process instead of on the machine.
+    pub user_mode: bool,
+    /// Arguments of the Linux process, which start with the binary.
+    pub args: Vec<String>,
+    /// Environment variables of the Linux process as `KEY=VALUE`.
+    pub envs: Vec<String>,
+    /// Files opened by the Linux process are inside this directory.
+    pub sandbox_root: String,
+    /// Boots the binary as a Multiboot kernel instead of running it from address 0.
+    pub multiboot: bool,
+    /// Runs the binary as a boot sector in real mode.
+    pub boot_sector: bool,
+    /// Command line of the Multiboot kernel.
+    pub cmdline: String,
+    /// Multiboot modules, each of which is a path followed by its arguments.
+    pub modules: Vec<String>,
 }
 
 fn print_usage(program: &str, opts: &Options) {
-    let brief = format!("Usage: {} BINARY [options]", program);
+    let brief = format!(
+        "Usage: {} [options] BINARY\n       {} --user [options] BINARY [ARGS...]\n       {} --multiboot [options] KERNEL\n       {} --boot [options] BOOT_SECTOR",
+        program, program, program, program
+    );
     print!("{}", opts.usage(&brief));
     process::exit(0);
 }
 
 pub fn parse_args() -> RustemuOptions {
     let options: Vec<String> = env::args().collect();
+    parse_options(&options)
+}
+
+/// Parses the command line, which starts with the program name.
+pub fn parse_options(options: &[String]) -> RustemuOptions {
     let program = options[0].clone();
 
     let mut opts = Options::new();
+    // Options after the binary are arguments of the user process.
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
     opts.optflag("h", "help", "Print this help menu");
     opts.optflag(
         "v",
@@ -33,6 +59,45 @@ pub fn parse_args() -> RustemuOptions {
         "Print verbose log messages during emulation",
     );
     opts.optflag("i", "interactive", "Run emulation with interactive shell.");
+    opts.optflag(
+        "u",
+        "user",
+        "Run a static x86_64 Linux binary as a user process.",
+    );
+    opts.optmulti(
+        "E",
+        "env",
+        "Set an environment variable of the user process. Can be given more than once.",
+        "KEY=VAL",
+    );
+    opts.optopt(
+        "r",
+        "root",
+        "Directory where the user process opens files. Default is the current directory.",
+        "DIR",
+    );
+    opts.optflag(
+        "m",
+        "multiboot",
+        "Boot a Multiboot kernel in 32-bit protected mode.",
+    );
+    opts.optflag(
+        "b",
+        "boot",
+        "Boot a boot sector from real mode as BIOS does.",
+    );
+    opts.optopt(
+        "a",
+        "append",
+        "Command line of the Multiboot kernel.",
+        "CMDLINE",
+    );
+    opts.optmulti(
+        "",
+        "module",
+        "Load a Multiboot module. Can be given more than once.",
+        "'FILE [ARGS]'",
+    );
 
     let matches = match opts.parse(&options[1..]) {
         Ok(m) => m,
@@ -58,5 +123,13 @@ pub fn parse_args() -> RustemuOptions {
     RustemuOptions {
         file_path: matches.free[0].clone(),
         emulation_mode: mode,
+        user_mode: matches.opt_present("u"),
+        args: matches.free.clone(),
+        envs: matches.opt_strs("E"),
+        sandbox_root: matches.opt_str("r").unwrap_or_else(|| ".".to_string()),
+        multiboot: matches.opt_present("m"),
+        boot_sector: matches.opt_present("b"),
+        cmdline: matches.opt_str("a").unwrap_or_default(),
+        modules: matches.opt_strs("module"),
     }
 }
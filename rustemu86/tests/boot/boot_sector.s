@@ -0,0 +1,76 @@
+# Boot sector which switches from real mode to long mode through 32-bit protected mode.
+# Writes a message to COM1 in 64-bit mode, then halts with interrupts disabled.
+#   as --32 boot_sector.s -o boot_sector.o && ld -m elf_i386 -s -Ttext=0x7c00 --oformat binary -o boot_sector boot_sector.o
+    .intel_syntax noprefix
+    .globl _start
+
+    .text
+    .code16
+_start:
+    cli
+    xor ax, ax
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+    mov sp, 0x7c00
+    lgdt [gdt_desc]
+    mov eax, cr0
+    or eax, 0x1
+    mov cr0, eax
+    ljmp 0x08, offset protected_mode
+
+    .code32
+protected_mode:
+    mov ax, 0x10
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+    mov esp, 0x7c00
+    # Identity maps the first 2MiB by PML4 at 0x1000, PDPT at 0x2000 and PD at 0x3000.
+    mov edi, 0x1000
+    xor eax, eax
+    mov ecx, 0xc00
+    rep stosd
+    mov dword ptr [0x1000], 0x2003
+    mov dword ptr [0x2000], 0x3003
+    mov dword ptr [0x3000], 0x83
+    # PAE, EFER.LME and then paging.
+    mov eax, cr4
+    or eax, 0x20
+    mov cr4, eax
+    mov eax, 0x1000
+    mov cr3, eax
+    mov ecx, 0xc0000080
+    rdmsr
+    or eax, 0x100
+    wrmsr
+    mov eax, cr0
+    or eax, 0x80000000
+    mov cr0, eax
+    ljmp 0x18, offset long_mode
+
+    .code64
+long_mode:
+    mov esi, offset msg
+    mov ecx, offset msg_len
+    mov edx, 0x3f8
+    rep outsb
+    hlt
+
+msg:
+    .ascii "Long mode\n"
+    .set msg_len, . - msg
+
+    .p2align 3
+gdt:
+    .quad 0
+    # 32-bit code, data and 64-bit code segments.
+    .quad 0x00cf9a000000ffff
+    .quad 0x00cf92000000ffff
+    .quad 0x00af9a000000ffff
+gdt_desc:
+    .word gdt_desc - gdt - 1
+    .long gdt
+
+    .org 510
+    .word 0xaa55
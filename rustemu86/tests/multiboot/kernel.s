@@ -0,0 +1,27 @@
+# Multiboot kernel for 32-bit protected mode boot tests.
+# Writes a message to COM1, then halts with interrupts disabled.
+#   as --32 kernel.s -o kernel.o && ld -m elf_i386 -n -s --build-id=none -Ttext=0x100000 -o kernel kernel.o
+    .intel_syntax noprefix
+    .globl _start
+
+    .set MAGIC, 0x1badb002
+    # Page aligned modules and the memory information.
+    .set FLAGS, 0x3
+
+    .text
+    .align 4
+    .long MAGIC
+    .long FLAGS
+    .long -(MAGIC + FLAGS)
+
+_start:
+    mov esi, offset msg
+    mov ecx, offset msg_len
+    mov edx, 0x3f8
+    rep outsb
+    hlt
+
+    .data
+msg:
+    .ascii "Multiboot\n"
+    .set msg_len, . - msg
@@ -0,0 +1,46 @@
+use peripherals::error::MemoryAccessError;
+use peripherals::memory_access::MemoryAccess;
+use peripherals::uart16550::{self, Target};
+use rustemu86::options::EmulationMode;
+use std::fs;
+
+struct FakeDisplay();
+impl MemoryAccess for FakeDisplay {
+    fn read_u8(&self, _addr: usize) -> Result<u8, MemoryAccessError> {
+        unimplemented!()
+    }
+
+    fn write_u8(&mut self, _addr: usize, _data: u8) -> Result<(), MemoryAccessError> {
+        unimplemented!()
+    }
+}
+
+#[test]
+fn test_boot_sector_to_long_mode() {
+    let boot_sector = fs::read("./tests/boot/boot_sector").unwrap();
+    let display: Box<dyn MemoryAccess> = Box::new(FakeDisplay());
+    let serial = uart16550::uart_factory(Target::File("test_boot_sector".to_string()));
+
+    let result = rustemu86::start_boot_sector_emulation(
+        &boot_sector,
+        EmulationMode::Test("test_boot_sector".to_string()),
+        serial,
+        display,
+    );
+    assert!(result.is_ok());
+
+    let contents = fs::read_to_string("test_boot_sector").unwrap();
+    assert_eq!(contents, "Long mode\n");
+    fs::remove_file("test_boot_sector").unwrap();
+}
+
+#[test]
+fn test_boot_sector_without_signature() {
+    let program = fs::read("./tests/asms/hello").unwrap();
+    let display: Box<dyn MemoryAccess> = Box::new(FakeDisplay());
+    let serial = uart16550::uart_factory(Target::Buffer);
+
+    let result =
+        rustemu86::start_boot_sector_emulation(&program, EmulationMode::Normal, serial, display);
+    assert!(result.is_err());
+}
@@ -0,0 +1,39 @@
+use rustemu86::options::{parse_options, EmulationMode};
+
+#[test]
+fn test_linux_hello() {
+    // The same binary is the fixture of the ELF loader.
+    let args = vec![
+        "../loader/tests/data/elf/hello-x86_64".to_string(),
+        "world".to_string(),
+    ];
+    let result = rustemu86::start_user_emulation(
+        &args,
+        &[],
+        ".",
+        EmulationMode::Test("test_linux_hello".to_string()),
+    );
+    // The program exits with argc.
+    assert_eq!(result.ok(), Some(2));
+}
+
+#[test]
+fn test_linux_non_x86_64_binary() {
+    let args = vec!["./tests/asms/hello".to_string()];
+    let result = rustemu86::start_user_emulation(&args, &[], ".", EmulationMode::Normal);
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_linux_guest_options() {
+    let options: Vec<String> = ["rustemu86", "--user", "-E", "A=1", "./prog", "-v", "-a"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    let options = parse_options(&options);
+    // Options after the binary are passed to the process.
+    assert!(options.user_mode);
+    assert_eq!(options.args, vec!["./prog", "-v", "-a"]);
+    assert!(options.cmdline.is_empty());
+    assert_eq!(options.envs, vec!["A=1"]);
+}
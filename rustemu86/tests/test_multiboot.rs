@@ -0,0 +1,55 @@
+use peripherals::error::MemoryAccessError;
+use peripherals::memory_access::MemoryAccess;
+use peripherals::uart16550::{self, Target};
+use rustemu86::options::EmulationMode;
+use std::fs;
+
+struct FakeDisplay();
+impl MemoryAccess for FakeDisplay {
+    fn read_u8(&self, _addr: usize) -> Result<u8, MemoryAccessError> {
+        unimplemented!()
+    }
+
+    fn write_u8(&mut self, _addr: usize, _data: u8) -> Result<(), MemoryAccessError> {
+        unimplemented!()
+    }
+}
+
+#[test]
+fn test_multiboot_kernel() {
+    let kernel = fs::read("./tests/multiboot/kernel").unwrap();
+    let modules = vec!["./tests/asms/hello hello".to_string()];
+    let display: Box<dyn MemoryAccess> = Box::new(FakeDisplay());
+    let serial = uart16550::uart_factory(Target::File("test_multiboot".to_string()));
+
+    let result = rustemu86::start_multiboot_emulation(
+        &kernel,
+        "console=ttyS0",
+        &modules,
+        EmulationMode::Test("test_multiboot".to_string()),
+        serial,
+        display,
+    );
+    assert!(result.is_ok());
+
+    let contents = fs::read_to_string("test_multiboot").unwrap();
+    assert_eq!(contents, "Multiboot\n");
+    fs::remove_file("test_multiboot").unwrap();
+}
+
+#[test]
+fn test_multiboot_non_multiboot_binary() {
+    let kernel = fs::read("./tests/asms/hello").unwrap();
+    let display: Box<dyn MemoryAccess> = Box::new(FakeDisplay());
+    let serial = uart16550::uart_factory(Target::Buffer);
+
+    let result = rustemu86::start_multiboot_emulation(
+        &kernel,
+        "",
+        &[],
+        EmulationMode::Normal,
+        serial,
+        display,
+    );
+    assert!(result.is_err());
+}